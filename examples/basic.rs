@@ -31,11 +31,9 @@ impl GraphicsCaptureApiHandler for Capture {
     fn new(message: Self::Flags) -> Result<Self, Self::Error> {
         println!("Got The Flag: {message}");
 
-        let encoder = VideoEncoder::new(
+        let encoder = VideoEncoder::new_auto(
             VideoEncoderType::Hevc,
             VideoEncoderQuality::HD1080p,
-            1920,
-            1080,
             "video.mp4",
             Some(30),
         )?;
@@ -1,58 +1,352 @@
 use std::{
+    collections::VecDeque,
+    ffi::c_void,
     mem,
     os::windows::prelude::AsRawHandle,
     sync::{
-        atomic::{self, AtomicBool},
+        atomic::{self, AtomicBool, AtomicU64},
         mpsc, Arc,
     },
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
-use parking_lot::Mutex;
+use parking_lot::{Condvar, Mutex};
 use windows::{
-    Foundation::AsyncActionCompletedHandler,
-    Graphics::Capture::GraphicsCaptureItem,
+    core::HSTRING,
+    Foundation::{AsyncActionCompletedHandler, TimeSpan},
+    Graphics::Capture::{GraphicsCaptureItem, GraphicsCaptureSession},
     Win32::{
-        Foundation::{HANDLE, LPARAM, WPARAM},
+        Foundation::{HANDLE, HMODULE, HWND, LPARAM, WPARAM},
         System::{
-            Threading::{GetCurrentThreadId, GetThreadId},
+            Threading::{
+                AvRevertMmThreadCharacteristics, AvSetMmThreadCharacteristicsW, GetCurrentThread,
+                GetCurrentThreadId, GetThreadId, GetThreadPriority, SetThreadPriority,
+                THREAD_PRIORITY, THREAD_PRIORITY_ABOVE_NORMAL, THREAD_PRIORITY_BELOW_NORMAL,
+                THREAD_PRIORITY_HIGHEST, THREAD_PRIORITY_IDLE, THREAD_PRIORITY_LOWEST,
+                THREAD_PRIORITY_NORMAL, THREAD_PRIORITY_TIME_CRITICAL,
+            },
             WinRT::{
                 CreateDispatcherQueueController, DispatcherQueueOptions, RoInitialize,
                 RoUninitialize, DQTAT_COM_NONE, DQTYPE_THREAD_CURRENT, RO_INIT_MULTITHREADED,
             },
         },
-        UI::WindowsAndMessaging::{
-            DispatchMessageW, GetMessageW, PostQuitMessage, PostThreadMessageW, TranslateMessage,
-            MSG, WM_QUIT,
+        UI::{
+            Accessibility::{SetWinEventHook, UnhookWinEvent, HWINEVENTHOOK},
+            WindowsAndMessaging::{
+                DispatchMessageW, GetMessageW, PeekMessageW, PostQuitMessage, PostThreadMessageW,
+                TranslateMessage, EVENT_SYSTEM_FOREGROUND, MSG, PM_REMOVE, WINEVENT_OUTOFCONTEXT,
+                WINEVENT_SKIPOWNPROCESS, WM_APP, WM_QUIT,
+            },
         },
     },
 };
 
 use crate::{
-    frame::Frame,
+    frame::{Frame, FrameSnapshot},
     graphics_capture_api::{self, GraphicsCaptureApi, InternalCaptureControl},
-    settings::Settings,
+    settings::{
+        CloseBehaviorSettings, CursorCaptureSettings, MmcssSettings, Settings, SettingsSnapshot,
+        ThreadPrioritySettings,
+    },
+    window::Window,
 };
 
+/// The id given to the next capture session, ids are unique for the lifetime of the process.
+static NEXT_CAPTURE_ID: AtomicU64 = AtomicU64::new(1);
+
+// Reserve a unique id for a new capture session.
+pub(crate) fn next_capture_id() -> u64 {
+    NEXT_CAPTURE_ID.fetch_add(1, atomic::Ordering::Relaxed)
+}
+
+/// How often `CaptureControl::wait_for_first_frame` checks if the capture thread has ended.
+const FINISHED_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How often a capture looks for a window to reconnect to after the captured window closed.
+const RECONNECT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The number of recent inter-frame gaps used by `CaptureControl::frame_pacing`.
+const PACING_WINDOW: usize = 240;
+
+/// The thread message that tells the capture thread a new item is waiting to be captured.
+const RETARGET_MESSAGE: u32 = WM_APP + 1;
+
+/// The thread message the foreground hook posts with the new foreground window.
+const FOREGROUND_MESSAGE: u32 = WM_APP + 2;
+
+/// A new item for the capture thread and where to send whether the capture switched to it.
+type RetargetRequest<E> = (
+    GraphicsCaptureItem,
+    mpsc::Sender<Result<(), GraphicsCaptureApiError<E>>>,
+);
+
 #[derive(thiserror::Error, Debug)]
 pub enum CaptureControlError<E> {
     #[error("Failed to join thread")]
     FailedToJoinThread,
+    #[error("No frame arrived before the timeout")]
+    FirstFrameTimeout,
+    #[error("The capture ended before a frame arrived")]
+    CaptureEndedWithoutFrame,
     #[error("Thread handle is taken out of struct")]
     ThreadHandleIsTaken,
+    #[error("The capture thread has ended")]
+    CaptureEnded,
     #[error("Failed to post thread message")]
     FailedToPostThreadMessage,
     #[error("Stopped handler error: {0}")]
     StoppedHandlerError(E),
+    #[error("The handler is still shared, a clone from CaptureControl::callback is alive")]
+    HandlerStillShared,
     #[error("Windows capture error: {0}")]
     GraphicsCaptureApiError(#[from] GraphicsCaptureApiError<E>),
 }
 
+/// Statistics of the gaps between recently delivered frames, based on the frame timestamps.
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub struct PacingStats {
+    samples: usize,
+    min: Duration,
+    max: Duration,
+    mean: Duration,
+    std_dev: Duration,
+}
+
+impl PacingStats {
+    // Compute the statistics of gaps in 100ns units.
+    fn from_gaps(gaps: &VecDeque<i64>) -> Self {
+        if gaps.is_empty() {
+            return Self::default();
+        }
+
+        let to_duration = |gap: f64| Duration::from_secs_f64(gap.max(0.0) / 10_000_000.0);
+
+        let count = gaps.len() as f64;
+        let mean = gaps.iter().map(|gap| *gap as f64).sum::<f64>() / count;
+        let variance = gaps
+            .iter()
+            .map(|gap| (*gap as f64 - mean).powi(2))
+            .sum::<f64>()
+            / count;
+
+        Self {
+            samples: gaps.len(),
+            min: to_duration(*gaps.iter().min().unwrap() as f64),
+            max: to_duration(*gaps.iter().max().unwrap() as f64),
+            mean: to_duration(mean),
+            std_dev: to_duration(variance.sqrt()),
+        }
+    }
+
+    /// Get the number of gaps the statistics are based on, zero until two frames arrived.
+    #[must_use]
+    pub const fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// Get the shortest gap between two frames.
+    #[must_use]
+    pub const fn min(&self) -> Duration {
+        self.min
+    }
+
+    /// Get the longest gap between two frames.
+    #[must_use]
+    pub const fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Get the mean gap between two frames.
+    #[must_use]
+    pub const fn mean(&self) -> Duration {
+        self.mean
+    }
+
+    /// Get the standard deviation of the gaps, the jitter of the frame pacing.
+    #[must_use]
+    pub const fn std_dev(&self) -> Duration {
+        self.std_dev
+    }
+
+    /// Get the rate the source produced new frames at, based on the mean gap.
+    ///
+    /// Repeated frames aren't counted, so a 60 Hz capture of a source that only updates 40
+    /// times a second reports about 40.
+    #[must_use]
+    pub fn frame_rate(&self) -> f64 {
+        if self.mean.is_zero() {
+            0.0
+        } else {
+            1.0 / self.mean.as_secs_f64()
+        }
+    }
+}
+
+/// The number of unique and duplicated frames delivered to the handler.
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub struct FrameCounts {
+    unique: u64,
+    duplicated: u64,
+}
+
+impl FrameCounts {
+    /// Get the number of delivered frames with new content from the source.
+    #[must_use]
+    pub const fn unique(&self) -> u64 {
+        self.unique
+    }
+
+    /// Get the number of delivered frames that repeat the previous frame, for example to fill
+    /// display refreshes with `FramePacingSettings::Vsync`.
+    #[must_use]
+    pub const fn duplicated(&self) -> u64 {
+        self.duplicated
+    }
+
+    /// Get the total number of delivered frames.
+    #[must_use]
+    pub const fn total(&self) -> u64 {
+        self.unique + self.duplicated
+    }
+}
+
+/// Cancels starting a capture from another thread, for example when the user changes their
+/// mind while a slow system is still setting the capture up. Clones share the same state.
+///
+/// # Example
+/// ```ignore
+/// let token = CancellationToken::new();
+/// let settings = settings.cancellation_token(token.clone());
+///
+/// // From the UI thread
+/// token.cancel();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Create a token that isn't cancelled.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the start of the captures that use the token, a capture that already started
+    /// keeps running.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, atomic::Ordering::Relaxed);
+    }
+
+    /// Checks if the token was cancelled.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(atomic::Ordering::Relaxed)
+    }
+
+    // Fail with `Cancelled` if the token was cancelled.
+    pub(crate) fn check<E>(token: Option<&Self>) -> Result<(), GraphicsCaptureApiError<E>> {
+        if token.is_some_and(Self::is_cancelled) {
+            return Err(GraphicsCaptureApiError::Cancelled);
+        }
+
+        Ok(())
+    }
+}
+
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.cancelled, &other.cancelled)
+    }
+}
+
+impl Eq for CancellationToken {}
+
+/// Copies of delivered frames shared between the capture thread and the `CaptureControl`.
+pub struct SharedFrame {
+    frame: Mutex<Option<FrameSnapshot>>,
+    arrived: Condvar,
+    keep_latest: bool,
+    pacing: Mutex<(Option<TimeSpan>, VecDeque<i64>)>,
+    unique_frames: AtomicU64,
+    duplicated_frames: AtomicU64,
+}
+
+impl SharedFrame {
+    /// Creates a new `SharedFrame`.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep_latest` - Whether every delivered frame is kept, otherwise only the first frame is.
+    ///
+    /// # Returns
+    ///
+    /// The newly created `SharedFrame`.
+    #[must_use]
+    pub fn new(keep_latest: bool) -> Self {
+        Self {
+            frame: Mutex::new(None),
+            arrived: Condvar::new(),
+            keep_latest,
+            pacing: Mutex::new((None, VecDeque::with_capacity(PACING_WINDOW))),
+            unique_frames: AtomicU64::new(0),
+            duplicated_frames: AtomicU64::new(0),
+        }
+    }
+
+    // Whether the capture thread should store a copy of the delivered frame.
+    pub(crate) fn wants_frame(&self) -> bool {
+        self.keep_latest || self.frame.lock().is_none()
+    }
+
+    // Store a copy of a delivered frame and wake up the threads waiting for it.
+    pub(crate) fn store(&self, snapshot: FrameSnapshot) {
+        *self.frame.lock() = Some(snapshot);
+        self.arrived.notify_all();
+    }
+
+    // Forget the time of the last frame so a pause isn't counted as a gap.
+    pub(crate) fn restart_pacing(&self) {
+        self.pacing.lock().0 = None;
+    }
+
+    // Count the frames delivered for one frame arrival.
+    pub(crate) fn record_delivered(&self, unique: u64, duplicated: u64) {
+        self.unique_frames
+            .fetch_add(unique, atomic::Ordering::Relaxed);
+        self.duplicated_frames
+            .fetch_add(duplicated, atomic::Ordering::Relaxed);
+    }
+
+    // Record the time of a delivered frame, only the most recent gaps are kept.
+    pub(crate) fn record_arrival(&self, timespan: TimeSpan) {
+        let mut pacing = self.pacing.lock();
+        let (last, gaps) = &mut *pacing;
+
+        if let Some(last) = last {
+            if gaps.len() == PACING_WINDOW {
+                gaps.pop_front();
+            }
+            gaps.push_back(timespan.Duration - last.Duration);
+        }
+
+        *last = Some(timespan);
+    }
+}
+
 /// Used to control the capture session
 pub struct CaptureControl<T: GraphicsCaptureApiHandler + Send + 'static + ?Sized, E> {
+    id: u64,
     thread_handle: Option<JoinHandle<Result<(), GraphicsCaptureApiError<E>>>>,
     halt_handle: Arc<AtomicBool>,
+    pause_handle: Arc<AtomicBool>,
     callback: Arc<Mutex<T>>,
+    shared_frame: Arc<SharedFrame>,
+    session: Arc<Mutex<GraphicsCaptureSession>>,
+    retarget_sender: mpsc::Sender<RetargetRequest<E>>,
+    settings: Mutex<SettingsSnapshot>,
 }
 
 impl<T: GraphicsCaptureApiHandler + Send + 'static, E> CaptureControl<T, E> {
@@ -60,26 +354,59 @@ impl<T: GraphicsCaptureApiHandler + Send + 'static, E> CaptureControl<T, E> {
     ///
     /// # Arguments
     ///
+    /// * `id` - The unique id of the capture session.
     /// * `thread_handle` - The join handle for the capture thread.
     /// * `halt_handle` - The atomic boolean used to pause the capture thread.
+    /// * `pause_handle` - The atomic boolean that holds back frames while it's set.
     /// * `callback` - The mutex-protected callback struct used to call struct methods directly.
+    /// * `shared_frame` - The copies of delivered frames shared with the capture thread.
+    /// * `session` - The capture session running on the capture thread, replaced by the capture
+    ///   thread when it switches to another item.
+    /// * `retarget_sender` - The sender used to hand new items to the capture thread.
+    /// * `settings` - The settings the capture was started with.
     ///
     /// # Returns
     ///
     /// The newly created CaptureControl struct.
     #[must_use]
     pub fn new(
+        id: u64,
         thread_handle: JoinHandle<Result<(), GraphicsCaptureApiError<E>>>,
         halt_handle: Arc<AtomicBool>,
+        pause_handle: Arc<AtomicBool>,
         callback: Arc<Mutex<T>>,
+        shared_frame: Arc<SharedFrame>,
+        session: Arc<Mutex<GraphicsCaptureSession>>,
+        retarget_sender: mpsc::Sender<RetargetRequest<E>>,
+        settings: SettingsSnapshot,
     ) -> Self {
         Self {
+            id,
             thread_handle: Some(thread_handle),
             halt_handle,
+            pause_handle,
             callback,
+            shared_frame,
+            session,
+            retarget_sender,
+            settings: Mutex::new(settings),
         }
     }
 
+    /// Gets the unique id of the capture session.
+    ///
+    /// The id is also available to the handler through `InternalCaptureControl::capture_id` and
+    /// the capture thread is named `windows-capture-{id}`, so output from several concurrent
+    /// captures can be told apart.
+    ///
+    /// # Returns
+    ///
+    /// The id of the capture session.
+    #[must_use]
+    pub const fn id(&self) -> u64 {
+        self.id
+    }
+
     /// Checks to see if the capture thread is finished.
     ///
     /// # Returns
@@ -112,6 +439,34 @@ impl<T: GraphicsCaptureApiHandler + Send + 'static, E> CaptureControl<T, E> {
         self.halt_handle.clone()
     }
 
+    /// Stops delivering frames without stopping the capture session, frames that arrive while
+    /// paused are discarded.
+    pub fn pause(&self) {
+        self.pause_handle.store(true, atomic::Ordering::Relaxed);
+    }
+
+    /// Resumes delivering frames after `pause` or `Settings::start_paused`.
+    pub fn resume(&self) {
+        self.pause_handle.store(false, atomic::Ordering::Relaxed);
+    }
+
+    /// Checks if delivering frames is paused.
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.pause_handle.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Gets the settings the capture is running with, changes made through the capture control
+    /// like `set_cursor_capture` are included.
+    ///
+    /// # Returns
+    ///
+    /// A snapshot of the settings.
+    #[must_use]
+    pub fn settings(&self) -> SettingsSnapshot {
+        self.settings.lock().clone()
+    }
+
     /// Gets the callback struct used to call struct methods directly.
     ///
     /// # Returns
@@ -122,6 +477,197 @@ impl<T: GraphicsCaptureApiHandler + Send + 'static, E> CaptureControl<T, E> {
         self.callback.clone()
     }
 
+    /// Gets a copy of the latest delivered frame.
+    ///
+    /// # Returns
+    ///
+    /// The latest frame, or `None` if no frame has arrived yet or `Settings::keep_latest_frame`
+    /// isn't enabled.
+    #[must_use]
+    pub fn latest_frame(&self) -> Option<FrameSnapshot> {
+        if self.shared_frame.keep_latest {
+            self.shared_frame.frame.lock().clone()
+        } else {
+            None
+        }
+    }
+
+    /// Gets statistics of the gaps between the recently delivered frames, to diagnose stutter.
+    ///
+    /// # Returns
+    ///
+    /// The minimum, maximum, mean and standard deviation of the last 240 gaps, all zero until
+    /// two frames arrived.
+    #[must_use]
+    pub fn frame_pacing(&self) -> PacingStats {
+        PacingStats::from_gaps(&self.shared_frame.pacing.lock().1)
+    }
+
+    /// Gets the number of unique and duplicated frames delivered since the capture started,
+    /// to tell a slowly updating source from a slow capture pipeline.
+    ///
+    /// # Returns
+    ///
+    /// The frame counts, duplicated frames are only delivered with
+    /// `FramePacingSettings::Vsync`.
+    #[must_use]
+    pub fn frame_counts(&self) -> FrameCounts {
+        FrameCounts {
+            unique: self
+                .shared_frame
+                .unique_frames
+                .load(atomic::Ordering::Relaxed),
+            duplicated: self
+                .shared_frame
+                .duplicated_frames
+                .load(atomic::Ordering::Relaxed),
+        }
+    }
+
+    /// Blocks until a frame has arrived and returns a copy of it, useful to take a screenshot
+    /// and stop the capture.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The maximum time to wait for a frame.
+    ///
+    /// # Returns
+    ///
+    /// The first frame, or the latest frame if `Settings::keep_latest_frame` is enabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CaptureControlError::FirstFrameTimeout` if no frame arrived in time, or
+    /// `CaptureControlError::CaptureEndedWithoutFrame` if the capture ended first, in which case
+    /// `wait` returns the error that ended it.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let control = Capture::start_free_threaded(settings)?;
+    /// let frame = control.wait_for_first_frame(Duration::from_secs(1))?;
+    /// control.stop()?;
+    /// frame.save_as_image("screenshot.png", ImageFormat::Png)?;
+    /// ```
+    pub fn wait_for_first_frame(
+        &self,
+        timeout: Duration,
+    ) -> Result<FrameSnapshot, CaptureControlError<E>> {
+        let deadline = Instant::now() + timeout;
+
+        let mut frame = self.shared_frame.frame.lock();
+        loop {
+            if let Some(snapshot) = frame.as_ref() {
+                return Ok(snapshot.clone());
+            }
+
+            if self.is_finished() {
+                return Err(CaptureControlError::CaptureEndedWithoutFrame);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(CaptureControlError::FirstFrameTimeout);
+            }
+
+            // The capture thread doesn't notify when it ends so wake up periodically to check
+            self.shared_frame
+                .arrived
+                .wait_for(&mut frame, (deadline - now).min(FINISHED_POLL_INTERVAL));
+        }
+    }
+
+    /// Turns capturing the cursor on or off while the capture is running, without restarting
+    /// the session.
+    ///
+    /// Captures using `CursorCaptureSettings::SeparateLayer` still get the cursor layer, turning
+    /// the cursor capture on draws the cursor into the frames as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether the cursor is drawn into the captured frames.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the cursor capture was changed, an error otherwise.
+    pub fn set_cursor_capture(&self, enabled: bool) -> Result<(), CaptureControlError<E>> {
+        let result = GraphicsCaptureApi::is_cursor_settings_supported().and_then(|supported| {
+            if !supported {
+                return Err(graphics_capture_api::Error::CursorConfigUnsupported);
+            }
+
+            Ok(self.session.lock().SetIsCursorCaptureEnabled(enabled)?)
+        });
+
+        if result.is_ok() {
+            let mut settings = self.settings.lock();
+            if settings.cursor_capture != CursorCaptureSettings::SeparateLayer {
+                settings.cursor_capture = if enabled {
+                    CursorCaptureSettings::WithCursor
+                } else {
+                    CursorCaptureSettings::WithoutCursor
+                };
+            }
+        }
+
+        result.map_err(|e| GraphicsCaptureApiError::GraphicsCaptureApiError(e).into())
+    }
+
+    /// Switches the capture to a new item without stopping the capture thread, the handler and
+    /// everything it holds, like an encoder, stay attached.
+    ///
+    /// The frame pool is recreated for the new item, so the frame size changes when the new
+    /// item has a different size. If the new capture can't be started the old item keeps being
+    /// captured. With `CloseBehaviorSettings::WaitAndReconnect` the capture reconnects to the
+    /// new item's window from then on.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The item to capture from now on.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the capture switched to the new item, an error otherwise.
+    pub fn retarget<I: TryInto<GraphicsCaptureItem>>(
+        &self,
+        item: I,
+    ) -> Result<(), CaptureControlError<E>> {
+        let item: GraphicsCaptureItem = item
+            .try_into()
+            .map_err(|_| GraphicsCaptureApiError::<E>::ItemConvertFailed)?;
+        if is_content_protected(&item) {
+            return Err(CaptureControlError::GraphicsCaptureApiError(
+                GraphicsCaptureApiError::ContentProtected,
+            ));
+        }
+
+        let Some(thread_handle) = &self.thread_handle else {
+            return Err(CaptureControlError::ThreadHandleIsTaken);
+        };
+
+        let (reply_sender, reply_receiver) = mpsc::channel();
+        self.retarget_sender
+            .send((item, reply_sender))
+            .map_err(|_| CaptureControlError::CaptureEnded)?;
+
+        let handle = HANDLE(thread_handle.as_raw_handle());
+        let thread_id = unsafe { GetThreadId(handle) };
+        unsafe {
+            PostThreadMessageW(
+                thread_id,
+                RETARGET_MESSAGE,
+                WPARAM::default(),
+                LPARAM::default(),
+            )
+        }
+        .map_err(|_| CaptureControlError::FailedToPostThreadMessage)?;
+
+        reply_receiver
+            .recv()
+            .map_err(|_| CaptureControlError::CaptureEnded)??;
+
+        Ok(())
+    }
+
     /// Waits until the capturing thread stops.
     ///
     /// # Returns
@@ -144,6 +690,14 @@ impl<T: GraphicsCaptureApiHandler + Send + 'static, E> CaptureControl<T, E> {
 
     /// Gracefully stops the capture thread.
     ///
+    /// Once `stop` is called no new call to `on_frame_arrived` starts, frames that arrive while
+    /// the capture shuts down are discarded. A call that is already running finishes before
+    /// `stop` returns, so the handler can be torn down safely afterwards. Storing `true` in the
+    /// `halt_handle` stops the delivery the same way without waiting for the thread.
+    ///
+    /// This holds after the capture switched to another item with `retarget`, by following the
+    /// foreground window or by reconnecting, and no capture is recreated once it's stopping.
+    ///
     /// # Returns
     ///
     /// `Ok(())` if the capture thread stops successfully, an error otherwise.
@@ -184,12 +738,36 @@ impl<T: GraphicsCaptureApiHandler + Send + 'static, E> CaptureControl<T, E> {
 
         Ok(())
     }
+
+    /// Stops the capture thread like `stop` and returns the handler, for example to read the
+    /// state it accumulated during the capture without sharing it through an `Arc<Mutex<_>>`.
+    ///
+    /// # Returns
+    ///
+    /// The handler once the capture thread stopped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `stop` if the capture ended with an error, the handler is dropped in
+    /// that case. Returns `HandlerStillShared` if a clone of the handler from `callback` is still
+    /// alive.
+    pub fn into_inner(self) -> Result<T, CaptureControlError<E>> {
+        let callback = self.callback.clone();
+
+        self.stop()?;
+
+        Arc::try_unwrap(callback)
+            .map(Mutex::into_inner)
+            .map_err(|_| CaptureControlError::HandlerStillShared)
+    }
 }
 
 #[derive(thiserror::Error, Eq, PartialEq, Clone, Debug)]
 pub enum GraphicsCaptureApiError<E> {
     #[error("Failed to join thread")]
     FailedToJoinThread,
+    #[error("Failed to spawn capture thread")]
+    FailedToSpawnThread,
     #[error("Failed to initialize WinRT")]
     FailedToInitWinRT,
     #[error("Failed to create dispatcher queue controller")]
@@ -198,14 +776,247 @@ pub enum GraphicsCaptureApiError<E> {
     FailedToShutdownDispatcherQueue,
     #[error("Failed to set dispatcher queue completed handler")]
     FailedToSetDispatcherQueueCompletedHandler,
+    #[error("Failed to set capture thread priority")]
+    FailedToSetThreadPriority,
+    #[error("Failed to register capture thread with MMCSS")]
+    FailedToRegisterMmcss,
     #[error("Failed to convert item to GraphicsCaptureItem")]
     ItemConvertFailed,
+    #[error("The content of the item is protected from capture")]
+    ContentProtected,
+    #[error("The frame size {0}x{1} is empty")]
+    EmptyFrameSize(u32, u32),
     #[error("Graphics capture error")]
     GraphicsCaptureApiError(graphics_capture_api::Error),
     #[error("New handler error")]
     NewHandlerError(E),
     #[error("Frame handler error")]
     FrameHandlerError(E),
+    #[error("Handler panicked: {0}")]
+    HandlerPanic(String),
+    #[error("The capture start was cancelled")]
+    Cancelled,
+    #[error("The capture backend doesn't support the {0} setting")]
+    UnsupportedSetting(&'static str),
+}
+
+/// Restores the priority of the current thread and reverts its MMCSS task when dropped, so a
+/// capture that ends or fails to start leaves the thread as it found it.
+struct ThreadSettingsGuard {
+    /// The priority the thread had before the capture changed it.
+    previous_priority: Option<THREAD_PRIORITY>,
+    /// The MMCSS handle of the task the thread was registered with.
+    mmcss_handle: Option<HANDLE>,
+}
+
+impl Drop for ThreadSettingsGuard {
+    fn drop(&mut self) {
+        if let Some(mmcss_handle) = self.mmcss_handle {
+            let _ = unsafe { AvRevertMmThreadCharacteristics(mmcss_handle) };
+        }
+
+        if let Some(previous_priority) = self.previous_priority {
+            let _ = unsafe { SetThreadPriority(GetCurrentThread(), previous_priority) };
+        }
+    }
+}
+
+/// Applies the thread priority and MMCSS settings to the current thread.
+///
+/// # Arguments
+///
+/// * `thread_priority` - The scheduling priority to set.
+/// * `mmcss` - The MMCSS task to register with.
+///
+/// # Returns
+///
+/// The guard that restores the thread when the capture ends.
+fn apply_thread_settings<E>(
+    thread_priority: ThreadPrioritySettings,
+    mmcss: MmcssSettings,
+) -> Result<ThreadSettingsGuard, GraphicsCaptureApiError<E>> {
+    let priority = match thread_priority {
+        ThreadPrioritySettings::Default => None,
+        ThreadPrioritySettings::Idle => Some(THREAD_PRIORITY_IDLE),
+        ThreadPrioritySettings::Lowest => Some(THREAD_PRIORITY_LOWEST),
+        ThreadPrioritySettings::BelowNormal => Some(THREAD_PRIORITY_BELOW_NORMAL),
+        ThreadPrioritySettings::Normal => Some(THREAD_PRIORITY_NORMAL),
+        ThreadPrioritySettings::AboveNormal => Some(THREAD_PRIORITY_ABOVE_NORMAL),
+        ThreadPrioritySettings::Highest => Some(THREAD_PRIORITY_HIGHEST),
+        ThreadPrioritySettings::TimeCritical => Some(THREAD_PRIORITY_TIME_CRITICAL),
+    };
+
+    let mut guard = ThreadSettingsGuard {
+        previous_priority: None,
+        mmcss_handle: None,
+    };
+
+    if let Some(priority) = priority {
+        let previous_priority = THREAD_PRIORITY(unsafe { GetThreadPriority(GetCurrentThread()) });
+        unsafe { SetThreadPriority(GetCurrentThread(), priority) }
+            .map_err(|_| GraphicsCaptureApiError::FailedToSetThreadPriority)?;
+        guard.previous_priority = Some(previous_priority);
+    }
+
+    // A failed registration drops the guard, which restores the priority
+    if let Some(task_name) = mmcss.task_name() {
+        let mut task_index = 0;
+        let mmcss_handle =
+            unsafe { AvSetMmThreadCharacteristicsW(&HSTRING::from(task_name), &mut task_index) }
+                .map_err(|_| GraphicsCaptureApiError::FailedToRegisterMmcss)?;
+        guard.mmcss_handle = Some(mmcss_handle);
+    }
+
+    Ok(guard)
+}
+
+/// How waiting for a window to reconnect to ended.
+enum WindowWait {
+    /// The window exists again.
+    Found(GraphicsCaptureItem),
+    /// `CaptureControl::retarget` asked for a new item or the foreground window changed, the
+    /// message is posted again for the message loop.
+    Retarget,
+    /// The capture was stopped.
+    Stopped,
+}
+
+/// Waits until a window with the title exists, used to reconnect after the captured window
+/// closed.
+///
+/// # Arguments
+///
+/// * `title` - The title of the window, or `None` to only wait for a retarget or a stop.
+/// * `halt` - The halt flag shared by the captures, the reconnected capture would never
+///   deliver a frame once it's set.
+///
+/// # Returns
+///
+/// The capture item of the window, or why the wait ended without one.
+fn wait_for_window(title: Option<&str>, halt: &AtomicBool) -> WindowWait {
+    loop {
+        if halt.load(atomic::Ordering::Relaxed) {
+            return WindowWait::Stopped;
+        }
+
+        // Stopping the capture posts WM_QUIT to the capture thread
+        let mut message = MSG::default();
+        while unsafe { PeekMessageW(&mut message, None, 0, 0, PM_REMOVE) }.as_bool() {
+            if message.message == WM_QUIT {
+                return WindowWait::Stopped;
+            }
+
+            if message.message == RETARGET_MESSAGE || message.message == FOREGROUND_MESSAGE {
+                let _ = unsafe {
+                    PostThreadMessageW(
+                        GetCurrentThreadId(),
+                        message.message,
+                        message.wParam,
+                        message.lParam,
+                    )
+                };
+
+                return WindowWait::Retarget;
+            }
+
+            unsafe {
+                let _ = TranslateMessage(&message);
+                DispatchMessageW(&message);
+            }
+        }
+
+        if let Some(Ok(item)) =
+            title.map(|title| Window::from_name(title).and_then(GraphicsCaptureItem::try_from))
+        {
+            if !is_content_protected(&item) {
+                return WindowWait::Found(item);
+            }
+        }
+
+        thread::sleep(RECONNECT_POLL_INTERVAL);
+    }
+}
+
+/// Installs the hook that posts `FOREGROUND_MESSAGE` to the current thread whenever the
+/// foreground window changes, the hook runs while the thread waits for messages.
+///
+/// # Returns
+///
+/// The hook, which must be removed with `UnhookWinEvent` when the capture ends.
+fn follow_foreground() -> HWINEVENTHOOK {
+    unsafe {
+        SetWinEventHook(
+            EVENT_SYSTEM_FOREGROUND,
+            EVENT_SYSTEM_FOREGROUND,
+            HMODULE::default(),
+            Some(foreground_changed),
+            0,
+            0,
+            WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS,
+        )
+    }
+}
+
+// Posts the new foreground window to the thread that installed the hook.
+unsafe extern "system" fn foreground_changed(
+    _hook: HWINEVENTHOOK,
+    _event: u32,
+    hwnd: HWND,
+    _object_id: i32,
+    _child_id: i32,
+    _event_thread: u32,
+    _event_time: u32,
+) {
+    let _ = PostThreadMessageW(
+        GetCurrentThreadId(),
+        FOREGROUND_MESSAGE,
+        WPARAM(hwnd.0 as usize),
+        LPARAM::default(),
+    );
+}
+
+/// Gets the window of a `FOREGROUND_MESSAGE` and its capture item.
+///
+/// # Arguments
+///
+/// * `message` - The foreground message.
+/// * `current` - The window that is captured now.
+///
+/// # Returns
+///
+/// The window and its capture item, or `None` if the window is already captured or can't be
+/// captured, like the taskbar or a protected window.
+fn foreground_item(
+    message: &MSG,
+    current: Option<Window>,
+) -> Option<(Window, GraphicsCaptureItem)> {
+    let window = Window::from_raw_hwnd(message.wParam.0 as *mut c_void);
+
+    if current == Some(window) || !window.is_valid() {
+        return None;
+    }
+
+    let item = GraphicsCaptureItem::try_from(window).ok()?;
+    if is_content_protected(&item) {
+        return None;
+    }
+
+    Some((window, item))
+}
+
+/// Checks if the item is a window that protects its content, it would only be captured as black
+/// frames.
+///
+/// # Arguments
+///
+/// * `item` - The capture item.
+///
+/// # Returns
+///
+/// `true` if the window of the item is protected, `false` for other items.
+fn is_content_protected(item: &GraphicsCaptureItem) -> bool {
+    Window::from_capture_item(item)
+        .is_some_and(|window| window.is_content_protected().unwrap_or(false))
 }
 
 /// A trait representing a graphics capture handler.
@@ -233,12 +1044,18 @@ pub trait GraphicsCaptureApiHandler: Sized {
         Self: Send + 'static,
         <Self as GraphicsCaptureApiHandler>::Flags: Send,
     {
+        let cancellation_token = settings.cancellation_token.clone();
+        CancellationToken::check(cancellation_token.as_ref())?;
+
         // Initialize WinRT
         unsafe {
             RoInitialize(RO_INIT_MULTITHREADED)
                 .map_err(|_| GraphicsCaptureApiError::FailedToInitWinRT)?;
         };
 
+        // Set thread priority and MMCSS task
+        let thread_settings = apply_thread_settings(settings.thread_priority, settings.mmcss)?;
+
         // Create a dispatcher queue for the current thread
         let options = DispatcherQueueOptions {
             dwSize: u32::try_from(mem::size_of::<DispatcherQueueOptions>()).unwrap(),
@@ -250,41 +1067,124 @@ pub trait GraphicsCaptureApiHandler: Sized {
                 .map_err(|_| GraphicsCaptureApiError::FailedToCreateDispatcherQueueController)?
         };
 
-        // Get current thread ID
-        let thread_id = unsafe { GetCurrentThreadId() };
-
         // Start capture
         let result = Arc::new(Mutex::new(None));
+        let snapshot = settings.snapshot();
         let callback = Arc::new(Mutex::new(
             Self::new(settings.flags).map_err(GraphicsCaptureApiError::NewHandlerError)?,
         ));
 
-        let item = settings
+        CancellationToken::check(cancellation_token.as_ref())?;
+
+        let item: GraphicsCaptureItem = settings
             .item
             .try_into()
             .map_err(|_| GraphicsCaptureApiError::ItemConvertFailed)?;
+        if is_content_protected(&item) {
+            return Err(GraphicsCaptureApiError::ContentProtected);
+        }
 
-        let mut capture = GraphicsCaptureApi::new(
-            item,
-            callback,
-            settings.cursor_capture,
-            settings.draw_border,
-            settings.color_format,
-            thread_id,
-            result.clone(),
-        )
-        .map_err(GraphicsCaptureApiError::GraphicsCaptureApiError)?;
-        capture
-            .start_capture()
+        // The title of the window to reconnect to when the item closes
+        let mut reconnect_title = match settings.on_close_behavior {
+            CloseBehaviorSettings::Stop => None,
+            CloseBehaviorSettings::WaitAndReconnect => {
+                item.DisplayName().ok().map(|title| title.to_string())
+            }
+        };
+
+        let capture_id = next_capture_id();
+        // Shared by every capture created below, so the handler stopping the capture reaches
+        // the one that is running after following the foreground window or reconnecting
+        let halt = Arc::new(AtomicBool::new(false));
+        let create_capture = |item| {
+            let mut capture = GraphicsCaptureApi::new(
+                item,
+                callback.clone(),
+                &snapshot,
+                result.clone(),
+                // There is no CaptureControl to read the latest frame from or to resume the
+                // capture
+                None,
+                Arc::new(AtomicBool::new(false)),
+                halt.clone(),
+                capture_id,
+            )
             .map_err(GraphicsCaptureApiError::GraphicsCaptureApiError)?;
+            capture
+                .start_capture()
+                .map_err(GraphicsCaptureApiError::GraphicsCaptureApiError)?;
 
-        // Message loop
-        let mut message = MSG::default();
-        unsafe {
-            while GetMessageW(&mut message, None, 0, 0).as_bool() {
-                let _ = TranslateMessage(&message);
-                DispatchMessageW(&message);
+            Ok::<_, GraphicsCaptureApiError<Self::Error>>(capture)
+        };
+
+        CancellationToken::check(cancellation_token.as_ref())?;
+
+        let mut capture = create_capture(item)?;
+
+        // The setup can't be cancelled anymore once the capture is running
+        if let Err(e) = CancellationToken::check(cancellation_token.as_ref()) {
+            capture.stop_capture();
+            return Err(e);
+        }
+
+        let foreground_hook = settings.follow_foreground.then(follow_foreground);
+        let mut foreground_window = None;
+
+        // Message loop, repeated after following the foreground window or reconnecting to a
+        // recreated window
+        loop {
+            let mut message = MSG::default();
+            unsafe {
+                while GetMessageW(&mut message, None, 0, 0).as_bool() {
+                    if message.message == FOREGROUND_MESSAGE {
+                        break;
+                    }
+
+                    let _ = TranslateMessage(&message);
+                    DispatchMessageW(&message);
+                }
             }
+
+            if message.message == FOREGROUND_MESSAGE {
+                // The old capture keeps running if the new window can't be captured, a capture
+                // that is stopping isn't recreated
+                if let Some((window, item)) = foreground_item(&message, foreground_window)
+                    .filter(|_| !halt.load(atomic::Ordering::Relaxed))
+                {
+                    if let Ok(new_capture) = create_capture(item) {
+                        mem::replace(&mut capture, new_capture).stop_capture();
+                        foreground_window = Some(window);
+
+                        if reconnect_title.is_some() {
+                            reconnect_title = window.title().ok();
+                        }
+                    }
+                }
+
+                continue;
+            }
+
+            if !capture.is_item_closed() || result.lock().is_some() {
+                break;
+            }
+
+            // Following the foreground window continues with the next foreground window
+            if reconnect_title.is_none() && foreground_hook.is_none() {
+                break;
+            }
+
+            let item = match wait_for_window(reconnect_title.as_deref(), &halt) {
+                WindowWait::Found(item) => item,
+                WindowWait::Retarget => continue,
+                WindowWait::Stopped => break,
+            };
+
+            capture.stop_capture();
+            capture = create_capture(item)?;
+        }
+
+        if let Some(foreground_hook) = foreground_hook {
+            let _ = unsafe { UnhookWinEvent(foreground_hook) };
         }
 
         // Shutdown dispatcher queue
@@ -312,12 +1212,15 @@ pub trait GraphicsCaptureApiHandler: Sized {
         // Stop capture
         capture.stop_capture();
 
+        // Restore thread priority and revert MMCSS task
+        drop(thread_settings);
+
         // Uninitialize WinRT
         unsafe { RoUninitialize() };
 
         // Check handler result
         if let Some(e) = result.lock().take() {
-            return Err(GraphicsCaptureApiError::FrameHandlerError(e));
+            return Err(e);
         }
 
         Ok(())
@@ -339,114 +1242,260 @@ pub trait GraphicsCaptureApiHandler: Sized {
         Self: Send + 'static,
         <Self as GraphicsCaptureApiHandler>::Flags: Send,
     {
-        let (halt_sender, halt_receiver) = mpsc::channel::<Arc<AtomicBool>>();
         let (callback_sender, callback_receiver) = mpsc::channel::<Arc<Mutex<Self>>>();
+        let (session_sender, session_receiver) =
+            mpsc::channel::<Arc<Mutex<GraphicsCaptureSession>>>();
+        let (retarget_sender, retarget_receiver) = mpsc::channel::<RetargetRequest<Self::Error>>();
 
-        let thread_handle = thread::spawn(
-            move || -> Result<(), GraphicsCaptureApiError<Self::Error>> {
-                // Initialize WinRT
-                unsafe {
-                    RoInitialize(RO_INIT_MULTITHREADED)
-                        .map_err(|_| GraphicsCaptureApiError::FailedToInitWinRT)?;
-                };
+        let shared_frame = Arc::new(SharedFrame::new(settings.keep_latest_frame));
+        let shared_frame_capture = shared_frame.clone();
+        let pause_handle = Arc::new(AtomicBool::new(settings.start_paused));
+        let pause_handle_capture = pause_handle.clone();
+        // Shared by every capture the thread creates, so stopping reaches the one that is
+        // running after retargeting, following the foreground window or reconnecting
+        let halt_handle = Arc::new(AtomicBool::new(false));
+        let halt_handle_capture = halt_handle.clone();
 
-                // Create a dispatcher queue for the current thread
-                let options = DispatcherQueueOptions {
-                    dwSize: u32::try_from(mem::size_of::<DispatcherQueueOptions>()).unwrap(),
-                    threadType: DQTYPE_THREAD_CURRENT,
-                    apartmentType: DQTAT_COM_NONE,
-                };
-                let controller = unsafe {
-                    CreateDispatcherQueueController(options).map_err(|_| {
-                        GraphicsCaptureApiError::FailedToCreateDispatcherQueueController
-                    })?
-                };
+        let id = next_capture_id();
+        let settings_snapshot = settings.snapshot();
 
-                // Get current thread ID
-                let thread_id = unsafe { GetCurrentThreadId() };
-
-                // Start capture
-                let result = Arc::new(Mutex::new(None));
-                let callback = Arc::new(Mutex::new(
-                    Self::new(settings.flags).map_err(GraphicsCaptureApiError::NewHandlerError)?,
-                ));
-
-                let item = settings
-                    .item
-                    .try_into()
-                    .map_err(|_| GraphicsCaptureApiError::ItemConvertFailed)?;
-
-                let mut capture = GraphicsCaptureApi::new(
-                    item,
-                    callback.clone(),
-                    settings.cursor_capture,
-                    settings.draw_border,
-                    settings.color_format,
-                    thread_id,
-                    result.clone(),
-                )
-                .map_err(GraphicsCaptureApiError::GraphicsCaptureApiError)?;
-                capture
-                    .start_capture()
-                    .map_err(GraphicsCaptureApiError::GraphicsCaptureApiError)?;
-
-                // Send halt handle
-                let halt_handle = capture.halt_handle();
-                halt_sender.send(halt_handle).unwrap();
-
-                // Send callback
-                callback_sender.send(callback).unwrap();
-
-                // Message loop
-                let mut message = MSG::default();
-                unsafe {
-                    while GetMessageW(&mut message, None, 0, 0).as_bool() {
-                        let _ = TranslateMessage(&message);
-                        DispatchMessageW(&message);
+        // Named after the capture so panics and thread-aware logs can be correlated
+        let thread_handle = thread::Builder::new()
+            .name(format!("windows-capture-{id}"))
+            .spawn(
+                move || -> Result<(), GraphicsCaptureApiError<Self::Error>> {
+                    let cancellation_token = settings.cancellation_token.clone();
+                    CancellationToken::check(cancellation_token.as_ref())?;
+
+                    // Initialize WinRT
+                    unsafe {
+                        RoInitialize(RO_INIT_MULTITHREADED)
+                            .map_err(|_| GraphicsCaptureApiError::FailedToInitWinRT)?;
+                    };
+
+                    // Set thread priority and MMCSS task
+                    let thread_settings =
+                        apply_thread_settings(settings.thread_priority, settings.mmcss)?;
+
+                    // Create a dispatcher queue for the current thread
+                    let options = DispatcherQueueOptions {
+                        dwSize: u32::try_from(mem::size_of::<DispatcherQueueOptions>()).unwrap(),
+                        threadType: DQTYPE_THREAD_CURRENT,
+                        apartmentType: DQTAT_COM_NONE,
+                    };
+                    let controller = unsafe {
+                        CreateDispatcherQueueController(options).map_err(|_| {
+                            GraphicsCaptureApiError::FailedToCreateDispatcherQueueController
+                        })?
+                    };
+
+                    // Start capture
+                    let result = Arc::new(Mutex::new(None));
+                    let snapshot = settings.snapshot();
+                    let callback = Arc::new(Mutex::new(
+                        Self::new(settings.flags)
+                            .map_err(GraphicsCaptureApiError::NewHandlerError)?,
+                    ));
+
+                    CancellationToken::check(cancellation_token.as_ref())?;
+
+                    let item: GraphicsCaptureItem = settings
+                        .item
+                        .try_into()
+                        .map_err(|_| GraphicsCaptureApiError::ItemConvertFailed)?;
+                    if is_content_protected(&item) {
+                        return Err(GraphicsCaptureApiError::ContentProtected);
                     }
-                }
 
-                // Shutdown dispatcher queue
-                let async_action = controller
-                    .ShutdownQueueAsync()
-                    .map_err(|_| GraphicsCaptureApiError::FailedToShutdownDispatcherQueue)?;
-
-                async_action
-                    .SetCompleted(&AsyncActionCompletedHandler::new(
-                        move |_, _| -> Result<(), windows::core::Error> {
-                            unsafe { PostQuitMessage(0) };
-                            Ok(())
-                        },
-                    ))
-                    .map_err(|_| {
-                        GraphicsCaptureApiError::FailedToSetDispatcherQueueCompletedHandler
-                    })?;
-
-                // Final message loop
-                let mut message = MSG::default();
-                unsafe {
-                    while GetMessageW(&mut message, None, 0, 0).as_bool() {
-                        let _ = TranslateMessage(&message);
-                        DispatchMessageW(&message);
+                    // The title of the window to reconnect to when the item closes
+                    let mut reconnect_title = match settings.on_close_behavior {
+                        CloseBehaviorSettings::Stop => None,
+                        CloseBehaviorSettings::WaitAndReconnect => {
+                            item.DisplayName().ok().map(|title| title.to_string())
+                        }
+                    };
+
+                    let create_capture = |item| {
+                        let mut capture = GraphicsCaptureApi::new(
+                            item,
+                            callback.clone(),
+                            &snapshot,
+                            result.clone(),
+                            Some(shared_frame_capture.clone()),
+                            pause_handle_capture.clone(),
+                            halt_handle_capture.clone(),
+                            id,
+                        )
+                        .map_err(GraphicsCaptureApiError::GraphicsCaptureApiError)?;
+                        capture
+                            .start_capture()
+                            .map_err(GraphicsCaptureApiError::GraphicsCaptureApiError)?;
+
+                        Ok::<_, GraphicsCaptureApiError<Self::Error>>(capture)
+                    };
+
+                    CancellationToken::check(cancellation_token.as_ref())?;
+
+                    let mut capture = create_capture(item)?;
+
+                    // The setup can't be cancelled anymore once the capture is running
+                    if let Err(e) = CancellationToken::check(cancellation_token.as_ref()) {
+                        capture.stop_capture();
+                        return Err(e);
                     }
-                }
 
-                // Stop capture
-                capture.stop_capture();
+                    // Send callback
+                    callback_sender.send(callback.clone()).unwrap();
 
-                // Uninitialize WinRT
-                unsafe { RoUninitialize() };
+                    // Send session, it's replaced when the capture switches to another item
+                    let session = Arc::new(Mutex::new(capture.session().unwrap()));
+                    session_sender.send(session.clone()).unwrap();
 
-                // Check handler result
-                if let Some(e) = result.lock().take() {
-                    return Err(GraphicsCaptureApiError::FrameHandlerError(e));
-                }
+                    let foreground_hook = settings.follow_foreground.then(follow_foreground);
+                    let mut foreground_window = None;
 
-                Ok(())
-            },
-        );
+                    // Message loop, repeated after retargeting or reconnecting to a recreated
+                    // window
+                    loop {
+                        let mut message = MSG::default();
+                        unsafe {
+                            while GetMessageW(&mut message, None, 0, 0).as_bool() {
+                                if message.message == RETARGET_MESSAGE
+                                    || message.message == FOREGROUND_MESSAGE
+                                {
+                                    break;
+                                }
+
+                                let _ = TranslateMessage(&message);
+                                DispatchMessageW(&message);
+                            }
+                        }
+
+                        if message.message == RETARGET_MESSAGE {
+                            // The old capture keeps running if the new one fails to start
+                            while let Ok((item, reply_sender)) = retarget_receiver.try_recv() {
+                                // A capture that is stopping isn't recreated, dropping the
+                                // reply sender ends `retarget` with `CaptureEnded`
+                                if halt_handle_capture.load(atomic::Ordering::Relaxed) {
+                                    continue;
+                                }
+
+                                let title = item.DisplayName();
+                                match create_capture(item) {
+                                    Ok(new_capture) => {
+                                        mem::replace(&mut capture, new_capture).stop_capture();
+                                        *session.lock() = capture.session().unwrap();
+
+                                        if reconnect_title.is_some() {
+                                            reconnect_title =
+                                                title.ok().map(|title| title.to_string());
+                                        }
+
+                                        let _ = reply_sender.send(Ok(()));
+                                    }
+                                    Err(e) => {
+                                        let _ = reply_sender.send(Err(e));
+                                    }
+                                }
+                            }
+
+                            continue;
+                        }
+
+                        if message.message == FOREGROUND_MESSAGE {
+                            // The old capture keeps running if the new window can't be captured,
+                            // a capture that is stopping isn't recreated
+                            if let Some((window, item)) =
+                                foreground_item(&message, foreground_window).filter(|_| {
+                                    !halt_handle_capture.load(atomic::Ordering::Relaxed)
+                                })
+                            {
+                                if let Ok(new_capture) = create_capture(item) {
+                                    mem::replace(&mut capture, new_capture).stop_capture();
+                                    *session.lock() = capture.session().unwrap();
+                                    foreground_window = Some(window);
+
+                                    if reconnect_title.is_some() {
+                                        reconnect_title = window.title().ok();
+                                    }
+                                }
+                            }
+
+                            continue;
+                        }
+
+                        if !capture.is_item_closed() || result.lock().is_some() {
+                            break;
+                        }
+
+                        // Following the foreground window continues with the next foreground window
+                        if reconnect_title.is_none() && foreground_hook.is_none() {
+                            break;
+                        }
+
+                        let item = match wait_for_window(
+                            reconnect_title.as_deref(),
+                            &halt_handle_capture,
+                        ) {
+                            WindowWait::Found(item) => item,
+                            WindowWait::Retarget => continue,
+                            WindowWait::Stopped => break,
+                        };
+
+                        capture.stop_capture();
+                        capture = create_capture(item)?;
+                        *session.lock() = capture.session().unwrap();
+                    }
+
+                    if let Some(foreground_hook) = foreground_hook {
+                        let _ = unsafe { UnhookWinEvent(foreground_hook) };
+                    }
+
+                    // Shutdown dispatcher queue
+                    let async_action = controller
+                        .ShutdownQueueAsync()
+                        .map_err(|_| GraphicsCaptureApiError::FailedToShutdownDispatcherQueue)?;
+
+                    async_action
+                        .SetCompleted(&AsyncActionCompletedHandler::new(
+                            move |_, _| -> Result<(), windows::core::Error> {
+                                unsafe { PostQuitMessage(0) };
+                                Ok(())
+                            },
+                        ))
+                        .map_err(|_| {
+                            GraphicsCaptureApiError::FailedToSetDispatcherQueueCompletedHandler
+                        })?;
+
+                    // Final message loop
+                    let mut message = MSG::default();
+                    unsafe {
+                        while GetMessageW(&mut message, None, 0, 0).as_bool() {
+                            let _ = TranslateMessage(&message);
+                            DispatchMessageW(&message);
+                        }
+                    }
+
+                    // Stop capture
+                    capture.stop_capture();
+
+                    // Restore thread priority and revert MMCSS task
+                    drop(thread_settings);
+
+                    // Uninitialize WinRT
+                    unsafe { RoUninitialize() };
 
-        let Ok(halt_handle) = halt_receiver.recv() else {
+                    // Check handler result
+                    if let Some(e) = result.lock().take() {
+                        return Err(e);
+                    }
+
+                    Ok(())
+                },
+            )
+            .map_err(|_| GraphicsCaptureApiError::FailedToSpawnThread)?;
+
+        let Ok(callback) = callback_receiver.recv() else {
             match thread_handle.join() {
                 Ok(result) => return Err(result.err().unwrap()),
                 Err(_) => {
@@ -455,7 +1504,7 @@ pub trait GraphicsCaptureApiHandler: Sized {
             }
         };
 
-        let Ok(callback) = callback_receiver.recv() else {
+        let Ok(session) = session_receiver.recv() else {
             match thread_handle.join() {
                 Ok(result) => return Err(result.err().unwrap()),
                 Err(_) => {
@@ -464,7 +1513,17 @@ pub trait GraphicsCaptureApiHandler: Sized {
             }
         };
 
-        Ok(CaptureControl::new(thread_handle, halt_handle, callback))
+        Ok(CaptureControl::new(
+            id,
+            thread_handle,
+            halt_handle,
+            pause_handle,
+            callback,
+            shared_frame,
+            session,
+            retarget_sender,
+            settings_snapshot,
+        ))
     }
 
     /// Function that will be called to create the struct. The flags can be passed from settings.
@@ -480,6 +1539,11 @@ pub trait GraphicsCaptureApiHandler: Sized {
 
     /// Called every time a new frame is available.
     ///
+    /// If this function panics the capture stops and the panic message is returned as
+    /// `GraphicsCaptureApiError::HandlerPanic` from `start`, `CaptureControl::wait` and `CaptureControl::stop`.
+    ///
+    /// It's never called after `CaptureControl::stop` returned or the halt handle was set.
+    ///
     /// # Arguments
     ///
     /// * `frame` - A mutable reference to the captured frame.
@@ -494,6 +1558,72 @@ pub trait GraphicsCaptureApiHandler: Sized {
         capture_control: InternalCaptureControl,
     ) -> Result<(), Self::Error>;
 
+    /// Optional handler called when frames were dropped because the handler was still busy with a
+    /// previous frame. It is called right before `on_frame_arrived` for the next delivered frame.
+    ///
+    /// Only called with `Settings::buffer_frames` or `Settings::report_dropped_frames`, without
+    /// them the frame pool has no room to hold the frames that arrive meanwhile.
+    ///
+    /// # Arguments
+    ///
+    /// * `dropped_count` - The number of frames dropped since the last delivered frame.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the handler execution was successful, otherwise returns an error of type `Self::Error`.
+    fn on_frame_dropped(&mut self, dropped_count: usize) -> Result<(), Self::Error> {
+        let _ = dropped_count;
+        Ok(())
+    }
+
+    /// Optional handler called when the frame pool was recreated because the size of the
+    /// captured content changed, for example when the captured window was resized.
+    ///
+    /// The frame that arrived with the new size is discarded, frames with the new size follow.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The new width of the content.
+    /// * `height` - The new height of the content.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the handler execution was successful, otherwise returns an error of type `Self::Error`.
+    fn on_frame_pool_recreated(&mut self, width: u32, height: u32) -> Result<(), Self::Error> {
+        let _ = (width, height);
+        Ok(())
+    }
+
+    /// Optional handler called when the GPU used by the capture was removed, for example when a
+    /// hybrid laptop switched the GPU of the captured window.
+    ///
+    /// The capture continues on new DirectX devices. Textures and surfaces kept from earlier
+    /// frames belong to the removed device and can't be used anymore.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the handler execution was successful, otherwise returns an error of type `Self::Error`.
+    fn on_adapter_changed(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Optional handler called when enough pixels of the region set with
+    /// `Settings::trigger_region` changed. It is called right before `on_frame_arrived`, the region
+    /// is read without waiting for the GPU so that frame is usually a frame or two after the one
+    /// that shows the change.
+    ///
+    /// # Arguments
+    ///
+    /// * `changed_pixels` - The number of pixels of the region that changed.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the handler execution was successful, otherwise returns an error of type `Self::Error`.
+    fn on_triggered(&mut self, changed_pixels: u32) -> Result<(), Self::Error> {
+        let _ = changed_pixels;
+        Ok(())
+    }
+
     /// Optional handler called when the capture item (usually a window) closes.
     ///
     /// # Returns
@@ -0,0 +1,273 @@
+use std::{mem, ptr, slice};
+
+use windows::Win32::{
+    Graphics::Gdi::{
+        CreateCompatibleDC, CreateDIBSection, DeleteDC, DeleteObject, GdiFlush, GetObjectW,
+        SelectObject, BITMAP, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, HBITMAP, HDC,
+    },
+    UI::WindowsAndMessaging::{
+        DrawIconEx, GetCursorInfo, GetIconInfo, CURSORINFO, CURSOR_SHOWING, DI_NORMAL, HICON,
+        ICONINFO,
+    },
+};
+
+#[derive(thiserror::Error, Eq, PartialEq, Clone, Debug)]
+pub enum Error {
+    #[error("Failed to create a device context for drawing the cursor")]
+    FailedToCreateDeviceContext,
+    #[error("Failed to get the cursor bitmap size")]
+    FailedToGetCursorSize,
+    #[error("Windows API error: {0}")]
+    WindowsError(#[from] windows::core::Error),
+}
+
+/// Represents the mouse cursor captured as a separate transparent layer.
+///
+/// The buffer is RGBA with straight (non-premultiplied) alpha, and the position is the top-left
+/// corner of the cursor bitmap with the hotspot already subtracted. The position is in screen
+/// coordinates for `CursorLayer::capture` and relative to the top-left corner of the frame for
+/// `Frame::cursor_layer`, there it's scaled with the frame while the bitmap keeps its size.
+///
+/// # Example
+/// ```ignore
+/// // Inside on_frame_arrived with CursorCaptureSettings::SeparateLayer
+/// if let Some(cursor) = frame.cursor_layer() {
+///     println!("Cursor at {}, {}", cursor.x(), cursor.y());
+/// }
+/// ```
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct CursorLayer {
+    buffer: Vec<u8>,
+    width: u32,
+    height: u32,
+    x: i32,
+    y: i32,
+}
+
+impl CursorLayer {
+    /// Captures the current mouse cursor.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the cursor is hidden.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the cursor bitmap could not be read.
+    pub fn capture() -> Result<Option<Self>, Error> {
+        let mut cursor_info = CURSORINFO {
+            cbSize: u32::try_from(mem::size_of::<CURSORINFO>()).unwrap(),
+            ..Default::default()
+        };
+        unsafe { GetCursorInfo(&mut cursor_info)? };
+
+        if cursor_info.flags.0 & CURSOR_SHOWING.0 == 0 || cursor_info.hCursor.is_invalid() {
+            return Ok(None);
+        }
+
+        let icon = HICON::from(cursor_info.hCursor);
+
+        let mut icon_info = ICONINFO::default();
+        unsafe { GetIconInfo(icon, &mut icon_info)? };
+
+        let size = Self::icon_size(&icon_info);
+
+        // GetIconInfo creates the bitmaps so they must be deleted by the caller
+        unsafe {
+            if !icon_info.hbmColor.is_invalid() {
+                let _ = DeleteObject(icon_info.hbmColor);
+            }
+            if !icon_info.hbmMask.is_invalid() {
+                let _ = DeleteObject(icon_info.hbmMask);
+            }
+        };
+
+        let (width, height) = size?;
+
+        // Draw the cursor on black and on white, the difference between the two gives the alpha
+        let on_black = Self::draw(icon, width, height, 0x00)?;
+        let on_white = Self::draw(icon, width, height, 0xFF)?;
+
+        let buffer = on_black
+            .chunks_exact(4)
+            .zip(on_white.chunks_exact(4))
+            .flat_map(|(black, white)| {
+                let alpha = 255 - white[1].saturating_sub(black[1]);
+
+                let unpremultiply = |value: u8| {
+                    if alpha == 0 {
+                        0
+                    } else {
+                        u8::try_from((u32::from(value) * 255 / u32::from(alpha)).min(255)).unwrap()
+                    }
+                };
+
+                // The DIB is BGRA
+                [
+                    unpremultiply(black[2]),
+                    unpremultiply(black[1]),
+                    unpremultiply(black[0]),
+                    alpha,
+                ]
+            })
+            .collect();
+
+        Ok(Some(Self {
+            buffer,
+            width,
+            height,
+            x: cursor_info.ptScreenPos.x - i32::try_from(icon_info.xHotspot).unwrap(),
+            y: cursor_info.ptScreenPos.y - i32::try_from(icon_info.yHotspot).unwrap(),
+        }))
+    }
+
+    /// Get the RGBA buffer of the cursor with straight alpha.
+    #[must_use]
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Get the width of the cursor.
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of the cursor.
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the x position of the top-left corner of the cursor.
+    #[must_use]
+    pub const fn x(&self) -> i32 {
+        self.x
+    }
+
+    /// Get the y position of the top-left corner of the cursor.
+    #[must_use]
+    pub const fn y(&self) -> i32 {
+        self.y
+    }
+
+    // Move the position from screen coordinates to a frame whose top-left corner is at the origin
+    // and that is scaled down by the mip level.
+    pub(crate) const fn relative_to(mut self, origin: (i32, i32), mip_level: u32) -> Self {
+        self.x = self.x.saturating_sub(origin.0) >> mip_level;
+        self.y = self.y.saturating_sub(origin.1) >> mip_level;
+        self
+    }
+
+    // Get the size of the cursor from its color bitmap, or from the mask for monochrome cursors.
+    fn icon_size(icon_info: &ICONINFO) -> Result<(u32, u32), Error> {
+        let monochrome = icon_info.hbmColor.is_invalid();
+        let bitmap_handle = if monochrome {
+            icon_info.hbmMask
+        } else {
+            icon_info.hbmColor
+        };
+
+        let mut bitmap = BITMAP::default();
+        let result = unsafe {
+            GetObjectW(
+                bitmap_handle,
+                i32::try_from(mem::size_of::<BITMAP>()).unwrap(),
+                Some(ptr::addr_of_mut!(bitmap).cast()),
+            )
+        };
+
+        if result == 0 || bitmap.bmWidth <= 0 || bitmap.bmHeight <= 0 {
+            return Err(Error::FailedToGetCursorSize);
+        }
+
+        // Monochrome cursors store the AND and XOR masks stacked in one bitmap
+        let height = if monochrome {
+            bitmap.bmHeight / 2
+        } else {
+            bitmap.bmHeight
+        };
+
+        Ok((
+            u32::try_from(bitmap.bmWidth).unwrap(),
+            u32::try_from(height).unwrap(),
+        ))
+    }
+
+    // Draw the cursor on a solid background and return the BGRA pixels.
+    fn draw(icon: HICON, width: u32, height: u32, background: u8) -> Result<Vec<u8>, Error> {
+        let hdc = unsafe { CreateCompatibleDC(None) };
+        if hdc.is_invalid() {
+            return Err(Error::FailedToCreateDeviceContext);
+        }
+
+        let result = Self::draw_on_dc(hdc, icon, width, height, background);
+
+        unsafe {
+            let _ = DeleteDC(hdc);
+        };
+
+        result
+    }
+
+    fn draw_on_dc(
+        hdc: HDC,
+        icon: HICON,
+        width: u32,
+        height: u32,
+        background: u8,
+    ) -> Result<Vec<u8>, Error> {
+        let bitmap_info = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: u32::try_from(mem::size_of::<BITMAPINFOHEADER>()).unwrap(),
+                biWidth: i32::try_from(width).unwrap(),
+                // Negative height for a top-down bitmap
+                biHeight: -i32::try_from(height).unwrap(),
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: BI_RGB.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut bits = ptr::null_mut();
+        let bitmap: HBITMAP =
+            unsafe { CreateDIBSection(hdc, &bitmap_info, DIB_RGB_COLORS, &mut bits, None, 0)? };
+
+        let len = width as usize * height as usize * 4;
+
+        let previous = unsafe { SelectObject(hdc, bitmap) };
+
+        let pixels = unsafe { slice::from_raw_parts_mut(bits.cast::<u8>(), len) };
+        pixels.fill(background);
+
+        let result = unsafe {
+            DrawIconEx(
+                hdc,
+                0,
+                0,
+                icon,
+                i32::try_from(width).unwrap(),
+                i32::try_from(height).unwrap(),
+                0,
+                None,
+                DI_NORMAL,
+            )
+        };
+
+        let buffer = result.map(|()| {
+            unsafe {
+                let _ = GdiFlush();
+            };
+            pixels.to_vec()
+        });
+
+        unsafe {
+            SelectObject(hdc, previous);
+            let _ = DeleteObject(bitmap);
+        };
+
+        Ok(buffer?)
+    }
+}
@@ -4,15 +4,16 @@ use windows::{
     Win32::{
         Graphics::{
             Direct3D::{
-                D3D_DRIVER_TYPE_HARDWARE, D3D_FEATURE_LEVEL, D3D_FEATURE_LEVEL_10_0,
-                D3D_FEATURE_LEVEL_10_1, D3D_FEATURE_LEVEL_11_0, D3D_FEATURE_LEVEL_11_1,
-                D3D_FEATURE_LEVEL_9_1, D3D_FEATURE_LEVEL_9_2, D3D_FEATURE_LEVEL_9_3,
+                D3D_DRIVER_TYPE, D3D_DRIVER_TYPE_HARDWARE, D3D_DRIVER_TYPE_UNKNOWN,
+                D3D_FEATURE_LEVEL, D3D_FEATURE_LEVEL_10_0, D3D_FEATURE_LEVEL_10_1,
+                D3D_FEATURE_LEVEL_11_0, D3D_FEATURE_LEVEL_11_1, D3D_FEATURE_LEVEL_9_1,
+                D3D_FEATURE_LEVEL_9_2, D3D_FEATURE_LEVEL_9_3,
             },
             Direct3D11::{
                 D3D11CreateDevice, ID3D11Device, ID3D11DeviceContext,
                 D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_SDK_VERSION,
             },
-            Dxgi::IDXGIDevice,
+            Dxgi::{IDXGIAdapter, IDXGIDevice},
         },
         System::WinRT::Direct3D11::CreateDirect3D11DeviceFromDXGIDevice,
     },
@@ -49,6 +50,28 @@ unsafe impl<T> Send for SendDirectX<T> {}
 
 /// Create `ID3D11Device` and `ID3D11DeviceContext`
 pub fn create_d3d_device() -> Result<(ID3D11Device, ID3D11DeviceContext), Error> {
+    create_d3d_device_with_driver_type(D3D_DRIVER_TYPE_HARDWARE)
+}
+
+/// Create `ID3D11Device` and `ID3D11DeviceContext` With A Specific Driver Type
+pub fn create_d3d_device_with_driver_type(
+    driver_type: D3D_DRIVER_TYPE,
+) -> Result<(ID3D11Device, ID3D11DeviceContext), Error> {
+    create_d3d_device_on(None, driver_type)
+}
+
+/// Create `ID3D11Device` and `ID3D11DeviceContext` On A Specific Graphics Adapter
+pub fn create_d3d_device_on_adapter(
+    adapter: &IDXGIAdapter,
+) -> Result<(ID3D11Device, ID3D11DeviceContext), Error> {
+    create_d3d_device_on(Some(adapter), D3D_DRIVER_TYPE_UNKNOWN)
+}
+
+// Create The Device On The Adapter, Or On The Default Adapter Of The Driver Type When None
+fn create_d3d_device_on(
+    adapter: Option<&IDXGIAdapter>,
+    driver_type: D3D_DRIVER_TYPE,
+) -> Result<(ID3D11Device, ID3D11DeviceContext), Error> {
     // Array of Direct3D feature levels.
     // The feature levels are listed in descending order of capability.
     // The highest feature level supported by the system is at index 0.
@@ -68,8 +91,8 @@ pub fn create_d3d_device() -> Result<(ID3D11Device, ID3D11DeviceContext), Error>
     let mut d3d_device_context = None;
     unsafe {
         D3D11CreateDevice(
-            None,
-            D3D_DRIVER_TYPE_HARDWARE,
+            adapter,
+            driver_type,
             None,
             D3D11_CREATE_DEVICE_BGRA_SUPPORT,
             Some(&feature_flags),
@@ -1,30 +1,40 @@
 use std::{
+    collections::VecDeque,
+    ffi::c_void,
     fs::{self, File},
-    path::Path,
-    slice,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    ptr, slice,
     sync::{
         atomic::{self, AtomicBool},
         mpsc, Arc,
     },
     thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use parking_lot::{Condvar, Mutex};
+use parking_lot::{Condvar, Mutex, MutexGuard};
 use windows::{
-    core::HSTRING,
-    Foundation::{EventRegistrationToken, TimeSpan, TypedEventHandler},
+    core::{Interface, GUID, HSTRING},
+    Foundation::{
+        EventRegistrationToken, PropertyType, PropertyValue, TimeSpan, TypedEventHandler,
+    },
     Graphics::{
         DirectX::Direct3D11::IDirect3DSurface,
-        Imaging::{BitmapAlphaMode, BitmapEncoder, BitmapPixelFormat},
+        Imaging::{
+            BitmapAlphaMode, BitmapEncoder, BitmapPixelFormat, BitmapPropertySet, BitmapTypedValue,
+        },
     },
     Media::{
         Core::{
-            MediaStreamSample, MediaStreamSource, MediaStreamSourceSampleRequestedEventArgs,
-            MediaStreamSourceStartingEventArgs, VideoStreamDescriptor,
+            AudioStreamDescriptor, MediaStreamSample, MediaStreamSource,
+            MediaStreamSourceSampleRequest, MediaStreamSourceSampleRequestDeferral,
+            MediaStreamSourceSampleRequestedEventArgs, MediaStreamSourceStartingEventArgs,
+            VideoStreamDescriptor,
         },
         MediaProperties::{
-            MediaEncodingProfile, MediaEncodingSubtypes, VideoEncodingProperties,
-            VideoEncodingQuality,
+            AudioEncodingProperties, MediaEncodingProfile, MediaEncodingSubtypes,
+            VideoEncodingProperties, VideoEncodingQuality,
         },
         Transcoding::MediaTranscoder,
     },
@@ -35,11 +45,42 @@ use windows::{
             Buffer, DataReader, IRandomAccessStream, InMemoryRandomAccessStream, InputStreamOptions,
         },
     },
+    Win32::{
+        Media::MediaFoundation::{
+            eAVEncCommonRateControlMode_LowDelayVBR, eAVEncCommonRateControlMode_Quality,
+            CODECAPI_AVEncCommonQuality, CODECAPI_AVEncCommonQualityVsSpeed,
+            CODECAPI_AVEncCommonRateControlMode, CODECAPI_AVEncH264CABACEnable,
+            CODECAPI_AVEncMPVDefaultBPictureCount, CODECAPI_AVEncNumWorkerThreads,
+            CODECAPI_AVEncVideoMaxNumRefFrame, CODECAPI_AVLowLatencyMode, IMFActivate,
+            IMFTransform, MFCreateMediaType, MFMediaType_Video, MFShutdown, MFStartup, MFTEnumEx,
+            MFVideoFormat_AV1, MFVideoFormat_H264, MFVideoFormat_HEVC, MFVideoFormat_VP90,
+            MFVideoInterlace_Progressive, MFSTARTUP_LITE, MFT_CATEGORY_VIDEO_ENCODER,
+            MFT_ENUM_FLAG_HARDWARE, MFT_ENUM_FLAG_SORTANDFILTER, MFT_REGISTER_TYPE_INFO,
+            MFT_SET_TYPE_TEST_ONLY, MF_MT_AVG_BITRATE, MF_MT_FRAME_RATE, MF_MT_FRAME_SIZE,
+            MF_MT_INTERLACE_MODE, MF_MT_MAJOR_TYPE, MF_MT_SUBTYPE, MF_TRANSFORM_ASYNC_UNLOCK,
+            MF_VERSION,
+        },
+        System::{
+            Com::CoTaskMemFree,
+            WinRT::{RoInitialize, RoUninitialize, RO_INIT_MULTITHREADED},
+        },
+    },
+};
+
+#[cfg(feature = "webp")]
+use std::{ffi::CStr, mem, ptr::NonNull};
+
+#[cfg(feature = "webp")]
+use libwebp_sys::{
+    WebPAnimEncoderAdd, WebPAnimEncoderAssemble, WebPAnimEncoderDelete, WebPAnimEncoderGetError,
+    WebPAnimEncoderNew, WebPAnimEncoderOptions, WebPAnimEncoderOptionsInit, WebPConfig,
+    WebPConfigInit, WebPData, WebPFree, WebPPicture, WebPPictureFree, WebPPictureImportBGRA,
+    WebPPictureImportRGBA, WebPPictureInit,
 };
 
 use crate::{
     d3d11::SendDirectX,
-    frame::{Frame, ImageFormat},
+    frame::{self, Frame, FrameSnapshot, ImageFormat},
     settings::ColorFormat,
 };
 
@@ -55,6 +96,7 @@ pub enum ImageEncoderError {
 pub struct ImageEncoder {
     format: ImageFormat,
     color_format: ColorFormat,
+    fast_png: bool,
 }
 
 impl ImageEncoder {
@@ -72,9 +114,22 @@ impl ImageEncoder {
         Self {
             format,
             color_format,
+            fast_png: false,
         }
     }
 
+    /// Encode PNG images without row filtering, which is several times faster but makes the
+    /// files larger. Useful when saving many frames quickly.
+    ///
+    /// # Arguments
+    ///
+    /// * `fast_png` - Whether to skip the row filtering of PNG images.
+    #[must_use]
+    pub const fn fast_png(mut self, fast_png: bool) -> Self {
+        self.fast_png = fast_png;
+        self
+    }
+
     /// Encode the image buffer to image bytes with the specified format.
     ///
     /// # Arguments
@@ -97,6 +152,7 @@ impl ImageEncoder {
         height: u32,
     ) -> Result<Vec<u8>, ImageEncoderError> {
         let encoder = match self.format {
+            ImageFormat::Dds => return Ok(self.encode_dds(image_buffer, width, height)),
             ImageFormat::Jpeg => BitmapEncoder::JpegEncoderId()?,
             ImageFormat::Png => BitmapEncoder::PngEncoderId()?,
             ImageFormat::Gif => BitmapEncoder::GifEncoderId()?,
@@ -106,7 +162,18 @@ impl ImageEncoder {
         };
 
         let stream = InMemoryRandomAccessStream::new()?;
-        let encoder = BitmapEncoder::CreateAsync(encoder, &stream)?.get()?;
+        let encoder = if self.fast_png && self.format == ImageFormat::Png {
+            // WICPngFilterNone, finding the best filter for every row is the slow part
+            let options = BitmapPropertySet::new()?;
+            options.Insert(
+                &HSTRING::from("FilterOption"),
+                &BitmapTypedValue::Create(&PropertyValue::CreateUInt8(1)?, PropertyType::UInt8)?,
+            )?;
+
+            BitmapEncoder::CreateWithEncodingOptionsAsync(encoder, &stream, &options)?.get()?
+        } else {
+            BitmapEncoder::CreateAsync(encoder, &stream)?.get()?
+        };
 
         let pixelformat = match self.color_format {
             ColorFormat::Bgra8 => BitmapPixelFormat::Bgra8,
@@ -138,6 +205,78 @@ impl ImageEncoder {
 
         Ok(bytes)
     }
+
+    // Write the buffer as an uncompressed DDS, the DDS header layout is documented at
+    // https://learn.microsoft.com/en-us/windows/win32/direct3ddds/dds-header
+    fn encode_dds(&self, image_buffer: &[u8], width: u32, height: u32) -> Vec<u8> {
+        const DDSD_CAPS: u32 = 0x1;
+        const DDSD_HEIGHT: u32 = 0x2;
+        const DDSD_WIDTH: u32 = 0x4;
+        const DDSD_PITCH: u32 = 0x8;
+        const DDSD_PIXELFORMAT: u32 = 0x1000;
+        const DDPF_ALPHAPIXELS: u32 = 0x1;
+        const DDPF_FOURCC: u32 = 0x4;
+        const DDPF_RGB: u32 = 0x40;
+        const DDSCAPS_TEXTURE: u32 = 0x1000;
+        const DDS_DIMENSION_TEXTURE2D: u32 = 3;
+
+        let bytes_per_pixel = match self.color_format {
+            ColorFormat::Rgba16F => 8,
+            ColorFormat::Rgba8 | ColorFormat::Bgra8 => 4,
+        };
+        let pitch = width * bytes_per_pixel;
+
+        // Flags, FourCC, bit count and the red, green, blue and alpha masks
+        let pixel_format = match self.color_format {
+            ColorFormat::Rgba16F => [DDPF_FOURCC, u32::from_le_bytes(*b"DX10"), 0, 0, 0, 0, 0],
+            ColorFormat::Rgba8 => [
+                DDPF_RGB | DDPF_ALPHAPIXELS,
+                0,
+                32,
+                0x0000_00FF,
+                0x0000_FF00,
+                0x00FF_0000,
+                0xFF00_0000,
+            ],
+            ColorFormat::Bgra8 => [
+                DDPF_RGB | DDPF_ALPHAPIXELS,
+                0,
+                32,
+                0x00FF_0000,
+                0x0000_FF00,
+                0x0000_00FF,
+                0xFF00_0000,
+            ],
+        };
+
+        let mut header = vec![
+            124,
+            DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PITCH | DDSD_PIXELFORMAT,
+            height,
+            width,
+            pitch,
+            0,
+            1,
+        ];
+        header.extend([0; 11]);
+        header.push(32);
+        header.extend(pixel_format);
+        header.extend([DDSCAPS_TEXTURE, 0, 0, 0, 0]);
+
+        if self.color_format == ColorFormat::Rgba16F {
+            // DXGI format, resource dimension, misc flags, array size and misc flags 2
+            header.extend([self.color_format as u32, DDS_DIMENSION_TEXTURE2D, 0, 1, 0]);
+        }
+
+        let mut bytes = Vec::with_capacity(4 + header.len() * 4 + image_buffer.len());
+        bytes.extend_from_slice(b"DDS ");
+        for value in header {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(image_buffer);
+
+        bytes
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -148,6 +287,18 @@ pub enum VideoEncoderError {
     FrameSendError(#[from] mpsc::SendError<Option<(VideoEncoderSource, TimeSpan)>>),
     #[error("IO Error: {0}")]
     IoError(#[from] std::io::Error),
+    #[error("The frame size isn't known until the first frame is sent with send_frame")]
+    FrameSizeUnknown,
+    #[error("The metadata can't be added because the MP4 index isn't at the end of the file")]
+    MetadataUnsupported,
+    #[error("There are no frames to encode")]
+    NoFrames,
+    #[error("All frames must have the size of the first frame")]
+    FrameSizeMismatch,
+    #[error("Frame error: {0}")]
+    FrameError(#[from] frame::Error),
+    #[error("The encoder is finished and doesn't take audio anymore")]
+    AudioSendError,
 }
 
 unsafe impl Send for VideoEncoderError {}
@@ -177,222 +328,1055 @@ pub enum VideoEncoderQuality {
     Uhd4320p = 9,
 }
 
-/// The `VideoEncoderSource` struct represents all the types that can be send to the encoder.
-pub enum VideoEncoderSource {
-    DirectX(SendDirectX<IDirect3DSurface>),
-    Buffer((SendDirectX<*const u8>, usize)),
+/// The entropy coding mode of H.264 streams.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum EntropyCoding {
+    /// Context-adaptive binary arithmetic coding, smaller output but not supported by the
+    /// Baseline profile.
+    Cabac,
+    /// Context-adaptive variable-length coding, supported by every H.264 decoder.
+    Cavlc,
 }
 
-/// The `VideoEncoder` struct represents a video encoder that can be used to encode video frames and save them to a specified file path.
-pub struct VideoEncoder {
-    first_timespan: Option<TimeSpan>,
-    frame_sender: mpsc::Sender<Option<(VideoEncoderSource, TimeSpan)>>,
-    sample_requested: EventRegistrationToken,
-    media_stream_source: MediaStreamSource,
-    starting: EventRegistrationToken,
-    transcode_thread: Option<JoinHandle<Result<(), VideoEncoderError>>>,
-    frame_notify: Arc<(Mutex<bool>, Condvar)>,
-    error_notify: Arc<AtomicBool>,
+/// The latency the video encoder is tuned for with `VideoSettingsBuilder::latency_preset`.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum LatencyPreset {
+    /// The lowest latency for interactive streaming, trades quality for encoding speed, uses
+    /// low delay rate control and a single reference frame.
+    UltraLow,
+    /// Low latency without B-frames, the encoder keeps its quality settings.
+    Low,
+    /// The default latency of the encoder, low latency mode is turned off.
+    Normal,
 }
 
-impl VideoEncoder {
-    /// Creates a new `VideoEncoder` instance with the specified parameters.
+/// The quality level used to calculate the bitrate with `VideoSettingsBuilder::auto_bitrate`.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum BitrateQuality {
+    /// Small files, fine for screen content with little motion.
+    Low,
+    /// A balance between size and quality.
+    Medium,
+    /// Large files that hold up with fast motion like games.
+    High,
+}
+
+impl BitrateQuality {
+    // The H.264 bits per pixel per frame in thousandths.
+    const fn millibits_per_pixel(self) -> u64 {
+        match self {
+            Self::Low => 50,
+            Self::Medium => 100,
+            Self::High => 200,
+        }
+    }
+}
+
+/// What happens to the timeline of the video while a `VideoEncoder` is paused.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum PauseTimeline {
+    /// The paused time is cut out, the video continues right where it was paused.
+    Freeze,
+    /// The paused time stays in the video as a gap that holds the last frame.
+    Advance,
+}
+
+/// How `VideoEncoder::encode_frames` times the frames of the video.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum FrameTiming {
+    /// Every frame is shown from the time it was captured until the next frame.
+    Timestamps,
+    /// The frames are played back to back at a fixed frame rate.
+    FixedRate(u32),
+}
+
+/// How `VideoEncoder::finish_timeout` ended.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum FinishOutcome {
+    /// The video was finalized and is complete.
+    Finished,
+    /// The encoder didn't finish in time and was abandoned, the video is partially written and
+    /// may be recoverable with a repair tool but is likely missing its index.
+    TimedOut,
+}
+
+/// The format of the log `VideoEncoder::timestamp_log` writes next to the video.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum TimestampLogFormat {
+    /// SubRip subtitles, one cue per frame that lasts until the next frame.
+    Srt,
+    /// WebVTT subtitles, one cue per frame that lasts until the next frame.
+    WebVtt,
+    /// Comma separated values with a `frame,pts_ms,wall_clock_ms` header.
+    Csv,
+}
+
+/// The frame sizes `EncoderCapabilities::probe` tries, from the largest down.
+const PROBE_RESOLUTIONS: [(u32, u32); 6] = [
+    (7680, 4320),
+    (5120, 2880),
+    (3840, 2160),
+    (2560, 1440),
+    (1920, 1080),
+    (1280, 720),
+];
+
+/// The frame rates `EncoderCapabilities::probe` tries at the largest accepted size, from the
+/// highest down.
+const PROBE_FRAME_RATES: [u32; 6] = [240, 144, 120, 60, 30, 24];
+
+/// The limits of the hardware video encoder of an encoder type.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct EncoderCapabilities {
+    /// The encoder type the limits are for.
+    pub encoder_type: VideoEncoderType,
+    /// The largest width and height the encoder accepts at 30 frames per second, a hint.
+    pub max_resolution: (u32, u32),
+    /// The highest frame rate the encoder accepts at `max_resolution`, a hint. Many encoders
+    /// accept any frame rate when they're configured.
+    pub max_fps: u32,
+}
+
+impl EncoderCapabilities {
+    /// Probe the preferred hardware encoder of an encoder type for the largest frame size and
+    /// frame rate it accepts.
+    ///
+    /// The encoder is asked to accept 8K, 5K, 4K, 1440p, 1080p and 720p at 30 frames per second
+    /// and then 240, 144, 120, 60, 30 and 24 frames per second at the largest accepted size.
+    /// The limits are hints, not guarantees. They're tested with `MFT_SET_TYPE_TEST_ONLY`, which
+    /// most hardware encoders accept regardless of the frame rate, so `max_fps` is often just
+    /// the highest rate tried, and a few drivers only fail once the first frames are encoded.
+    /// The frame rate is only tested at `max_resolution`. Activating the encoder takes a moment,
+    /// probe once.
     ///
     /// # Arguments
     ///
-    /// * `encoder_type` - The type of video encoder to use.
-    /// * `encoder_quality` - The quality of the video encoder.
-    /// * `width` - The width of the video frames.
-    /// * `height` - The height of the video frames.
-    /// * `path` - The file path where the encoded video will be saved.
+    /// * `encoder_type` - The encoder type, only `Mp4`, `Hevc`, `Av1` and `Vp9` have hardware
+    ///   encoders.
     ///
     /// # Returns
     ///
-    /// Returns a `Result` containing the `VideoEncoder` instance if successful, or a
-    /// `VideoEncoderError` if an error occurs.
-    pub fn new<P: AsRef<Path>>(
-        encoder_type: VideoEncoderType,
-        encoder_quality: VideoEncoderQuality,
-        width: u32,
-        height: u32,
-        path: P,
-        fps: Option<u32>,
-    ) -> Result<Self, VideoEncoderError> {
-        let path = path.as_ref();
-
-        let media_encoding_profile = match encoder_type {
-            VideoEncoderType::Avi => {
-                MediaEncodingProfile::CreateAvi(VideoEncodingQuality(encoder_quality as i32))?
-            }
-            VideoEncoderType::Hevc => {
-                MediaEncodingProfile::CreateHevc(VideoEncodingQuality(encoder_quality as i32))?
-            }
-            VideoEncoderType::Mp4 => {
-                MediaEncodingProfile::CreateMp4(VideoEncodingQuality(encoder_quality as i32))?
-            }
-            VideoEncoderType::Wmv => {
-                MediaEncodingProfile::CreateWmv(VideoEncodingQuality(encoder_quality as i32))?
-            }
-            VideoEncoderType::Av1 => {
-                MediaEncodingProfile::CreateAv1(VideoEncodingQuality(encoder_quality as i32))?
-            }
-            VideoEncoderType::Vp9 => {
-                MediaEncodingProfile::CreateVp9(VideoEncodingQuality(encoder_quality as i32))?
-            }
+    /// The limits of the encoder, or `None` if there is no hardware encoder for the type or it
+    /// accepts none of the sizes.
+    #[must_use]
+    pub fn probe(encoder_type: VideoEncoderType) -> Option<Self> {
+        let subtype = match encoder_type {
+            VideoEncoderType::Mp4 => MFVideoFormat_H264,
+            VideoEncoderType::Hevc => MFVideoFormat_HEVC,
+            VideoEncoderType::Av1 => MFVideoFormat_AV1,
+            VideoEncoderType::Vp9 => MFVideoFormat_VP90,
+            VideoEncoderType::Avi | VideoEncoderType::Wmv => return None,
         };
-        media_encoding_profile
-            .Video()?
-            .SetWidth(width)?;
-        media_encoding_profile
-            .Video()?
-            .SetHeight(height)?;
-        if fps.is_some() {
-            media_encoding_profile
-                .Video()?
-                .FrameRate()?
-                .SetNumerator(fps.unwrap())?;
-            media_encoding_profile
-                .Video()?
-                .FrameRate()?
-                .SetDenominator(1)?;
-        }
-    
-        let video_encoding_properties = VideoEncodingProperties::CreateUncompressed(
-            &MediaEncodingSubtypes::Bgra8()?,
-            width,
-            height,
-        )?;
-
-        let video_stream_descriptor = VideoStreamDescriptor::Create(&video_encoding_properties)?;
 
-        let media_stream_source =
-            MediaStreamSource::CreateFromDescriptor(&video_stream_descriptor)?;
-        media_stream_source.SetBufferTime(TimeSpan::default())?;
-
-        let (frame_sender, frame_receiver) =
-            mpsc::channel::<Option<(VideoEncoderSource, TimeSpan)>>();
+        // S_FALSE means COM was already initialized and still needs the matching uninitialize
+        let initialized = unsafe { RoInitialize(RO_INIT_MULTITHREADED) }.is_ok();
+        let started = unsafe { MFStartup(MF_VERSION, MFSTARTUP_LITE) }.is_ok();
 
-        let starting = media_stream_source.Starting(&TypedEventHandler::<
-            MediaStreamSource,
-            MediaStreamSourceStartingEventArgs,
-        >::new(move |_, stream_start| {
-            let stream_start = stream_start
-                .as_ref()
-                .expect("MediaStreamSource Starting parameter was None This Should Not Happen.");
+        let limits = started
+            .then(|| probe_hardware_encoder(subtype).ok().flatten())
+            .flatten();
 
-            stream_start
-                .Request()?
-                .SetActualStartPosition(TimeSpan { Duration: 0 })?;
-            Ok(())
-        }))?;
+        unsafe {
+            if started {
+                let _ = MFShutdown();
+            }
 
-        let frame_notify = Arc::new((Mutex::new(false), Condvar::new()));
+            if initialized {
+                RoUninitialize();
+            }
+        };
 
-        let sample_requested = media_stream_source.SampleRequested(&TypedEventHandler::<
-            MediaStreamSource,
-            MediaStreamSourceSampleRequestedEventArgs,
-        >::new({
-            let frame_receiver = frame_receiver;
-            let frame_notify = frame_notify.clone();
+        limits.map(|(max_resolution, max_fps)| Self {
+            encoder_type,
+            max_resolution,
+            max_fps,
+        })
+    }
+}
 
-            move |_, sample_requested| {
-                let sample_requested = sample_requested.as_ref().expect(
-                    "MediaStreamSource SampleRequested parameter was None This Should Not Happen.",
-                );
+/// What a finished `VideoEncoder` wrote to the video, returned by `VideoEncoder::finish`.
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub struct EncodeSummary {
+    frame_count: u64,
+    duration: Duration,
+    metadata_failed: bool,
+}
 
-                let frame = match frame_receiver.recv() {
-                    Ok(frame) => frame,
-                    Err(e) => panic!("Failed to receive frame from frame sender: {e}"),
-                };
+impl EncodeSummary {
+    /// Get the number of frames written to the video.
+    #[must_use]
+    pub const fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
 
-                match frame {
-                    Some((source, timespan)) => {
-                        let sample = match source {
-                            VideoEncoderSource::DirectX(surface) => {
-                                MediaStreamSample::CreateFromDirect3D11Surface(
-                                    &surface.0, timespan,
-                                )?
-                            }
-                            VideoEncoderSource::Buffer(buffer_data) => {
-                                let buffer = buffer_data.0;
-                                let buffer =
-                                    unsafe { slice::from_raw_parts(buffer.0, buffer_data.1) };
-                                let buffer = CryptographicBuffer::CreateFromByteArray(buffer)?;
-                                MediaStreamSample::CreateFromBuffer(&buffer, timespan)?
-                            }
-                        };
+    /// Get the time from the first to the last frame of the video.
+    #[must_use]
+    pub const fn duration(&self) -> Duration {
+        self.duration
+    }
 
-                        sample_requested.Request()?.SetSample(&sample)?;
-                    }
-                    None => {
-                        sample_requested.Request()?.SetSample(None)?;
-                    }
-                }
+    /// Get whether the metadata tags of `VideoSettingsBuilder::metadata` couldn't be added to the
+    /// video, for example because the MP4 index isn't at the end of the file. The video itself
+    /// is complete either way.
+    #[must_use]
+    pub const fn metadata_failed(&self) -> bool {
+        self.metadata_failed
+    }
 
-                let (lock, cvar) = &*frame_notify;
-                *lock.lock() = true;
-                cvar.notify_one();
+    /// Get the average frame rate of the video, based on the frames written and the time
+    /// between the first and the last frame.
+    ///
+    /// Frames dropped or repeated while capturing are included, so it can differ from the
+    /// frame rate of the video settings. Zero until two frames were written.
+    #[must_use]
+    pub fn frame_rate(&self) -> f64 {
+        if self.frame_count < 2 || self.duration.is_zero() {
+            0.0
+        } else {
+            (self.frame_count - 1) as f64 / self.duration.as_secs_f64()
+        }
+    }
+}
 
-                Ok(())
-            }
-        }))?;
+// How the bitrate of the video stream is chosen.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+enum BitrateSettings {
+    Fixed(u32),
+    Auto(BitrateQuality),
+}
 
-        let media_transcoder = MediaTranscoder::new()?;
-        media_transcoder.SetHardwareAccelerationEnabled(true)?;
+/// The `VideoSettingsBuilder` struct is used to configure the video stream of a `VideoEncoder`.
+///
+/// # Example
+/// ```no_run
+/// use windows_capture::encoder::{
+///     EntropyCoding, VideoEncoder, VideoEncoderQuality, VideoEncoderType, VideoSettingsBuilder,
+/// };
+///
+/// let video_settings = VideoSettingsBuilder::new(1920, 1080)
+///     .encoder_type(VideoEncoderType::Mp4)
+///     .encoder_quality(VideoEncoderQuality::HD1080p)
+///     .max_reference_frames(1)
+///     .entropy_coding(EntropyCoding::Cavlc);
+///
+/// let encoder = VideoEncoder::new_with_settings(video_settings, "video.mp4").unwrap();
+/// ```
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct VideoSettingsBuilder {
+    encoder_type: VideoEncoderType,
+    encoder_quality: VideoEncoderQuality,
+    width: u32,
+    height: u32,
+    frame_rate: Option<(u32, u32)>,
+    max_reference_frames: Option<u32>,
+    max_threads: Option<u32>,
+    entropy_coding: Option<EntropyCoding>,
+    bitrate: Option<BitrateSettings>,
+    quality_level: Option<u32>,
+    input_size: Option<(u32, u32)>,
+    latency_preset: Option<LatencyPreset>,
+    metadata: Vec<(String, String)>,
+    audio: Option<(u32, u32)>,
+}
 
-        File::create(path)?;
-        let path = fs::canonicalize(path).unwrap().to_string_lossy()[4..].to_string();
-        let path = Path::new(&path);
+impl VideoSettingsBuilder {
+    /// Create video settings for an Mp4 stream with HD1080p quality.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the video frames.
+    /// * `height` - The height of the video frames.
+    #[must_use]
+    pub const fn new(width: u32, height: u32) -> Self {
+        Self {
+            encoder_type: VideoEncoderType::Mp4,
+            encoder_quality: VideoEncoderQuality::HD1080p,
+            width,
+            height,
+            frame_rate: None,
+            max_reference_frames: None,
+            max_threads: None,
+            entropy_coding: None,
+            bitrate: None,
+            quality_level: None,
+            input_size: None,
+            latency_preset: None,
+            metadata: Vec::new(),
+            audio: None,
+        }
+    }
 
-        let path = &HSTRING::from(path.as_os_str().to_os_string());
+    /// Set the type of video encoder to use.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder_type` - The type of video encoder to use.
+    #[must_use]
+    pub const fn encoder_type(mut self, encoder_type: VideoEncoderType) -> Self {
+        self.encoder_type = encoder_type;
+        self
+    }
 
-        let file = StorageFile::GetFileFromPathAsync(path)?.get()?;
-        let media_stream_output = file.OpenAsync(FileAccessMode::ReadWrite)?.get()?;
+    /// Set the quality preset of the video encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder_quality` - The quality of the video encoder.
+    #[must_use]
+    pub const fn encoder_quality(mut self, encoder_quality: VideoEncoderQuality) -> Self {
+        self.encoder_quality = encoder_quality;
+        self
+    }
 
-        let transcode = media_transcoder
-            .PrepareMediaStreamSourceTranscodeAsync(
-                &media_stream_source,
-                &media_stream_output,
-                &media_encoding_profile,
-            )?
-            .get()?;
+    /// Set the frame rate of the video stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `fps` - The number of frames per second.
+    #[must_use]
+    pub const fn fps(mut self, fps: u32) -> Self {
+        self.frame_rate = Some((fps, 1));
+        self
+    }
 
-        let error_notify = Arc::new(AtomicBool::new(false));
-        let transcode_thread = thread::spawn({
-            let error_notify = error_notify.clone();
+    /// Set a fractional frame rate of the video stream, for example `30000 / 1001` for 29.97
+    /// frames per second.
+    ///
+    /// # Arguments
+    ///
+    /// * `numerator` - The numerator of the frame rate.
+    /// * `denominator` - The denominator of the frame rate.
+    #[must_use]
+    pub const fn frame_rate_ratio(mut self, numerator: u32, denominator: u32) -> Self {
+        self.frame_rate = Some((numerator, denominator));
+        self
+    }
 
-            move || -> Result<(), VideoEncoderError> {
-                let result = transcode.TranscodeAsync();
+    /// Set the maximum number of reference frames the encoder may use, maps to
+    /// `CODECAPI_AVEncVideoMaxNumRefFrame`.
+    ///
+    /// Some hardware decoders reject streams with more reference frames than they support.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_reference_frames` - The maximum number of reference frames.
+    #[must_use]
+    pub const fn max_reference_frames(mut self, max_reference_frames: u32) -> Self {
+        self.max_reference_frames = Some(max_reference_frames);
+        self
+    }
 
-                if result.is_err() {
-                    error_notify.store(true, atomic::Ordering::Relaxed);
-                }
+    /// Set the maximum number of worker threads the encoder may use, maps to
+    /// `CODECAPI_AVEncNumWorkerThreads`.
+    ///
+    /// Caps the CPU usage of software encoders so background recording doesn't starve other
+    /// work, hardware encoders mostly ignore it.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_threads` - The maximum number of worker threads, 0 lets the encoder decide.
+    #[must_use]
+    pub const fn max_threads(mut self, max_threads: u32) -> Self {
+        self.max_threads = Some(max_threads);
+        self
+    }
 
-                result?.get()?;
+    /// Set the entropy coding mode of H.264 streams, maps to `CODECAPI_AVEncH264CABACEnable`.
+    ///
+    /// # Arguments
+    ///
+    /// * `entropy_coding` - The entropy coding mode.
+    #[must_use]
+    pub const fn entropy_coding(mut self, entropy_coding: EntropyCoding) -> Self {
+        self.entropy_coding = Some(entropy_coding);
+        self
+    }
 
-                drop(media_transcoder);
+    /// Set the bitrate of the video stream, replaces the bitrate of the quality preset.
+    ///
+    /// # Arguments
+    ///
+    /// * `bitrate` - The bitrate in bits per second.
+    #[must_use]
+    pub const fn bitrate(mut self, bitrate: u32) -> Self {
+        self.bitrate = Some(BitrateSettings::Fixed(bitrate));
+        self
+    }
 
-                Ok(())
-            }
-        });
+    /// Pick the bitrate so a recording of the given duration fits into a storage budget, for
+    /// example 2 GB for a one hour session.
+    ///
+    /// 2% of the budget is left for the container overhead.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The maximum size of the file in bytes.
+    /// * `duration` - The expected duration of the recording.
+    #[must_use]
+    pub const fn target_file_size(mut self, bytes: u64, duration: Duration) -> Self {
+        let millis = duration.as_millis();
+        let millis = if millis == 0 { 1 } else { millis };
+
+        let bitrate = bytes as u128 * 8 * 1000 * 98 / 100 / millis;
+        let bitrate = if bitrate > u32::MAX as u128 {
+            u32::MAX
+        } else {
+            bitrate as u32
+        };
 
-        Ok(Self {
-            first_timespan: None,
-            frame_sender,
-            sample_requested,
-            media_stream_source,
-            starting,
-            transcode_thread: Some(transcode_thread),
-            frame_notify,
-            error_notify,
-        })
+        self.bitrate = Some(BitrateSettings::Fixed(bitrate));
+        self
     }
 
-    /// Creates a new `VideoEncoder` instance with the specified parameters.
+    /// Calculate the bitrate of the video stream from the resolution, frame rate and encoder
+    /// type instead of using the bitrate of the quality preset.
+    ///
+    /// HEVC, AV1 and VP9 get a lower bitrate than H.264 for the same quality.
     ///
     /// # Arguments
     ///
-    /// * `encoder_type` - The type of video encoder to use.
+    /// * `quality` - The quality level, for example `BitrateQuality::Medium`.
+    #[must_use]
+    pub const fn auto_bitrate(mut self, quality: BitrateQuality) -> Self {
+        self.bitrate = Some(BitrateSettings::Auto(quality));
+        self
+    }
+
+    /// Encode with a constant quality instead of a target bitrate, the bitrate is whatever the
+    /// content needs. Maps to `CODECAPI_AVEncCommonRateControlMode` set to quality mode and
+    /// `CODECAPI_AVEncCommonQuality`.
+    ///
+    /// Encoders without quality mode support ignore it and use the bitrate.
+    ///
+    /// # Arguments
+    ///
+    /// * `quality_level` - The quality from 0 to 100, higher values are clamped to 100.
+    #[must_use]
+    pub const fn quality_level(mut self, quality_level: u32) -> Self {
+        self.quality_level = Some(if quality_level > 100 {
+            100
+        } else {
+            quality_level
+        });
+        self
+    }
+
+    /// Set the size of the frames sent to the encoder when it differs from the size of the
+    /// video, the frames are scaled to the video size on the GPU.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the frames sent to the encoder.
+    /// * `height` - The height of the frames sent to the encoder.
+    #[must_use]
+    pub const fn input_size(mut self, width: u32, height: u32) -> Self {
+        self.input_size = Some((width, height));
+        self
+    }
+
+    /// Tune the encoder for a latency, maps to `CODECAPI_AVLowLatencyMode`.
+    ///
+    /// The Intel Quick Sync, NVIDIA NVENC and AMD AMF encoders switch to their low latency
+    /// presets in low latency mode. `LatencyPreset::Low` also turns off B-frames with
+    /// `CODECAPI_AVEncMPVDefaultBPictureCount`. `LatencyPreset::UltraLow` additionally sets
+    /// `CODECAPI_AVEncCommonQualityVsSpeed` to the fastest encoding, low delay VBR rate control
+    /// unless `quality_level` is set and a single reference frame unless `max_reference_frames`
+    /// is set. Encoders ignore the values they don't support.
+    ///
+    /// # Arguments
+    ///
+    /// * `latency_preset` - The latency preset, for example `LatencyPreset::UltraLow`.
+    #[must_use]
+    pub const fn latency_preset(mut self, latency_preset: LatencyPreset) -> Self {
+        self.latency_preset = Some(latency_preset);
+        self
+    }
+
+    /// Add an audio track, the samples are sent as 16-bit PCM through the `AudioSender` of the
+    /// encoder and encoded with the audio codec of the encoder type, AAC for `Mp4` and `Hevc`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate` - The sample rate of the audio, for example 48000.
+    /// * `channels` - The number of channels, the samples of the channels are interleaved.
+    #[must_use]
+    pub const fn audio(mut self, sample_rate: u32, channels: u32) -> Self {
+        self.audio = Some((sample_rate, channels));
+        self
+    }
+
+    /// Add a metadata tag to the file, for example the capture time or the title of the captured
+    /// window.
+    ///
+    /// The tags are written as iTunes style freeform tags in the `udta` box when the encoder
+    /// finishes, so they are only written for the `Mp4` and `Hevc` encoder types.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The name of the tag.
+    /// * `value` - The value of the tag.
+    #[must_use]
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    // Get the metadata tags that can be written to the container of the encoder type.
+    fn container_metadata(&self) -> Vec<(String, String)> {
+        match self.encoder_type {
+            VideoEncoderType::Mp4 | VideoEncoderType::Hevc => self.metadata.clone(),
+            VideoEncoderType::Avi
+            | VideoEncoderType::Wmv
+            | VideoEncoderType::Av1
+            | VideoEncoderType::Vp9 => Vec::new(),
+        }
+    }
+
+    // Calculate the bitrate for a quality level, the frame rate is a ratio.
+    fn calculate_bitrate(&self, quality: BitrateQuality, frame_rate: (u32, u32)) -> u32 {
+        let (numerator, denominator) = frame_rate;
+
+        let bits = u64::from(self.width)
+            * u64::from(self.height)
+            * u64::from(numerator)
+            * quality.millibits_per_pixel()
+            / (u64::from(denominator.max(1)) * 1000);
+
+        // Newer codecs need around 40% less for the same quality
+        let bits = match self.encoder_type {
+            VideoEncoderType::Hevc | VideoEncoderType::Av1 | VideoEncoderType::Vp9 => bits * 3 / 5,
+            VideoEncoderType::Avi | VideoEncoderType::Mp4 | VideoEncoderType::Wmv => bits,
+        };
+
+        u32::try_from(bits).unwrap_or(u32::MAX)
+    }
+
+    // Create the encoding profile described by the settings.
+    fn create_media_encoding_profile(&self) -> Result<MediaEncodingProfile, windows::core::Error> {
+        let quality = VideoEncodingQuality(self.encoder_quality as i32);
+
+        let media_encoding_profile = match self.encoder_type {
+            VideoEncoderType::Avi => MediaEncodingProfile::CreateAvi(quality)?,
+            VideoEncoderType::Hevc => MediaEncodingProfile::CreateHevc(quality)?,
+            VideoEncoderType::Mp4 => MediaEncodingProfile::CreateMp4(quality)?,
+            VideoEncoderType::Wmv => MediaEncodingProfile::CreateWmv(quality)?,
+            VideoEncoderType::Av1 => MediaEncodingProfile::CreateAv1(quality)?,
+            VideoEncoderType::Vp9 => MediaEncodingProfile::CreateVp9(quality)?,
+        };
+
+        if let Some((sample_rate, channels)) = self.audio {
+            let audio = media_encoding_profile.Audio()?;
+            audio.SetSampleRate(sample_rate)?;
+            audio.SetChannelCount(channels)?;
+        }
+
+        let video = media_encoding_profile.Video()?;
+        video.SetWidth(self.width)?;
+        video.SetHeight(self.height)?;
+
+        if let Some((numerator, denominator)) = self.frame_rate {
+            video.FrameRate()?.SetNumerator(numerator)?;
+            video.FrameRate()?.SetDenominator(denominator)?;
+        }
+
+        match self.bitrate {
+            Some(BitrateSettings::Fixed(bitrate)) => video.SetBitrate(bitrate)?,
+            Some(BitrateSettings::Auto(quality)) => {
+                let frame_rate = video.FrameRate()?;
+                let frame_rate = (frame_rate.Numerator()?, frame_rate.Denominator()?);
+
+                video.SetBitrate(self.calculate_bitrate(quality, frame_rate))?;
+            }
+            None => (),
+        }
+
+        // Codec API values set on the output type are picked up by the encoder
+        let properties = video.Properties()?;
+
+        if let Some(max_reference_frames) = self.max_reference_frames {
+            properties.Insert(
+                CODECAPI_AVEncVideoMaxNumRefFrame,
+                &PropertyValue::CreateUInt32(max_reference_frames)?,
+            )?;
+        }
+
+        if let Some(max_threads) = self.max_threads {
+            properties.Insert(
+                CODECAPI_AVEncNumWorkerThreads,
+                &PropertyValue::CreateUInt32(max_threads)?,
+            )?;
+        }
+
+        if let Some(entropy_coding) = self.entropy_coding {
+            properties.Insert(
+                CODECAPI_AVEncH264CABACEnable,
+                &PropertyValue::CreateBoolean(entropy_coding == EntropyCoding::Cabac)?,
+            )?;
+        }
+
+        if let Some(latency_preset) = self.latency_preset {
+            properties.Insert(
+                CODECAPI_AVLowLatencyMode,
+                &PropertyValue::CreateBoolean(latency_preset != LatencyPreset::Normal)?,
+            )?;
+
+            if latency_preset != LatencyPreset::Normal {
+                properties.Insert(
+                    CODECAPI_AVEncMPVDefaultBPictureCount,
+                    &PropertyValue::CreateUInt32(0)?,
+                )?;
+            }
+
+            if latency_preset == LatencyPreset::UltraLow {
+                properties.Insert(
+                    CODECAPI_AVEncCommonQualityVsSpeed,
+                    &PropertyValue::CreateUInt32(0)?,
+                )?;
+
+                if self.quality_level.is_none() {
+                    properties.Insert(
+                        CODECAPI_AVEncCommonRateControlMode,
+                        &PropertyValue::CreateUInt32(
+                            eAVEncCommonRateControlMode_LowDelayVBR.0 as u32,
+                        )?,
+                    )?;
+                }
+
+                if self.max_reference_frames.is_none() {
+                    properties.Insert(
+                        CODECAPI_AVEncVideoMaxNumRefFrame,
+                        &PropertyValue::CreateUInt32(1)?,
+                    )?;
+                }
+            }
+        }
+
+        if let Some(quality_level) = self.quality_level {
+            properties.Insert(
+                CODECAPI_AVEncCommonRateControlMode,
+                &PropertyValue::CreateUInt32(eAVEncCommonRateControlMode_Quality.0 as u32)?,
+            )?;
+            properties.Insert(
+                CODECAPI_AVEncCommonQuality,
+                &PropertyValue::CreateUInt32(quality_level)?,
+            )?;
+        }
+
+        Ok(media_encoding_profile)
+    }
+}
+
+/// The `VideoEncoderSource` struct represents all the types that can be send to the encoder.
+pub enum VideoEncoderSource {
+    DirectX(SendDirectX<IDirect3DSurface>),
+    Buffer((SendDirectX<*const u8>, usize)),
+}
+
+/// Describes the frame layout the `VideoEncoder` expects as input.
+///
+/// This is the uncompressed format the encoder declares for its frames, always `BGRA8`. It's
+/// not negotiated with the hardware encoder, the transcoder converts the frames to the format
+/// of the codec, for example NV12, on its own.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct InputFormatInfo {
+    /// The media subtype of the input, for example `BGRA8`.
+    pub subtype: String,
+    /// The matching color format, or `None` if the subtype has no matching `ColorFormat`.
+    pub color_format: Option<ColorFormat>,
+    /// The width of the input frames.
+    pub width: u32,
+    /// The height of the input frames.
+    pub height: u32,
+    /// The number of bytes per row, rows have no padding.
+    pub stride: u32,
+    /// Whether buffers sent with `send_frame_buffer` must start with the bottom row.
+    pub bottom_up: bool,
+}
+
+/// How often `VideoEncoder::finish_timeout` checks if the transcoder finished.
+const FINISH_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How far the audio may fall behind the frames before the gap is filled with silence.
+const AUDIO_GAP_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The longest silence filled in at once in 100ns units, the transcoder asks for the rest.
+const AUDIO_GAP_MAX_FILL: i64 = 10_000_000;
+
+// The time in the video of the frames, shared with the audio senders so audio lines up with them.
+#[derive(Default)]
+struct Timeline {
+    first_timespan: Option<TimeSpan>,
+    paused: Option<(PauseTimeline, Instant)>,
+    // The paused time cut out of the timeline in 100ns units
+    frozen_duration: i64,
+}
+
+impl Timeline {
+    // Converts an audio timespan to the time in the video, audio before the first frame or while
+    // paused has no place in the video.
+    fn audio_timespan(&self, timespan: i64) -> Option<TimeSpan> {
+        if self.paused.is_some() {
+            return None;
+        }
+
+        let first_timespan = self.first_timespan?;
+        let duration = timespan - first_timespan.Duration - self.frozen_duration;
+
+        (duration >= 0).then_some(TimeSpan { Duration: duration })
+    }
+}
+
+// The audio samples with their time and duration, `None` ends the audio stream.
+type AudioSample = Option<(Vec<u8>, TimeSpan, TimeSpan)>;
+
+// The queue the audio samples go through to the transcoder.
+struct AudioChannel {
+    queue: Arc<Mutex<AudioQueue>>,
+    sample_rate: u32,
+    channels: u32,
+}
+
+impl AudioChannel {
+    fn new((sample_rate, channels): (u32, u32)) -> Self {
+        Self {
+            queue: Arc::new(Mutex::new(AudioQueue::new(sample_rate, channels))),
+            sample_rate,
+            channels,
+        }
+    }
+}
+
+// The audio samples waiting for the transcoder, shared by the audio senders, the transcoder and
+// the session that sends the frames.
+struct AudioQueue {
+    samples: VecDeque<(Vec<u8>, TimeSpan, TimeSpan)>,
+    ended: bool,
+    sample_rate: i64,
+    block_align: usize,
+    // The end of the audio and the time of the latest frame in 100ns units
+    audio_end: i64,
+    video_time: i64,
+    // The audio request of the transcoder that waits for audio or a frame
+    pending: Option<(MediaStreamSourceSampleRequest, MediaStreamSourceSampleRequestDeferral)>,
+}
+
+impl AudioQueue {
+    fn new(sample_rate: u32, channels: u32) -> Self {
+        Self {
+            samples: VecDeque::new(),
+            ended: false,
+            sample_rate: i64::from(sample_rate.max(1)),
+            block_align: channels.max(1) as usize * 2,
+            audio_end: 0,
+            video_time: 0,
+            pending: None,
+        }
+    }
+
+    // Takes the next sample for the transcoder without waiting, `None` if there is none yet.
+    //
+    // A missing or slow audio source must not stall the video, so the part of the gap up to the
+    // latest frame that is more than `lag` behind it is filled with silence. Samples that arrive
+    // later are cut where the silence ends, so the timestamps of the audio stream keep
+    // increasing.
+    fn next_sample(&mut self, lag: i64) -> Option<AudioSample> {
+        while let Some((bytes, timespan, duration)) = self.samples.pop_front() {
+            let overlap = self.audio_end - timespan.Duration;
+            if overlap <= 0 {
+                self.audio_end = timespan.Duration + duration.Duration;
+                return Some(Some((bytes, timespan, duration)));
+            }
+
+            let skipped_frames = (overlap * self.sample_rate + 9_999_999) / 10_000_000;
+            let skipped_bytes = usize::try_from(skipped_frames)
+                .unwrap_or(usize::MAX)
+                .saturating_mul(self.block_align);
+            if skipped_bytes >= bytes.len() {
+                continue;
+            }
+
+            let skipped_duration = skipped_frames * 10_000_000 / self.sample_rate;
+            let timespan = TimeSpan {
+                Duration: timespan.Duration + skipped_duration,
+            };
+            let duration = TimeSpan {
+                Duration: (duration.Duration - skipped_duration).max(0),
+            };
+            self.audio_end = timespan.Duration + duration.Duration;
+
+            return Some(Some((bytes[skipped_bytes..].to_vec(), timespan, duration)));
+        }
+
+        if self.ended {
+            return Some(None);
+        }
+
+        let gap = (self.video_time - lag - self.audio_end).min(AUDIO_GAP_MAX_FILL);
+        let frames = gap * self.sample_rate / 10_000_000;
+        if frames <= 0 {
+            return None;
+        }
+
+        let timespan = TimeSpan {
+            Duration: self.audio_end,
+        };
+        let duration = TimeSpan {
+            Duration: frames * 10_000_000 / self.sample_rate,
+        };
+        self.audio_end += duration.Duration;
+
+        Some(Some((
+            vec![0; usize::try_from(frames).unwrap_or(0) * self.block_align],
+            timespan,
+            duration,
+        )))
+    }
+
+    // Answers the waiting audio request of the transcoder if there is a sample for it, the
+    // request is completed after the queue is unlocked because the transcoder may ask for the
+    // next sample right away.
+    fn answer_pending(queue: &Mutex<Self>, lag: i64) -> windows::core::Result<()> {
+        let ((request, deferral), sample) = {
+            let mut queue = queue.lock();
+            let Some(pending) = queue.pending.take() else {
+                return Ok(());
+            };
+
+            match queue.next_sample(lag) {
+                Some(sample) => (pending, sample),
+                None => {
+                    queue.pending = Some(pending);
+                    return Ok(());
+                }
+            }
+        };
+
+        set_audio_sample(&request, sample)?;
+        deferral.Complete()
+    }
+}
+
+// Sets the sample of an audio request of the transcoder, `None` ends the audio stream.
+fn set_audio_sample(
+    request: &MediaStreamSourceSampleRequest,
+    sample: AudioSample,
+) -> windows::core::Result<()> {
+    match sample {
+        Some((bytes, timespan, duration)) => {
+            let buffer = CryptographicBuffer::CreateFromByteArray(&bytes)?;
+            let sample = MediaStreamSample::CreateFromBuffer(&buffer, timespan)?;
+            sample.SetDuration(duration)?;
+            request.SetSample(&sample)
+        }
+        None => request.SetSample(None),
+    }
+}
+
+// Converts `AUDIO_GAP_TIMEOUT` to 100ns units.
+fn audio_lag() -> i64 {
+    i64::try_from(AUDIO_GAP_TIMEOUT.as_nanos() / 100).unwrap_or(0)
+}
+
+/// The `AudioSender` struct sends the samples of the audio track of a `VideoEncoder`, it can be
+/// cloned and moved to other threads.
+#[derive(Clone)]
+pub struct AudioSender {
+    queue: Arc<Mutex<AudioQueue>>,
+    timeline: Arc<Mutex<Timeline>>,
+    sample_rate: u32,
+    channels: u32,
+}
+
+impl AudioSender {
+    /// Sends audio samples to the encoder, they are interleaved with the video frames by their
+    /// timestamps.
+    ///
+    /// Samples sent before the first video frame or while the encoder is paused are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - The 16-bit PCM samples, interleaved for multiple channels.
+    /// * `timespan` - The timestamp of the first sample in 100ns units, on the same clock as the
+    ///   timespans of the frames.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the samples were sent or dropped, or `AudioSendError` if the encoder
+    /// is finished.
+    pub fn send_audio_sample(
+        &self,
+        samples: &[i16],
+        timespan: i64,
+    ) -> Result<(), VideoEncoderError> {
+        let Some(timespan) = self.timeline.lock().audio_timespan(timespan) else {
+            return Ok(());
+        };
+
+        let frames = samples.len() as u64 / u64::from(self.channels.max(1));
+        let duration = TimeSpan {
+            Duration: i64::try_from(frames * 10_000_000 / u64::from(self.sample_rate.max(1)))
+                .unwrap_or(i64::MAX),
+        };
+
+        let bytes = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+
+        {
+            let mut queue = self.queue.lock();
+            if queue.ended {
+                return Err(VideoEncoderError::AudioSendError);
+            }
+
+            queue.samples.push_back((bytes, timespan, duration));
+        }
+
+        AudioQueue::answer_pending(&self.queue, audio_lag())?;
+
+        Ok(())
+    }
+
+    /// Gets the sample rate of the audio track.
+    #[must_use]
+    pub const fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Gets the number of channels of the audio track.
+    #[must_use]
+    pub const fn channels(&self) -> u32 {
+        self.channels
+    }
+}
+
+/// The `VideoEncoder` struct represents a video encoder that can be used to encode video frames and save them to a specified file path.
+pub struct VideoEncoder {
+    timeline: Arc<Mutex<Timeline>>,
+    audio: Option<AudioChannel>,
+    session: Option<EncoderSession>,
+    pending: Option<(VideoSettingsBuilder, IRandomAccessStream)>,
+    output: IRandomAccessStream,
+    metadata: Vec<(String, String)>,
+    summary: EncodeSummary,
+    timestamp_log: Option<TimestampLog>,
+}
+
+impl VideoEncoder {
+    /// Creates a new `VideoEncoder` instance with the specified parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder_type` - The type of video encoder to use.
+    /// * `encoder_quality` - The quality of the video encoder.
+    /// * `width` - The width of the video frames.
+    /// * `height` - The height of the video frames.
+    /// * `path` - The file path where the encoded video will be saved.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the `VideoEncoder` instance if successful, or a
+    /// `VideoEncoderError` if an error occurs.
+    pub fn new<P: AsRef<Path>>(
+        encoder_type: VideoEncoderType,
+        encoder_quality: VideoEncoderQuality,
+        width: u32,
+        height: u32,
+        path: P,
+        fps: Option<u32>,
+    ) -> Result<Self, VideoEncoderError> {
+        let mut video_settings = VideoSettingsBuilder::new(width, height)
+            .encoder_type(encoder_type)
+            .encoder_quality(encoder_quality);
+        if let Some(fps) = fps {
+            video_settings = video_settings.fps(fps);
+        }
+
+        Self::new_with_settings(video_settings, path)
+    }
+
+    /// Creates a new `VideoEncoder` instance from video settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `video_settings` - The settings of the video stream.
+    /// * `path` - The file path where the encoded video will be saved.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the `VideoEncoder` instance if successful, or a
+    /// `VideoEncoderError` if an error occurs.
+    pub fn new_with_settings<P: AsRef<Path>>(
+        video_settings: VideoSettingsBuilder,
+        path: P,
+    ) -> Result<Self, VideoEncoderError> {
+        let media_stream_output = Self::open_file_stream(path.as_ref())?;
+
+        Self::from_settings(&video_settings, media_stream_output)
+    }
+
+    /// Creates a new `VideoEncoder` instance that takes the width and height from the first
+    /// frame sent with `send_frame` instead of guessing them up front.
+    ///
+    /// The encoder is started when the first frame arrives, so `input_format` returns `None`
+    /// until then and `send_frame_buffer` can't be used before the first `send_frame`.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder_type` - The type of video encoder to use.
+    /// * `encoder_quality` - The quality of the video encoder.
+    /// * `path` - The file path where the encoded video will be saved.
+    /// * `fps` - The frame rate of the video, or `None` to use the rate of the quality preset.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the `VideoEncoder` instance if successful, or a
+    /// `VideoEncoderError` if an error occurs.
+    pub fn new_auto<P: AsRef<Path>>(
+        encoder_type: VideoEncoderType,
+        encoder_quality: VideoEncoderQuality,
+        path: P,
+        fps: Option<u32>,
+    ) -> Result<Self, VideoEncoderError> {
+        let mut video_settings = VideoSettingsBuilder::new(0, 0)
+            .encoder_type(encoder_type)
+            .encoder_quality(encoder_quality);
+        if let Some(fps) = fps {
+            video_settings = video_settings.fps(fps);
+        }
+
+        let media_stream_output = Self::open_file_stream(path.as_ref())?;
+
+        Ok(Self {
+            timeline: Arc::new(Mutex::new(Timeline::default())),
+            audio: video_settings.audio.map(AudioChannel::new),
+            session: None,
+            metadata: video_settings.container_metadata(),
+            pending: Some((video_settings, media_stream_output.clone())),
+            output: media_stream_output,
+            summary: EncodeSummary::default(),
+            timestamp_log: None,
+        })
+    }
+
+    // Creates the file and opens it as a stream the transcoder can write to.
+    fn open_file_stream(path: &Path) -> Result<IRandomAccessStream, VideoEncoderError> {
+        File::create(path)?;
+        let path = fs::canonicalize(path).unwrap().to_string_lossy()[4..].to_string();
+        let path = Path::new(&path);
+
+        let path = &HSTRING::from(path.as_os_str().to_os_string());
+
+        let file = StorageFile::GetFileFromPathAsync(path)?.get()?;
+
+        Ok(file.OpenAsync(FileAccessMode::ReadWrite)?.get()?)
+    }
+
+    /// Creates a new `VideoEncoder` instance with the specified parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder_type` - The type of video encoder to use.
     /// * `encoder_quality` - The quality of the video encoder.
     /// * `width` - The width of the video frames.
     /// * `height` - The height of the video frames.
@@ -400,35 +1384,1477 @@ impl VideoEncoder {
     ///
     /// # Returns
     ///
-    /// Returns a `Result` containing the `VideoEncoder` instance if successful, or a
-    /// `VideoEncoderError` if an error occurs.
-    pub fn new_from_stream<P: AsRef<Path>>(
-        encoder_type: VideoEncoderType,
-        encoder_quality: VideoEncoderQuality,
-        width: u32,
-        height: u32,
-        stream: IRandomAccessStream,
-    ) -> Result<Self, VideoEncoderError> {
-        let media_encoding_profile = match encoder_type {
-            VideoEncoderType::Avi => {
-                MediaEncodingProfile::CreateAvi(VideoEncodingQuality(encoder_quality as i32))?
-            }
-            VideoEncoderType::Hevc => {
-                MediaEncodingProfile::CreateHevc(VideoEncodingQuality(encoder_quality as i32))?
-            }
-            VideoEncoderType::Mp4 => {
-                MediaEncodingProfile::CreateMp4(VideoEncodingQuality(encoder_quality as i32))?
-            }
-            VideoEncoderType::Wmv => {
-                MediaEncodingProfile::CreateWmv(VideoEncodingQuality(encoder_quality as i32))?
-            }
-            VideoEncoderType::Av1 => {
-                MediaEncodingProfile::CreateAv1(VideoEncodingQuality(encoder_quality as i32))?
-            }
-            VideoEncoderType::Vp9 => {
-                MediaEncodingProfile::CreateVp9(VideoEncodingQuality(encoder_quality as i32))?
+    /// Returns a `Result` containing the `VideoEncoder` instance if successful, or a
+    /// `VideoEncoderError` if an error occurs.
+    pub fn new_from_stream<P: AsRef<Path>>(
+        encoder_type: VideoEncoderType,
+        encoder_quality: VideoEncoderQuality,
+        width: u32,
+        height: u32,
+        stream: IRandomAccessStream,
+    ) -> Result<Self, VideoEncoderError> {
+        let video_settings = VideoSettingsBuilder::new(width, height)
+            .encoder_type(encoder_type)
+            .encoder_quality(encoder_quality);
+
+        Self::new_from_stream_with_settings(video_settings, stream)
+    }
+
+    /// Creates a new `VideoEncoder` instance from video settings that writes to a stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `video_settings` - The settings of the video stream.
+    /// * `stream` - The stream where the encoded video will be saved.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the `VideoEncoder` instance if successful, or a
+    /// `VideoEncoderError` if an error occurs.
+    pub fn new_from_stream_with_settings(
+        video_settings: VideoSettingsBuilder,
+        stream: IRandomAccessStream,
+    ) -> Result<Self, VideoEncoderError> {
+        Self::from_settings(&video_settings, stream)
+    }
+
+    // Creates an encoder that is started right away.
+    fn from_settings(
+        video_settings: &VideoSettingsBuilder,
+        stream: IRandomAccessStream,
+    ) -> Result<Self, VideoEncoderError> {
+        let audio = video_settings.audio.map(AudioChannel::new);
+
+        Ok(Self {
+            timeline: Arc::new(Mutex::new(Timeline::default())),
+            session: Some(EncoderSession::new(
+                video_settings,
+                &stream,
+                audio.as_ref(),
+            )?),
+            audio,
+            pending: None,
+            output: stream,
+            metadata: video_settings.container_metadata(),
+            summary: EncodeSummary::default(),
+            timestamp_log: None,
+        })
+    }
+
+    /// Encodes a sequence of frames into a video in one call, creating and finishing the encoder
+    /// internally.
+    ///
+    /// The video size is taken from the first frame and the frames are converted to `Bgra8`,
+    /// with `FrameTiming::FixedRate` the frame rate of the settings is replaced as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - The frames to encode, for example collected with `Frame::snapshot`.
+    /// * `video_settings` - The settings of the video stream.
+    /// * `timing` - Whether the frames are timed by their timestamps or at a fixed rate.
+    /// * `path` - The file path where the encoded video will be saved.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the video was written, or a `VideoEncoderError` if there are no
+    /// frames, a frame has another size than the first one or encoding failed.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use windows_capture::encoder::{FrameTiming, VideoEncoder, VideoSettingsBuilder};
+    /// # let frames = Vec::new();
+    ///
+    /// VideoEncoder::encode_frames(
+    ///     frames,
+    ///     VideoSettingsBuilder::new(0, 0),
+    ///     FrameTiming::FixedRate(30),
+    ///     "video.mp4",
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn encode_frames<I: IntoIterator<Item = FrameSnapshot>, P: AsRef<Path>>(
+        frames: I,
+        mut video_settings: VideoSettingsBuilder,
+        timing: FrameTiming,
+        path: P,
+    ) -> Result<(), VideoEncoderError> {
+        let mut frames = frames.into_iter().peekable();
+        let (width, height) = frames
+            .peek()
+            .map(|frame| (frame.width(), frame.height()))
+            .ok_or(VideoEncoderError::NoFrames)?;
+
+        video_settings.width = width;
+        video_settings.height = height;
+        if let FrameTiming::FixedRate(fps) = timing {
+            video_settings = video_settings.fps(fps.max(1));
+        }
+
+        let mut encoder = Self::new_with_settings(video_settings, path)?;
+
+        let mut buffer = Vec::with_capacity(width as usize * height as usize * 4);
+        for (index, frame) in frames.enumerate() {
+            if frame.width() != width || frame.height() != height {
+                return Err(VideoEncoderError::FrameSizeMismatch);
+            }
+
+            let frame = frame.to_color_format(ColorFormat::Bgra8)?;
+
+            // The encoder expects the rows from bottom to top
+            buffer.clear();
+            for row in frame.as_raw_buffer().chunks_exact(width as usize * 4).rev() {
+                buffer.extend_from_slice(row);
+            }
+
+            let timespan = match timing {
+                FrameTiming::Timestamps => frame.timespan().Duration,
+                FrameTiming::FixedRate(fps) => {
+                    i64::try_from(index).unwrap_or(i64::MAX) * 10_000_000 / i64::from(fps.max(1))
+                }
+            };
+
+            encoder.send_frame_buffer(&buffer, timespan)?;
+        }
+
+        encoder.finish()?;
+
+        Ok(())
+    }
+
+    /// Gets the frame layout the encoder expects as input.
+    ///
+    /// Frames sent with `send_frame` are converted by the encoder, buffers sent with
+    /// `send_frame_buffer` must match this layout exactly. The layout is fixed by the encoder,
+    /// the format of the codec is never exposed, see `InputFormatInfo`.
+    ///
+    /// # Returns
+    ///
+    /// The input format of the encoder, or `None` if the encoder was created with `new_auto` and
+    /// no frame was sent yet.
+    #[must_use]
+    pub fn input_format(&self) -> Option<&InputFormatInfo> {
+        self.session.as_ref().map(|session| &session.input_format)
+    }
+
+    /// Sends a video frame to the video encoder for encoding.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - A mutable reference to the `Frame` to be encoded.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the frame is successfully sent for encoding, or a `VideoEncoderError`
+    /// if an error occurs.
+    pub fn send_frame(&mut self, frame: &mut Frame) -> Result<(), VideoEncoderError> {
+        let timespan = frame.timespan().Duration;
+
+        self.send_frame_at(frame, timespan)
+    }
+
+    /// Sends a video frame to the video encoder with a timestamp other than the time the frame
+    /// was captured at, for example to speed up or slow down the video.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - A mutable reference to the `Frame` to be encoded.
+    /// * `timespan` - The timespan of the frame in 100ns units, the first frame sent starts the
+    ///   video.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the frame is successfully sent for encoding, or a `VideoEncoderError`
+    /// if an error occurs.
+    pub fn send_frame_at(
+        &mut self,
+        frame: &mut Frame,
+        timespan: i64,
+    ) -> Result<(), VideoEncoderError> {
+        if self.is_paused() {
+            return Ok(());
+        }
+
+        // Start the encoder created with new_auto with the size of the first frame
+        if let Some((mut video_settings, stream)) = self.pending.take() {
+            video_settings.width = frame.width();
+            video_settings.height = frame.height();
+
+            self.session = Some(EncoderSession::new(
+                &video_settings,
+                &stream,
+                self.audio.as_ref(),
+            )?);
+        }
+
+        let surface = SendDirectX::new(unsafe { frame.as_raw_surface() });
+
+        self.send_source(VideoEncoderSource::DirectX(surface), timespan)
+    }
+
+    /// Sends a video frame to the video encoder for encoding.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - A reference to the byte slice to be encoded Windows API expect this to be Bgra and bottom-top.
+    /// * `timespan` - The timespan that correlates to the frame buffer.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the frame is successfully sent for encoding, or a `VideoEncoderError`
+    /// if an error occurs.
+    pub fn send_frame_buffer(
+        &mut self,
+        buffer: &[u8],
+        timespan: i64,
+    ) -> Result<(), VideoEncoderError> {
+        if self.is_paused() {
+            return Ok(());
+        }
+
+        if self.session.is_none() {
+            return Err(VideoEncoderError::FrameSizeUnknown);
+        }
+
+        self.send_source(
+            VideoEncoderSource::Buffer((SendDirectX::new(buffer.as_ptr()), buffer.len())),
+            timespan,
+        )
+    }
+
+    /// Pauses writing to the video, frames sent while paused are discarded but the encoder keeps
+    /// the file open.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeline` - Whether the paused time is cut out of the video or stays in it as a gap.
+    pub fn pause(&mut self, timeline: PauseTimeline) {
+        let mut state = self.timeline.lock();
+        if state.paused.is_none() {
+            state.paused = Some((timeline, Instant::now()));
+        }
+    }
+
+    /// Resumes writing to the video after `pause`.
+    pub fn resume(&mut self) {
+        let mut state = self.timeline.lock();
+        if let Some((timeline, paused_at)) = state.paused.take() {
+            // Nothing to cut before the first frame, the video starts with the next frame
+            if timeline == PauseTimeline::Freeze && state.first_timespan.is_some() {
+                state.frozen_duration +=
+                    i64::try_from(paused_at.elapsed().as_nanos() / 100).unwrap_or(i64::MAX);
+            }
+        }
+    }
+
+    /// Checks if the encoder is paused.
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.timeline.lock().paused.is_some()
+    }
+
+    /// Get a sender for the samples of the audio track, it can be moved to the thread that
+    /// captures the audio while frames are sent on another thread.
+    ///
+    /// The transcoder takes the audio and video samples in the order of their timestamps. When
+    /// the audio falls more than 100ms behind the frames the gap up to the latest frame is filled
+    /// with silence, so a paused or missing audio source doesn't stall the video. Audio that
+    /// arrives after its gap was filled is cut where the silence ends.
+    ///
+    /// # Returns
+    ///
+    /// The sender, or `None` if the video settings have no audio track.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let encoder = VideoEncoder::new_with_settings(
+    ///     VideoSettingsBuilder::new(1920, 1080).audio(48000, 2),
+    ///     "video.mp4",
+    /// )?;
+    /// let audio_sender = encoder.audio_sender().unwrap();
+    ///
+    /// thread::spawn(move || {
+    ///     // Timestamps on the clock of Frame::timespan, the performance counter
+    ///     audio_sender.send_audio_sample(&samples, timespan)?;
+    /// });
+    /// ```
+    #[must_use]
+    pub fn audio_sender(&self) -> Option<AudioSender> {
+        self.audio.as_ref().map(|audio| AudioSender {
+            queue: audio.queue.clone(),
+            timeline: self.timeline.clone(),
+            sample_rate: audio.sample_rate,
+            channels: audio.channels,
+        })
+    }
+
+    /// Write a log next to the video that maps every written frame to its time in the video and
+    /// the wall clock time it was sent at, for example to line up log lines or sensor data with
+    /// the recording.
+    ///
+    /// Each entry has the index of the frame, its presentation time in the video and the time
+    /// it was sent to the encoder in milliseconds since the Unix epoch. The subtitle formats
+    /// show the index and the wall clock time as the text of the cue. The log is complete once
+    /// the encoder is finished.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file path of the log, for example `video.srt`.
+    /// * `format` - The format of the log.
+    ///
+    /// # Returns
+    ///
+    /// Returns the encoder writing the log, or a `VideoEncoderError` if the file can't be created.
+    pub fn timestamp_log<P: AsRef<Path>>(
+        mut self,
+        path: P,
+        format: TimestampLogFormat,
+    ) -> Result<Self, VideoEncoderError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        match format {
+            TimestampLogFormat::Srt => (),
+            TimestampLogFormat::WebVtt => writeln!(writer, "WEBVTT\n")?,
+            TimestampLogFormat::Csv => writeln!(writer, "frame,pts_ms,wall_clock_ms")?,
+        }
+
+        self.timestamp_log = Some(TimestampLog {
+            writer,
+            format,
+            pending: None,
+            // A single frame is shown as long as a frame at 30 fps
+            last_duration: 333_333,
+        });
+
+        Ok(self)
+    }
+
+    /// Finishes encoding the video and performs any necessary cleanup.
+    ///
+    /// # Returns
+    ///
+    /// Returns the `EncodeSummary` of the written video if the encoding is successfully
+    /// finished, or a `VideoEncoderError` if an error occurs. A failure to add the metadata tags
+    /// is reported by `EncodeSummary::metadata_failed`.
+    pub fn finish(mut self) -> Result<EncodeSummary, VideoEncoderError> {
+        if let Some(timestamp_log) = &mut self.timestamp_log {
+            timestamp_log.finish()?;
+        }
+
+        // An encoder created with new_auto that never got a frame has nothing to finish
+        let Some(session) = &mut self.session else {
+            return Ok(self.summary);
+        };
+
+        session.finish()?;
+
+        if !self.metadata.is_empty() {
+            self.summary.metadata_failed =
+                write_mp4_metadata(&self.output, &self.metadata).is_err();
+        }
+
+        Ok(self.summary)
+    }
+
+    /// Finishes encoding the video like `finish`, but gives up if the encoder doesn't finish
+    /// within the timeout, for example because a hardware encoder stopped responding.
+    ///
+    /// After a timeout the encoder is abandoned on its thread, the output is left as it is and
+    /// the metadata is not written.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The longest time to wait for the encoder to finish.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(FinishOutcome::Finished)` if the video was finalized,
+    /// `Ok(FinishOutcome::TimedOut)` if the timeout elapsed first, or a `VideoEncoderError` if an
+    /// error occurs.
+    pub fn finish_timeout(mut self, timeout: Duration) -> Result<FinishOutcome, VideoEncoderError> {
+        if let Some(timestamp_log) = &mut self.timestamp_log {
+            timestamp_log.finish()?;
+        }
+
+        let Some(session) = &mut self.session else {
+            return Ok(FinishOutcome::Finished);
+        };
+
+        if !session.finish_timeout(timeout)? {
+            return Ok(FinishOutcome::TimedOut);
+        }
+
+        if !self.metadata.is_empty() {
+            write_mp4_metadata(&self.output, &self.metadata)?;
+        }
+
+        Ok(FinishOutcome::Finished)
+    }
+
+    // Converts a frame timespan to the time in the video, the first frame starts the video.
+    fn video_timespan(&mut self, frame_timespan: i64) -> TimeSpan {
+        let mut state = self.timeline.lock();
+        let first_timespan = *state.first_timespan.get_or_insert(TimeSpan {
+            Duration: frame_timespan,
+        });
+
+        TimeSpan {
+            Duration: frame_timespan - first_timespan.Duration - state.frozen_duration,
+        }
+    }
+
+    // Sends a frame to the session at its time in the video and counts it for the summary.
+    fn send_source(
+        &mut self,
+        source: VideoEncoderSource,
+        frame_timespan: i64,
+    ) -> Result<(), VideoEncoderError> {
+        let timespan = self.video_timespan(frame_timespan);
+        self.session_mut()?.send(source, timespan)?;
+
+        if let Some(timestamp_log) = &mut self.timestamp_log {
+            timestamp_log.write(self.summary.frame_count, timespan.Duration)?;
+        }
+
+        self.summary.frame_count += 1;
+        self.summary.duration = self.summary.duration.max(Duration::from_nanos(
+            u64::try_from(timespan.Duration)
+                .unwrap_or(0)
+                .saturating_mul(100),
+        ));
+
+        Ok(())
+    }
+
+    // Gets the started session, fails if the size of the video isn't known yet.
+    fn session_mut(&mut self) -> Result<&mut EncoderSession, VideoEncoderError> {
+        self.session
+            .as_mut()
+            .ok_or(VideoEncoderError::FrameSizeUnknown)
+    }
+}
+
+/// The `MultiVideoEncoder` struct encodes the frames of one capture into several renditions at
+/// once, for example 1080p, 720p and 480p videos for adaptive streaming.
+///
+/// Every rendition has its own settings and file, the frames are scaled to the size of each
+/// rendition on the GPU. The renditions are started when the first frame arrives, the size of
+/// the frames is taken from it.
+///
+/// The frames are stretched to the size of a rendition, give every rendition the aspect ratio
+/// of the capture to keep it.
+///
+/// # Example
+/// ```ignore
+/// let mut encoder = MultiVideoEncoder::new(vec![
+///     (VideoSettingsBuilder::new(1920, 1080).bitrate(8_000_000), "1080p.mp4"),
+///     (VideoSettingsBuilder::new(1280, 720).bitrate(4_000_000), "720p.mp4"),
+///     (VideoSettingsBuilder::new(854, 480).bitrate(1_500_000), "480p.mp4"),
+/// ])?;
+///
+/// // In on_frame_arrived
+/// encoder.send_frame(frame)?;
+/// ```
+pub struct MultiVideoEncoder {
+    pending: Vec<(VideoSettingsBuilder, IRandomAccessStream, PathBuf)>,
+    encoders: Vec<VideoEncoder>,
+}
+
+impl MultiVideoEncoder {
+    /// Creates a new `MultiVideoEncoder` instance, the files of the renditions are created right
+    /// away and removed by `finish` if no frame arrived.
+    ///
+    /// # Arguments
+    ///
+    /// * `renditions` - The settings of every rendition with the file path where it will be
+    ///   saved.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the `MultiVideoEncoder` instance if successful, or a
+    /// `VideoEncoderError` if an error occurs.
+    pub fn new<P: AsRef<Path>>(
+        renditions: Vec<(VideoSettingsBuilder, P)>,
+    ) -> Result<Self, VideoEncoderError> {
+        let pending = renditions
+            .into_iter()
+            .map(|(video_settings, path)| {
+                Ok((
+                    video_settings,
+                    VideoEncoder::open_file_stream(path.as_ref())?,
+                    path.as_ref().to_path_buf(),
+                ))
+            })
+            .collect::<Result<Vec<_>, VideoEncoderError>>()?;
+
+        Ok(Self {
+            pending,
+            encoders: Vec::new(),
+        })
+    }
+
+    /// Get the number of renditions.
+    #[must_use]
+    pub fn rendition_count(&self) -> usize {
+        self.pending.len() + self.encoders.len()
+    }
+
+    /// Sends a video frame to the encoder of every rendition.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - A mutable reference to the `Frame` to be encoded.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the frame is successfully sent to every rendition, or a
+    /// `VideoEncoderError` if an error occurs.
+    pub fn send_frame(&mut self, frame: &mut Frame) -> Result<(), VideoEncoderError> {
+        // Start the renditions with the size of the first frame as the input size
+        for (video_settings, stream, _) in self.pending.drain(..) {
+            let video_settings = video_settings.input_size(frame.width(), frame.height());
+
+            self.encoders
+                .push(VideoEncoder::from_settings(&video_settings, stream)?);
+        }
+
+        for encoder in &mut self.encoders {
+            encoder.send_frame(frame)?;
+        }
+
+        Ok(())
+    }
+
+    /// Finishes encoding every rendition.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if every rendition is successfully finished, otherwise the first
+    /// `VideoEncoderError`.
+    pub fn finish(self) -> Result<(), VideoEncoderError> {
+        let mut result = Ok(());
+
+        // Without a frame the renditions were never started and their files are empty
+        for (_, stream, path) in self.pending {
+            drop(stream);
+
+            if let Err(e) = fs::remove_file(path) {
+                if result.is_ok() {
+                    result = Err(e.into());
+                }
+            }
+        }
+
+        for encoder in self.encoders {
+            if let Err(e) = encoder.finish() {
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// The `SegmentedVideoEncoder` struct records into a series of files, a new file is started
+/// whenever the current one reaches the segment duration.
+///
+/// The segments are saved next to the path with the segment index appended to the file name,
+/// `video.mp4` becomes `video_0000.mp4`, `video_0001.mp4` and so on. Every segment starts with
+/// a key frame so each file plays on its own.
+///
+/// # Example
+/// ```ignore
+/// let encoder = SegmentedVideoEncoder::new(
+///     VideoSettingsBuilder::new(1920, 1080),
+///     "video.mp4",
+///     Duration::from_secs(600),
+/// )
+/// // Lower the bitrate of every other segment
+/// .on_new_segment(|index| {
+///     VideoSettingsBuilder::new(1920, 1080).bitrate(if index % 2 == 0 { 8_000_000 } else { 4_000_000 })
+/// });
+/// ```
+pub struct SegmentedVideoEncoder {
+    video_settings: VideoSettingsBuilder,
+    path: PathBuf,
+    segment_duration: Duration,
+    new_segment: Option<Box<dyn FnMut(usize) -> VideoSettingsBuilder + Send>>,
+    encoder: Option<VideoEncoder>,
+    segment_start: Option<TimeSpan>,
+    segment_index: usize,
+    max_segments: Option<usize>,
+}
+
+impl SegmentedVideoEncoder {
+    /// Creates a new `SegmentedVideoEncoder`, the first segment is started by the first frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `video_settings` - The settings of the video stream of every segment.
+    /// * `path` - The file path the segment paths are derived from.
+    /// * `segment_duration` - The duration of every segment, measured with the frame timestamps.
+    ///
+    /// # Returns
+    ///
+    /// The new `SegmentedVideoEncoder`.
+    #[must_use]
+    pub fn new<P: AsRef<Path>>(
+        video_settings: VideoSettingsBuilder,
+        path: P,
+        segment_duration: Duration,
+    ) -> Self {
+        Self {
+            video_settings,
+            path: path.as_ref().to_path_buf(),
+            segment_duration,
+            new_segment: None,
+            encoder: None,
+            segment_start: None,
+            segment_index: 0,
+            max_segments: None,
+        }
+    }
+
+    /// Choose the video settings of every segment, for example to lower the bitrate at night.
+    ///
+    /// # Arguments
+    ///
+    /// * `new_segment` - Called with the index of the segment before it's started, returns the
+    ///   settings of the segment instead of the settings given to `new`.
+    #[must_use]
+    pub fn on_new_segment<F: FnMut(usize) -> VideoSettingsBuilder + Send + 'static>(
+        mut self,
+        new_segment: F,
+    ) -> Self {
+        self.new_segment = Some(Box::new(new_segment));
+        self
+    }
+
+    /// Keep only the most recent segments on disk like a dashcam, the oldest finished segment is
+    /// deleted when finishing a segment exceeds the limit.
+    ///
+    /// The segment that is recorded now isn't counted, so up to `max_segments + 1` files exist
+    /// at a time. Segments that were already moved or deleted are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_segments` - The number of finished segments to keep, at least one is kept.
+    #[must_use]
+    pub fn ring(mut self, max_segments: usize) -> Self {
+        self.max_segments = Some(max_segments.max(1));
+        self
+    }
+
+    /// Get the index of the segment that is recorded now.
+    #[must_use]
+    pub const fn segment_index(&self) -> usize {
+        self.segment_index
+    }
+
+    /// Get the path of a segment.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the segment.
+    ///
+    /// # Returns
+    ///
+    /// The path with the index appended to the file name.
+    #[must_use]
+    pub fn segment_path(&self, index: usize) -> PathBuf {
+        let stem = self
+            .path
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+
+        let mut file_name = format!("{stem}_{index:04}");
+        if let Some(extension) = self.path.extension() {
+            file_name.push('.');
+            file_name.push_str(&extension.to_string_lossy());
+        }
+
+        self.path.with_file_name(file_name)
+    }
+
+    /// Sends a frame to the current segment, the segment is finished and a new one is started
+    /// first when the segment duration is reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The frame to encode.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the frame was sent, or a `VideoEncoderError` if starting or finishing
+    /// a segment failed.
+    pub fn send_frame(&mut self, frame: &mut Frame) -> Result<(), VideoEncoderError> {
+        let timespan = frame.timespan();
+
+        if let Some(segment_start) = self.segment_start {
+            let elapsed = u64::try_from(timespan.Duration - segment_start.Duration).unwrap_or(0);
+            if Duration::from_nanos(elapsed * 100) >= self.segment_duration {
+                self.finish_segment()?;
+            }
+        }
+
+        if self.encoder.is_none() {
+            let video_settings = match &mut self.new_segment {
+                Some(new_segment) => new_segment(self.segment_index),
+                None => self.video_settings.clone(),
+            };
+
+            let path = self.segment_path(self.segment_index);
+            self.encoder = Some(VideoEncoder::new_with_settings(video_settings, path)?);
+            self.segment_start = Some(timespan);
+        }
+
+        self.encoder.as_mut().unwrap().send_frame(frame)
+    }
+
+    /// Finishes the current segment, the next frame starts a new segment.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the segment was finished or no segment was started, or a
+    /// `VideoEncoderError` if an error occurs.
+    pub fn finish_segment(&mut self) -> Result<(), VideoEncoderError> {
+        let Some(encoder) = self.encoder.take() else {
+            return Ok(());
+        };
+
+        self.segment_start = None;
+        self.segment_index += 1;
+
+        encoder.finish()?;
+
+        // Every finished segment pushes out at most one old segment
+        if let Some(max_segments) = self.max_segments {
+            if self.segment_index > max_segments {
+                let oldest = self.segment_path(self.segment_index - max_segments - 1);
+
+                match fs::remove_file(oldest) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                    _ => (),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finishes the current segment.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the encoding is successfully finished, or a `VideoEncoderError` if an
+    /// error occurs.
+    pub fn finish(mut self) -> Result<(), VideoEncoderError> {
+        self.finish_segment()
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RawVideoEncoderError {
+    #[error("This color format is not supported for raw video")]
+    UnsupportedFormat,
+    #[error("The buffer is smaller than the frame size")]
+    InvalidSize,
+    #[error("Failed to read the frame buffer: {0}")]
+    FrameError(#[from] crate::frame::Error),
+    #[error("IO Error: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+/// The plane layout of the frames written by `RawVideoEncoder`.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum RawVideoFormat {
+    /// A luma plane followed by one plane of interleaved U and V samples.
+    Nv12,
+    /// A luma plane followed by a U plane and a V plane, also known as YUV420p.
+    I420,
+}
+
+/// The `RawVideoEncoder` struct writes frames as raw YUV 4:2:0 planes with no container, the
+/// format most encoder comparison tools read.
+///
+/// The frames are converted with BT.709 limited range coefficients, chroma is the average of
+/// each 2x2 block. Every frame is written back to back, so a sidecar file at the path with
+/// `.txt` appended gets one `width height` line per frame.
+///
+/// # Example
+/// ```ignore
+/// let mut encoder = RawVideoEncoder::new(RawVideoFormat::Nv12, "video.yuv")?;
+///
+/// // Inside on_frame_arrived
+/// encoder.send_frame(frame)?;
+///
+/// // After the capture stopped
+/// encoder.finish()?;
+/// ```
+pub struct RawVideoEncoder {
+    format: RawVideoFormat,
+    file: BufWriter<File>,
+    sidecar: BufWriter<File>,
+    planes: Vec<u8>,
+}
+
+impl RawVideoEncoder {
+    /// Creates a new `RawVideoEncoder` writing to the file at the path.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The plane layout of the written frames.
+    /// * `path` - The file path where the raw video will be saved.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the `RawVideoEncoder` instance if successful, or a
+    /// `RawVideoEncoderError` if the files can't be created.
+    pub fn new<P: AsRef<Path>>(
+        format: RawVideoFormat,
+        path: P,
+    ) -> Result<Self, RawVideoEncoderError> {
+        let path = path.as_ref();
+
+        let mut sidecar_path = path.as_os_str().to_owned();
+        sidecar_path.push(".txt");
+
+        Ok(Self {
+            format,
+            file: BufWriter::new(File::create(path)?),
+            sidecar: BufWriter::new(File::create(sidecar_path)?),
+            planes: Vec::new(),
+        })
+    }
+
+    /// Converts the frame and appends it to the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The frame to write, must be `ColorFormat::Rgba8` or `ColorFormat::Bgra8`.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the frame was written, or a `RawVideoEncoderError` otherwise.
+    pub fn send_frame(&mut self, frame: &mut Frame) -> Result<(), RawVideoEncoderError> {
+        let width = frame.width();
+        let height = frame.height();
+        let color_format = frame.color_format();
+
+        let mut buffer = frame.buffer()?;
+        self.send_frame_buffer(
+            buffer.as_raw_nopadding_buffer()?,
+            width,
+            height,
+            color_format,
+        )
+    }
+
+    /// Converts a frame buffer and appends it to the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The pixels of the frame, rows have no padding.
+    /// * `width` - The width of the frame.
+    /// * `height` - The height of the frame.
+    /// * `color_format` - The color format of the buffer, must be `ColorFormat::Rgba8` or
+    ///   `ColorFormat::Bgra8`.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the frame was written, or a `RawVideoEncoderError` otherwise.
+    pub fn send_frame_buffer(
+        &mut self,
+        buffer: &[u8],
+        width: u32,
+        height: u32,
+        color_format: ColorFormat,
+    ) -> Result<(), RawVideoEncoderError> {
+        let (red, blue) = match color_format {
+            ColorFormat::Rgba8 => (0, 2),
+            ColorFormat::Bgra8 => (2, 0),
+            ColorFormat::Rgba16F => return Err(RawVideoEncoderError::UnsupportedFormat),
+        };
+
+        let width = width as usize;
+        let height = height as usize;
+        if buffer.len() < width * height * 4 {
+            return Err(RawVideoEncoderError::InvalidSize);
+        }
+
+        let chroma_width = width.div_ceil(2);
+        let chroma_height = height.div_ceil(2);
+        let luma_size = width * height;
+        let chroma_size = chroma_width * chroma_height;
+
+        self.planes.clear();
+        self.planes.resize(luma_size + chroma_size * 2, 0);
+        let (luma, chroma) = self.planes.split_at_mut(luma_size);
+
+        let pixel = |x: usize, y: usize| {
+            let index = (y * width + x) * 4;
+            (
+                i32::from(buffer[index + red]),
+                i32::from(buffer[index + 1]),
+                i32::from(buffer[index + blue]),
+            )
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                let (r, g, b) = pixel(x, y);
+                luma[y * width + x] = (((47 * r + 157 * g + 16 * b + 128) >> 8) + 16) as u8;
+            }
+        }
+
+        for y in 0..chroma_height {
+            for x in 0..chroma_width {
+                // Average the 2x2 block, edge blocks of odd sizes repeat the last pixel
+                let (mut r, mut g, mut b) = (0, 0, 0);
+                for (block_x, block_y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let (pixel_r, pixel_g, pixel_b) = pixel(
+                        (x * 2 + block_x).min(width - 1),
+                        (y * 2 + block_y).min(height - 1),
+                    );
+                    r += pixel_r;
+                    g += pixel_g;
+                    b += pixel_b;
+                }
+                let (r, g, b) = (r / 4, g / 4, b / 4);
+
+                let u = (((-26 * r - 87 * g + 112 * b + 128) >> 8) + 128) as u8;
+                let v = (((112 * r - 102 * g - 10 * b + 128) >> 8) + 128) as u8;
+
+                let index = y * chroma_width + x;
+                match self.format {
+                    RawVideoFormat::Nv12 => {
+                        chroma[index * 2] = u;
+                        chroma[index * 2 + 1] = v;
+                    }
+                    RawVideoFormat::I420 => {
+                        chroma[index] = u;
+                        chroma[chroma_size + index] = v;
+                    }
+                }
+            }
+        }
+
+        self.file.write_all(&self.planes)?;
+        writeln!(self.sidecar, "{width} {height}")?;
+
+        Ok(())
+    }
+
+    /// Flushes the raw video and the sidecar file.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the files were flushed, or a `RawVideoEncoderError` otherwise.
+    pub fn finish(mut self) -> Result<(), RawVideoEncoderError> {
+        self.file.flush()?;
+        self.sidecar.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "webp")]
+#[derive(thiserror::Error, Debug)]
+pub enum WebPAnimEncoderError {
+    #[error("This color format is not supported for WebP")]
+    UnsupportedFormat,
+    #[error("The buffer is smaller than the frame size or the frame size doesn't match")]
+    InvalidSize,
+    #[error("The animation has no frames")]
+    NoFrames,
+    #[error("libwebp failed to encode the animation: {0}")]
+    EncodeFailed(String),
+    #[error("Failed to read the frame buffer: {0}")]
+    FrameError(#[from] crate::frame::Error),
+    #[error("IO Error: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+/// The `WebPAnimEncoder` struct encodes frames into an animated WebP with libwebp, every frame
+/// is shown for its own duration and the alpha channel is kept.
+///
+/// WebP stores straight alpha, capture transparent content with `AlphaModeSettings::Straight`
+/// so the colors of translucent pixels are correct. Requires the `webp` feature.
+///
+/// # Example
+/// ```ignore
+/// let mut encoder = WebPAnimEncoder::new(640, 360)?.quality(80.0);
+///
+/// // Inside on_frame_arrived
+/// encoder.send_frame(frame, Duration::from_millis(100))?;
+///
+/// // After the capture stopped
+/// encoder.save("clip.webp")?;
+/// ```
+#[cfg(feature = "webp")]
+pub struct WebPAnimEncoder {
+    encoder: Option<NonNull<libwebp_sys::WebPAnimEncoder>>,
+    options: WebPAnimEncoderOptions,
+    config: WebPConfig,
+    width: u32,
+    height: u32,
+    timestamp: Duration,
+}
+
+// The libwebp encoder isn't tied to the thread that created it
+#[cfg(feature = "webp")]
+unsafe impl Send for WebPAnimEncoder {}
+
+#[cfg(feature = "webp")]
+impl WebPAnimEncoder {
+    /// Creates a new `WebPAnimEncoder` for frames of the size, lossy with quality 75 and looping
+    /// forever.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the frames.
+    /// * `height` - The height of the frames.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the `WebPAnimEncoder` instance if successful, or a
+    /// `WebPAnimEncoderError` if the size is invalid or libwebp doesn't match its headers.
+    pub fn new(width: u32, height: u32) -> Result<Self, WebPAnimEncoderError> {
+        // WebP frames are at most 16383 pixels wide and high
+        if width == 0 || height == 0 || width > 16383 || height > 16383 {
+            return Err(WebPAnimEncoderError::InvalidSize);
+        }
+
+        let mut options = unsafe { mem::zeroed::<WebPAnimEncoderOptions>() };
+        let mut config = unsafe { mem::zeroed::<WebPConfig>() };
+        if unsafe {
+            WebPAnimEncoderOptionsInit(&mut options) == 0 || WebPConfigInit(&mut config) == 0
+        } {
+            return Err(WebPAnimEncoderError::EncodeFailed(String::from(
+                "libwebp version mismatch",
+            )));
+        }
+
+        Ok(Self {
+            encoder: None,
+            options,
+            config,
+            width,
+            height,
+            timestamp: Duration::ZERO,
+        })
+    }
+
+    /// Set the quality of lossy frames, or how hard lossless frames are compressed.
+    ///
+    /// # Arguments
+    ///
+    /// * `quality` - The quality from 0 to 100, values outside are clamped.
+    #[must_use]
+    pub fn quality(mut self, quality: f32) -> Self {
+        self.config.quality = quality.clamp(0.0, 100.0);
+        self
+    }
+
+    /// Encode the frames losslessly, larger but exact, for example for clips of text.
+    ///
+    /// # Arguments
+    ///
+    /// * `lossless` - Whether to encode the frames losslessly.
+    #[must_use]
+    pub fn lossless(mut self, lossless: bool) -> Self {
+        self.config.lossless = i32::from(lossless);
+        self
+    }
+
+    /// Set how often the animation is played.
+    ///
+    /// # Arguments
+    ///
+    /// * `loop_count` - The number of times the animation is played, 0 loops forever.
+    #[must_use]
+    pub fn loop_count(mut self, loop_count: u32) -> Self {
+        self.options.anim_params.loop_count = i32::try_from(loop_count).unwrap_or(i32::MAX);
+        self
+    }
+
+    /// Reads the frame buffer and adds it to the animation.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The frame to add, must have the size of the encoder and be
+    ///   `ColorFormat::Rgba8` or `ColorFormat::Bgra8`.
+    /// * `duration` - How long the frame is shown.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the frame was added, or a `WebPAnimEncoderError` otherwise.
+    pub fn send_frame(
+        &mut self,
+        frame: &mut Frame,
+        duration: Duration,
+    ) -> Result<(), WebPAnimEncoderError> {
+        if frame.width() != self.width || frame.height() != self.height {
+            return Err(WebPAnimEncoderError::InvalidSize);
+        }
+
+        let color_format = frame.color_format();
+        let mut buffer = frame.buffer()?;
+        self.send_frame_buffer(buffer.as_raw_nopadding_buffer()?, color_format, duration)
+    }
+
+    /// Adds a frame buffer to the animation.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The pixels of the frame with the size of the encoder, rows have no padding.
+    /// * `color_format` - The color format of the buffer, must be `ColorFormat::Rgba8` or
+    ///   `ColorFormat::Bgra8`.
+    /// * `duration` - How long the frame is shown.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the frame was added, or a `WebPAnimEncoderError` otherwise.
+    pub fn send_frame_buffer(
+        &mut self,
+        buffer: &[u8],
+        color_format: ColorFormat,
+        duration: Duration,
+    ) -> Result<(), WebPAnimEncoderError> {
+        let import = match color_format {
+            ColorFormat::Rgba8 => WebPPictureImportRGBA,
+            ColorFormat::Bgra8 => WebPPictureImportBGRA,
+            ColorFormat::Rgba16F => return Err(WebPAnimEncoderError::UnsupportedFormat),
+        };
+
+        if buffer.len() < self.width as usize * self.height as usize * 4 {
+            return Err(WebPAnimEncoderError::InvalidSize);
+        }
+
+        let encoder = self.encoder()?;
+
+        let mut picture = unsafe { mem::zeroed::<WebPPicture>() };
+        if unsafe { WebPPictureInit(&mut picture) } == 0 {
+            return Err(WebPAnimEncoderError::EncodeFailed(String::from(
+                "libwebp version mismatch",
+            )));
+        }
+
+        // The size is checked in new so it fits
+        picture.use_argb = 1;
+        picture.width = self.width as i32;
+        picture.height = self.height as i32;
+
+        let added = unsafe {
+            let added = import(&mut picture, buffer.as_ptr(), self.width as i32 * 4) != 0
+                && WebPAnimEncoderAdd(
+                    encoder.as_ptr(),
+                    &mut picture,
+                    timestamp_ms(self.timestamp),
+                    &self.config,
+                ) != 0;
+            WebPPictureFree(&mut picture);
+
+            added
+        };
+
+        if !added {
+            return Err(Self::error(encoder));
+        }
+
+        self.timestamp += duration;
+
+        Ok(())
+    }
+
+    /// Finishes the animation, the last frame is shown for its duration.
+    ///
+    /// # Returns
+    ///
+    /// Returns the bytes of the animated WebP, or a `WebPAnimEncoderError` if no frame was added
+    /// or the animation can't be assembled.
+    pub fn finish(self) -> Result<Vec<u8>, WebPAnimEncoderError> {
+        let Some(encoder) = self.encoder else {
+            return Err(WebPAnimEncoderError::NoFrames);
+        };
+
+        // A frame without a picture ends the last frame
+        let mut data = unsafe { mem::zeroed::<WebPData>() };
+        let assembled = unsafe {
+            WebPAnimEncoderAdd(
+                encoder.as_ptr(),
+                ptr::null_mut(),
+                timestamp_ms(self.timestamp),
+                ptr::null(),
+            ) != 0
+                && WebPAnimEncoderAssemble(encoder.as_ptr(), &mut data) != 0
+        };
+
+        if !assembled {
+            return Err(Self::error(encoder));
+        }
+
+        let bytes = unsafe {
+            let bytes = slice::from_raw_parts(data.bytes, data.size).to_vec();
+            WebPFree(data.bytes.cast_mut().cast());
+
+            bytes
+        };
+
+        Ok(bytes)
+    }
+
+    /// Finishes the animation and writes it to the file at the path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file path where the animated WebP will be saved.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the animation was written, or a `WebPAnimEncoderError` otherwise.
+    pub fn save<P: AsRef<Path>>(self, path: P) -> Result<(), WebPAnimEncoderError> {
+        let bytes = self.finish()?;
+        fs::write(path, bytes)?;
+
+        Ok(())
+    }
+
+    // Get the libwebp encoder, it's created with the first frame so the options can still be
+    // changed before.
+    fn encoder(&mut self) -> Result<NonNull<libwebp_sys::WebPAnimEncoder>, WebPAnimEncoderError> {
+        if let Some(encoder) = self.encoder {
+            return Ok(encoder);
+        }
+
+        let encoder =
+            unsafe { WebPAnimEncoderNew(self.width as i32, self.height as i32, &self.options) };
+        let encoder = NonNull::new(encoder).ok_or_else(|| {
+            WebPAnimEncoderError::EncodeFailed(String::from("failed to create the encoder"))
+        })?;
+        self.encoder = Some(encoder);
+
+        Ok(encoder)
+    }
+
+    // Get the error message of the last failed libwebp call.
+    fn error(encoder: NonNull<libwebp_sys::WebPAnimEncoder>) -> WebPAnimEncoderError {
+        let message = unsafe { WebPAnimEncoderGetError(encoder.as_ptr()) };
+        let message = if message.is_null() {
+            String::from("unknown error")
+        } else {
+            unsafe { CStr::from_ptr(message) }
+                .to_string_lossy()
+                .into_owned()
+        };
+
+        WebPAnimEncoderError::EncodeFailed(message)
+    }
+}
+
+#[cfg(feature = "webp")]
+impl Drop for WebPAnimEncoder {
+    fn drop(&mut self) {
+        if let Some(encoder) = self.encoder {
+            unsafe { WebPAnimEncoderDelete(encoder.as_ptr()) };
+        }
+    }
+}
+
+// Convert the start of a WebP frame to the millisecond timestamp libwebp expects.
+#[cfg(feature = "webp")]
+fn timestamp_ms(timestamp: Duration) -> i32 {
+    i32::try_from(timestamp.as_millis()).unwrap_or(i32::MAX)
+}
+
+// Activate the preferred hardware encoder for the subtype and find the largest frame size at 30
+// frames per second and the highest frame rate at that size it accepts as output type.
+fn probe_hardware_encoder(
+    subtype: GUID,
+) -> Result<Option<((u32, u32), u32)>, windows::core::Error> {
+    let output_type = MFT_REGISTER_TYPE_INFO {
+        guidMajorType: MFMediaType_Video,
+        guidSubtype: subtype,
+    };
+
+    let mut activates = ptr::null_mut();
+    let mut count = 0;
+    unsafe {
+        MFTEnumEx(
+            MFT_CATEGORY_VIDEO_ENCODER,
+            MFT_ENUM_FLAG_HARDWARE | MFT_ENUM_FLAG_SORTANDFILTER,
+            None,
+            Some(&output_type),
+            &mut activates,
+            &mut count,
+        )?;
+    };
+
+    if activates.is_null() {
+        return Ok(None);
+    }
+
+    // Take the activation objects out of the array so they're released, the array itself is
+    // freed with CoTaskMemFree
+    let activates = unsafe {
+        let taken = slice::from_raw_parts_mut(activates, count as usize)
+            .iter_mut()
+            .map(Option::take)
+            .collect::<Vec<Option<IMFActivate>>>();
+        CoTaskMemFree(Some(activates.cast_const().cast::<c_void>()));
+
+        taken
+    };
+
+    let Some(activate) = activates.into_iter().flatten().next() else {
+        return Ok(None);
+    };
+
+    let transform = unsafe { activate.ActivateObject::<IMFTransform>()? };
+
+    // Hardware encoders are asynchronous and must be unlocked before they are configured
+    unsafe {
+        transform
+            .GetAttributes()?
+            .SetUINT32(&MF_TRANSFORM_ASYNC_UNLOCK, 1)?;
+    };
+
+    let accepts = |(width, height): (u32, u32), fps: u32| -> Result<bool, windows::core::Error> {
+        unsafe {
+            let media_type = MFCreateMediaType()?;
+            media_type.SetGUID(&MF_MT_MAJOR_TYPE, &MFMediaType_Video)?;
+            media_type.SetGUID(&MF_MT_SUBTYPE, &subtype)?;
+            media_type.SetUINT64(
+                &MF_MT_FRAME_SIZE,
+                (u64::from(width) << 32) | u64::from(height),
+            )?;
+            media_type.SetUINT64(&MF_MT_FRAME_RATE, (u64::from(fps) << 32) | 1)?;
+            media_type.SetUINT32(&MF_MT_INTERLACE_MODE, MFVideoInterlace_Progressive.0 as u32)?;
+            media_type.SetUINT32(&MF_MT_AVG_BITRATE, 20_000_000)?;
+
+            Ok(transform
+                .SetOutputType(0, &media_type, MFT_SET_TYPE_TEST_ONLY.0 as u32)
+                .is_ok())
+        }
+    };
+
+    let mut limits = None;
+    for resolution in PROBE_RESOLUTIONS {
+        if accepts(resolution, 30)? {
+            let mut max_fps = 30;
+            for fps in PROBE_FRAME_RATES {
+                if accepts(resolution, fps)? {
+                    max_fps = fps;
+                    break;
+                }
             }
+
+            limits = Some((resolution, max_fps));
+            break;
+        }
+    }
+
+    unsafe {
+        let _ = activate.ShutdownObject();
+    };
+
+    Ok(limits)
+}
+
+// Writes the entries of `VideoEncoder::timestamp_log`, a subtitle cue is written once the next
+// frame tells when it ends.
+struct TimestampLog {
+    writer: BufWriter<File>,
+    format: TimestampLogFormat,
+    // The index, the presentation time in 100ns units and the wall clock time in milliseconds of
+    // the last frame
+    pending: Option<(u64, i64, u128)>,
+    // The duration of the last cue in 100ns units, the duration of the final cue
+    last_duration: i64,
+}
+
+impl TimestampLog {
+    // Log a frame that was sent to the encoder now.
+    fn write(&mut self, index: u64, pts: i64) -> io::Result<()> {
+        let wall_clock = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+
+        if self.format == TimestampLogFormat::Csv {
+            return writeln!(self.writer, "{index},{},{wall_clock}", pts / 10_000);
+        }
+
+        if let Some((index, start, wall_clock)) = self.pending.take() {
+            self.write_cue(index, start, pts, wall_clock)?;
+        }
+        self.pending = Some((index, pts, wall_clock));
+
+        Ok(())
+    }
+
+    // Write the cue of the last frame, it lasts as long as the frame before it, and flush.
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some((index, start, wall_clock)) = self.pending.take() {
+            self.write_cue(index, start, start + self.last_duration, wall_clock)?;
+        }
+
+        self.writer.flush()
+    }
+
+    // Write a subtitle cue followed by the blank line that ends it.
+    fn write_cue(&mut self, index: u64, start: i64, end: i64, wall_clock: u128) -> io::Result<()> {
+        self.last_duration = end - start;
+
+        let separator = if self.format == TimestampLogFormat::Srt {
+            ','
+        } else {
+            '.'
         };
+        let cue_time = |time: i64| {
+            let millis = time.max(0) / 10_000;
+            format!(
+                "{:02}:{:02}:{:02}{separator}{:03}",
+                millis / 3_600_000,
+                millis / 60_000 % 60,
+                millis / 1000 % 60,
+                millis % 1000
+            )
+        };
+
+        if self.format == TimestampLogFormat::Srt {
+            writeln!(self.writer, "{}", index + 1)?;
+        }
+        writeln!(self.writer, "{} --> {}", cue_time(start), cue_time(end))?;
+        writeln!(self.writer, "frame {index} at {wall_clock}\n")
+    }
+}
+
+// The media stream source and transcoder of a started `VideoEncoder`.
+struct EncoderSession {
+    frame_sender: mpsc::Sender<Option<(VideoEncoderSource, TimeSpan)>>,
+    audio_queue: Option<Arc<Mutex<AudioQueue>>>,
+    sample_requested: EventRegistrationToken,
+    media_stream_source: MediaStreamSource,
+    starting: EventRegistrationToken,
+    transcode_thread: Option<JoinHandle<Result<(), VideoEncoderError>>>,
+    frame_notify: Arc<(Mutex<bool>, Condvar)>,
+    error_notify: Arc<AtomicBool>,
+    input_format: InputFormatInfo,
+}
+
+impl EncoderSession {
+    // Creates the media stream source and starts the transcoder writing to the stream.
+    fn new(
+        video_settings: &VideoSettingsBuilder,
+        stream: &IRandomAccessStream,
+        audio: Option<&AudioChannel>,
+    ) -> Result<Self, VideoEncoderError> {
+        let media_encoding_profile = video_settings.create_media_encoding_profile()?;
+        let (width, height) = video_settings
+            .input_size
+            .unwrap_or((video_settings.width, video_settings.height));
 
         let video_encoding_properties = VideoEncodingProperties::CreateUncompressed(
             &MediaEncodingSubtypes::Bgra8()?,
@@ -436,10 +2862,38 @@ impl VideoEncoder {
             height,
         )?;
 
+        // The frames are declared as BGRA8, the transcoder converts them to the codec format
+        let input_format = InputFormatInfo {
+            subtype: video_encoding_properties.Subtype()?.to_string(),
+            color_format: Some(ColorFormat::Bgra8),
+            width,
+            height,
+            stride: width * 4,
+            bottom_up: true,
+        };
+
         let video_stream_descriptor = VideoStreamDescriptor::Create(&video_encoding_properties)?;
 
-        let media_stream_source =
-            MediaStreamSource::CreateFromDescriptor(&video_stream_descriptor)?;
+        let (media_stream_source, audio_queue) = match audio {
+            Some(audio) => {
+                let audio_encoding_properties =
+                    AudioEncodingProperties::CreatePcm(audio.sample_rate, audio.channels, 16)?;
+                let audio_stream_descriptor =
+                    AudioStreamDescriptor::Create(&audio_encoding_properties)?;
+
+                (
+                    MediaStreamSource::CreateFromDescriptors(
+                        &video_stream_descriptor,
+                        &audio_stream_descriptor,
+                    )?,
+                    Some(audio.queue.clone()),
+                )
+            }
+            None => (
+                MediaStreamSource::CreateFromDescriptor(&video_stream_descriptor)?,
+                None,
+            ),
+        };
         media_stream_source.SetBufferTime(TimeSpan::default())?;
 
         let (frame_sender, frame_receiver) =
@@ -467,18 +2921,41 @@ impl VideoEncoder {
         >::new({
             let frame_receiver = frame_receiver;
             let frame_notify = frame_notify.clone();
+            let audio_queue = audio_queue.clone();
 
             move |_, sample_requested| {
                 let sample_requested = sample_requested.as_ref().expect(
                     "MediaStreamSource SampleRequested parameter was None This Should Not Happen.",
                 );
 
-                let frame = match frame_receiver.recv() {
-                    Ok(frame) => frame,
-                    Err(e) => panic!("Failed to receive frame from frame sender: {e}"),
-                };
+                // The transcoder asks for the stream that is behind, so audio and video are
+                // interleaved by their timestamps
+                if let Some(audio_queue) = &audio_queue {
+                    let request = sample_requested.Request()?;
+                    if request
+                        .StreamDescriptor()?
+                        .cast::<AudioStreamDescriptor>()
+                        .is_ok()
+                    {
+                        let mut queue = audio_queue.lock();
+                        match queue.next_sample(audio_lag()) {
+                            Some(sample) => {
+                                drop(queue);
+                                set_audio_sample(&request, sample)?;
+                            }
+                            // Without blocking the transcoder, the request is answered when audio
+                            // or the next frame arrives
+                            None => {
+                                queue.pending = Some((request.clone(), request.GetDeferral()?));
+                            }
+                        }
+
+                        return Ok(());
+                    }
+                }
 
-                match frame {
+                // The sender is gone once the session was dropped or abandoned, end the stream
+                match frame_receiver.recv().ok().flatten() {
                     Some((source, timespan)) => {
                         let sample = match source {
                             VideoEncoderSource::DirectX(surface) => {
@@ -516,7 +2993,7 @@ impl VideoEncoder {
         let transcode = media_transcoder
             .PrepareMediaStreamSourceTranscodeAsync(
                 &media_stream_source,
-                &stream,
+                stream,
                 &media_encoding_profile,
             )?
             .get()?;
@@ -541,47 +3018,43 @@ impl VideoEncoder {
         });
 
         Ok(Self {
-            first_timespan: None,
             frame_sender,
+            audio_queue,
             sample_requested,
             media_stream_source,
             starting,
             transcode_thread: Some(transcode_thread),
             frame_notify,
             error_notify,
+            input_format,
         })
     }
 
-    /// Sends a video frame to the video encoder for encoding.
-    ///
-    /// # Arguments
-    ///
-    /// * `frame` - A mutable reference to the `Frame` to be encoded.
-    ///
-    /// # Returns
-    ///
-    /// Returns `Ok(())` if the frame is successfully sent for encoding, or a `VideoEncoderError`
-    /// if an error occurs.
-    pub fn send_frame(&mut self, frame: &mut Frame) -> Result<(), VideoEncoderError> {
-        let timespan = match self.first_timespan {
-            Some(timespan) => TimeSpan {
-                Duration: frame.timespan().Duration - timespan.Duration,
-            },
-            None => {
-                let timespan = frame.timespan();
-                self.first_timespan = Some(timespan);
-                TimeSpan { Duration: 0 }
-            }
-        };
-        let surface = SendDirectX::new(unsafe { frame.as_raw_surface() });
+    // Sends a frame to the transcoder and waits until it was consumed.
+    fn send(
+        &mut self,
+        source: VideoEncoderSource,
+        timespan: TimeSpan,
+    ) -> Result<(), VideoEncoderError> {
+        self.frame_sender.send(Some((source, timespan)))?;
 
-        self.frame_sender
-            .send(Some((VideoEncoderSource::DirectX(surface), timespan)))?;
+        // The transcoder may wait for audio up to the frame before it takes the frame
+        if let Some(audio_queue) = &self.audio_queue {
+            audio_queue.lock().video_time = timespan.Duration;
+            AudioQueue::answer_pending(audio_queue, audio_lag())?;
+        }
 
         let (lock, cvar) = &*self.frame_notify;
         let mut processed = lock.lock();
-        if !*processed {
-            cvar.wait(&mut processed);
+        while !*processed {
+            if cvar.wait_for(&mut processed, AUDIO_GAP_TIMEOUT).timed_out() {
+                // The audio didn't catch up with the frame, fill the gap up to it with silence
+                if let Some(audio_queue) = &self.audio_queue {
+                    MutexGuard::unlocked(&mut processed, || {
+                        AudioQueue::answer_pending(audio_queue, 0)
+                    })?;
+                }
+            }
         }
         *processed = false;
         drop(processed);
@@ -597,68 +3070,49 @@ impl VideoEncoder {
         Ok(())
     }
 
-    /// Sends a video frame to the video encoder for encoding.
-    ///
-    /// # Arguments
-    ///
-    /// * `buffer` - A reference to the byte slice to be encoded Windows API expect this to be Bgra and bottom-top.
-    /// * `timespan` - The timespan that correlates to the frame buffer.
-    ///
-    /// # Returns
-    ///
-    /// Returns `Ok(())` if the frame is successfully sent for encoding, or a `VideoEncoderError`
-    /// if an error occurs.
-    pub fn send_frame_buffer(
-        &mut self,
-        buffer: &[u8],
-        timespan: i64,
-    ) -> Result<(), VideoEncoderError> {
-        let frame_timespan = timespan;
-        let timespan = match self.first_timespan {
-            Some(timespan) => TimeSpan {
-                Duration: frame_timespan - timespan.Duration,
-            },
-            None => {
-                let timespan = frame_timespan;
-                self.first_timespan = Some(TimeSpan { Duration: timespan });
-                TimeSpan { Duration: 0 }
-            }
-        };
-
-        self.frame_sender.send(Some((
-            VideoEncoderSource::Buffer((SendDirectX::new(buffer.as_ptr()), buffer.len())),
-            timespan,
-        )))?;
+    // Ends the stream and waits for the transcoder to finish writing.
+    fn finish(&mut self) -> Result<(), VideoEncoderError> {
+        self.frame_sender.send(None)?;
+        self.end_audio();
 
-        let (lock, cvar) = &*self.frame_notify;
-        let mut processed = lock.lock();
-        if !*processed {
-            cvar.wait(&mut processed);
+        if let Some(transcode_thread) = self.transcode_thread.take() {
+            transcode_thread
+                .join()
+                .expect("Failed to join transcode thread")?;
         }
-        *processed = false;
-        drop(processed);
 
-        if self.error_notify.load(atomic::Ordering::Relaxed) {
-            if let Some(transcode_thread) = self.transcode_thread.take() {
-                transcode_thread
-                    .join()
-                    .expect("Failed to join transcode thread")?;
-            }
-        }
+        self.media_stream_source.RemoveStarting(self.starting)?;
+        self.media_stream_source
+            .RemoveSampleRequested(self.sample_requested)?;
 
         Ok(())
     }
 
-    /// Finishes encoding the video and performs any necessary cleanup.
-    ///
-    /// # Returns
-    ///
-    /// Returns `Ok(())` if the encoding is successfully finished, or a `VideoEncoderError` if an
-    /// error occurs.
-    pub fn finish(mut self) -> Result<(), VideoEncoderError> {
+    // Finishes the transcoder like finish, returns false and detaches the transcode thread if it
+    // doesn't end within the timeout.
+    fn finish_timeout(&mut self, timeout: Duration) -> Result<bool, VideoEncoderError> {
         self.frame_sender.send(None)?;
+        self.end_audio();
 
+        let deadline = Instant::now() + timeout;
         if let Some(transcode_thread) = self.transcode_thread.take() {
+            while !transcode_thread.is_finished() {
+                if Instant::now() >= deadline {
+                    // Dropping the handle detaches the thread, so dropping the session won't block
+                    drop(transcode_thread);
+
+                    // The abandoned transcoder may still ask for samples, without the handlers it
+                    // can't reach the channels of the session
+                    self.media_stream_source.RemoveStarting(self.starting)?;
+                    self.media_stream_source
+                        .RemoveSampleRequested(self.sample_requested)?;
+
+                    return Ok(false);
+                }
+
+                thread::sleep(FINISH_POLL_INTERVAL);
+            }
+
             transcode_thread
                 .join()
                 .expect("Failed to join transcode thread")?;
@@ -668,13 +3122,22 @@ impl VideoEncoder {
         self.media_stream_source
             .RemoveSampleRequested(self.sample_requested)?;
 
-        Ok(())
+        Ok(true)
+    }
+
+    // Ends the audio stream, samples sent afterwards are never encoded.
+    fn end_audio(&mut self) {
+        if let Some(audio_queue) = self.audio_queue.take() {
+            audio_queue.lock().ended = true;
+            let _ = AudioQueue::answer_pending(&audio_queue, 0);
+        }
     }
 }
 
-impl Drop for VideoEncoder {
+impl Drop for EncoderSession {
     fn drop(&mut self) {
         let _ = self.frame_sender.send(None);
+        self.end_audio();
 
         if let Some(transcode_thread) = self.transcode_thread.take() {
             let _ = transcode_thread.join();
@@ -684,3 +3147,207 @@ impl Drop for VideoEncoder {
 
 #[allow(clippy::non_send_fields_in_send_ty)]
 unsafe impl Send for VideoEncoder {}
+
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl Send for MultiVideoEncoder {}
+
+// Add the metadata tags to the moov box of an MP4 stream, the moov box must be the last box so
+// the offsets into the media data stay valid.
+fn write_mp4_metadata(
+    stream: &IRandomAccessStream,
+    metadata: &[(String, String)],
+) -> Result<(), VideoEncoderError> {
+    let size = stream.Size()?;
+
+    // Walk the top-level boxes to find the last one
+    let mut position = 0;
+    let mut last_box = None;
+    while position + 8 <= size {
+        let header = read_stream_at(stream, position, 16.min(size - position))?;
+
+        let size_field = u32::from_be_bytes(header[0..4].try_into().unwrap());
+        let box_size = match size_field {
+            0 => size - position,
+            1 if header.len() == 16 => u64::from_be_bytes(header[8..16].try_into().unwrap()),
+            box_size => u64::from(box_size),
+        };
+
+        if box_size < 8 {
+            return Err(VideoEncoderError::MetadataUnsupported);
+        }
+
+        last_box = Some((
+            position,
+            box_size,
+            [header[4], header[5], header[6], header[7]],
+            size_field,
+        ));
+        position += box_size;
+    }
+
+    let Some((moov_position, moov_size, kind, size_field)) = last_box else {
+        return Err(VideoEncoderError::MetadataUnsupported);
+    };
+
+    // Only a moov box with a 32-bit size can be grown in place
+    if &kind != b"moov" || size_field <= 1 || moov_position + moov_size != size {
+        return Err(VideoEncoderError::MetadataUnsupported);
+    }
+
+    // The moov box only grows and nothing follows it, so it's rewritten where it is
+    let mut moov = read_stream_at(stream, moov_position, moov_size)?;
+    add_mp4_metadata(&mut moov, metadata)?;
+
+    write_stream_at(stream, moov_position, &moov)?;
+    stream.FlushAsync()?.get()?;
+
+    Ok(())
+}
+
+// Add the metadata tags to a moov box, they are merged into the udta, meta and ilst boxes it
+// already has and only the missing boxes are created.
+fn add_mp4_metadata(
+    moov: &mut Vec<u8>,
+    metadata: &[(String, String)],
+) -> Result<(), VideoEncoderError> {
+    // Find the udta, meta and ilst boxes nested in each other, the meta box is a full box so its
+    // children start after the version and flags
+    let moov_size = mp4_box_size(moov, 0).ok_or(VideoEncoderError::MetadataUnsupported)?;
+    let mut path = vec![(0, moov_size)];
+    let mut children = 8;
+    for (kind, header_size) in [(b"udta", 8), (b"meta", 12), (b"ilst", 8)] {
+        let &(parent, parent_size) = path.last().unwrap();
+        let Some(child) = find_mp4_box(moov, children, parent + parent_size, kind) else {
+            break;
+        };
+
+        let child_size = mp4_box_size(moov, child).ok_or(VideoEncoderError::MetadataUnsupported)?;
+        path.push((child, child_size));
+        children = child + header_size;
+    }
+
+    let items = mp4_ilst_items(metadata);
+    let inserted = match path.len() {
+        1 => mp4_box(b"udta", &mp4_meta_box(&items)),
+        2 => mp4_meta_box(&items),
+        3 => mp4_box(b"ilst", &items),
+        _ => items,
+    };
+
+    // The new boxes go at the end of the deepest box found, every box around them grows
+    for &(position, box_size) in &path {
+        let new_size = u32::try_from(box_size + inserted.len())
+            .map_err(|_| VideoEncoderError::MetadataUnsupported)?;
+        moov[position..position + 4].copy_from_slice(&new_size.to_be_bytes());
+    }
+
+    let &(parent, parent_size) = path.last().unwrap();
+    moov.splice(parent + parent_size..parent + parent_size, inserted);
+
+    Ok(())
+}
+
+// Get the size of the box at a position of the bytes, `None` if it doesn't have a 32-bit size
+// that fits the bytes.
+fn mp4_box_size(bytes: &[u8], position: usize) -> Option<usize> {
+    let size = u32::from_be_bytes(bytes.get(position..position + 4)?.try_into().unwrap()) as usize;
+
+    (size >= 8 && position + size <= bytes.len()).then_some(size)
+}
+
+// Find the first box of a kind between two positions of the bytes.
+fn find_mp4_box(bytes: &[u8], mut position: usize, end: usize, kind: &[u8; 4]) -> Option<usize> {
+    while position + 8 <= end {
+        if &bytes[position + 4..position + 8] == kind {
+            return Some(position);
+        }
+
+        position += mp4_box_size(bytes, position)?;
+    }
+
+    None
+}
+
+// Build the items of an iTunes style metadata list of freeform tags.
+fn mp4_ilst_items(metadata: &[(String, String)]) -> Vec<u8> {
+    let mut items = Vec::new();
+    for (key, value) in metadata {
+        let mut item = mp4_full_box(b"mean", b"com.apple.iTunes");
+        item.extend(mp4_full_box(b"name", key.as_bytes()));
+
+        // Type 1 is UTF-8 text, followed by the locale
+        let mut data = vec![0, 0, 0, 1, 0, 0, 0, 0];
+        data.extend_from_slice(value.as_bytes());
+        item.extend(mp4_box(b"data", &data));
+
+        items.extend(mp4_box(b"----", &item));
+    }
+
+    items
+}
+
+// Build a meta box with a metadata list of the items.
+fn mp4_meta_box(items: &[u8]) -> Vec<u8> {
+    // Pre-defined, handler type, reserved and an empty name
+    let mut handler = vec![0, 0, 0, 0];
+    handler.extend_from_slice(b"mdirappl");
+    handler.extend_from_slice(&[0; 9]);
+
+    let mut meta = mp4_full_box(b"hdlr", &handler);
+    meta.extend(mp4_box(b"ilst", items));
+
+    mp4_full_box(b"meta", &meta)
+}
+
+// Build an MP4 box from its type and payload.
+fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(8 + payload.len());
+    bytes.extend_from_slice(&u32::try_from(8 + payload.len()).unwrap().to_be_bytes());
+    bytes.extend_from_slice(kind);
+    bytes.extend_from_slice(payload);
+
+    bytes
+}
+
+// Build an MP4 full box, the version and flags are zero.
+fn mp4_full_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut full_payload = vec![0, 0, 0, 0];
+    full_payload.extend_from_slice(payload);
+
+    mp4_box(kind, &full_payload)
+}
+
+// Read bytes from a position of a stream.
+fn read_stream_at(
+    stream: &IRandomAccessStream,
+    position: u64,
+    len: u64,
+) -> Result<Vec<u8>, VideoEncoderError> {
+    let len = u32::try_from(len).unwrap();
+
+    stream.Seek(position)?;
+    let buffer = Buffer::Create(len)?;
+    let buffer = stream
+        .ReadAsync(&buffer, len, InputStreamOptions::None)?
+        .get()?;
+
+    let data_reader = DataReader::FromBuffer(&buffer)?;
+    let mut bytes = vec![0u8; data_reader.UnconsumedBufferLength()? as usize];
+    data_reader.ReadBytes(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+// Write bytes at a position of a stream.
+fn write_stream_at(
+    stream: &IRandomAccessStream,
+    position: u64,
+    bytes: &[u8],
+) -> Result<(), VideoEncoderError> {
+    stream.Seek(position)?;
+    stream
+        .WriteAsync(&CryptographicBuffer::CreateFromByteArray(bytes)?)?
+        .get()?;
+
+    Ok(())
+}
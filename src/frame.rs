@@ -1,37 +1,80 @@
 use std::{
     fs::{self},
-    io,
+    io, mem,
     path::Path,
-    ptr, slice,
+    ptr, slice, thread,
+    time::{Duration, Instant},
 };
 
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon::{
+    iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator},
+    slice::{ParallelSlice, ParallelSliceMut},
+};
 use windows::{
+    core::{w, Array, Interface, HSTRING, PCWSTR},
     Foundation::TimeSpan,
-    Graphics::DirectX::Direct3D11::IDirect3DSurface,
-    Win32::Graphics::{
-        Direct3D11::{
-            ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D, D3D11_BOX, D3D11_CPU_ACCESS_READ,
-            D3D11_CPU_ACCESS_WRITE, D3D11_MAPPED_SUBRESOURCE, D3D11_MAP_READ_WRITE,
-            D3D11_TEXTURE2D_DESC, D3D11_USAGE_STAGING,
+    Graphics::{Capture::Direct3D11CaptureFrame, DirectX::Direct3D11::IDirect3DSurface},
+    Security::Cryptography::{
+        Core::{HashAlgorithmNames, HashAlgorithmProvider},
+        CryptographicBuffer,
+    },
+    Win32::{
+        Foundation::{CloseHandle, BOOL, HANDLE},
+        Graphics::{
+            Direct2D::{
+                Common::{
+                    D2D1_ALPHA_MODE_PREMULTIPLIED, D2D1_COLOR_F, D2D1_PIXEL_FORMAT, D2D_POINT_2F,
+                    D2D_RECT_F,
+                },
+                D2D1CreateFactory, ID2D1Factory, ID2D1RenderTarget, D2D1_DRAW_TEXT_OPTIONS_NONE,
+                D2D1_ELLIPSE, D2D1_FACTORY_TYPE_SINGLE_THREADED, D2D1_FEATURE_LEVEL_DEFAULT,
+                D2D1_RENDER_TARGET_PROPERTIES, D2D1_RENDER_TARGET_TYPE_DEFAULT,
+                D2D1_RENDER_TARGET_USAGE_NONE,
+            },
+            Direct3D11::{
+                ID3D11Device, ID3D11DeviceContext, ID3D11Query, ID3D11Texture2D,
+                D3D11_ASYNC_GETDATA_DONOTFLUSH, D3D11_BIND_RENDER_TARGET,
+                D3D11_BIND_SHADER_RESOURCE, D3D11_BOX, D3D11_CPU_ACCESS_READ,
+                D3D11_CPU_ACCESS_WRITE, D3D11_MAPPED_SUBRESOURCE, D3D11_MAP_READ_WRITE,
+                D3D11_QUERY_DESC, D3D11_QUERY_EVENT, D3D11_RESOURCE_MISC_SHARED,
+                D3D11_RESOURCE_MISC_SHARED_NTHANDLE, D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT,
+                D3D11_USAGE_STAGING,
+            },
+            DirectWrite::{
+                DWriteCreateFactory, IDWriteFactory, IDWriteTextLayout, DWRITE_FACTORY_TYPE_SHARED,
+                DWRITE_FONT_STRETCH_NORMAL, DWRITE_FONT_STYLE_NORMAL, DWRITE_FONT_WEIGHT_NORMAL,
+                DWRITE_TEXT_METRICS,
+            },
+            Dxgi::{
+                Common::{DXGI_FORMAT, DXGI_SAMPLE_DESC},
+                IDXGIResource1, IDXGISurface, DXGI_SHARED_RESOURCE_READ,
+            },
         },
-        Dxgi::Common::{DXGI_FORMAT, DXGI_SAMPLE_DESC},
     },
 };
 
 use crate::{
+    cursor::{self, CursorLayer},
     encoder::{self, ImageEncoder},
-    settings::ColorFormat,
+    settings::{AlphaModeSettings, ColorFormat},
 };
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Invalid box size")]
     InvalidSize,
+    #[error("The frames have a different size or color format")]
+    FrameMismatch,
+    #[error("Unknown image file extension: {0}")]
+    UnknownImageExtension(String),
     #[error("This color format is not supported for saving as image")]
     UnsupportedFormat,
+    #[error("Frames can't be converted from {0:?} to {1:?}")]
+    UnsupportedConversion(ColorFormat, ColorFormat),
     #[error("Failed to encode image buffer to image bytes with specified format: {0}")]
     ImageEncoderError(#[from] encoder::ImageEncoderError),
+    #[error("Failed to capture the cursor: {0}")]
+    CursorError(#[from] cursor::Error),
     #[error("IO error: {0}")]
     IoError(#[from] io::Error),
     #[error("Windows API error: {0}")]
@@ -46,8 +89,227 @@ pub enum ImageFormat {
     Tiff,
     Bmp,
     JpegXr,
+    /// Uncompressed DirectDraw Surface, `Rgba16F` frames are written with the DX10 header.
+    Dds,
+}
+
+impl ImageFormat {
+    /// Get the image format matching a file extension, the comparison ignores case.
+    ///
+    /// # Arguments
+    ///
+    /// * `extension` - The file extension without the dot, for example `png`.
+    ///
+    /// # Returns
+    ///
+    /// The image format, or `None` if the extension is unknown.
+    #[must_use]
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "png" => Some(Self::Png),
+            "gif" => Some(Self::Gif),
+            "tif" | "tiff" => Some(Self::Tiff),
+            "bmp" => Some(Self::Bmp),
+            "jxr" | "wdp" => Some(Self::JpegXr),
+            "dds" => Some(Self::Dds),
+            _ => None,
+        }
+    }
+
+    /// Get the image format matching the extension of a path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the image file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::UnknownImageExtension` if the path has no known image extension.
+    pub fn from_path<T: AsRef<Path>>(path: T) -> Result<Self, Error> {
+        let extension = path
+            .as_ref()
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        Self::from_extension(&extension).ok_or(Error::UnknownImageExtension(extension))
+    }
+
+    /// Get the MIME type of the image format, for example `image/png`.
+    #[must_use]
+    pub const fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::Gif => "image/gif",
+            Self::Tiff => "image/tiff",
+            Self::Bmp => "image/bmp",
+            Self::JpegXr => "image/vnd.ms-photo",
+            Self::Dds => "image/vnd-ms.dds",
+        }
+    }
+}
+
+/// A rectangle in pixels, relative to the top-left corner of the frame.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// How `Frame::redact` hides the content of a region.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum RedactMode {
+    /// Fill the region with opaque black.
+    Black,
+    /// Blur the region strongly enough that text is unreadable, not supported for `Rgba16F`.
+    Blur,
+}
+
+/// The font, colors and padding used by `Frame::draw_text`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct TextStyle {
+    font_family: String,
+    font_size: f32,
+    color: [u8; 4],
+    background: Option<[u8; 4]>,
+    padding: f32,
+}
+
+impl TextStyle {
+    /// Creates a text style with the given font and size, white text on an opaque black
+    /// background.
+    ///
+    /// # Arguments
+    ///
+    /// * `font_family` - The name of the font family, for example `Consolas`.
+    /// * `font_size` - The height of the font in pixels.
+    ///
+    /// # Returns
+    ///
+    /// A new text style.
+    #[must_use]
+    pub fn new(font_family: &str, font_size: f32) -> Self {
+        Self {
+            font_family: font_family.to_string(),
+            font_size,
+            color: [255, 255, 255, 255],
+            background: Some([0, 0, 0, 255]),
+            padding: font_size / 4.0,
+        }
+    }
+
+    /// Sets the color of the text in RGBA order.
+    #[must_use]
+    pub const fn color(mut self, color: [u8; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Sets the color of the box behind the text in RGBA order, `None` draws the text directly
+    /// on the frame.
+    #[must_use]
+    pub const fn background(mut self, background: Option<[u8; 4]>) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// Sets the space between the text and the edge of its box in pixels.
+    #[must_use]
+    pub const fn padding(mut self, padding: f32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Get the name of the font family.
+    #[must_use]
+    pub fn font_family(&self) -> &str {
+        &self.font_family
+    }
+
+    /// Get the height of the font in pixels.
+    #[must_use]
+    pub const fn font_size(&self) -> f32 {
+        self.font_size
+    }
+
+    /// Measures the box `Frame::draw_text` draws for a text, for example to center it.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to measure.
+    ///
+    /// # Returns
+    ///
+    /// The width and height of the box of the text including the padding.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::WindowsError` if the font could not be loaded.
+    pub fn measure(&self, text: &str) -> Result<(u32, u32), Error> {
+        let (_, width, height) = self.layout(text)?;
+
+        Ok((width, height))
+    }
+
+    // Lay out the text with DirectWrite, returns the layout and the size of its box.
+    fn layout(&self, text: &str) -> Result<(IDWriteTextLayout, u32, u32), Error> {
+        let dwrite_factory: IDWriteFactory =
+            unsafe { DWriteCreateFactory(DWRITE_FACTORY_TYPE_SHARED)? };
+        let text_format = unsafe {
+            dwrite_factory.CreateTextFormat(
+                &HSTRING::from(self.font_family.as_str()),
+                None,
+                DWRITE_FONT_WEIGHT_NORMAL,
+                DWRITE_FONT_STYLE_NORMAL,
+                DWRITE_FONT_STRETCH_NORMAL,
+                self.font_size,
+                w!(""),
+            )?
+        };
+
+        let text = text.encode_utf16().collect::<Vec<_>>();
+        let text_layout =
+            unsafe { dwrite_factory.CreateTextLayout(&text, &text_format, f32::MAX, f32::MAX)? };
+
+        let mut metrics = DWRITE_TEXT_METRICS::default();
+        unsafe { text_layout.GetMetrics(&mut metrics)? };
+
+        let width = (metrics.widthIncludingTrailingWhitespace + self.padding * 2.0).ceil() as u32;
+        let height = (metrics.height + self.padding * 2.0).ceil() as u32;
+
+        Ok((text_layout, width, height))
+    }
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self::new("Consolas", 24.0)
+    }
 }
 
+/// The radius of the box blur used by `RedactMode::Blur`.
+const REDACT_BLUR_RADIUS: usize = 12;
+
+/// The number of box blur passes used by `RedactMode::Blur`, three passes approximate a
+/// gaussian blur.
+const REDACT_BLUR_PASSES: usize = 3;
+
+/// The distance between the sampled pixels and rows when checking for black frames.
+const BLACK_SAMPLE_STEP: usize = 8;
+
+/// The highest 8-bit channel value that still counts as black.
+const BLACK_THRESHOLD: u8 = 16;
+
+/// The highest half float channel value that still counts as black, `0.0625` without the sign.
+const BLACK_THRESHOLD_F16: u16 = 0x2C00;
+
+/// The brightness of SDR white in nits that scRGB `1.0` stands for.
+pub const SDR_REFERENCE_WHITE_LEVEL: u32 = 80;
+
 /// Represents a frame captured from a graphics capture item.
 ///
 /// # Example
@@ -65,7 +327,17 @@ pub struct Frame<'a> {
     buffer: &'a mut Vec<u8>,
     width: u32,
     height: u32,
+    content_size: (u32, u32),
     color_format: ColorFormat,
+    cursor_layer: Option<CursorLayer>,
+    readback_duration: Option<Duration>,
+    capture_frame: Option<Direct3D11CaptureFrame>,
+    previous_time: Option<TimeSpan>,
+    alpha_mode: AlphaModeSettings,
+    sdr_format: Option<ColorFormat>,
+    sdr_white_level: u32,
+    acquisition_latency: Option<Duration>,
+    pending_readback: Option<(ID3D11Texture2D, ID3D11Query, Instant)>,
 }
 
 impl<'a> Frame<'a> {
@@ -81,7 +353,9 @@ impl<'a> Frame<'a> {
     /// * `buffer` - The mutable Vec<u8> representing the frame buffer.
     /// * `width` - The width of the frame.
     /// * `height` - The height of the frame.
+    /// * `content_size` - The width and height of the captured content inside the texture.
     /// * `color_format` - The ColorFormat of the frame.
+    /// * `cursor_layer` - The cursor captured separately from the frame, if any.
     ///
     /// # Returns
     ///
@@ -97,7 +371,9 @@ impl<'a> Frame<'a> {
         buffer: &'a mut Vec<u8>,
         width: u32,
         height: u32,
+        content_size: (u32, u32),
         color_format: ColorFormat,
+        cursor_layer: Option<CursorLayer>,
     ) -> Self {
         Self {
             d3d_device,
@@ -108,10 +384,91 @@ impl<'a> Frame<'a> {
             buffer,
             width,
             height,
+            content_size,
             color_format,
+            cursor_layer,
+            readback_duration: None,
+            capture_frame: None,
+            previous_time: None,
+            alpha_mode: AlphaModeSettings::Premultiplied,
+            sdr_format: None,
+            sdr_white_level: SDR_REFERENCE_WHITE_LEVEL,
+            acquisition_latency: None,
+            pending_readback: None,
         }
     }
 
+    /// Attach the capture frame the texture belongs to, so `guard` can keep it alive.
+    ///
+    /// # Arguments
+    ///
+    /// * `capture_frame` - The Direct3D11CaptureFrame received from the frame pool.
+    #[must_use]
+    pub fn with_capture_frame(mut self, capture_frame: Direct3D11CaptureFrame) -> Self {
+        self.capture_frame = Some(capture_frame);
+        self
+    }
+
+    /// Set the timestamp of the frame delivered before this one, used by
+    /// `duration_since_previous`.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous_timespan` - The timestamp of the previous frame, or `None` for the first
+    ///   frame.
+    #[must_use]
+    pub fn with_previous_timespan(mut self, previous_timespan: Option<TimeSpan>) -> Self {
+        self.previous_time = previous_timespan;
+        self
+    }
+
+    /// Set how the alpha channel of the buffers read from the frame is delivered.
+    ///
+    /// # Arguments
+    ///
+    /// * `alpha_mode` - The alpha mode of `buffer` and `buffer_crop`.
+    #[must_use]
+    pub fn with_alpha_mode(mut self, alpha_mode: AlphaModeSettings) -> Self {
+        self.alpha_mode = alpha_mode;
+        self
+    }
+
+    /// Tone map `Rgba16F` frames to 8-bit SDR with dithering when their buffers are read.
+    ///
+    /// # Arguments
+    ///
+    /// * `sdr_format` - The 8-bit color format of `buffer` and `buffer_crop`, or `None` to read
+    ///   the buffers in the format of the texture.
+    #[must_use]
+    pub fn with_hdr_to_sdr(mut self, sdr_format: Option<ColorFormat>) -> Self {
+        self.sdr_format = sdr_format;
+        self
+    }
+
+    /// Set the brightness SDR content is composed at, `Rgba16F` pixels are divided by it before
+    /// they are tone mapped so SDR content keeps its brightness, see `Monitor::sdr_white_level`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sdr_white_level` - The brightness of SDR white in nits, `SDR_REFERENCE_WHITE_LEVEL`
+    ///   by default.
+    #[must_use]
+    pub fn with_sdr_white_level(mut self, sdr_white_level: u32) -> Self {
+        self.sdr_white_level = sdr_white_level.max(1);
+        self
+    }
+
+    /// Set the time from the OS stamping the frame until it was handed to the handler.
+    ///
+    /// # Arguments
+    ///
+    /// * `acquisition_latency` - The latency of the capture pipeline.
+    #[must_use]
+    pub fn with_acquisition_latency(mut self, acquisition_latency: Duration) -> Self {
+        self.acquisition_latency = Some(acquisition_latency);
+        self
+    }
+
     /// Get the width of the frame.
     ///
     /// # Returns
@@ -132,6 +489,38 @@ impl<'a> Frame<'a> {
         self.height
     }
 
+    /// Get the color format of the frame.
+    ///
+    /// # Returns
+    ///
+    /// The color format of the frame.
+    #[must_use]
+    pub const fn color_format(&self) -> ColorFormat {
+        self.color_format
+    }
+
+    /// Get the size of the texture, same as `width` and `height`.
+    ///
+    /// # Returns
+    ///
+    /// The width and height of the texture.
+    #[must_use]
+    pub const fn texture_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Get the size of the captured content. The texture can be larger than the content, for
+    /// example right after a window is resized, and the pixels outside of the content are
+    /// undefined, crop to this size with `buffer_crop` to only get the content.
+    ///
+    /// # Returns
+    ///
+    /// The width and height of the content, never larger than the texture.
+    #[must_use]
+    pub const fn content_size(&self) -> (u32, u32) {
+        self.content_size
+    }
+
     /// Get the time of the frame.
     ///
     /// # Returns
@@ -142,6 +531,58 @@ impl<'a> Frame<'a> {
         self.time
     }
 
+    /// Get the time since the previous delivered frame, the presentation duration of the
+    /// previous frame when encoding with a variable frame rate.
+    ///
+    /// # Returns
+    ///
+    /// The duration, or `None` for the first frame and the first frame after a pause.
+    #[must_use]
+    pub fn duration_since_previous(&self) -> Option<Duration> {
+        let previous_time = self.previous_time?;
+        let gap = u64::try_from(self.time.Duration - previous_time.Duration).ok()?;
+
+        Some(Duration::from_nanos(gap * 100))
+    }
+
+    /// Get the cursor captured as a separate layer.
+    ///
+    /// # Returns
+    ///
+    /// The cursor layer, or `None` if the capture isn't using
+    /// `CursorCaptureSettings::SeparateLayer` or the cursor is hidden.
+    #[must_use]
+    pub const fn cursor_layer(&self) -> Option<&CursorLayer> {
+        self.cursor_layer.as_ref()
+    }
+
+    /// Get how long the last GPU to CPU readback took, measured from the texture copy until
+    /// the staging texture was mapped by `buffer` or `buffer_crop`.
+    ///
+    /// Comparing it with the time spent in `on_frame_arrived` shows whether the readback or the
+    /// handler is the bottleneck.
+    ///
+    /// # Returns
+    ///
+    /// The readback duration, or `None` if the frame buffer wasn't read yet.
+    #[must_use]
+    pub const fn readback_duration(&self) -> Option<Duration> {
+        self.readback_duration
+    }
+
+    /// Get the time from the OS stamping the frame with its `SystemRelativeTime` until it was
+    /// handed to the handler, including the GPU cropping, scaling and masking but not the time
+    /// spent in `on_frame_arrived`.
+    ///
+    /// # Returns
+    ///
+    /// The acquisition latency, or `None` for frames that were not captured live, like
+    /// repeated or held frames.
+    #[must_use]
+    pub const fn acquisition_latency(&self) -> Option<Duration> {
+        self.acquisition_latency
+    }
+
     /// Get the raw surface of the frame.
     ///
     /// # Returns
@@ -156,13 +597,40 @@ impl<'a> Frame<'a> {
         self.frame_surface.clone()
     }
 
-    /// Get the frame buffer.
+    /// Keep the capture frame alive after the handler returns, so its texture can be used
+    /// safely a bit longer.
+    ///
+    /// The frame pool only has two buffers and the buffer of the frame is recycled when the last
+    /// guard is dropped, no new frames arrive while both buffers are held by guards.
     ///
     /// # Returns
     ///
-    /// The FrameBuffer containing the frame data.
-    pub fn buffer(&mut self) -> Result<FrameBuffer, Error> {
-        // Texture Settings
+    /// The guard, or `None` if the frame doesn't come from the frame pool, for example a frame
+    /// repeated by `FramePacingSettings::Vsync`.
+    #[must_use]
+    pub fn guard(&self) -> Option<FrameGuard> {
+        self.capture_frame.as_ref().map(|capture_frame| FrameGuard {
+            capture_frame: capture_frame.clone(),
+            texture: self.frame_texture.clone(),
+            time: self.time,
+            width: self.width,
+            height: self.height,
+            color_format: self.color_format,
+        })
+    }
+
+    /// Copy the frame into a texture that can be opened by other devices and APIs, for example
+    /// to render it with wgpu.
+    ///
+    /// The copy stays on the GPU and is shared through an NT handle. To get a wgpu texture, open
+    /// the handle on the D3D12 device of wgpu with `ID3D12Device::OpenSharedHandle` and wrap the
+    /// resulting `ID3D12Resource` with `wgpu::hal::dx12::Device::texture_from_raw` and
+    /// `wgpu::Device::create_texture_from_hal`.
+    ///
+    /// # Returns
+    ///
+    /// The shared texture, the handle is closed when it's dropped.
+    pub fn shared_texture(&self) -> Result<SharedTexture, Error> {
         let texture_desc = D3D11_TEXTURE2D_DESC {
             Width: self.width,
             Height: self.height,
@@ -173,13 +641,13 @@ impl<'a> Frame<'a> {
                 Count: 1,
                 Quality: 0,
             },
-            Usage: D3D11_USAGE_STAGING,
-            BindFlags: 0,
-            CPUAccessFlags: D3D11_CPU_ACCESS_READ.0 as u32 | D3D11_CPU_ACCESS_WRITE.0 as u32,
-            MiscFlags: 0,
+            Usage: D3D11_USAGE_DEFAULT,
+            BindFlags: D3D11_BIND_SHADER_RESOURCE.0 as u32,
+            CPUAccessFlags: 0,
+            MiscFlags: (D3D11_RESOURCE_MISC_SHARED.0 | D3D11_RESOURCE_MISC_SHARED_NTHANDLE.0)
+                as u32,
         };
 
-        // Create a texture that CPU can read
         let mut texture = None;
         unsafe {
             self.d3d_device
@@ -187,75 +655,70 @@ impl<'a> Frame<'a> {
         };
         let texture = texture.unwrap();
 
-        // Copy the real texture to copy texture
+        // Flush so the copy is done before another device reads the texture
         unsafe {
             self.context.CopyResource(&texture, &self.frame_texture);
+            self.context.Flush();
         };
 
-        // Map the texture to enable CPU access
-        let mut mapped_resource = D3D11_MAPPED_SUBRESOURCE::default();
-        unsafe {
-            self.context.Map(
-                &texture,
-                0,
-                D3D11_MAP_READ_WRITE,
-                0,
-                Some(&mut mapped_resource),
-            )?;
-        };
-
-        // Get the mapped resource data slice
-        let mapped_frame_data = unsafe {
-            slice::from_raw_parts_mut(
-                mapped_resource.pData.cast(),
-                (self.height * mapped_resource.RowPitch) as usize,
-            )
+        let resource: IDXGIResource1 = texture.cast()?;
+        let handle = unsafe {
+            resource.CreateSharedHandle(None, DXGI_SHARED_RESOURCE_READ.0, PCWSTR::null())?
         };
 
-        // Create frame buffer from slice
-        let frame_buffer = FrameBuffer::new(
-            mapped_frame_data,
-            self.buffer,
-            self.width,
-            self.height,
-            mapped_resource.RowPitch,
-            mapped_resource.DepthPitch,
-            self.color_format,
-        );
-
-        Ok(frame_buffer)
+        Ok(SharedTexture {
+            handle,
+            texture,
+            width: self.width,
+            height: self.height,
+            color_format: self.color_format,
+        })
     }
 
-    /// Get a cropped frame buffer.
-    ///
-    /// # Arguments
+    /// Queue the copy of the frame to a CPU readable texture, `buffer` then waits on an event
+    /// query for the copy instead of stalling in the map.
     ///
-    /// * `start_width` - The starting width of the cropped frame.
-    /// * `start_height` - The starting height of the cropped frame.
-    /// * `end_width` - The ending width of the cropped frame.
-    /// * `end_height` - The ending height of the cropped frame.
+    /// The capture calls it before the frame is handed to the handler with
+    /// `ReadbackModeSettings::Deferred`. Drawing on the frame afterwards, for example with
+    /// `redact` or `draw_text`, discards the queued copy.
     ///
     /// # Returns
     ///
-    /// The FrameBuffer containing the cropped frame data.
-    pub fn buffer_crop(
-        &mut self,
-        start_width: u32,
-        start_height: u32,
-        end_width: u32,
-        end_height: u32,
-    ) -> Result<FrameBuffer, Error> {
-        if start_width >= end_width || start_height >= end_height {
-            return Err(Error::InvalidSize);
-        }
+    /// An empty Result if successful, or an Error if the copy could not be queued.
+    pub fn start_readback(&mut self) -> Result<(), Error> {
+        let texture = self.readback_texture()?;
 
-        let texture_width = end_width - start_width;
-        let texture_height = end_height - start_height;
+        let query_desc = D3D11_QUERY_DESC {
+            Query: D3D11_QUERY_EVENT,
+            MiscFlags: 0,
+        };
 
+        let mut query = None;
+        unsafe {
+            self.d3d_device.CreateQuery(&query_desc, Some(&mut query))?;
+        };
+        let query = query.unwrap();
+
+        // The event is signaled once the GPU is done with the copy, flushing submits the copy so
+        // it runs while the handler works
+        let readback_start = Instant::now();
+        unsafe {
+            self.context.CopyResource(&texture, &self.frame_texture);
+            self.context.End(&query);
+            self.context.Flush();
+        };
+
+        self.pending_readback = Some((texture, query, readback_start));
+
+        Ok(())
+    }
+
+    // Create a texture that CPU can read with the size and format of the frame.
+    fn readback_texture(&self) -> Result<ID3D11Texture2D, Error> {
         // Texture Settings
         let texture_desc = D3D11_TEXTURE2D_DESC {
-            Width: texture_width,
-            Height: texture_height,
+            Width: self.width,
+            Height: self.height,
             MipLevels: 1,
             ArraySize: 1,
             Format: DXGI_FORMAT(self.color_format as i32),
@@ -269,7 +732,227 @@ impl<'a> Frame<'a> {
             MiscFlags: 0,
         };
 
-        // Create a texture that CPU can read
+        let mut texture = None;
+        unsafe {
+            self.d3d_device
+                .CreateTexture2D(&texture_desc, None, Some(&mut texture))?;
+        };
+
+        Ok(texture.unwrap())
+    }
+
+    /// Get the frame buffer.
+    ///
+    /// # Returns
+    ///
+    /// The FrameBuffer containing the frame data.
+    pub fn buffer(&mut self) -> Result<FrameBuffer, Error> {
+        let (texture, readback_start) = match self.pending_readback.take() {
+            Some((texture, query, readback_start)) => {
+                // Poll the event query of the queued copy, the map below doesn't have to wait
+                let mut done = BOOL(0);
+                loop {
+                    unsafe {
+                        self.context.GetData(
+                            &query,
+                            Some(ptr::addr_of_mut!(done).cast()),
+                            mem::size_of::<BOOL>() as u32,
+                            D3D11_ASYNC_GETDATA_DONOTFLUSH.0 as u32,
+                        )?;
+                    };
+
+                    if done.as_bool() {
+                        break;
+                    }
+
+                    thread::yield_now();
+                }
+
+                (texture, readback_start)
+            }
+            None => {
+                let texture = self.readback_texture()?;
+
+                // Copy the real texture to copy texture
+                let readback_start = Instant::now();
+                unsafe {
+                    self.context.CopyResource(&texture, &self.frame_texture);
+                };
+
+                (texture, readback_start)
+            }
+        };
+
+        // Map the texture to enable CPU access
+        let mut mapped_resource = D3D11_MAPPED_SUBRESOURCE::default();
+        unsafe {
+            self.context.Map(
+                &texture,
+                0,
+                D3D11_MAP_READ_WRITE,
+                0,
+                Some(&mut mapped_resource),
+            )?;
+        };
+
+        // Map waits for the copy to finish on the GPU unless it was queued earlier
+        self.readback_duration = Some(readback_start.elapsed());
+
+        // Get the mapped resource data slice
+        let mapped_frame_data = unsafe {
+            slice::from_raw_parts_mut(
+                mapped_resource.pData.cast(),
+                (self.height * mapped_resource.RowPitch) as usize,
+            )
+        };
+
+        let color_format = self.convert_to_sdr(
+            mapped_frame_data,
+            mapped_resource.RowPitch as usize,
+            self.width as usize,
+        );
+
+        convert_alpha(
+            mapped_frame_data,
+            mapped_resource.RowPitch as usize,
+            self.width as usize,
+            color_format,
+            self.alpha_mode,
+        );
+
+        // Create frame buffer from slice
+        let frame_buffer = FrameBuffer::new(
+            mapped_frame_data,
+            self.buffer,
+            self.width,
+            self.height,
+            mapped_resource.RowPitch,
+            mapped_resource.DepthPitch,
+            color_format,
+        )
+        .with_sdr_white_level(self.sdr_white_level);
+
+        Ok(frame_buffer)
+    }
+
+    /// Check if the frame is black or nearly black.
+    ///
+    /// Only every eighth pixel of every eighth row is read, so the check is cheap but can miss
+    /// small details on an otherwise black frame.
+    ///
+    /// # Returns
+    ///
+    /// `true` if every sampled pixel is near black, otherwise `false`.
+    pub fn is_black(&mut self) -> Result<bool, Error> {
+        Ok(self.buffer()?.is_black())
+    }
+
+    /// Get the average color of the frame in RGBA order, see `FrameBuffer::mean_color`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_step` - Only every nth pixel of every nth row is read, 1 reads every pixel.
+    ///
+    /// # Returns
+    ///
+    /// The average red, green, blue and alpha values.
+    pub fn mean_color(&mut self, sample_step: usize) -> Result<[u8; 4], Error> {
+        Ok(self.buffer()?.mean_color(sample_step))
+    }
+
+    /// Count the values of every channel and the luminance of the frame, see
+    /// `FrameBuffer::histogram`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_step` - Only every nth pixel of every nth row is read, 1 reads every pixel.
+    ///
+    /// # Returns
+    ///
+    /// The histogram of the sampled pixels.
+    pub fn histogram(&mut self, sample_step: usize) -> Result<Histogram, Error> {
+        Ok(self.buffer()?.histogram(sample_step))
+    }
+
+    /// Get a small copy of the frame for previews, see `FrameBuffer::thumbnail`.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_dim` - The longest side of the thumbnail in pixels.
+    ///
+    /// # Returns
+    ///
+    /// The 8-bit RGBA pixels of the thumbnail without padding.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidSize` if `max_dim` is zero.
+    pub fn thumbnail(&mut self, max_dim: u32) -> Result<Vec<u8>, Error> {
+        if max_dim == 0 {
+            return Err(Error::InvalidSize);
+        }
+
+        Ok(self.buffer()?.thumbnail(max_dim))
+    }
+
+    /// Get a cropped frame buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_width` - The starting width of the cropped frame.
+    /// * `start_height` - The starting height of the cropped frame.
+    /// * `end_width` - The ending width of the cropped frame.
+    /// * `end_height` - The ending height of the cropped frame.
+    ///
+    /// # Returns
+    ///
+    /// The FrameBuffer containing the cropped frame data.
+    pub fn buffer_crop(
+        &mut self,
+        start_width: u32,
+        start_height: u32,
+        end_width: u32,
+        end_height: u32,
+    ) -> Result<FrameBuffer, Error> {
+        self.map_region(start_width, start_height, end_width, end_height, true)
+    }
+
+    // Copy a region of the frame texture to the CPU, with `convert` the pixels are tone mapped
+    // and their alpha converted like they are delivered, otherwise they are left as they are in
+    // the texture.
+    fn map_region(
+        &mut self,
+        start_width: u32,
+        start_height: u32,
+        end_width: u32,
+        end_height: u32,
+        convert: bool,
+    ) -> Result<FrameBuffer, Error> {
+        if start_width >= end_width || start_height >= end_height {
+            return Err(Error::InvalidSize);
+        }
+
+        let texture_width = end_width - start_width;
+        let texture_height = end_height - start_height;
+
+        // Texture Settings
+        let texture_desc = D3D11_TEXTURE2D_DESC {
+            Width: texture_width,
+            Height: texture_height,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: DXGI_FORMAT(self.color_format as i32),
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Usage: D3D11_USAGE_STAGING,
+            BindFlags: 0,
+            CPUAccessFlags: D3D11_CPU_ACCESS_READ.0 as u32 | D3D11_CPU_ACCESS_WRITE.0 as u32,
+            MiscFlags: 0,
+        };
+
+        // Create a texture that CPU can read
         let mut texture = None;
         unsafe {
             self.d3d_device
@@ -288,6 +971,7 @@ impl<'a> Frame<'a> {
         };
 
         // Copy the real texture to copy texture
+        let readback_start = Instant::now();
         unsafe {
             self.context.CopySubresourceRegion(
                 &texture,
@@ -313,6 +997,9 @@ impl<'a> Frame<'a> {
             )?;
         };
 
+        // Map waits for the copy to finish on the GPU
+        self.readback_duration = Some(readback_start.elapsed());
+
         // Get the mapped resource data slice
         let mapped_frame_data = unsafe {
             slice::from_raw_parts_mut(
@@ -321,6 +1008,26 @@ impl<'a> Frame<'a> {
             )
         };
 
+        let color_format = if convert {
+            let color_format = self.convert_to_sdr(
+                mapped_frame_data,
+                mapped_resource.RowPitch as usize,
+                texture_width as usize,
+            );
+
+            convert_alpha(
+                mapped_frame_data,
+                mapped_resource.RowPitch as usize,
+                texture_width as usize,
+                color_format,
+                self.alpha_mode,
+            );
+
+            color_format
+        } else {
+            self.color_format
+        };
+
         // Create frame buffer from slice
         let frame_buffer = FrameBuffer::new(
             mapped_frame_data,
@@ -329,166 +1036,1286 @@ impl<'a> Frame<'a> {
             texture_height,
             mapped_resource.RowPitch,
             mapped_resource.DepthPitch,
-            self.color_format,
-        );
+            color_format,
+        )
+        .with_sdr_white_level(self.sdr_white_level);
 
         Ok(frame_buffer)
     }
 
-    /// Save the frame buffer as an image to the specified path.
-    ///
-    /// # Arguments
-    ///
-    /// * `path` - The path where the image will be saved.
-    /// * `format` - The ImageFormat of the saved image.
+    // Tone map the mapped rows to SDR in place if requested, returns the format of the rows.
+    fn convert_to_sdr(&self, data: &mut [u8], row_pitch: usize, width: usize) -> ColorFormat {
+        match self.sdr_format {
+            Some(sdr_format)
+                if self.color_format == ColorFormat::Rgba16F
+                    && sdr_format != ColorFormat::Rgba16F =>
+            {
+                tone_map_to_sdr(data, row_pitch, width, sdr_format, self.sdr_white_level);
+                sdr_format
+            }
+            _ => self.color_format,
+        }
+    }
+
+    /// Copy the frame into an owned snapshot that can outlive the frame.
     ///
     /// # Returns
     ///
-    /// An empty Result if successful, or an Error if there was an issue saving the image.
-    pub fn save_as_image<T: AsRef<Path>>(
-        &mut self,
-        path: T,
-        format: ImageFormat,
-    ) -> Result<(), Error> {
+    /// The FrameSnapshot containing a copy of the frame data without padding.
+    pub fn snapshot(&mut self) -> Result<FrameSnapshot, Error> {
+        let time = self.time;
+        let cursor_layer = self.cursor_layer.clone();
+
         let mut frame_buffer = self.buffer()?;
 
-        frame_buffer.save_as_image(path, format)?;
+        let width = frame_buffer.width();
+        let height = frame_buffer.height();
+        let color_format = frame_buffer.color_format;
+        let buffer = frame_buffer.as_raw_nopadding_buffer()?.to_vec();
 
-        Ok(())
+        Ok(FrameSnapshot {
+            buffer,
+            width,
+            height,
+            color_format,
+            sdr_white_level: self.sdr_white_level,
+            time,
+            cursor_layer,
+        })
     }
-}
 
-/// Represents a frame buffer containing pixel data.
-///
-/// # Example
-/// ```ignore
-/// // Get frame from the capture session
-/// let mut buffer = frame.buffer()?;
-/// buffer.save_as_image("screenshot.png", ImageFormat::Png)?;
-/// ```
-pub struct FrameBuffer<'a> {
-    raw_buffer: &'a mut [u8],
-    buffer: &'a mut Vec<u8>,
-    width: u32,
-    height: u32,
-    row_pitch: u32,
-    depth_pitch: u32,
-    color_format: ColorFormat,
-}
+    /// Copy the frame into a snapshot in another color format, see
+    /// `FrameSnapshot::to_color_format`.
+    ///
+    /// # Arguments
+    ///
+    /// * `color_format` - The color format of the snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the frame can't be read or converted.
+    pub fn to_color_format(&mut self, color_format: ColorFormat) -> Result<FrameSnapshot, Error> {
+        self.snapshot()?.to_color_format(color_format)
+    }
 
-impl<'a> FrameBuffer<'a> {
-    /// Create a new Frame Buffer.
+    /// Compare the frame with a snapshot of an earlier frame, see `FrameSnapshot::diff`.
     ///
     /// # Arguments
     ///
-    /// * `raw_buffer` - A mutable reference to the raw pixel data buffer.
-    /// * `buffer` - A mutable reference to the buffer used for copying pixel data without padding.
-    /// * `width` - The width of the frame buffer.
-    /// * `height` - The height of the frame buffer.
-    /// * `row_pitch` - The row pitch of the frame buffer.
-    /// * `depth_pitch` - The depth pitch of the frame buffer.
-    /// * `color_format` - The color format of the frame buffer.
+    /// * `other` - The snapshot to compare with.
     ///
     /// # Returns
     ///
-    /// A new `FrameBuffer` instance.
-    #[must_use]
-    pub fn new(
-        raw_buffer: &'a mut [u8],
-        buffer: &'a mut Vec<u8>,
-        width: u32,
-        height: u32,
-        row_pitch: u32,
-        depth_pitch: u32,
-        color_format: ColorFormat,
-    ) -> Self {
-        Self {
-            raw_buffer,
-            buffer,
-            width,
-            height,
-            row_pitch,
-            depth_pitch,
-            color_format,
-        }
+    /// The `DiffResult` without a diff image.
+    pub fn diff(&mut self, other: &FrameSnapshot) -> Result<DiffResult, Error> {
+        self.snapshot()?.diff(other)
     }
 
-    /// Get the width of the frame buffer.
-    #[must_use]
-    pub const fn width(&self) -> u32 {
-        self.width
-    }
+    /// Copy the frame into an owned snapshot with the cursor drawn on top.
+    ///
+    /// The frame itself is not modified, so a capture without the cursor can show the frame in a
+    /// live preview and save screenshots with the cursor. The cursor layer of the frame is used
+    /// when capturing with `CursorCaptureSettings::SeparateLayer`, otherwise the cursor is
+    /// captured when this is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin` - The screen position of the top-left corner of the captured item, for a
+    ///   monitor this is `Monitor::position`. Unused with a cursor layer, it's already placed
+    ///   relative to the frame.
+    ///
+    /// # Returns
+    ///
+    /// The FrameSnapshot with the cursor drawn, the cursor is missing if it's hidden.
+    /// `Error::UnsupportedFormat` if the buffer is `Rgba16F`, frames tone mapped with
+    /// `Settings::hdr_to_sdr` are supported.
+    pub fn with_cursor_drawn(&mut self, origin: (i32, i32)) -> Result<FrameSnapshot, Error> {
+        let (cursor_layer, origin) = match &self.cursor_layer {
+            Some(cursor_layer) => (Some(cursor_layer.clone()), (0, 0)),
+            None => (CursorLayer::capture()?, origin),
+        };
 
-    /// Get the height of the frame buffer.
-    #[must_use]
-    pub const fn height(&self) -> u32 {
-        self.height
-    }
+        let mut snapshot = self.snapshot()?;
 
-    /// Get the row pitch of the frame buffer.
+        // The buffer of an Rgba16F frame is 8-bit when it's tone mapped
+        if snapshot.color_format == ColorFormat::Rgba16F {
+            return Err(Error::UnsupportedFormat);
+        }
+
+        if let Some(cursor_layer) = cursor_layer {
+            snapshot.draw_cursor(&cursor_layer, origin);
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Black out or blur regions of the frame.
+    ///
+    /// The frame texture itself is modified, so the regions are hidden from everything that uses
+    /// the frame afterwards, including `VideoEncoder::send_frame` and `buffer`. Regions are
+    /// clipped to the frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `rects` - The regions to hide.
+    /// * `mode` - How to hide the regions.
+    ///
+    /// # Returns
+    ///
+    /// An empty Result if successful, or an Error if the regions could not be hidden.
+    pub fn redact(&mut self, rects: &[Rect], mode: RedactMode) -> Result<(), Error> {
+        if mode == RedactMode::Blur && self.color_format == ColorFormat::Rgba16F {
+            return Err(Error::UnsupportedFormat);
+        }
+
+        // A queued copy no longer matches the frame
+        self.pending_readback = None;
+
+        let black_pixel: &[u8] = match self.color_format {
+            // 1.0 as a half precision float for the alpha channel
+            ColorFormat::Rgba16F => &[0, 0, 0, 0, 0, 0, 0x00, 0x3C],
+            ColorFormat::Rgba8 | ColorFormat::Bgra8 => &[0, 0, 0, 255],
+        };
+
+        for rect in rects {
+            let left = rect.x.min(self.width);
+            let top = rect.y.min(self.height);
+            let right = rect.x.saturating_add(rect.width).min(self.width);
+            let bottom = rect.y.saturating_add(rect.height).min(self.height);
+
+            if left >= right || top >= bottom {
+                continue;
+            }
+
+            let width = right - left;
+            let height = bottom - top;
+
+            let pixels = match mode {
+                RedactMode::Black => black_pixel.repeat(width as usize * height as usize),
+                RedactMode::Blur => {
+                    let mut pixels = self.read_region(left, top, right, bottom)?;
+                    for _ in 0..REDACT_BLUR_PASSES {
+                        box_blur(&mut pixels, width as usize, height as usize);
+                    }
+                    pixels
+                }
+            };
+
+            let destination_box = D3D11_BOX {
+                left,
+                top,
+                front: 0,
+                right,
+                bottom,
+                back: 1,
+            };
+
+            unsafe {
+                self.context.UpdateSubresource(
+                    &self.frame_texture,
+                    0,
+                    Some(&destination_box),
+                    pixels.as_ptr().cast(),
+                    u32::try_from(pixels.len()).unwrap() / height,
+                    0,
+                );
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Draws text onto the frame, for example to burn a timestamp into a recording.
+    ///
+    /// The text is rendered with DirectWrite into the frame texture itself like `redact`, so it
+    /// is part of everything that uses the frame afterwards, including `VideoEncoder::send_frame`
+    /// and `buffer`. The box of the text is clipped to the frame, `Rgba16F` frames are not
+    /// supported.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to draw, it may contain line breaks.
+    /// * `x` - The left edge of the box of the text.
+    /// * `y` - The top edge of the box of the text.
+    /// * `style` - The font, colors and padding of the text.
+    ///
+    /// # Returns
+    ///
+    /// An empty Result if successful, or an Error if the text could not be drawn.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let time = frame.timespan().Duration;
+    /// frame.draw_text(&format!("{time}"), 16, 16, &TextStyle::default())?;
+    /// ```
+    pub fn draw_text(
+        &mut self,
+        text: &str,
+        x: u32,
+        y: u32,
+        style: &TextStyle,
+    ) -> Result<(), Error> {
+        if self.color_format == ColorFormat::Rgba16F {
+            return Err(Error::UnsupportedFormat);
+        }
+
+        if x >= self.width || y >= self.height || text.is_empty() {
+            return Ok(());
+        }
+
+        let (text_layout, box_width, box_height) = style.layout(text)?;
+
+        // The box of the text, clipped to the frame
+        let width = box_width.min(self.width - x);
+        let height = box_height.min(self.height - y);
+
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        self.draw_2d(x, y, width, height, |render_target| unsafe {
+            if let Some(background) = style.background {
+                let brush = render_target.CreateSolidColorBrush(&color_f(background), None)?;
+                render_target.FillRectangle(
+                    &D2D_RECT_F {
+                        left: 0.0,
+                        top: 0.0,
+                        right: width as f32,
+                        bottom: height as f32,
+                    },
+                    &brush,
+                );
+            }
+
+            let brush = render_target.CreateSolidColorBrush(&color_f(style.color), None)?;
+            render_target.DrawTextLayout(
+                D2D_POINT_2F {
+                    x: style.padding,
+                    y: style.padding,
+                },
+                &text_layout,
+                &brush,
+                D2D1_DRAW_TEXT_OPTIONS_NONE,
+            );
+
+            Ok(())
+        })
+    }
+
+    /// Draws the outline of a circle onto the frame, for example to highlight a click.
+    ///
+    /// The circle is drawn into the frame texture itself like `draw_text` and blended with the
+    /// frame, the parts outside the frame are clipped. `Rgba16F` frames are not supported.
+    ///
+    /// # Arguments
+    ///
+    /// * `center_x` - The horizontal position of the center in pixels.
+    /// * `center_y` - The vertical position of the center in pixels.
+    /// * `radius` - The radius of the circle in pixels.
+    /// * `stroke_width` - The width of the outline in pixels.
+    /// * `color` - The color of the outline in RGBA order.
+    ///
+    /// # Returns
+    ///
+    /// An empty Result if successful, or an Error if the circle could not be drawn.
+    pub fn draw_circle(
+        &mut self,
+        center_x: f32,
+        center_y: f32,
+        radius: f32,
+        stroke_width: f32,
+        color: [u8; 4],
+    ) -> Result<(), Error> {
+        if self.color_format == ColorFormat::Rgba16F {
+            return Err(Error::UnsupportedFormat);
+        }
+
+        // The bounds of the circle and its outline, clipped to the frame
+        let extent = radius + stroke_width / 2.0;
+        let left = (center_x - extent).floor().clamp(0.0, self.width as f32) as u32;
+        let top = (center_y - extent).floor().clamp(0.0, self.height as f32) as u32;
+        let right = (center_x + extent).ceil().clamp(0.0, self.width as f32) as u32;
+        let bottom = (center_y + extent).ceil().clamp(0.0, self.height as f32) as u32;
+
+        if left >= right || top >= bottom {
+            return Ok(());
+        }
+
+        self.draw_2d(
+            left,
+            top,
+            right - left,
+            bottom - top,
+            |render_target| unsafe {
+                let brush = render_target.CreateSolidColorBrush(&color_f(color), None)?;
+                render_target.DrawEllipse(
+                    &D2D1_ELLIPSE {
+                        point: D2D_POINT_2F {
+                            x: center_x - left as f32,
+                            y: center_y - top as f32,
+                        },
+                        radiusX: radius,
+                        radiusY: radius,
+                    },
+                    &brush,
+                    stroke_width,
+                    None,
+                );
+
+                Ok(())
+            },
+        )
+    }
+
+    // Draw with Direct2D on a region of the frame texture, the region is copied to a render
+    // target so drawings blend with the frame and copied back after drawing.
+    fn draw_2d(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        draw: impl FnOnce(&ID2D1RenderTarget) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        // A queued copy no longer matches the frame
+        self.pending_readback = None;
+
+        let texture_desc = D3D11_TEXTURE2D_DESC {
+            Width: width,
+            Height: height,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: DXGI_FORMAT(self.color_format as i32),
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Usage: D3D11_USAGE_DEFAULT,
+            BindFlags: D3D11_BIND_RENDER_TARGET.0 as u32,
+            CPUAccessFlags: 0,
+            MiscFlags: 0,
+        };
+
+        let mut texture = None;
+        unsafe {
+            self.d3d_device
+                .CreateTexture2D(&texture_desc, None, Some(&mut texture))?;
+        };
+        let texture = texture.unwrap();
+
+        let region_box = D3D11_BOX {
+            left: x,
+            top: y,
+            front: 0,
+            right: x + width,
+            bottom: y + height,
+            back: 1,
+        };
+
+        unsafe {
+            self.context.CopySubresourceRegion(
+                &texture,
+                0,
+                0,
+                0,
+                0,
+                &self.frame_texture,
+                0,
+                Some(&region_box),
+            );
+        };
+
+        let d2d_factory: ID2D1Factory =
+            unsafe { D2D1CreateFactory(D2D1_FACTORY_TYPE_SINGLE_THREADED, None)? };
+        let surface: IDXGISurface = texture.cast()?;
+        let render_target = unsafe {
+            d2d_factory.CreateDxgiSurfaceRenderTarget(
+                &surface,
+                &D2D1_RENDER_TARGET_PROPERTIES {
+                    r#type: D2D1_RENDER_TARGET_TYPE_DEFAULT,
+                    pixelFormat: D2D1_PIXEL_FORMAT {
+                        format: DXGI_FORMAT(self.color_format as i32),
+                        alphaMode: D2D1_ALPHA_MODE_PREMULTIPLIED,
+                    },
+                    // 96 DPI makes one device independent pixel one pixel
+                    dpiX: 96.0,
+                    dpiY: 96.0,
+                    usage: D2D1_RENDER_TARGET_USAGE_NONE,
+                    minLevel: D2D1_FEATURE_LEVEL_DEFAULT,
+                },
+            )?
+        };
+
+        unsafe { render_target.BeginDraw() };
+        let result = draw(&render_target);
+        unsafe { render_target.EndDraw(None, None)? };
+        result?;
+
+        unsafe {
+            self.context
+                .CopySubresourceRegion(&self.frame_texture, 0, x, y, 0, &texture, 0, None);
+        };
+
+        Ok(())
+    }
+
+    // Read a region of the frame texture without padding.
+    fn read_region(
+        &mut self,
+        left: u32,
+        top: u32,
+        right: u32,
+        bottom: u32,
+    ) -> Result<Vec<u8>, Error> {
+        // The pixels are written back to the texture, so they stay premultiplied and in the format
+        // of the texture
+        let mut frame_buffer = self.map_region(left, top, right, bottom, false)?;
+        let pixels = frame_buffer.as_raw_nopadding_buffer()?.to_vec();
+
+        Ok(pixels)
+    }
+
+    /// Save the frame buffer as an image to the specified path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path where the image will be saved.
+    /// * `format` - The ImageFormat of the saved image.
+    ///
+    /// # Returns
+    ///
+    /// An empty Result if successful, or an Error if there was an issue saving the image.
+    pub fn save_as_image<T: AsRef<Path>>(
+        &mut self,
+        path: T,
+        format: ImageFormat,
+    ) -> Result<(), Error> {
+        let mut frame_buffer = self.buffer()?;
+
+        frame_buffer.save_as_image(path, format)?;
+
+        Ok(())
+    }
+
+    /// Save as an image, the format is picked from the file extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path where the image will be saved, for example `screenshot.png`.
+    ///
+    /// # Returns
+    ///
+    /// An empty Result if successful, or an Error if the extension is unknown or there was an
+    /// issue saving the image.
+    pub fn save<T: AsRef<Path>>(&mut self, path: T) -> Result<(), Error> {
+        let format = ImageFormat::from_path(&path)?;
+
+        self.save_as_image(path, format)
+    }
+
+    /// Encode the frame as a PNG image without row filtering, which is much faster than
+    /// `save_as_image` for large frames at the cost of bigger files.
+    ///
+    /// # Returns
+    ///
+    /// The PNG bytes, or an Error if the color format is unsupported or encoding failed.
+    pub fn to_png_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        self.buffer()?.to_png_bytes()
+    }
+
+    /// Compute the SHA-256 digest of the frame pixels, see `FrameBuffer::sha256`.
+    ///
+    /// # Returns
+    ///
+    /// The 32 byte digest, or an Error if reading the frame buffer failed.
+    pub fn sha256(&mut self) -> Result<[u8; 32], Error> {
+        self.buffer()?.sha256()
+    }
+
+    /// Encode the frame as an image and wrap it in a base64 data URI, for example to show the
+    /// frame in a web page.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The image format to encode the frame as.
+    ///
+    /// # Returns
+    ///
+    /// The data URI, for example `data:image/png;base64,...`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the frame can't be read or encoded.
+    pub fn to_data_uri(&mut self, format: ImageFormat) -> Result<String, Error> {
+        self.buffer()?.to_data_uri(format)
+    }
+}
+
+/// Represents a frame buffer containing pixel data.
+///
+/// # Example
+/// ```ignore
+/// // Get frame from the capture session
+/// let mut buffer = frame.buffer()?;
+/// buffer.save_as_image("screenshot.png", ImageFormat::Png)?;
+/// ```
+pub struct FrameBuffer<'a> {
+    raw_buffer: &'a mut [u8],
+    buffer: &'a mut Vec<u8>,
+    width: u32,
+    height: u32,
+    row_pitch: u32,
+    depth_pitch: u32,
+    color_format: ColorFormat,
+    sdr_white_level: u32,
+}
+
+impl<'a> FrameBuffer<'a> {
+    /// Create a new Frame Buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `raw_buffer` - A mutable reference to the raw pixel data buffer.
+    /// * `buffer` - A mutable reference to the buffer used for copying pixel data without padding.
+    /// * `width` - The width of the frame buffer.
+    /// * `height` - The height of the frame buffer.
+    /// * `row_pitch` - The row pitch of the frame buffer.
+    /// * `depth_pitch` - The depth pitch of the frame buffer.
+    /// * `color_format` - The color format of the frame buffer.
+    ///
+    /// # Returns
+    ///
+    /// A new `FrameBuffer` instance.
+    #[must_use]
+    pub fn new(
+        raw_buffer: &'a mut [u8],
+        buffer: &'a mut Vec<u8>,
+        width: u32,
+        height: u32,
+        row_pitch: u32,
+        depth_pitch: u32,
+        color_format: ColorFormat,
+    ) -> Self {
+        Self {
+            raw_buffer,
+            buffer,
+            width,
+            height,
+            row_pitch,
+            depth_pitch,
+            color_format,
+            sdr_white_level: SDR_REFERENCE_WHITE_LEVEL,
+        }
+    }
+
+    /// Set the brightness SDR content is composed at, `Rgba16F` pixels are divided by it when
+    /// they are tone mapped to 8-bit, see `Frame::with_sdr_white_level`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sdr_white_level` - The brightness of SDR white in nits.
+    #[must_use]
+    pub fn with_sdr_white_level(mut self, sdr_white_level: u32) -> Self {
+        self.sdr_white_level = sdr_white_level.max(1);
+        self
+    }
+
+    /// Get the width of the frame buffer.
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of the frame buffer.
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the row pitch of the frame buffer.
+    #[must_use]
+    pub const fn row_pitch(&self) -> u32 {
+        self.row_pitch
+    }
+
+    /// Get the depth pitch of the frame buffer.
+    #[must_use]
+    pub const fn depth_pitch(&self) -> u32 {
+        self.depth_pitch
+    }
+
+    /// Get the color format of the frame buffer.
+    #[must_use]
+    pub const fn color_format(&self) -> ColorFormat {
+        self.color_format
+    }
+
+    /// Check if the buffer has padding.
+    #[must_use]
+    pub const fn has_padding(&self) -> bool {
+        self.width * 4 != self.row_pitch
+    }
+
+    /// Check if the frame buffer is black or nearly black, only every eighth pixel of every
+    /// eighth row is read.
+    #[must_use]
+    pub fn is_black(&self) -> bool {
+        let pixel_size = match self.color_format {
+            ColorFormat::Rgba16F => 8,
+            ColorFormat::Rgba8 | ColorFormat::Bgra8 => 4,
+        };
+
+        self.raw_buffer
+            .chunks(self.row_pitch as usize)
+            .take(self.height as usize)
+            .step_by(BLACK_SAMPLE_STEP)
+            .all(|row| {
+                row[..self.width as usize * pixel_size]
+                    .chunks_exact(pixel_size)
+                    .step_by(BLACK_SAMPLE_STEP)
+                    .all(|pixel| match self.color_format {
+                        // The alpha channel is ignored
+                        ColorFormat::Rgba16F => pixel[..6].chunks_exact(2).all(|channel| {
+                            u16::from_le_bytes([channel[0], channel[1]]) & 0x7FFF
+                                <= BLACK_THRESHOLD_F16
+                        }),
+                        ColorFormat::Rgba8 | ColorFormat::Bgra8 => {
+                            pixel[..3].iter().all(|&channel| channel <= BLACK_THRESHOLD)
+                        }
+                    })
+            })
+    }
+
+    /// Get the average color of the frame buffer in RGBA order whatever the color format,
+    /// `Rgba16F` pixels are tone mapped to sRGB like with `Settings::hdr_to_sdr`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_step` - Only every nth pixel of every nth row is read, 1 reads every pixel.
+    #[must_use]
+    pub fn mean_color(&self, sample_step: usize) -> [u8; 4] {
+        let mut sums = [0u64; 4];
+        let mut count = 0u64;
+
+        self.for_each_sample(sample_step, |rgba| {
+            for (sum, channel) in sums.iter_mut().zip(rgba) {
+                *sum += u64::from(channel);
+            }
+            count += 1;
+        });
+
+        if count == 0 {
+            return [0; 4];
+        }
+
+        sums.map(|sum| u8::try_from((sum + count / 2) / count).unwrap_or(u8::MAX))
+    }
+
+    /// Count the values of every channel and the luminance of the frame buffer, the pixels are
+    /// read like with `mean_color`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_step` - Only every nth pixel of every nth row is read, 1 reads every pixel.
+    #[must_use]
+    pub fn histogram(&self, sample_step: usize) -> Histogram {
+        let mut histogram = Histogram {
+            red: [0; 256],
+            green: [0; 256],
+            blue: [0; 256],
+            luminance: [0; 256],
+            samples: 0,
+        };
+
+        self.for_each_sample(sample_step, |[red, green, blue, _]| {
+            histogram.red[usize::from(red)] += 1;
+            histogram.green[usize::from(green)] += 1;
+            histogram.blue[usize::from(blue)] += 1;
+
+            // Rec. 709 weights in 1/256 steps
+            let luminance =
+                (54 * u32::from(red) + 183 * u32::from(green) + 19 * u32::from(blue)) >> 8;
+            histogram.luminance[luminance as usize] += 1;
+
+            histogram.samples += 1;
+        });
+
+        histogram
+    }
+
+    /// Shrink the frame buffer to fit within a square of `max_dim` pixels, every thumbnail pixel
+    /// is the average of the box of pixels it covers and the pixels are converted like with
+    /// `mean_color`.
+    ///
+    /// The longer side becomes `max_dim` and the shorter side is scaled by the same factor,
+    /// rounded and at least one pixel. A frame buffer that already fits keeps its size.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_dim` - The longest side of the thumbnail in pixels, zero returns no pixels.
+    ///
+    /// # Returns
+    ///
+    /// The 8-bit RGBA pixels of the thumbnail without padding.
+    #[must_use]
+    pub fn thumbnail(&self, max_dim: u32) -> Vec<u8> {
+        let (thumbnail_width, thumbnail_height) = thumbnail_size(self.width, self.height, max_dim);
+        if thumbnail_width == 0 || thumbnail_height == 0 {
+            return Vec::new();
+        }
+
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let thumbnail_width = thumbnail_width as usize;
+        let thumbnail_height = thumbnail_height as usize;
+        let pixel_size = self.color_format.bytes_per_pixel();
+
+        // The first source column of every thumbnail column, the last one ends the last column
+        let columns = (0..=thumbnail_width)
+            .map(|x| x * width / thumbnail_width)
+            .collect::<Vec<_>>();
+
+        let mut thumbnail = vec![0; thumbnail_width * thumbnail_height * 4];
+        thumbnail
+            .par_chunks_mut(thumbnail_width * 4)
+            .enumerate()
+            .for_each(|(y, thumbnail_row)| {
+                let top = y * height / thumbnail_height;
+                let bottom = (y + 1) * height / thumbnail_height;
+
+                let mut sums = vec![[0u64; 4]; thumbnail_width];
+                for row in self
+                    .raw_buffer
+                    .chunks(self.row_pitch as usize)
+                    .skip(top)
+                    .take(bottom - top)
+                {
+                    for (x, sum) in sums.iter_mut().enumerate() {
+                        for pixel in row[columns[x] * pixel_size..columns[x + 1] * pixel_size]
+                            .chunks_exact(pixel_size)
+                        {
+                            for (sum, channel) in sum.iter_mut().zip(rgba8(
+                                pixel,
+                                self.color_format,
+                                self.sdr_white_level,
+                            )) {
+                                *sum += u64::from(channel);
+                            }
+                        }
+                    }
+                }
+
+                for (x, (sum, pixel)) in sums
+                    .iter()
+                    .zip(thumbnail_row.chunks_exact_mut(4))
+                    .enumerate()
+                {
+                    let count = ((bottom - top) * (columns[x + 1] - columns[x])) as u64;
+                    for (channel, sum) in pixel.iter_mut().zip(sum) {
+                        *channel = u8::try_from((sum + count / 2) / count).unwrap_or(u8::MAX);
+                    }
+                }
+            });
+
+        thumbnail
+    }
+
+    // Call a function with every sampled pixel as 8-bit RGBA.
+    fn for_each_sample(&self, sample_step: usize, mut f: impl FnMut([u8; 4])) {
+        let sample_step = sample_step.max(1);
+        let pixel_size = self.color_format.bytes_per_pixel();
+
+        for row in self
+            .raw_buffer
+            .chunks(self.row_pitch as usize)
+            .take(self.height as usize)
+            .step_by(sample_step)
+        {
+            for pixel in row[..self.width as usize * pixel_size]
+                .chunks_exact(pixel_size)
+                .step_by(sample_step)
+            {
+                f(rgba8(pixel, self.color_format, self.sdr_white_level));
+            }
+        }
+    }
+
+    /// Get the raw pixel data with possible padding.
+    #[must_use]
+    pub fn as_raw_buffer(&mut self) -> &mut [u8] {
+        self.raw_buffer
+    }
+
+    /// Get the raw pixel data without padding.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the buffer containing pixel data without padding.
+    pub fn as_raw_nopadding_buffer(&mut self) -> Result<&mut [u8], Error> {
+        if !self.has_padding() {
+            return Ok(self.raw_buffer);
+        }
+
+        let multiplyer = match self.color_format {
+            ColorFormat::Rgba16F => 8,
+            ColorFormat::Rgba8 => 4,
+            ColorFormat::Bgra8 => 4,
+        };
+
+        let frame_size = (self.width * self.height * multiplyer) as usize;
+        if self.buffer.capacity() < frame_size {
+            self.buffer.resize(frame_size, 0);
+        }
+
+        let width_size = (self.width * multiplyer) as usize;
+        let buffer_address = self.buffer.as_mut_ptr() as isize;
+        (0..self.height).into_par_iter().for_each(|y| {
+            let index = (y * self.row_pitch) as usize;
+            let ptr = buffer_address as *mut u8;
+
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    self.raw_buffer.as_ptr().add(index),
+                    ptr.add(y as usize * width_size),
+                    width_size,
+                );
+            }
+        });
+
+        Ok(&mut self.buffer[0..frame_size])
+    }
+
+    /// Save the frame buffer as an image to the specified path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path where the image will be saved.
+    /// * `format` - The image format to use for saving.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result if the image is successfully saved, or an `Err` result if there was an error.
+    pub fn save_as_image<T: AsRef<Path>>(
+        &mut self,
+        path: T,
+        format: ImageFormat,
+    ) -> Result<(), Error> {
+        let width = self.width;
+        let height = self.height;
+
+        let bytes = ImageEncoder::new(format, self.color_format).encode(
+            self.as_raw_nopadding_buffer()?,
+            width,
+            height,
+        )?;
+
+        fs::write(path, bytes)?;
+
+        Ok(())
+    }
+
+    /// Save as an image, the format is picked from the file extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path where the image will be saved, for example `screenshot.png`.
+    ///
+    /// # Returns
+    ///
+    /// An empty Result if successful, or an Error if the extension is unknown or there was an
+    /// issue saving the image.
+    pub fn save<T: AsRef<Path>>(&mut self, path: T) -> Result<(), Error> {
+        let format = ImageFormat::from_path(&path)?;
+
+        self.save_as_image(path, format)
+    }
+
+    /// Encode the frame buffer as a PNG image without row filtering, which is much faster than
+    /// `save_as_image` for large frames at the cost of bigger files.
+    ///
+    /// # Returns
+    ///
+    /// The PNG bytes, or an Error if the color format is unsupported or encoding failed.
+    pub fn to_png_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        let width = self.width;
+        let height = self.height;
+
+        let bytes = ImageEncoder::new(ImageFormat::Png, self.color_format)
+            .fast_png(true)
+            .encode(self.as_raw_nopadding_buffer()?, width, height)?;
+
+        Ok(bytes)
+    }
+
+    /// Compute the SHA-256 digest of the packed frame buffer, the row padding is excluded so
+    /// the digest only depends on the pixels and is reproducible across GPUs.
+    ///
+    /// Unlike `Frame::snapshot` diffing this is a cryptographic digest, suitable for proving a
+    /// recording wasn't altered.
+    ///
+    /// # Returns
+    ///
+    /// The 32 byte digest, or an Error if hashing failed.
+    pub fn sha256(&mut self) -> Result<[u8; 32], Error> {
+        let provider = HashAlgorithmProvider::OpenAlgorithm(&HashAlgorithmNames::Sha256()?)?;
+        let buffer = CryptographicBuffer::CreateFromByteArray(self.as_raw_nopadding_buffer()?)?;
+        let digest_buffer = provider.HashData(&buffer)?;
+
+        let mut digest_bytes = Array::<u8>::new();
+        CryptographicBuffer::CopyToByteArray(&digest_buffer, &mut digest_bytes)?;
+
+        let mut digest = [0; 32];
+        digest.copy_from_slice(&digest_bytes);
+
+        Ok(digest)
+    }
+
+    /// Encode the frame buffer as an image and wrap it in a base64 data URI, for example to show
+    /// the frame in a web page.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The image format to encode the frame buffer as.
+    ///
+    /// # Returns
+    ///
+    /// The data URI, for example `data:image/png;base64,...`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the frame buffer can't be encoded.
+    pub fn to_data_uri(&mut self, format: ImageFormat) -> Result<String, Error> {
+        let width = self.width;
+        let height = self.height;
+
+        let bytes = ImageEncoder::new(format, self.color_format).encode(
+            self.as_raw_nopadding_buffer()?,
+            width,
+            height,
+        )?;
+
+        let base64 = CryptographicBuffer::EncodeToBase64String(
+            &CryptographicBuffer::CreateFromByteArray(&bytes)?,
+        )?;
+
+        Ok(format!("data:{};base64,{base64}", format.mime_type()))
+    }
+}
+
+/// Keeps a captured frame and its texture alive, the frame pool buffer is recycled when the
+/// guard is dropped.
+///
+/// # Example
+/// ```ignore
+/// // Keep the texture of the frame for the render thread
+/// if let Some(guard) = frame.guard() {
+///     render_sender.send(guard)?;
+/// }
+/// ```
+pub struct FrameGuard {
+    capture_frame: Direct3D11CaptureFrame,
+    texture: ID3D11Texture2D,
+    time: TimeSpan,
+    width: u32,
+    height: u32,
+    color_format: ColorFormat,
+}
+
+impl FrameGuard {
+    /// Get the width of the frame texture.
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of the frame texture.
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the color format of the frame.
+    #[must_use]
+    pub const fn color_format(&self) -> ColorFormat {
+        self.color_format
+    }
+
+    /// Get the timespan of the frame.
+    #[must_use]
+    pub const fn timespan(&self) -> TimeSpan {
+        self.time
+    }
+
+    /// Get the raw surface of the frame.
+    ///
+    /// # Returns
+    ///
+    /// The IDirect3DSurface of the frame, valid for as long as the guard exists.
+    pub fn surface(&self) -> Result<IDirect3DSurface, Error> {
+        Ok(self.capture_frame.Surface()?)
+    }
+
+    /// Get the raw texture of the frame.
+    ///
+    /// # Returns
+    ///
+    /// The ID3D11Texture2D of the frame.
+    ///
+    /// # Safety
+    ///
+    /// The texture must not be used after the guard is dropped, the frame pool writes later
+    /// frames into it.
+    #[must_use]
+    pub unsafe fn as_raw_texture(&self) -> ID3D11Texture2D {
+        self.texture.clone()
+    }
+}
+
+/// A GPU copy of a frame that can be opened by other devices through an NT handle.
+///
+/// # Example
+/// ```ignore
+/// // Share the frame with the renderer
+/// let shared = frame.shared_texture()?;
+/// let resource: ID3D12Resource = unsafe { d3d12_device.OpenSharedHandle(shared.handle())? };
+/// ```
+pub struct SharedTexture {
+    handle: HANDLE,
+    texture: ID3D11Texture2D,
+    width: u32,
+    height: u32,
+    color_format: ColorFormat,
+}
+
+unsafe impl Send for SharedTexture {}
+
+impl SharedTexture {
+    /// Get the NT handle of the texture, it's only valid while the `SharedTexture` exists.
+    #[must_use]
+    pub const fn handle(&self) -> HANDLE {
+        self.handle
+    }
+
+    /// Get the width of the texture.
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of the texture.
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the color format of the texture, it matches the DXGI format with the same value.
+    #[must_use]
+    pub const fn color_format(&self) -> ColorFormat {
+        self.color_format
+    }
+
+    /// Get the raw texture on the capture device.
+    #[must_use]
+    pub fn as_raw_texture(&self) -> ID3D11Texture2D {
+        self.texture.clone()
+    }
+}
+
+impl Drop for SharedTexture {
+    fn drop(&mut self) {
+        let _ = unsafe { CloseHandle(self.handle) };
+    }
+}
+
+/// Represents an owned copy of a frame that isn't tied to the capture thread.
+///
+/// # Example
+/// ```ignore
+/// // Get the latest frame from another thread
+/// if let Some(snapshot) = capture_control.latest_frame() {
+///     snapshot.save_as_image("thumbnail.png", ImageFormat::Png)?;
+/// }
+/// ```
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct FrameSnapshot {
+    buffer: Vec<u8>,
+    width: u32,
+    height: u32,
+    color_format: ColorFormat,
+    sdr_white_level: u32,
+    time: TimeSpan,
+    cursor_layer: Option<CursorLayer>,
+}
+
+impl FrameSnapshot {
+    // Create a snapshot from a buffer without padding.
+    pub(crate) const fn new(
+        buffer: Vec<u8>,
+        width: u32,
+        height: u32,
+        color_format: ColorFormat,
+        time: TimeSpan,
+        cursor_layer: Option<CursorLayer>,
+    ) -> Self {
+        Self {
+            buffer,
+            width,
+            height,
+            color_format,
+            sdr_white_level: SDR_REFERENCE_WHITE_LEVEL,
+            time,
+            cursor_layer,
+        }
+    }
+
+    /// Get the width of the snapshot.
     #[must_use]
-    pub const fn row_pitch(&self) -> u32 {
-        self.row_pitch
+    pub const fn width(&self) -> u32 {
+        self.width
     }
 
-    /// Get the depth pitch of the frame buffer.
+    /// Get the height of the snapshot.
     #[must_use]
-    pub const fn depth_pitch(&self) -> u32 {
-        self.depth_pitch
+    pub const fn height(&self) -> u32 {
+        self.height
     }
 
-    /// Check if the buffer has padding.
+    /// Get the color format of the snapshot.
     #[must_use]
-    pub const fn has_padding(&self) -> bool {
-        self.width * 4 != self.row_pitch
+    pub const fn color_format(&self) -> ColorFormat {
+        self.color_format
     }
 
-    /// Get the raw pixel data with possible padding.
+    /// Get the time of the frame the snapshot was taken from.
     #[must_use]
-    pub fn as_raw_buffer(&mut self) -> &mut [u8] {
-        self.raw_buffer
+    pub const fn timespan(&self) -> TimeSpan {
+        self.time
     }
 
-    /// Get the raw pixel data without padding.
+    /// Get the cursor layer of the frame the snapshot was taken from.
+    #[must_use]
+    pub const fn cursor_layer(&self) -> Option<&CursorLayer> {
+        self.cursor_layer.as_ref()
+    }
+
+    /// Get the pixel data without padding.
+    #[must_use]
+    pub fn as_raw_buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Consume the snapshot and return the pixel data without padding.
+    #[must_use]
+    pub fn into_raw_buffer(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    /// Convert the snapshot to another color format, the channels are reordered as described by
+    /// `ColorFormat::channel_order`.
     ///
-    /// # Returns
+    /// `ColorFormat::Rgba16F` snapshots are tone mapped to 8-bit with dithering like
+    /// `Settings::hdr_to_sdr`.
     ///
-    /// A mutable reference to the buffer containing pixel data without padding.
-    pub fn as_raw_nopadding_buffer(&mut self) -> Result<&mut [u8], Error> {
-        if !self.has_padding() {
-            return Ok(self.raw_buffer);
-        }
+    /// # Arguments
+    ///
+    /// * `color_format` - The color format to convert to.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::UnsupportedConversion` when converting an 8-bit snapshot to
+    /// `ColorFormat::Rgba16F`.
+    pub fn to_color_format(&self, color_format: ColorFormat) -> Result<Self, Error> {
+        let width = self.width as usize;
 
-        let multiplyer = match self.color_format {
-            ColorFormat::Rgba16F => 8,
-            ColorFormat::Rgba8 => 4,
-            ColorFormat::Bgra8 => 4,
+        let buffer = match (self.color_format, color_format) {
+            (from, to) if from == to => self.buffer.clone(),
+            (ColorFormat::Rgba16F, to) if width > 0 => {
+                let mut buffer = self.buffer.clone();
+                tone_map_to_sdr(&mut buffer, width * 8, width, to, self.sdr_white_level);
+
+                // The 8-bit pixels are packed at the start of the half float rows
+                buffer
+                    .chunks_exact(width * 8)
+                    .flat_map(|row| &row[..width * 4])
+                    .copied()
+                    .collect()
+            }
+            (from, ColorFormat::Rgba16F) => {
+                return Err(Error::UnsupportedConversion(from, ColorFormat::Rgba16F));
+            }
+            (from, to) => {
+                let from_order = from.channel_order();
+                let order = to.channel_order().map(|channel| {
+                    from_order
+                        .iter()
+                        .position(|from_channel| *from_channel == channel)
+                        .unwrap()
+                });
+
+                let mut buffer = vec![0; self.buffer.len()];
+                buffer
+                    .par_chunks_exact_mut(4)
+                    .zip(self.buffer.par_chunks_exact(4))
+                    .for_each(|(target, source)| {
+                        for (target, index) in target.iter_mut().zip(order) {
+                            *target = source[index];
+                        }
+                    });
+
+                buffer
+            }
         };
 
-        let frame_size = (self.width * self.height * multiplyer) as usize;
-        if self.buffer.capacity() < frame_size {
-            self.buffer.resize(frame_size, 0);
-        }
+        Ok(Self {
+            buffer,
+            width: self.width,
+            height: self.height,
+            color_format,
+            sdr_white_level: self.sdr_white_level,
+            time: self.time,
+            cursor_layer: self.cursor_layer.clone(),
+        })
+    }
 
-        let width_size = (self.width * multiplyer) as usize;
-        let buffer_address = self.buffer.as_mut_ptr() as isize;
-        (0..self.height).into_par_iter().for_each(|y| {
-            let index = (y * self.row_pitch) as usize;
-            let ptr = buffer_address as *mut u8;
+    // Blend the cursor on top of the 8-bit pixels.
+    fn draw_cursor(&mut self, cursor_layer: &CursorLayer, origin: (i32, i32)) {
+        let cursor_x = i64::from(cursor_layer.x()) - i64::from(origin.0);
+        let cursor_y = i64::from(cursor_layer.y()) - i64::from(origin.1);
+        let cursor_width = cursor_layer.width() as usize;
 
-            unsafe {
-                ptr::copy_nonoverlapping(
-                    self.raw_buffer.as_ptr().add(index),
-                    ptr.add(y as usize * width_size),
-                    width_size,
-                );
+        for (index, cursor_pixel) in cursor_layer.buffer().chunks_exact(4).enumerate() {
+            let x = cursor_x + (index % cursor_width) as i64;
+            let y = cursor_y + (index / cursor_width) as i64;
+
+            if x < 0 || y < 0 || x >= i64::from(self.width) || y >= i64::from(self.height) {
+                continue;
             }
-        });
 
-        Ok(&mut self.buffer[0..frame_size])
+            let offset = (y as usize * self.width as usize + x as usize) * 4;
+            let pixel = &mut self.buffer[offset..offset + 4];
+
+            let [red, green, blue, alpha] = [
+                cursor_pixel[0],
+                cursor_pixel[1],
+                cursor_pixel[2],
+                cursor_pixel[3],
+            ];
+            let alpha = u32::from(alpha);
+            let color = match self.color_format {
+                ColorFormat::Bgra8 => [blue, green, red],
+                ColorFormat::Rgba8 | ColorFormat::Rgba16F => [red, green, blue],
+            };
+
+            for (channel, value) in pixel.iter_mut().zip(color) {
+                *channel = u8::try_from(
+                    (u32::from(value) * alpha + u32::from(*channel) * (255 - alpha)) / 255,
+                )
+                .unwrap();
+            }
+        }
     }
 
-    /// Save the frame buffer as an image to the specified path.
+    /// Save the snapshot as an image to the specified path.
     ///
     /// # Arguments
     ///
@@ -498,22 +2325,462 @@ impl<'a> FrameBuffer<'a> {
     /// # Returns
     ///
     /// An `Ok` result if the image is successfully saved, or an `Err` result if there was an error.
-    pub fn save_as_image<T: AsRef<Path>>(
-        &mut self,
-        path: T,
-        format: ImageFormat,
-    ) -> Result<(), Error> {
-        let width = self.width;
-        let height = self.height;
-
+    pub fn save_as_image<T: AsRef<Path>>(&self, path: T, format: ImageFormat) -> Result<(), Error> {
         let bytes = ImageEncoder::new(format, self.color_format).encode(
-            self.as_raw_nopadding_buffer()?,
-            width,
-            height,
+            &self.buffer,
+            self.width,
+            self.height,
         )?;
 
         fs::write(path, bytes)?;
 
         Ok(())
     }
+
+    /// Save the snapshot as an image, the format is picked from the file extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path where the image will be saved, for example `screenshot.png`.
+    ///
+    /// # Returns
+    ///
+    /// An `Ok` result if the image is successfully saved, or an `Err` result if the extension is
+    /// unknown or there was an error.
+    pub fn save<T: AsRef<Path>>(&self, path: T) -> Result<(), Error> {
+        let format = ImageFormat::from_path(&path)?;
+
+        self.save_as_image(path, format)
+    }
+
+    /// Compare the snapshot with another snapshot of the same size and color format, pixels are
+    /// compared exactly.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The snapshot to compare with.
+    ///
+    /// # Returns
+    ///
+    /// The `DiffResult` without a diff image.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FrameMismatch` if the snapshots have a different size or color format.
+    pub fn diff(&self, other: &Self) -> Result<DiffResult, Error> {
+        let bytes_per_pixel = self.check_comparable(other)?;
+
+        let differing_pixels = self
+            .buffer
+            .par_chunks_exact(bytes_per_pixel)
+            .zip(other.buffer.par_chunks_exact(bytes_per_pixel))
+            .filter(|(pixel, other_pixel)| pixel != other_pixel)
+            .count();
+
+        Ok(DiffResult {
+            differing_pixels: differing_pixels as u64,
+            total_pixels: u64::from(self.width) * u64::from(self.height),
+            diff_image: None,
+        })
+    }
+
+    /// Compare the snapshot with another snapshot and generate an image highlighting the
+    /// changes, differing pixels are red and the other pixels are a dimmed grayscale copy of
+    /// this snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The snapshot to compare with.
+    ///
+    /// # Returns
+    ///
+    /// The `DiffResult` with a diff image in the color format of the snapshots.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FrameMismatch` if the snapshots have a different size or color format,
+    /// or `Error::UnsupportedFormat` for `Rgba16F` snapshots.
+    pub fn diff_with_image(&self, other: &Self) -> Result<DiffResult, Error> {
+        self.check_comparable(other)?;
+
+        let red = match self.color_format {
+            ColorFormat::Rgba8 => [255, 0, 0, 255],
+            ColorFormat::Bgra8 => [0, 0, 255, 255],
+            ColorFormat::Rgba16F => return Err(Error::UnsupportedFormat),
+        };
+
+        let mut buffer = vec![0u8; self.buffer.len()];
+        let differing_pixels = buffer
+            .par_chunks_exact_mut(4)
+            .zip(self.buffer.par_chunks_exact(4))
+            .zip(other.buffer.par_chunks_exact(4))
+            .filter_map(|((target, pixel), other_pixel)| {
+                if pixel == other_pixel {
+                    let gray = u8::try_from(
+                        (u32::from(pixel[0]) + u32::from(pixel[1]) + u32::from(pixel[2])) / 12,
+                    )
+                    .unwrap();
+                    target.copy_from_slice(&[gray, gray, gray, 255]);
+
+                    None
+                } else {
+                    target.copy_from_slice(&red);
+
+                    Some(())
+                }
+            })
+            .count();
+
+        Ok(DiffResult {
+            differing_pixels: differing_pixels as u64,
+            total_pixels: u64::from(self.width) * u64::from(self.height),
+            diff_image: Some(Self::new(
+                buffer,
+                self.width,
+                self.height,
+                self.color_format,
+                self.time,
+                None,
+            )),
+        })
+    }
+
+    // Check that two snapshots can be compared and get their number of bytes per pixel.
+    fn check_comparable(&self, other: &Self) -> Result<usize, Error> {
+        if self.width != other.width
+            || self.height != other.height
+            || self.color_format != other.color_format
+        {
+            return Err(Error::FrameMismatch);
+        }
+
+        Ok(match self.color_format {
+            ColorFormat::Rgba16F => 8,
+            ColorFormat::Rgba8 | ColorFormat::Bgra8 => 4,
+        })
+    }
+}
+
+/// The result of comparing two snapshots with `FrameSnapshot::diff`.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct DiffResult {
+    differing_pixels: u64,
+    total_pixels: u64,
+    diff_image: Option<FrameSnapshot>,
+}
+
+impl DiffResult {
+    /// Get the number of pixels that differ.
+    #[must_use]
+    pub const fn differing_pixels(&self) -> u64 {
+        self.differing_pixels
+    }
+
+    /// Get the number of pixels that were compared.
+    #[must_use]
+    pub const fn total_pixels(&self) -> u64 {
+        self.total_pixels
+    }
+
+    /// Check if the snapshots are identical.
+    #[must_use]
+    pub const fn is_identical(&self) -> bool {
+        self.differing_pixels == 0
+    }
+
+    /// Get the image highlighting the differences, only generated by
+    /// `FrameSnapshot::diff_with_image`.
+    #[must_use]
+    pub const fn diff_image(&self) -> Option<&FrameSnapshot> {
+        self.diff_image.as_ref()
+    }
+
+    /// Take the image highlighting the differences.
+    #[must_use]
+    pub fn into_diff_image(self) -> Option<FrameSnapshot> {
+        self.diff_image
+    }
+}
+
+/// The number of sampled pixels with every 8-bit value of the channels and the luminance, from
+/// `Frame::histogram`.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct Histogram {
+    red: [u32; 256],
+    green: [u32; 256],
+    blue: [u32; 256],
+    luminance: [u32; 256],
+    samples: u32,
+}
+
+impl Histogram {
+    /// Get the number of pixels with every red value.
+    #[must_use]
+    pub const fn red(&self) -> &[u32; 256] {
+        &self.red
+    }
+
+    /// Get the number of pixels with every green value.
+    #[must_use]
+    pub const fn green(&self) -> &[u32; 256] {
+        &self.green
+    }
+
+    /// Get the number of pixels with every blue value.
+    #[must_use]
+    pub const fn blue(&self) -> &[u32; 256] {
+        &self.blue
+    }
+
+    /// Get the number of pixels with every luminance value, weighted by Rec. 709.
+    #[must_use]
+    pub const fn luminance(&self) -> &[u32; 256] {
+        &self.luminance
+    }
+
+    /// Get the number of sampled pixels.
+    #[must_use]
+    pub const fn samples(&self) -> u32 {
+        self.samples
+    }
+}
+
+// Convert premultiplied pixels to the requested alpha mode in place, row by row.
+fn convert_alpha(
+    data: &mut [u8],
+    row_pitch: usize,
+    width: usize,
+    color_format: ColorFormat,
+    alpha_mode: AlphaModeSettings,
+) {
+    if alpha_mode == AlphaModeSettings::Premultiplied || row_pitch == 0 {
+        return;
+    }
+
+    let bytes_per_pixel = if color_format == ColorFormat::Rgba16F {
+        8
+    } else {
+        4
+    };
+
+    data.par_chunks_mut(row_pitch).for_each(|row| {
+        let row_len = (width * bytes_per_pixel).min(row.len());
+        for pixel in row[..row_len].chunks_exact_mut(bytes_per_pixel) {
+            match (color_format, alpha_mode) {
+                // 1.0 in half precision
+                (ColorFormat::Rgba16F, AlphaModeSettings::Ignore) => {
+                    pixel[6..8].copy_from_slice(&0x3C00u16.to_le_bytes());
+                }
+                (_, AlphaModeSettings::Ignore) => pixel[3] = 255,
+                (ColorFormat::Rgba16F, _) | (_, AlphaModeSettings::Premultiplied) => (),
+                (_, AlphaModeSettings::Straight) => {
+                    let alpha = u32::from(pixel[3]);
+                    if alpha != 0 && alpha != 255 {
+                        for channel in &mut pixel[..3] {
+                            let value = (u32::from(*channel) * 255 + alpha / 2) / alpha;
+                            *channel = u8::try_from(value.min(255)).unwrap();
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
+
+// Convert a pixel to 8-bit RGBA, half float pixels are tone mapped to sRGB.
+fn rgba8(pixel: &[u8], color_format: ColorFormat, sdr_white_level: u32) -> [u8; 4] {
+    match color_format {
+        ColorFormat::Rgba8 => [pixel[0], pixel[1], pixel[2], pixel[3]],
+        ColorFormat::Bgra8 => [pixel[2], pixel[1], pixel[0], pixel[3]],
+        ColorFormat::Rgba16F => {
+            let half = |channel: usize| {
+                half_to_f32(u16::from_le_bytes([
+                    pixel[channel * 2],
+                    pixel[channel * 2 + 1],
+                ]))
+            };
+            let encode = |value: f32| {
+                (linear_to_srgb(tone_map(value, sdr_white_level)) * 255.0)
+                    .round()
+                    .clamp(0.0, 255.0) as u8
+            };
+
+            [
+                encode(half(0)),
+                encode(half(1)),
+                encode(half(2)),
+                (half(3).clamp(0.0, 1.0) * 255.0).round() as u8,
+            ]
+        }
+    }
+}
+
+// Fit a size within a square of `max_dim` pixels keeping the aspect ratio, a size that already
+// fits is kept.
+fn thumbnail_size(width: u32, height: u32, max_dim: u32) -> (u32, u32) {
+    let longer = width.max(height);
+    if longer == 0 || max_dim == 0 {
+        return (0, 0);
+    }
+
+    if longer <= max_dim {
+        return (width, height);
+    }
+
+    let scale = |side: u32| {
+        let side =
+            (u64::from(side) * u64::from(max_dim) + u64::from(longer) / 2) / u64::from(longer);
+        u32::try_from(side).unwrap_or(max_dim).max(1)
+    };
+
+    (scale(width), scale(height))
+}
+
+// Tone map linear scRGB half float pixels to dithered 8-bit sRGB, the rows keep their pitch and
+// the 8-bit pixels are packed at the start of every row.
+fn tone_map_to_sdr(
+    data: &mut [u8],
+    row_pitch: usize,
+    width: usize,
+    sdr_format: ColorFormat,
+    sdr_white_level: u32,
+) {
+    if row_pitch == 0 {
+        return;
+    }
+
+    // A 4x4 Bayer matrix spreads the rounding error so gradients don't band
+    const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+    data.par_chunks_mut(row_pitch)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let width = width.min(row.len() / 8);
+
+            // Every 8-bit pixel is written before the half float pixels behind it are read
+            for x in 0..width {
+                let half = |channel: usize| {
+                    half_to_f32(u16::from_le_bytes([
+                        row[x * 8 + channel * 2],
+                        row[x * 8 + channel * 2 + 1],
+                    ]))
+                };
+                let (red, green, blue, alpha) = (half(0), half(1), half(2), half(3));
+
+                let threshold = (f32::from(BAYER[y % 4][x % 4]) + 0.5) / 16.0;
+                let encode = |value: f32| {
+                    let srgb = linear_to_srgb(tone_map(value, sdr_white_level));
+                    (srgb * 255.0 + threshold).clamp(0.0, 255.0) as u8
+                };
+
+                let (first, third) = if sdr_format == ColorFormat::Bgra8 {
+                    (encode(blue), encode(red))
+                } else {
+                    (encode(red), encode(blue))
+                };
+
+                row[x * 4] = first;
+                row[x * 4 + 1] = encode(green);
+                row[x * 4 + 2] = third;
+                row[x * 4 + 3] = (alpha.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        });
+}
+
+// Compress linear values above the SDR range smoothly into it, SDR content is left untouched.
+// scRGB `1.0` is 80 nits while SDR white is composed at the SDR white level of the monitor, the
+// value is scaled so SDR white maps to `1.0` before the knee.
+fn tone_map(value: f32, sdr_white_level: u32) -> f32 {
+    const KNEE: f32 = 0.8;
+
+    let value = (value * SDR_REFERENCE_WHITE_LEVEL as f32 / sdr_white_level.max(1) as f32).max(0.0);
+    if value <= KNEE {
+        value
+    } else {
+        (1.0 - KNEE).mul_add(1.0 - (-(value - KNEE) / (1.0 - KNEE)).exp(), KNEE)
+    }
+}
+
+// Encode a linear value with the sRGB transfer function.
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055f32.mul_add(value.powf(1.0 / 2.4), -0.055)
+    }
+}
+
+// Convert an IEEE half precision float to f32.
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let exponent = i32::from((half >> 10) & 0x1F);
+    let mantissa = f32::from(half & 0x3FF);
+
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1F if half & 0x3FF == 0 => sign * f32::INFINITY,
+        0x1F => f32::NAN,
+        exponent => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+// Blur 8-bit four channel pixels horizontally and then vertically with a running sum.
+fn box_blur(pixels: &mut [u8], width: usize, height: usize) {
+    let mut line = Vec::new();
+
+    for y in 0..height {
+        line.clear();
+        line.extend_from_slice(&pixels[y * width * 4..(y + 1) * width * 4]);
+        blur_line(&line, width, |x, pixel| {
+            pixels[(y * width + x) * 4..(y * width + x) * 4 + 4].copy_from_slice(&pixel);
+        });
+    }
+
+    for x in 0..width {
+        line.clear();
+        for y in 0..height {
+            line.extend_from_slice(&pixels[(y * width + x) * 4..(y * width + x) * 4 + 4]);
+        }
+        blur_line(&line, height, |y, pixel| {
+            pixels[(y * width + x) * 4..(y * width + x) * 4 + 4].copy_from_slice(&pixel);
+        });
+    }
+}
+
+// Blur one line of pixels, edge pixels are repeated outside the line.
+fn blur_line(line: &[u8], len: usize, mut write: impl FnMut(usize, [u8; 4])) {
+    let radius = REDACT_BLUR_RADIUS;
+    let window = u32::try_from(radius * 2 + 1).unwrap();
+    let pixel = |index: usize| &line[index.min(len - 1) * 4..index.min(len - 1) * 4 + 4];
+
+    let mut sum = [0u32; 4];
+    for offset in 0..=radius * 2 {
+        let index = offset.saturating_sub(radius);
+        for (channel, value) in pixel(index).iter().enumerate() {
+            sum[channel] += u32::from(*value);
+        }
+    }
+
+    for index in 0..len {
+        write(
+            index,
+            sum.map(|channel_sum| u8::try_from(channel_sum / window).unwrap()),
+        );
+
+        let removed = pixel(index.saturating_sub(radius));
+        let added = pixel(index + radius + 1);
+        for ((channel_sum, added), removed) in sum.iter_mut().zip(added).zip(removed) {
+            *channel_sum = *channel_sum + u32::from(*added) - u32::from(*removed);
+        }
+    }
+}
+
+// Convert an 8-bit RGBA color to a Direct2D color.
+fn color_f(color: [u8; 4]) -> D2D1_COLOR_F {
+    D2D1_COLOR_F {
+        r: f32::from(color[0]) / 255.0,
+        g: f32::from(color[1]) / 255.0,
+        b: f32::from(color[2]) / 255.0,
+        a: f32::from(color[3]) / 255.0,
+    }
 }
@@ -0,0 +1,162 @@
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+
+use windows::Graphics::Capture::GraphicsCaptureItem;
+
+use crate::{
+    capture::{
+        CaptureControl, CaptureControlError, GraphicsCaptureApiError, GraphicsCaptureApiHandler,
+    },
+    frame::{self, Frame, FrameSnapshot},
+    graphics_capture_api::InternalCaptureControl,
+    settings::Settings,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to copy the frame: {0}")]
+    FrameError(#[from] frame::Error),
+}
+
+/// A pull based alternative to implementing `GraphicsCaptureApiHandler`, the capture runs on its
+/// own thread and frames are received by iterating.
+///
+/// Only `capacity` frames are buffered, frames that arrive while the buffer is full are dropped
+/// so a slow consumer never stalls the capture thread. The iterator ends when the capture ends,
+/// for example when the captured window closes.
+///
+/// # Example
+/// ```no_run
+/// use windows_capture::{
+///     monitor::Monitor,
+///     settings::{ColorFormat, CursorCaptureSettings, DrawBorderSettings, Settings},
+/// };
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let monitor = Monitor::primary()?;
+///     let settings = Settings::new(
+///         monitor,
+///         CursorCaptureSettings::Default,
+///         DrawBorderSettings::Default,
+///         ColorFormat::Rgba8,
+///         (),
+///     );
+///     let mut frames = monitor.frames(settings)?;
+///
+///     while let Some(frame) = frames.next() {
+///         println!("Frame {}x{}", frame.width(), frame.height());
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+pub struct FrameIterator {
+    receiver: Option<Receiver<FrameSnapshot>>,
+    capture_control: Option<CaptureControl<FrameIteratorHandler, Error>>,
+}
+
+impl FrameIterator {
+    /// Starts a capture that delivers frames through the iterator, one frame is buffered.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The capture settings.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(FrameIterator)` if the capture started, otherwise returns an error of type
+    /// `GraphicsCaptureApiError`.
+    pub fn new<T: TryInto<GraphicsCaptureItem> + Send + 'static>(
+        settings: Settings<(), T>,
+    ) -> Result<Self, GraphicsCaptureApiError<Error>> {
+        Self::with_capacity(settings, 1)
+    }
+
+    /// Starts a capture that delivers frames through the iterator.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The capture settings.
+    /// * `capacity` - The number of frames buffered before new frames are dropped.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(FrameIterator)` if the capture started, otherwise returns an error of type
+    /// `GraphicsCaptureApiError`.
+    pub fn with_capacity<T: TryInto<GraphicsCaptureItem> + Send + 'static>(
+        settings: Settings<(), T>,
+        capacity: usize,
+    ) -> Result<Self, GraphicsCaptureApiError<Error>> {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+
+        let capture_control =
+            FrameIteratorHandler::start_free_threaded(settings.with_flags(sender))?;
+
+        Ok(Self {
+            receiver: Some(receiver),
+            capture_control: Some(capture_control),
+        })
+    }
+
+    /// Stops the capture.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the capture stopped successfully, otherwise the error that ended the capture.
+    pub fn stop(mut self) -> Result<(), CaptureControlError<Error>> {
+        // The capture thread stops sending once the receiver is gone
+        drop(self.receiver.take());
+
+        self.capture_control
+            .take()
+            .map_or(Ok(()), CaptureControl::stop)
+    }
+}
+
+impl Iterator for FrameIterator {
+    type Item = FrameSnapshot;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.as_ref()?.recv().ok()
+    }
+}
+
+impl Drop for FrameIterator {
+    fn drop(&mut self) {
+        drop(self.receiver.take());
+
+        if let Some(capture_control) = self.capture_control.take() {
+            let _ = capture_control.stop();
+        }
+    }
+}
+
+/// The handler that sends the frames of a `FrameIterator`.
+pub struct FrameIteratorHandler {
+    sender: SyncSender<FrameSnapshot>,
+}
+
+impl GraphicsCaptureApiHandler for FrameIteratorHandler {
+    type Flags = SyncSender<FrameSnapshot>;
+    type Error = Error;
+
+    fn new(sender: Self::Flags) -> Result<Self, Self::Error> {
+        Ok(Self { sender })
+    }
+
+    fn on_frame_arrived(
+        &mut self,
+        frame: &mut Frame,
+        capture_control: InternalCaptureControl,
+    ) -> Result<(), Self::Error> {
+        let snapshot = frame.snapshot()?;
+
+        match self.sender.try_send(snapshot) {
+            // The consumer is behind, the frame is dropped
+            Ok(()) | Err(TrySendError::Full(_)) => (),
+            // The iterator was dropped
+            Err(TrySendError::Disconnected(_)) => capture_control.stop(),
+        }
+
+        Ok(())
+    }
+}
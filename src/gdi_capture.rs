@@ -0,0 +1,415 @@
+use std::{mem, ptr, slice};
+
+use windows::{
+    Graphics::Capture::GraphicsCaptureItem,
+    Win32::{
+        Foundation::HWND,
+        Graphics::Gdi::{
+            BitBlt, CreateCompatibleDC, CreateDIBSection, DeleteDC, DeleteObject, GdiFlush, GetDC,
+            ReleaseDC, SelectObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, CAPTUREBLT,
+            DIB_RGB_COLORS, HBITMAP, SRCCOPY,
+        },
+    },
+};
+
+use crate::{
+    capture::{CancellationToken, GraphicsCaptureApiError, GraphicsCaptureApiHandler},
+    cursor::CursorLayer,
+    graphics_capture_api::{self, GraphicsCaptureApi},
+    monitor::Monitor,
+    polling_capture::{self, Poll},
+    settings::{ColorFormat, CursorCaptureSettings, Settings},
+    window::Window,
+};
+
+/// The source a capture gets its frames from.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum CaptureBackend {
+    /// The Graphics Capture API if the system supports it, otherwise GDI.
+    Auto,
+    /// The Graphics Capture API, every setting is supported.
+    GraphicsCapture,
+    /// Copies of the screen made with GDI `BitBlt`, see `GdiCapture`.
+    Gdi,
+}
+
+impl CaptureBackend {
+    /// Resolve `Auto` to the backend this system uses.
+    ///
+    /// # Returns
+    ///
+    /// `GraphicsCapture` if the Graphics Capture API is supported, otherwise `Gdi`. Other
+    /// backends are returned as they are.
+    #[must_use]
+    pub fn resolve(self) -> Self {
+        match self {
+            Self::Auto => {
+                if GraphicsCaptureApi::is_supported().unwrap_or(false) {
+                    Self::GraphicsCapture
+                } else {
+                    Self::Gdi
+                }
+            }
+            backend => backend,
+        }
+    }
+
+    /// Starts a capture with the backend and takes control of the current thread, like
+    /// `GraphicsCaptureApiHandler::start`.
+    ///
+    /// With the GDI backend only the item, the color format, the cursor capture and the
+    /// cancellation token of the settings are used, frames are delivered at 30 frames per
+    /// second. The other settings are ignored, frames aren't scaled, cropped, paced or masked and
+    /// no border is drawn.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The settings of the capture, the item is a `Monitor` or a `Window`.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` when the capture stops, otherwise returns an error of type
+    /// `GraphicsCaptureApiError`, `UnsupportedSetting` for `CursorCaptureSettings::SeparateLayer`
+    /// with the GDI backend.
+    ///
+    /// # Example
+    /// ```ignore
+    /// // Falls back to GDI in sessions without the Graphics Capture API, like some RDP sessions
+    /// CaptureBackend::Auto.start::<Capture, _>(settings)?;
+    /// ```
+    pub fn start<H, T>(
+        self,
+        settings: Settings<H::Flags, T>,
+    ) -> Result<(), GraphicsCaptureApiError<H::Error>>
+    where
+        H: GraphicsCaptureApiHandler + Send + 'static,
+        T: TryInto<GraphicsCaptureItem> + Into<GdiTarget>,
+        H::Flags: Send,
+    {
+        if self.resolve() != Self::Gdi {
+            return H::start(settings);
+        }
+
+        CancellationToken::check(settings.cancellation_token.as_ref())?;
+
+        let capture_cursor = match settings.cursor_capture {
+            CursorCaptureSettings::Default | CursorCaptureSettings::WithCursor => true,
+            CursorCaptureSettings::WithoutCursor => false,
+            // GDI copies can't leave the cursor out to deliver it as a layer
+            CursorCaptureSettings::SeparateLayer => {
+                return Err(GraphicsCaptureApiError::UnsupportedSetting("SeparateLayer"));
+            }
+        };
+
+        GdiCapture::new(settings.item)
+            .color_format(settings.color_format)
+            .capture_cursor(capture_cursor)
+            .start::<H>(settings.flags)
+    }
+}
+
+/// The monitor or the window a `GdiCapture` copies.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum GdiTarget {
+    Monitor(Monitor),
+    Window(Window),
+}
+
+impl GdiTarget {
+    // Get the area of the target in the virtual desktop, `None` once the target is gone.
+    fn bounds(&self) -> Option<(i32, i32, u32, u32)> {
+        match self {
+            Self::Monitor(monitor) => {
+                let (x, y) = monitor.position().ok()?;
+
+                Some((x, y, monitor.width().ok()?, monitor.height().ok()?))
+            }
+            Self::Window(window) => {
+                let rect = Window::frame_bounds(HWND(window.as_raw_hwnd())).ok()?;
+
+                Some((
+                    rect.left,
+                    rect.top,
+                    u32::try_from(rect.right - rect.left).unwrap_or(0),
+                    u32::try_from(rect.bottom - rect.top).unwrap_or(0),
+                ))
+            }
+        }
+    }
+}
+
+impl From<Monitor> for GdiTarget {
+    fn from(monitor: Monitor) -> Self {
+        Self::Monitor(monitor)
+    }
+}
+
+impl From<Window> for GdiTarget {
+    fn from(window: Window) -> Self {
+        Self::Window(window)
+    }
+}
+
+/// A capture that copies a monitor or a window from the screen with GDI `BitBlt` and delivers
+/// the copies to a `GraphicsCaptureApiHandler`, for systems without the Graphics Capture API
+/// like some remote desktop sessions.
+///
+/// Every frame is copied on the CPU and uploaded to the GPU, so it's slower than the Graphics
+/// Capture API and frames are delivered at a fixed rate instead of when the content changes. A
+/// window is copied as it's seen on screen, windows covering it are included. A software (WARP)
+/// device is used when no GPU is available.
+///
+/// # Example
+/// ```no_run
+/// use windows_capture::{
+///     capture::GraphicsCaptureApiHandler, frame::Frame, gdi_capture::GdiCapture,
+///     graphics_capture_api::InternalCaptureControl, monitor::Monitor,
+/// };
+///
+/// struct Capture;
+///
+/// impl GraphicsCaptureApiHandler for Capture {
+///     type Flags = ();
+///     type Error = Box<dyn std::error::Error + Send + Sync>;
+///
+///     fn new(_: Self::Flags) -> Result<Self, Self::Error> {
+///         Ok(Self)
+///     }
+///
+///     fn on_frame_arrived(
+///         &mut self,
+///         frame: &mut Frame,
+///         _: InternalCaptureControl,
+///     ) -> Result<(), Self::Error> {
+///         println!("{}x{}", frame.width(), frame.height());
+///         Ok(())
+///     }
+/// }
+///
+/// GdiCapture::new(Monitor::primary().unwrap()).fps(10).start::<Capture>(()).unwrap();
+/// ```
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct GdiCapture {
+    target: GdiTarget,
+    fps: u32,
+    color_format: ColorFormat,
+    capture_cursor: bool,
+}
+
+impl GdiCapture {
+    /// Create a new GDI capture that delivers Bgra8 frames with the cursor at 30 frames per
+    /// second until stopped.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The `Monitor` or `Window` to copy.
+    #[must_use]
+    pub fn new<T: Into<GdiTarget>>(target: T) -> Self {
+        Self {
+            target: target.into(),
+            fps: 30,
+            color_format: ColorFormat::Bgra8,
+            capture_cursor: true,
+        }
+    }
+
+    /// Set the rate the screen is copied at, `0` copies it as fast as the handler accepts frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `fps` - The number of frames per second.
+    #[must_use]
+    pub const fn fps(mut self, fps: u32) -> Self {
+        self.fps = fps;
+        self
+    }
+
+    /// Set the color format of the frames. GDI only copies 8-bit colors, `ColorFormat::Rgba16F`
+    /// delivers `ColorFormat::Bgra8` frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `color_format` - The color format of the frames.
+    #[must_use]
+    pub const fn color_format(mut self, color_format: ColorFormat) -> Self {
+        self.color_format = color_format;
+        self
+    }
+
+    /// Set whether the cursor is drawn onto the frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `capture_cursor` - Whether to draw the cursor.
+    #[must_use]
+    pub const fn capture_cursor(mut self, capture_cursor: bool) -> Self {
+        self.capture_cursor = capture_cursor;
+        self
+    }
+
+    /// Starts copying the screen and takes control of the current thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `flags` - The flags used to create the handler.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` when the handler stops the capture or the target is gone, after
+    /// `on_closed` was called, otherwise returns an error of type `GraphicsCaptureApiError`.
+    pub fn start<H: GraphicsCaptureApiHandler>(
+        &self,
+        flags: H::Flags,
+    ) -> Result<(), GraphicsCaptureApiError<H::Error>> {
+        let color_format = match self.color_format {
+            ColorFormat::Rgba16F => ColorFormat::Bgra8,
+            color_format => color_format,
+        };
+
+        polling_capture::run::<H>(flags, self.fps, color_format, |_| {
+            let Some((x, y, width, height)) = self.target.bounds() else {
+                return Ok(Poll::Closed);
+            };
+
+            // A minimized window has no area to copy, wait until it's restored
+            if width == 0 || height == 0 {
+                return Ok(Poll::Skip);
+            }
+
+            let mut pixels = copy_screen(x, y, width, height)?;
+
+            if self.capture_cursor {
+                if let Ok(Some(cursor)) = CursorLayer::capture() {
+                    draw_cursor(&mut pixels, width, height, (x, y), &cursor);
+                }
+            }
+
+            if color_format == ColorFormat::Rgba8 {
+                for pixel in pixels.chunks_exact_mut(4) {
+                    pixel.swap(0, 2);
+                }
+            }
+
+            Ok(Poll::Frame {
+                pixels,
+                width,
+                height,
+                // The performance counter, the clock the Graphics Capture API stamps frames with
+                time: graphics_capture_api::system_relative_time().unwrap_or_default(),
+            })
+        })
+    }
+}
+
+// Copy an area of the screen into BGRA pixels without padding, the alpha is opaque.
+fn copy_screen(x: i32, y: i32, width: u32, height: u32) -> windows::core::Result<Vec<u8>> {
+    let screen_dc = unsafe { GetDC(None) };
+    let memory_dc = unsafe { CreateCompatibleDC(screen_dc) };
+
+    let bitmap_info = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: u32::try_from(mem::size_of::<BITMAPINFOHEADER>()).unwrap(),
+            biWidth: i32::try_from(width).unwrap_or(i32::MAX),
+            // Negative height for a top-down bitmap
+            biHeight: -i32::try_from(height).unwrap_or(i32::MAX),
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: BI_RGB.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let mut bits = ptr::null_mut();
+    let result =
+        unsafe { CreateDIBSection(screen_dc, &bitmap_info, DIB_RGB_COLORS, &mut bits, None, 0) }
+            .and_then(|bitmap: HBITMAP| {
+                let previous = unsafe { SelectObject(memory_dc, bitmap) };
+
+                // CAPTUREBLT includes layered windows like tooltips
+                let result = unsafe {
+                    BitBlt(
+                        memory_dc,
+                        0,
+                        0,
+                        i32::try_from(width).unwrap_or(i32::MAX),
+                        i32::try_from(height).unwrap_or(i32::MAX),
+                        screen_dc,
+                        x,
+                        y,
+                        SRCCOPY | CAPTUREBLT,
+                    )
+                }
+                .map(|()| {
+                    unsafe {
+                        let _ = GdiFlush();
+                    };
+
+                    let len = width as usize * height as usize * 4;
+                    let mut pixels =
+                        unsafe { slice::from_raw_parts(bits.cast::<u8>(), len) }.to_vec();
+                    for pixel in pixels.chunks_exact_mut(4) {
+                        pixel[3] = 255;
+                    }
+
+                    pixels
+                });
+
+                unsafe {
+                    SelectObject(memory_dc, previous);
+                    let _ = DeleteObject(bitmap);
+                };
+
+                result
+            });
+
+    unsafe {
+        let _ = DeleteDC(memory_dc);
+        ReleaseDC(None, screen_dc);
+    };
+
+    result
+}
+
+// Blend the cursor onto BGRA pixels of an area of the screen that starts at the origin.
+fn draw_cursor(
+    pixels: &mut [u8],
+    width: u32,
+    height: u32,
+    origin: (i32, i32),
+    cursor: &CursorLayer,
+) {
+    let cursor_width = cursor.width() as usize;
+
+    for (row, cursor_row) in cursor.buffer().chunks_exact(cursor_width * 4).enumerate() {
+        let Ok(y) = u32::try_from(cursor.y() - origin.1 + row as i32) else {
+            continue;
+        };
+        if y >= height {
+            break;
+        }
+
+        for (column, cursor_pixel) in cursor_row.chunks_exact(4).enumerate() {
+            let Ok(x) = u32::try_from(cursor.x() - origin.0 + column as i32) else {
+                continue;
+            };
+            if x >= width {
+                break;
+            }
+
+            // The cursor is RGBA with straight alpha, the pixels are BGRA
+            let alpha = u32::from(cursor_pixel[3]);
+            let offset = (y as usize * width as usize + x as usize) * 4;
+            for (channel, value) in [cursor_pixel[2], cursor_pixel[1], cursor_pixel[0]]
+                .into_iter()
+                .enumerate()
+            {
+                let pixel = &mut pixels[offset + channel];
+                *pixel = u8::try_from(
+                    (u32::from(value) * alpha + u32::from(*pixel) * (255 - alpha)) / 255,
+                )
+                .unwrap_or(u8::MAX);
+            }
+        }
+    }
+}
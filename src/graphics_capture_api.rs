@@ -1,31 +1,77 @@
-use std::sync::{
-    atomic::{self, AtomicBool},
-    Arc,
+use std::{
+    any::Any,
+    collections::{hash_map::DefaultHasher, VecDeque},
+    hash::{Hash, Hasher},
+    mem,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc,
+    },
+    time::Duration,
 };
 
 use parking_lot::Mutex;
 use windows::{
     core::{IInspectable, Interface, HSTRING},
-    Foundation::{EventRegistrationToken, Metadata::ApiInformation, TypedEventHandler},
+    Foundation::{EventRegistrationToken, Metadata::ApiInformation, TimeSpan, TypedEventHandler},
     Graphics::{
-        Capture::{Direct3D11CaptureFramePool, GraphicsCaptureItem, GraphicsCaptureSession},
-        DirectX::{Direct3D11::IDirect3DDevice, DirectXPixelFormat},
+        Capture::{
+            Direct3D11CaptureFrame, Direct3D11CaptureFramePool, GraphicsCaptureItem,
+            GraphicsCaptureSession,
+        },
+        DirectX::{
+            Direct3D11::{IDirect3DDevice, IDirect3DSurface},
+            DirectXPixelFormat,
+        },
     },
     Win32::{
-        Foundation::{LPARAM, WPARAM},
-        Graphics::Direct3D11::{
-            ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D, D3D11_TEXTURE2D_DESC,
+        Foundation::{LPARAM, POINT, WPARAM},
+        Graphics::{
+            Direct3D::{
+                Fxc::D3DReflect, D3D_FEATURE_LEVEL, D3D_FEATURE_LEVEL_10_0, D3D_FEATURE_LEVEL_10_1,
+                D3D_FEATURE_LEVEL_9_1, D3D_FEATURE_LEVEL_9_2, D3D_FEATURE_LEVEL_9_3,
+            },
+            Direct3D11::{
+                ID3D11Buffer, ID3D11ComputeShader, ID3D11Device, ID3D11DeviceContext,
+                ID3D11ShaderReflection, ID3D11ShaderResourceView, ID3D11Texture2D,
+                ID3D11UnorderedAccessView, D3D11_BIND_CONSTANT_BUFFER, D3D11_BIND_RENDER_TARGET,
+                D3D11_BIND_SHADER_RESOURCE, D3D11_BIND_UNORDERED_ACCESS, D3D11_BOX,
+                D3D11_BUFFER_DESC, D3D11_CPU_ACCESS_READ,
+                D3D11_FORMAT_SUPPORT_TYPED_UNORDERED_ACCESS_VIEW, D3D11_MAPPED_SUBRESOURCE,
+                D3D11_MAP_FLAG_DO_NOT_WAIT, D3D11_MAP_READ, D3D11_REQ_TEXTURE2D_U_OR_V_DIMENSION,
+                D3D11_RESOURCE_MISC_GENERATE_MIPS, D3D11_SUBRESOURCE_DATA, D3D11_TEXTURE2D_DESC,
+                D3D11_USAGE_DEFAULT, D3D11_USAGE_STAGING,
+            },
+            Dxgi::{IDXGIAdapter, IDXGIDevice, IDXGISurface, DXGI_ERROR_WAS_STILL_DRAWING},
+        },
+        System::{
+            Performance::{QueryPerformanceCounter, QueryPerformanceFrequency},
+            Threading::GetCurrentThreadId,
+            WinRT::Direct3D11::{
+                CreateDirect3D11SurfaceFromDXGISurface, IDirect3DDxgiInterfaceAccess,
+            },
         },
-        System::WinRT::Direct3D11::IDirect3DDxgiInterfaceAccess,
-        UI::WindowsAndMessaging::{PostThreadMessageW, WM_QUIT},
+        UI::WindowsAndMessaging::{GetCursorPos, PostThreadMessageW, WM_QUIT},
     },
 };
 
 use crate::{
-    capture::GraphicsCaptureApiHandler,
-    d3d11::{self, create_d3d_device, create_direct3d_device, SendDirectX},
-    frame::Frame,
-    settings::{ColorFormat, CursorCaptureSettings, DrawBorderSettings},
+    capture::{GraphicsCaptureApiError, GraphicsCaptureApiHandler, SharedFrame},
+    cursor::{self, CursorLayer},
+    d3d11::{
+        self, create_d3d_device, create_d3d_device_on_adapter, create_direct3d_device, SendDirectX,
+    },
+    encoder::{EncoderCapabilities, VideoEncoderType},
+    frame::{Frame, Rect, RedactMode, SDR_REFERENCE_WHITE_LEVEL},
+    monitor::Monitor,
+    settings::{
+        CaptureScaleSettings, ColorFormat, CropInsetSettings, CursorCaptureSettings,
+        DrawBorderSettings, FramePacingSettings, GpuShaderSettings, ReadbackModeSettings,
+        SettingsSnapshot,
+    },
+    window::Window,
 };
 
 #[derive(thiserror::Error, Eq, PartialEq, Clone, Debug)]
@@ -38,15 +84,29 @@ pub enum Error {
     BorderConfigUnsupported,
     #[error("Already started")]
     AlreadyStarted,
+    #[error("The color format doesn't support unordered access views needed by the GPU shader")]
+    GpuShaderFormatUnsupported,
+    #[error("The GPU shader is declared with numthreads({0}, {1}, {2}) instead of (8, 8, 1)")]
+    GpuShaderThreadGroupSize(u32, u32, u32),
+    #[error("The window of the capture item is unknown, create the item from a Window")]
+    ItemWindowUnknown,
+    #[error("Cursor error: {0}")]
+    CursorError(#[from] cursor::Error),
+    #[error("Failed to mask the areas covered by other windows: {0}")]
+    VisibleRegionError(String),
     #[error("DirectX error: {0}")]
     DirectXError(#[from] d3d11::Error),
     #[error("Windows API error: {0}")]
     WindowsError(#[from] windows::core::Error),
 }
 
+/// The number of buffers in the frame pool without queued frames.
+const FRAME_POOL_BUFFER_COUNT: i32 = 1;
+
 /// Used to control the capture session
 pub struct InternalCaptureControl {
     stop: Arc<AtomicBool>,
+    capture_id: u64,
 }
 
 impl InternalCaptureControl {
@@ -55,13 +115,20 @@ impl InternalCaptureControl {
     /// # Arguments
     ///
     /// * `stop` - An `Arc<AtomicBool>` indicating whether the capture should stop.
+    /// * `capture_id` - The unique id of the capture session.
     ///
     /// # Returns
     ///
     /// A new instance of `InternalCaptureControl`.
     #[must_use]
-    pub fn new(stop: Arc<AtomicBool>) -> Self {
-        Self { stop }
+    pub const fn new(stop: Arc<AtomicBool>, capture_id: u64) -> Self {
+        Self { stop, capture_id }
+    }
+
+    /// Gets the unique id of the capture session, the same id is returned by `CaptureControl::id`.
+    #[must_use]
+    pub const fn capture_id(&self) -> u64 {
+        self.capture_id
     }
 
     /// Gracefully stop the capture thread.
@@ -70,22 +137,992 @@ impl InternalCaptureControl {
     }
 }
 
+/// Snaps frames to display refreshes and keeps a copy of the last delivered frame so it can be
+/// repeated on refreshes without a new frame.
+struct VsyncPacer {
+    refresh_rate: u32,
+    last_slot: Option<i64>,
+    last_frame: Option<(SendDirectX<IDirect3DSurface>, ID3D11Texture2D)>,
+}
+
+impl VsyncPacer {
+    /// The most refreshes the previous frame is repeated for, after a longer gap like a stalled
+    /// handler the earlier refreshes are skipped instead of replayed back to back.
+    const MAX_REPEATED_SLOTS: i64 = 8;
+
+    const fn new(refresh_rate: u32) -> Self {
+        Self {
+            refresh_rate,
+            last_slot: None,
+            last_frame: None,
+        }
+    }
+
+    // The index of the display refresh the time falls into, the time is in 100ns units.
+    fn slot(&self, time: TimeSpan) -> i64 {
+        i64::try_from(i128::from(time.Duration) * i128::from(self.refresh_rate) / 10_000_000)
+            .unwrap()
+    }
+
+    // The start time of a display refresh.
+    fn slot_time(&self, slot: i64) -> TimeSpan {
+        TimeSpan {
+            Duration: i64::try_from(i128::from(slot) * 10_000_000 / i128::from(self.refresh_rate))
+                .unwrap(),
+        }
+    }
+
+    // Copy the frame texture because the frame pool reuses it for later frames.
+    fn store(
+        &mut self,
+        d3d_device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        texture: &ID3D11Texture2D,
+    ) -> windows::core::Result<()> {
+        copy_texture(d3d_device, context, texture, &mut self.last_frame)
+    }
+}
+
+/// Omits black frames at the start and end of the capture by discarding them until the first
+/// non-black frame and holding back later ones until the next non-black frame.
+struct BlackFrameTrimmer {
+    started: bool,
+    held: Vec<TimeSpan>,
+    held_size: (u32, u32),
+    held_content_size: (u32, u32),
+    held_frame: Option<(SendDirectX<IDirect3DSurface>, ID3D11Texture2D)>,
+}
+
+impl BlackFrameTrimmer {
+    const fn new() -> Self {
+        Self {
+            started: false,
+            held: Vec::new(),
+            held_size: (0, 0),
+            held_content_size: (0, 0),
+            held_frame: None,
+        }
+    }
+
+    // Hold back a black frame, only the latest black frame is kept and repeated for every held
+    // time because they all look the same.
+    fn hold(
+        &mut self,
+        d3d_device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        texture: &ID3D11Texture2D,
+        timespan: TimeSpan,
+        size: (u32, u32),
+        content_size: (u32, u32),
+    ) -> windows::core::Result<()> {
+        copy_texture(d3d_device, context, texture, &mut self.held_frame)?;
+        self.held.push(timespan);
+        self.held_size = size;
+        self.held_content_size = content_size;
+
+        Ok(())
+    }
+}
+
+/// Watches a region of the frames and reports when enough of its pixels changed, the region is
+/// hashed so frames where it's unchanged are skipped without comparing pixels.
+struct RegionTrigger {
+    rect: Rect,
+    threshold: u32,
+    hash: Option<u64>,
+    pixels: Vec<u8>,
+    pending: VecDeque<ID3D11Texture2D>,
+    free: Vec<ID3D11Texture2D>,
+}
+
+impl RegionTrigger {
+    /// The number of region copies that can wait for the GPU, a frame whose copy doesn't fit is
+    /// not checked.
+    const PENDING_COPY_COUNT: usize = 3;
+
+    const fn new(rect: Rect, threshold: u32) -> Self {
+        Self {
+            rect,
+            threshold,
+            hash: None,
+            pixels: Vec::new(),
+            pending: VecDeque::new(),
+            free: Vec::new(),
+        }
+    }
+
+    // Queue a copy of the region of a frame texture and compare the oldest copy the GPU finished,
+    // the pixel count is returned if it reaches the threshold and the changed region becomes the
+    // new reference. Nothing waits for the GPU so a change is reported on a frame or two later.
+    fn check(
+        &mut self,
+        d3d_device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        texture: &ID3D11Texture2D,
+        pixel_size: usize,
+    ) -> windows::core::Result<Option<u32>> {
+        let triggered_pixels = self.read_pending(context, pixel_size)?;
+        self.queue_copy(d3d_device, context, texture)?;
+
+        Ok(triggered_pixels)
+    }
+
+    // Copy the region of the texture into a free staging texture, the copies are recreated when
+    // the clipped region changed size.
+    fn queue_copy(
+        &mut self,
+        d3d_device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        texture: &ID3D11Texture2D,
+    ) -> windows::core::Result<()> {
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { texture.GetDesc(&mut desc) };
+
+        let right = self.rect.x.saturating_add(self.rect.width).min(desc.Width);
+        let bottom = self
+            .rect
+            .y
+            .saturating_add(self.rect.height)
+            .min(desc.Height);
+        if self.rect.x >= right || self.rect.y >= bottom {
+            return Ok(());
+        }
+
+        let same_size = |copy: &ID3D11Texture2D| {
+            let mut copy_desc = D3D11_TEXTURE2D_DESC::default();
+            unsafe { copy.GetDesc(&mut copy_desc) };
+            copy_desc.Width == right - self.rect.x
+                && copy_desc.Height == bottom - self.rect.y
+                && copy_desc.Format == desc.Format
+        };
+        if !self.pending.iter().chain(&self.free).all(same_size) {
+            self.pending.clear();
+            self.free.clear();
+        }
+
+        if self.pending.len() >= Self::PENDING_COPY_COUNT {
+            return Ok(());
+        }
+
+        let copy = match self.free.pop() {
+            Some(copy) => copy,
+            None => {
+                let copy_desc = D3D11_TEXTURE2D_DESC {
+                    Width: right - self.rect.x,
+                    Height: bottom - self.rect.y,
+                    MipLevels: 1,
+                    ArraySize: 1,
+                    Usage: D3D11_USAGE_STAGING,
+                    BindFlags: 0,
+                    CPUAccessFlags: D3D11_CPU_ACCESS_READ.0 as u32,
+                    MiscFlags: 0,
+                    ..desc
+                };
+
+                let mut copy = None;
+                unsafe { d3d_device.CreateTexture2D(&copy_desc, None, Some(&mut copy))? };
+                copy.unwrap()
+            }
+        };
+
+        let region = D3D11_BOX {
+            left: self.rect.x,
+            top: self.rect.y,
+            front: 0,
+            right,
+            bottom,
+            back: 1,
+        };
+        unsafe { context.CopySubresourceRegion(&copy, 0, 0, 0, 0, texture, 0, Some(&region)) };
+
+        self.pending.push_back(copy);
+
+        Ok(())
+    }
+
+    // Compare the oldest queued copy if the GPU finished it.
+    fn read_pending(
+        &mut self,
+        context: &ID3D11DeviceContext,
+        pixel_size: usize,
+    ) -> windows::core::Result<Option<u32>> {
+        let Some(copy) = self.pending.front() else {
+            return Ok(None);
+        };
+
+        let mut mapped_resource = D3D11_MAPPED_SUBRESOURCE::default();
+        match unsafe {
+            context.Map(
+                copy,
+                0,
+                D3D11_MAP_READ,
+                D3D11_MAP_FLAG_DO_NOT_WAIT.0 as u32,
+                Some(&mut mapped_resource),
+            )
+        } {
+            Ok(()) => (),
+            Err(e) if e.code() == DXGI_ERROR_WAS_STILL_DRAWING => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { copy.GetDesc(&mut desc) };
+
+        // Drop the row padding so the pixels can be hashed and compared
+        let row_size = desc.Width as usize * pixel_size;
+        let mut pixels = Vec::with_capacity(row_size * desc.Height as usize);
+        for row in 0..desc.Height as usize {
+            let row_data = unsafe {
+                slice::from_raw_parts(
+                    mapped_resource
+                        .pData
+                        .cast::<u8>()
+                        .add(row * mapped_resource.RowPitch as usize),
+                    row_size,
+                )
+            };
+            pixels.extend_from_slice(row_data);
+        }
+
+        unsafe { context.Unmap(copy, 0) };
+
+        let copy = self.pending.pop_front().unwrap();
+        self.free.push(copy);
+
+        Ok(self.compare(&pixels, pixel_size))
+    }
+
+    // Get the number of pixels of the region that changed since the reference if it reaches the
+    // threshold, the changed region becomes the new reference.
+    fn compare(&mut self, pixels: &[u8], pixel_size: usize) -> Option<u32> {
+        let mut hasher = DefaultHasher::new();
+        pixels.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        if self.hash == Some(hash) {
+            return None;
+        }
+
+        // The first frame and a region clipped to a resized frame only set the reference
+        if self.hash.is_none() || self.pixels.len() != pixels.len() {
+            self.hash = Some(hash);
+            self.pixels = pixels.to_vec();
+            return None;
+        }
+
+        let changed_pixels = pixels
+            .chunks_exact(pixel_size)
+            .zip(self.pixels.chunks_exact(pixel_size))
+            .filter(|(pixel, reference)| pixel != reference)
+            .count();
+        let changed_pixels = u32::try_from(changed_pixels).unwrap_or(u32::MAX);
+
+        if changed_pixels < self.threshold.max(1) {
+            return None;
+        }
+
+        self.hash = Some(hash);
+        self.pixels.copy_from_slice(pixels);
+
+        Some(changed_pixels)
+    }
+}
+
+/// Scales frames down on the GPU by generating the mips of a copy of the frame and keeping the
+/// mip level of the requested size.
+struct FrameScaler {
+    mip_level: u32,
+    mip_texture: Option<(ID3D11Texture2D, ID3D11ShaderResourceView)>,
+    scaled_frame: Option<(SendDirectX<IDirect3DSurface>, ID3D11Texture2D)>,
+}
+
+impl FrameScaler {
+    const fn new(mip_level: u32) -> Self {
+        Self {
+            mip_level,
+            mip_texture: None,
+            scaled_frame: None,
+        }
+    }
+
+    // Scale a frame texture down, the returned texture is reused for the next frames. Returns
+    // the mip level that was used, it's lower than requested for tiny frames.
+    fn scale(
+        &mut self,
+        d3d_device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        texture: &ID3D11Texture2D,
+    ) -> windows::core::Result<(IDirect3DSurface, ID3D11Texture2D, u32)> {
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { texture.GetDesc(&mut desc) };
+
+        let mip_level = self
+            .mip_level
+            .min(desc.Width.max(desc.Height).max(1).ilog2());
+
+        let reusable = self.mip_texture.as_ref().is_some_and(|(mip_texture, _)| {
+            let mut mip_desc = D3D11_TEXTURE2D_DESC::default();
+            unsafe { mip_texture.GetDesc(&mut mip_desc) };
+            mip_desc.Width == desc.Width
+                && mip_desc.Height == desc.Height
+                && mip_desc.MipLevels == mip_level + 1
+        });
+
+        if !reusable {
+            let mut mip_desc = desc;
+            mip_desc.MipLevels = mip_level + 1;
+            mip_desc.ArraySize = 1;
+            mip_desc.Usage = D3D11_USAGE_DEFAULT;
+            mip_desc.BindFlags = (D3D11_BIND_SHADER_RESOURCE.0 | D3D11_BIND_RENDER_TARGET.0) as u32;
+            mip_desc.CPUAccessFlags = 0;
+            mip_desc.MiscFlags = D3D11_RESOURCE_MISC_GENERATE_MIPS.0 as u32;
+
+            let mut mip_texture = None;
+            unsafe { d3d_device.CreateTexture2D(&mip_desc, None, Some(&mut mip_texture))? };
+            let mip_texture = mip_texture.unwrap();
+
+            let mut view = None;
+            unsafe { d3d_device.CreateShaderResourceView(&mip_texture, None, Some(&mut view))? };
+
+            self.mip_texture = Some((mip_texture, view.unwrap()));
+        }
+
+        let (mip_texture, view) = self.mip_texture.as_ref().unwrap();
+        unsafe {
+            context.CopySubresourceRegion(mip_texture, 0, 0, 0, 0, texture, 0, None);
+            context.GenerateMips(view);
+        };
+
+        let scaled_width = (desc.Width >> mip_level).max(1);
+        let scaled_height = (desc.Height >> mip_level).max(1);
+
+        let reusable = self
+            .scaled_frame
+            .as_ref()
+            .is_some_and(|(_, scaled_texture)| {
+                let mut scaled_desc = D3D11_TEXTURE2D_DESC::default();
+                unsafe { scaled_texture.GetDesc(&mut scaled_desc) };
+                scaled_desc.Width == scaled_width && scaled_desc.Height == scaled_height
+            });
+
+        if !reusable {
+            let mut scaled_desc = desc;
+            scaled_desc.Width = scaled_width;
+            scaled_desc.Height = scaled_height;
+            scaled_desc.MipLevels = 1;
+            scaled_desc.ArraySize = 1;
+            scaled_desc.Usage = D3D11_USAGE_DEFAULT;
+            scaled_desc.BindFlags = D3D11_BIND_SHADER_RESOURCE.0 as u32;
+            scaled_desc.CPUAccessFlags = 0;
+            scaled_desc.MiscFlags = 0;
+
+            let mut scaled_texture = None;
+            unsafe { d3d_device.CreateTexture2D(&scaled_desc, None, Some(&mut scaled_texture))? };
+            let scaled_texture = scaled_texture.unwrap();
+
+            let dxgi_surface: IDXGISurface = scaled_texture.cast()?;
+            let surface: IDirect3DSurface =
+                unsafe { CreateDirect3D11SurfaceFromDXGISurface(&dxgi_surface)? }.cast()?;
+
+            self.scaled_frame = Some((SendDirectX::new(surface), scaled_texture));
+        }
+
+        let (surface, scaled_texture) = self.scaled_frame.as_ref().unwrap();
+        unsafe {
+            context.CopySubresourceRegion(scaled_texture, 0, 0, 0, 0, mip_texture, mip_level, None);
+        };
+
+        Ok((surface.0.clone(), scaled_texture.clone(), mip_level))
+    }
+}
+
+/// Maps an area of the captured window to the frame after cropping and scaling, rounded outwards
+/// so no covered pixel is left unmasked.
+///
+/// # Arguments
+///
+/// * `rect` - The area relative to the window rect where the frame starts.
+/// * `crop_inset` - The inset cut off the edges of the frame.
+/// * `mip_level` - The mip level the frame was scaled down to.
+///
+/// # Returns
+///
+/// The area in frame coordinates, `Frame::redact` clips it to the frame.
+const fn occluded_frame_rect(rect: &Rect, crop_inset: CropInsetSettings, mip_level: u32) -> Rect {
+    let left = rect.x.saturating_sub(crop_inset.left) >> mip_level;
+    let top = rect.y.saturating_sub(crop_inset.top) >> mip_level;
+
+    let round_up = (1 << mip_level) - 1;
+    let right = (rect
+        .x
+        .saturating_add(rect.width)
+        .saturating_sub(crop_inset.left)
+        .saturating_add(round_up))
+        >> mip_level;
+    let bottom = (rect
+        .y
+        .saturating_add(rect.height)
+        .saturating_sub(crop_inset.top)
+        .saturating_add(round_up))
+        >> mip_level;
+
+    Rect {
+        x: left,
+        y: top,
+        width: right.saturating_sub(left),
+        height: bottom.saturating_sub(top),
+    }
+}
+
+/// Gets the inset that crops a frame to a region centered on the cursor, the region is moved
+/// inside the content near its edges.
+///
+/// # Arguments
+///
+/// * `region_size` - The size of the region, it's shrunk to the content if it's larger.
+/// * `origin` - The position of the top-left corner of the frame in the virtual desktop.
+/// * `content_size` - The size of the captured content.
+///
+/// # Returns
+///
+/// The inset, or `None` if the cursor position can't be read.
+fn cursor_region_inset(
+    region_size: (u32, u32),
+    origin: (i32, i32),
+    content_size: (u32, u32),
+) -> Option<CropInsetSettings> {
+    let mut cursor = POINT::default();
+    unsafe { GetCursorPos(&mut cursor) }.ok()?;
+
+    // The start and the end inset of one axis
+    let place = |cursor: i32, origin: i32, region: u32, content: u32| {
+        let content = content.max(1);
+        let region = region.clamp(1, content);
+        let center = i64::from(cursor) - i64::from(origin);
+        let start = (center - i64::from(region / 2)).clamp(0, i64::from(content - region));
+        let start = u32::try_from(start).unwrap_or(0);
+
+        (start, content - region - start)
+    };
+
+    let (left, right) = place(cursor.x, origin.0, region_size.0, content_size.0);
+    let (top, bottom) = place(cursor.y, origin.1, region_size.1, content_size.1);
+
+    Some(CropInsetSettings::new(left, top, right, bottom))
+}
+
+/// Gets the time that passed since a capture timestamp.
+///
+/// # Arguments
+///
+/// * `capture_time` - The `SystemRelativeTime` of a frame, the performance counter in 100ns
+///   units.
+///
+/// # Returns
+///
+/// The time since the timestamp, zero if the performance counter can't be read.
+fn acquisition_latency(capture_time: TimeSpan) -> Duration {
+    let Some(now) = system_relative_time() else {
+        return Duration::ZERO;
+    };
+
+    let elapsed = u64::try_from(now.Duration - capture_time.Duration).unwrap_or(0);
+
+    Duration::from_nanos(elapsed.saturating_mul(100))
+}
+
+/// Gets the current time on the clock of `Frame::timespan`, the performance counter in 100ns
+/// units.
+///
+/// # Returns
+///
+/// The current time, or `None` if the performance counter can't be read.
+pub(crate) fn system_relative_time() -> Option<TimeSpan> {
+    let mut counter = 0;
+    let mut frequency = 0;
+    let read = unsafe {
+        QueryPerformanceCounter(&mut counter)
+            .and_then(|()| QueryPerformanceFrequency(&mut frequency))
+    };
+
+    if read.is_err() || frequency == 0 {
+        return None;
+    }
+
+    Some(TimeSpan {
+        Duration: i64::try_from(i128::from(counter) * 10_000_000 / i128::from(frequency))
+            .unwrap_or(i64::MAX),
+    })
+}
+
+/// Cuts the inset off the edges of frames on the GPU by copying the remaining content into a
+/// texture of its size.
+struct FrameCropper {
+    crop_inset: CropInsetSettings,
+    cropped_frame: Option<(SendDirectX<IDirect3DSurface>, ID3D11Texture2D)>,
+}
+
+impl FrameCropper {
+    const fn new(crop_inset: CropInsetSettings) -> Self {
+        Self {
+            crop_inset,
+            cropped_frame: None,
+        }
+    }
+
+    // Copy the content inside the inset to a texture that is reused for the next frames. Returns
+    // the size of the cropped content, at least one pixel is kept.
+    fn crop(
+        &mut self,
+        d3d_device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        texture: &ID3D11Texture2D,
+        content_size: (u32, u32),
+    ) -> windows::core::Result<(IDirect3DSurface, ID3D11Texture2D, (u32, u32))> {
+        let (content_width, content_height) = (content_size.0.max(1), content_size.1.max(1));
+
+        let left = self.crop_inset.left.min(content_width - 1);
+        let top = self.crop_inset.top.min(content_height - 1);
+        let right = (content_width - self.crop_inset.right.min(content_width)).max(left + 1);
+        let bottom = (content_height - self.crop_inset.bottom.min(content_height)).max(top + 1);
+
+        let cropped_width = right - left;
+        let cropped_height = bottom - top;
+
+        let reusable = self
+            .cropped_frame
+            .as_ref()
+            .is_some_and(|(_, cropped_texture)| {
+                let mut cropped_desc = D3D11_TEXTURE2D_DESC::default();
+                unsafe { cropped_texture.GetDesc(&mut cropped_desc) };
+                cropped_desc.Width == cropped_width && cropped_desc.Height == cropped_height
+            });
+
+        if !reusable {
+            let mut cropped_desc = D3D11_TEXTURE2D_DESC::default();
+            unsafe { texture.GetDesc(&mut cropped_desc) };
+            cropped_desc.Width = cropped_width;
+            cropped_desc.Height = cropped_height;
+            cropped_desc.MipLevels = 1;
+            cropped_desc.ArraySize = 1;
+            cropped_desc.Usage = D3D11_USAGE_DEFAULT;
+            cropped_desc.BindFlags = D3D11_BIND_SHADER_RESOURCE.0 as u32;
+            cropped_desc.CPUAccessFlags = 0;
+            cropped_desc.MiscFlags = 0;
+
+            let mut cropped_texture = None;
+            unsafe {
+                d3d_device.CreateTexture2D(&cropped_desc, None, Some(&mut cropped_texture))?;
+            };
+            let cropped_texture = cropped_texture.unwrap();
+
+            let dxgi_surface: IDXGISurface = cropped_texture.cast()?;
+            let surface: IDirect3DSurface =
+                unsafe { CreateDirect3D11SurfaceFromDXGISurface(&dxgi_surface)? }.cast()?;
+
+            self.cropped_frame = Some((SendDirectX::new(surface), cropped_texture));
+        }
+
+        let (surface, cropped_texture) = self.cropped_frame.as_ref().unwrap();
+        let source_box = D3D11_BOX {
+            left,
+            top,
+            front: 0,
+            right,
+            bottom,
+            back: 1,
+        };
+        unsafe {
+            context.CopySubresourceRegion(
+                cropped_texture,
+                0,
+                0,
+                0,
+                0,
+                texture,
+                0,
+                Some(&source_box),
+            );
+        };
+
+        Ok((
+            surface.0.clone(),
+            cropped_texture.clone(),
+            (cropped_width, cropped_height),
+        ))
+    }
+}
+
+/// Runs the compute shader of `Settings::gpu_shader` on frames, the shader reads the frame from
+/// `t0`, writes the output to `u0` and gets the constants in `b0`.
+struct FrameShader {
+    gpu_shader: GpuShaderSettings,
+    shader: Option<(ID3D11ComputeShader, Option<ID3D11Buffer>)>,
+    input_texture: Option<(ID3D11Texture2D, ID3D11ShaderResourceView)>,
+    output_frames: Vec<(
+        SendDirectX<IDirect3DSurface>,
+        ID3D11Texture2D,
+        ID3D11UnorderedAccessView,
+    )>,
+    next_output: usize,
+}
+
+impl FrameShader {
+    /// The thread group size the shader must be declared with, in both dimensions.
+    const THREAD_GROUP_SIZE: u32 = 8;
+
+    /// The number of output textures the shader writes to in turn, a delivered frame keeps its
+    /// content until this many newer frames were shaded.
+    const OUTPUT_FRAME_COUNT: usize = 3;
+
+    const fn new(gpu_shader: GpuShaderSettings) -> Self {
+        Self {
+            gpu_shader,
+            shader: None,
+            input_texture: None,
+            output_frames: Vec::new(),
+            next_output: 0,
+        }
+    }
+
+    // Read the thread group size the shader is declared with from its bytecode.
+    fn thread_group_size(&self) -> windows::core::Result<(u32, u32, u32)> {
+        let mut reflector = ptr::null_mut();
+        unsafe {
+            D3DReflect(
+                self.gpu_shader.bytecode.as_ptr().cast(),
+                self.gpu_shader.bytecode.len(),
+                &ID3D11ShaderReflection::IID,
+                &mut reflector,
+            )?;
+        }
+        let reflection = unsafe { ID3D11ShaderReflection::from_raw(reflector) };
+
+        let (mut x, mut y, mut z) = (0, 0, 0);
+        unsafe { reflection.GetThreadGroupSize(Some(&mut x), Some(&mut y), Some(&mut z)) };
+
+        Ok((x, y, z))
+    }
+
+    // Create the shader and the constant buffer on the device, they are kept until the device is
+    // recreated.
+    fn prepare(&mut self, d3d_device: &ID3D11Device) -> windows::core::Result<()> {
+        if self.shader.is_some() {
+            return Ok(());
+        }
+
+        let mut shader = None;
+        unsafe {
+            d3d_device.CreateComputeShader(&self.gpu_shader.bytecode, None, Some(&mut shader))?;
+        };
+
+        let constant_buffer = if self.gpu_shader.constants.is_empty() {
+            None
+        } else {
+            // Constant buffers are sized in multiples of 16 bytes
+            let mut constants = self.gpu_shader.constants.clone();
+            constants.resize(constants.len().next_multiple_of(16), 0);
+
+            let buffer_desc = D3D11_BUFFER_DESC {
+                ByteWidth: u32::try_from(constants.len()).unwrap_or(u32::MAX),
+                Usage: D3D11_USAGE_DEFAULT,
+                BindFlags: D3D11_BIND_CONSTANT_BUFFER.0 as u32,
+                ..Default::default()
+            };
+            let initial_data = D3D11_SUBRESOURCE_DATA {
+                pSysMem: constants.as_ptr().cast(),
+                ..Default::default()
+            };
+
+            let mut constant_buffer = None;
+            unsafe {
+                d3d_device.CreateBuffer(
+                    &buffer_desc,
+                    Some(&initial_data),
+                    Some(&mut constant_buffer),
+                )?;
+            };
+
+            constant_buffer
+        };
+
+        self.shader = Some((shader.unwrap(), constant_buffer));
+
+        Ok(())
+    }
+
+    // Run the shader on a frame texture, the returned texture is reused after
+    // `OUTPUT_FRAME_COUNT` more frames.
+    fn run(
+        &mut self,
+        d3d_device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        texture: &ID3D11Texture2D,
+    ) -> windows::core::Result<(IDirect3DSurface, ID3D11Texture2D)> {
+        self.prepare(d3d_device)?;
+
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { texture.GetDesc(&mut desc) };
+
+        let same_size = |other: &ID3D11Texture2D| {
+            let mut other_desc = D3D11_TEXTURE2D_DESC::default();
+            unsafe { other.GetDesc(&mut other_desc) };
+            other_desc.Width == desc.Width && other_desc.Height == desc.Height
+        };
+
+        // The frame may not be bindable as a shader resource, the shader reads a copy
+        if !self
+            .input_texture
+            .as_ref()
+            .is_some_and(|(input_texture, _)| same_size(input_texture))
+        {
+            let mut input_desc = desc;
+            input_desc.MipLevels = 1;
+            input_desc.ArraySize = 1;
+            input_desc.Usage = D3D11_USAGE_DEFAULT;
+            input_desc.BindFlags = D3D11_BIND_SHADER_RESOURCE.0 as u32;
+            input_desc.CPUAccessFlags = 0;
+            input_desc.MiscFlags = 0;
+
+            let mut input_texture = None;
+            unsafe { d3d_device.CreateTexture2D(&input_desc, None, Some(&mut input_texture))? };
+            let input_texture = input_texture.unwrap();
+
+            let mut view = None;
+            unsafe { d3d_device.CreateShaderResourceView(&input_texture, None, Some(&mut view))? };
+
+            self.input_texture = Some((input_texture, view.unwrap()));
+        }
+
+        if !self
+            .output_frames
+            .first()
+            .is_some_and(|(_, output_texture, _)| same_size(output_texture))
+        {
+            self.output_frames.clear();
+            self.next_output = 0;
+        }
+
+        // The outputs are written in turn so a delivered frame that is still read, for example
+        // by the encoder, isn't overwritten by the next frame
+        let index = self.next_output;
+        self.next_output = (index + 1) % Self::OUTPUT_FRAME_COUNT;
+
+        if self.output_frames.len() <= index {
+            let mut output_desc = desc;
+            output_desc.MipLevels = 1;
+            output_desc.ArraySize = 1;
+            output_desc.Usage = D3D11_USAGE_DEFAULT;
+            output_desc.BindFlags =
+                (D3D11_BIND_SHADER_RESOURCE.0 | D3D11_BIND_UNORDERED_ACCESS.0) as u32;
+            output_desc.CPUAccessFlags = 0;
+            output_desc.MiscFlags = 0;
+
+            let mut output_texture = None;
+            unsafe { d3d_device.CreateTexture2D(&output_desc, None, Some(&mut output_texture))? };
+            let output_texture = output_texture.unwrap();
+
+            let mut view = None;
+            unsafe {
+                d3d_device.CreateUnorderedAccessView(&output_texture, None, Some(&mut view))?;
+            };
+
+            let dxgi_surface: IDXGISurface = output_texture.cast()?;
+            let surface: IDirect3DSurface =
+                unsafe { CreateDirect3D11SurfaceFromDXGISurface(&dxgi_surface)? }.cast()?;
+
+            self.output_frames
+                .push((SendDirectX::new(surface), output_texture, view.unwrap()));
+        }
+
+        let (shader, constant_buffer) = self.shader.as_ref().unwrap();
+        let (input_texture, input_view) = self.input_texture.as_ref().unwrap();
+        let (surface, output_texture, output_view) = &self.output_frames[index];
+
+        unsafe {
+            context.CopySubresourceRegion(input_texture, 0, 0, 0, 0, texture, 0, None);
+
+            context.CSSetShader(shader, None);
+            context.CSSetShaderResources(0, Some(&[Some(input_view.clone())]));
+            context.CSSetUnorderedAccessViews(0, 1, Some(&Some(output_view.clone())), None);
+            if constant_buffer.is_some() {
+                context.CSSetConstantBuffers(0, Some(&[constant_buffer.clone()]));
+            }
+
+            context.Dispatch(
+                desc.Width.div_ceil(Self::THREAD_GROUP_SIZE),
+                desc.Height.div_ceil(Self::THREAD_GROUP_SIZE),
+                1,
+            );
+
+            // Unbind the textures so the output can be copied and read by the frame
+            context.CSSetShaderResources(0, Some(&[None]));
+            context.CSSetUnorderedAccessViews(0, 1, Some(&None), None);
+        };
+
+        Ok((surface.0.clone(), output_texture.clone()))
+    }
+}
+
+// Create DirectX devices on the GPU that shows the item, or on the default GPU when no output
+// shows it.
+fn create_devices(
+    item: &GraphicsCaptureItem,
+) -> Result<(ID3D11Device, ID3D11DeviceContext, IDirect3DDevice), d3d11::Error> {
+    let (d3d_device, d3d_device_context) = match item_adapter(item) {
+        Some(adapter) => create_d3d_device_on_adapter(&adapter)?,
+        None => create_d3d_device()?,
+    };
+    let direct3d_device = create_direct3d_device(&d3d_device)?;
+
+    Ok((d3d_device, d3d_device_context, direct3d_device))
+}
+
+// Check if a device runs on another GPU than the adapter, an unknown adapter counts as the same.
+fn is_on_other_adapter(d3d_device: &ID3D11Device, adapter: &IDXGIAdapter) -> bool {
+    let device_luid = d3d_device
+        .cast::<IDXGIDevice>()
+        .and_then(|dxgi_device| unsafe { dxgi_device.GetAdapter() })
+        .and_then(|device_adapter| unsafe { device_adapter.GetDesc() })
+        .map(|desc| desc.AdapterLuid);
+    let adapter_luid = unsafe { adapter.GetDesc() }.map(|desc| desc.AdapterLuid);
+
+    match (device_luid, adapter_luid) {
+        (Ok(device_luid), Ok(adapter_luid)) => device_luid != adapter_luid,
+        _ => false,
+    }
+}
+
+// Get the next frame of the pool, None when the pool is empty.
+fn next_frame(
+    frame_pool: &Direct3D11CaptureFramePool,
+) -> windows::core::Result<Option<Direct3D11CaptureFrame>> {
+    match frame_pool.TryGetNextFrame() {
+        Ok(frame) => Ok(Some(frame)),
+        // An empty pool returns no frame without a failure code
+        Err(e) if e.code().is_ok() => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Copy a texture into a reusable copy, the copy is recreated when the size changed.
+fn copy_texture(
+    d3d_device: &ID3D11Device,
+    context: &ID3D11DeviceContext,
+    texture: &ID3D11Texture2D,
+    copy: &mut Option<(SendDirectX<IDirect3DSurface>, ID3D11Texture2D)>,
+) -> windows::core::Result<()> {
+    let mut desc = D3D11_TEXTURE2D_DESC::default();
+    unsafe { texture.GetDesc(&mut desc) };
+
+    let reusable = copy.as_ref().is_some_and(|(_, copied_texture)| {
+        let mut copy_desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { copied_texture.GetDesc(&mut copy_desc) };
+        copy_desc.Width == desc.Width && copy_desc.Height == desc.Height
+    });
+
+    if !reusable {
+        desc.Usage = D3D11_USAGE_DEFAULT;
+        desc.CPUAccessFlags = 0;
+        desc.MiscFlags = 0;
+
+        let mut new_texture = None;
+        unsafe { d3d_device.CreateTexture2D(&desc, None, Some(&mut new_texture))? };
+        let new_texture = new_texture.unwrap();
+
+        let dxgi_surface: IDXGISurface = new_texture.cast()?;
+        let surface: IDirect3DSurface =
+            unsafe { CreateDirect3D11SurfaceFromDXGISurface(&dxgi_surface)? }.cast()?;
+
+        *copy = Some((SendDirectX::new(surface), new_texture));
+    }
+
+    if let Some((_, copied_texture)) = copy {
+        unsafe { context.CopyResource(&*copied_texture, texture) };
+    }
+
+    Ok(())
+}
+
+/// A snapshot of the properties of a capture item, read at once so a UI doesn't need a WinRT
+/// call per property.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct CaptureItemInfo {
+    display_name: String,
+    width: u32,
+    height: u32,
+    is_closed: bool,
+}
+
+impl CaptureItemInfo {
+    // Read the properties of an item whose source exists.
+    pub(crate) fn from_item(item: &GraphicsCaptureItem) -> Result<Self, windows::core::Error> {
+        let size = item.Size()?;
+
+        Ok(Self {
+            display_name: item.DisplayName()?.to_string(),
+            width: u32::try_from(size.Width).unwrap_or(0),
+            height: u32::try_from(size.Height).unwrap_or(0),
+            is_closed: false,
+        })
+    }
+
+    // The info of an item whose source no longer exists.
+    pub(crate) const fn closed() -> Self {
+        Self {
+            display_name: String::new(),
+            width: 0,
+            height: 0,
+            is_closed: true,
+        }
+    }
+
+    /// Get the display name of the item, empty when the item is closed.
+    #[must_use]
+    pub fn display_name(&self) -> &str {
+        &self.display_name
+    }
+
+    /// Get the width of the item, zero when the item is closed.
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of the item, zero when the item is closed.
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get whether the window was closed or the monitor was disconnected.
+    #[must_use]
+    pub const fn is_closed(&self) -> bool {
+        self.is_closed
+    }
+}
+
 /// Represents the GraphicsCaptureApi struct.
 pub struct GraphicsCaptureApi {
     /// The GraphicsCaptureItem associated with the GraphicsCaptureApi.
     item: GraphicsCaptureItem,
-    /// The ID3D11Device associated with the GraphicsCaptureApi.
-    _d3d_device: ID3D11Device,
-    /// The IDirect3DDevice associated with the GraphicsCaptureApi.
-    _direct3d_device: IDirect3DDevice,
-    /// The ID3D11DeviceContext associated with the GraphicsCaptureApi.
-    _d3d_device_context: ID3D11DeviceContext,
+    /// The ID3D11Device, ID3D11DeviceContext and IDirect3DDevice associated with the
+    /// GraphicsCaptureApi, replaced when the devices are recreated on another GPU.
+    _devices: Arc<
+        Mutex<(
+            ID3D11Device,
+            ID3D11DeviceContext,
+            SendDirectX<IDirect3DDevice>,
+        )>,
+    >,
     /// The optional Arc<Direct3D11CaptureFramePool> associated with the GraphicsCaptureApi.
     frame_pool: Option<Arc<Direct3D11CaptureFramePool>>,
     /// The optional GraphicsCaptureSession associated with the GraphicsCaptureApi.
     session: Option<GraphicsCaptureSession>,
     /// The Arc<AtomicBool> used to halt the GraphicsCaptureApi.
     halt: Arc<AtomicBool>,
+    /// Set when the capture item was closed.
+    item_closed: Arc<AtomicBool>,
     /// Indicates whether the GraphicsCaptureApi is active or not.
     active: bool,
     /// The EventRegistrationToken associated with the capture closed event.
@@ -97,15 +1134,19 @@ pub struct GraphicsCaptureApi {
 impl GraphicsCaptureApi {
     /// Create a new Graphics Capture API struct.
     ///
+    /// The capture must be created on the thread that runs its message loop.
+    ///
     /// # Arguments
     ///
     /// * `item` - The graphics capture item to capture.
     /// * `callback` - The callback handler for capturing frames.
-    /// * `capture_cursor` - Optional flag to capture the cursor.
-    /// * `draw_border` - Optional flag to draw a border around the captured region.
-    /// * `color_format` - The color format for the captured frames.
-    /// * `thread_id` - The ID of the thread where the capture is running.
+    /// * `settings` - The settings the capture was started with.
     /// * `result` - The result of the capture operation.
+    /// * `shared_frame` - Where to keep copies of delivered frames for the `CaptureControl`.
+    /// * `paused` - Frames are discarded instead of delivered while it's set.
+    /// * `halt` - Stops the delivery once it's set, shared by every capture the `CaptureControl`
+    ///   recreates so stopping reaches the one that is currently running.
+    /// * `capture_id` - The unique id of the capture session.
     ///
     /// # Returns
     ///
@@ -116,12 +1157,65 @@ impl GraphicsCaptureApi {
     >(
         item: GraphicsCaptureItem,
         callback: Arc<Mutex<T>>,
-        cursor_capture: CursorCaptureSettings,
-        draw_border: DrawBorderSettings,
-        color_format: ColorFormat,
-        thread_id: u32,
-        result: Arc<Mutex<Option<E>>>,
+        settings: &SettingsSnapshot,
+        result: Arc<Mutex<Option<GraphicsCaptureApiError<E>>>>,
+        shared_frame: Option<Arc<SharedFrame>>,
+        paused: Arc<AtomicBool>,
+        halt: Arc<AtomicBool>,
+        capture_id: u64,
     ) -> Result<Self, Error> {
+        let SettingsSnapshot {
+            cursor_capture,
+            draw_border,
+            color_format,
+            frame_pacing,
+            skip_initial_frames,
+            trim_black_frames,
+            capture_scale,
+            buffer_frames,
+            report_dropped_frames,
+            alpha_mode,
+            crop_inset,
+            hdr_to_sdr,
+            visible_region_only,
+            include_window_shadow,
+            crop_to_child,
+            native_color_format,
+            min_update_interval,
+            gpu_shader,
+            follow_cursor,
+            readback_mode,
+            trigger_region,
+            ..
+        } = settings.clone();
+
+        // The region that follows the cursor and the cursor layer are placed relative to the
+        // monitor or the window, an item that is neither would be captured without the mask, the
+        // crop or the cursor position it asked for
+        let window = Window::from_capture_item(&item);
+        let monitor = if window.is_none() {
+            Monitor::from_capture_item(&item)
+        } else {
+            None
+        };
+        if window.is_none()
+            && monitor.is_none()
+            && (visible_region_only
+                || !include_window_shadow
+                || follow_cursor.is_some()
+                || cursor_capture == CursorCaptureSettings::SeparateLayer)
+        {
+            return Err(Error::ItemWindowUnknown);
+        }
+        let color_format = if native_color_format {
+            item_native_color_format(&item)
+        } else {
+            color_format
+        };
+        let hdr_to_sdr = hdr_to_sdr && !native_color_format;
+
+        let thread_id = unsafe { GetCurrentThreadId() };
+
         // Check support
         if !Self::is_supported()? {
             return Err(Error::Unsupported);
@@ -138,24 +1232,93 @@ impl GraphicsCaptureApi {
         }
 
         // Create DirectX devices
-        let (d3d_device, d3d_device_context) = create_d3d_device()?;
-        let direct3d_device = create_direct3d_device(&d3d_device)?;
+        let (d3d_device, d3d_device_context, direct3d_device) = create_devices(&item)?;
+        let devices = Arc::new(Mutex::new((
+            d3d_device.clone(),
+            d3d_device_context.clone(),
+            SendDirectX::new(direct3d_device.clone()),
+        )));
 
+        // Tone mapped frames are captured in half float and converted when their buffers are read
+        let (color_format, sdr_format) = if hdr_to_sdr && color_format != ColorFormat::Rgba16F {
+            (ColorFormat::Rgba16F, Some(color_format))
+        } else {
+            (color_format, None)
+        };
         let pixel_format = DirectXPixelFormat(color_format as i32);
 
-        // Create frame pool
-        let frame_pool =
-            Direct3D11CaptureFramePool::Create(&direct3d_device, pixel_format, 1, item.Size()?)?;
+        // Half float frames compose SDR content at the SDR white level of the monitor instead of
+        // scRGB 1.0, it's read once when the capture starts
+        let sdr_white_level = if color_format == ColorFormat::Rgba16F {
+            item_display_monitor(&item)
+                .and_then(|monitor| monitor.sdr_white_level().ok())
+                .unwrap_or(SDR_REFERENCE_WHITE_LEVEL)
+        } else {
+            SDR_REFERENCE_WHITE_LEVEL
+        };
+
+        // The shader writes the frames through an unordered access view of their format, an
+        // invalid shader is reported here instead of on the first frame
+        let frame_shader = match gpu_shader {
+            Some(gpu_shader) => {
+                let format_support =
+                    unsafe { d3d_device.CheckFormatSupport(color_format.dxgi_format())? };
+                if format_support & D3D11_FORMAT_SUPPORT_TYPED_UNORDERED_ACCESS_VIEW.0 as u32 == 0 {
+                    return Err(Error::GpuShaderFormatUnsupported);
+                }
+
+                let mut frame_shader = FrameShader::new(gpu_shader);
+                frame_shader.prepare(&d3d_device)?;
+
+                // The shader is dispatched in groups of 8x8 pixels, any other group size would
+                // leave pixels unwritten
+                let (x, y, z) = frame_shader.thread_group_size()?;
+                let group_size = FrameShader::THREAD_GROUP_SIZE;
+                if (x, y, z) != (group_size, group_size, 1) {
+                    return Err(Error::GpuShaderThreadGroupSize(x, y, z));
+                }
+                Some(frame_shader)
+            }
+            None => None,
+        };
+
+        // Create frame pool, with a buffer for every queued frame and an extra buffer that lets
+        // frames wait while the handler is busy so they can be reported as dropped
+        let frame_pool_buffer_count = FRAME_POOL_BUFFER_COUNT
+            .saturating_add(i32::from(report_dropped_frames))
+            .saturating_add(i32::try_from(buffer_frames).unwrap_or(i32::MAX));
+        let frame_pool = Direct3D11CaptureFramePool::Create(
+            &direct3d_device,
+            pixel_format,
+            frame_pool_buffer_count,
+            item.Size()?,
+        )?;
         let frame_pool = Arc::new(frame_pool);
 
         // Create capture session
         let session = frame_pool.CreateCaptureSession(&item)?;
 
+        // Windows 10 can't limit the update rate of the session, the early frames are dropped
+        // instead, in 100ns units
+        let throttle_interval = match min_update_interval {
+            Some(min_update_interval) if Self::is_min_update_interval_supported()? => {
+                session.SetMinUpdateInterval(TimeSpan {
+                    Duration: i64::try_from(min_update_interval.as_nanos() / 100)
+                        .unwrap_or(i64::MAX),
+                })?;
+                None
+            }
+            min_update_interval => min_update_interval.map(|min_update_interval| {
+                i64::try_from(min_update_interval.as_nanos() / 100).unwrap_or(i64::MAX)
+            }),
+        };
+
         // Preallocate memory
         let mut buffer = vec![0u8; 3840 * 2160 * 4];
 
-        // Indicates if the capture is closed
-        let halt = Arc::new(AtomicBool::new(false));
+        // Indicates if the capture item is closed, the halt flag outlives this capture so closing
+        // the item doesn't set it
+        let item_closed = Arc::new(AtomicBool::new(false));
 
         // Set capture session closed event
         let capture_closed_event_token = item.Closed(&TypedEventHandler::<
@@ -164,15 +1327,26 @@ impl GraphicsCaptureApi {
         >::new({
             // Init
             let callback_closed = callback.clone();
-            let halt_closed = halt.clone();
+            let item_closed = item_closed.clone();
             let result_closed = result.clone();
 
             move |_, _| {
-                halt_closed.store(true, atomic::Ordering::Relaxed);
+                item_closed.store(true, atomic::Ordering::Relaxed);
 
                 // Notify the struct that the capture session is closed
-                if let Err(e) = callback_closed.lock().on_closed() {
-                    *result_closed.lock() = Some(e);
+                let closed_result =
+                    panic::catch_unwind(AssertUnwindSafe(|| callback_closed.lock().on_closed()));
+
+                match closed_result {
+                    Ok(Ok(())) => (),
+                    Ok(Err(e)) => {
+                        *result_closed.lock() = Some(GraphicsCaptureApiError::FrameHandlerError(e));
+                    }
+                    Err(payload) => {
+                        *result_closed.lock() = Some(GraphicsCaptureApiError::HandlerPanic(
+                            panic_message(&*payload),
+                        ));
+                    }
                 }
 
                 // To stop message loop
@@ -185,120 +1359,853 @@ impl GraphicsCaptureApi {
         }))?;
 
         // Set frame pool frame arrived event
-        let frame_arrived_event_token = frame_pool.FrameArrived(&TypedEventHandler::<
-            Direct3D11CaptureFramePool,
-            IInspectable,
-        >::new({
-            // Init
-            let frame_pool_recreate = frame_pool.clone();
-            let halt_frame_pool = halt.clone();
-            let d3d_device_frame_pool = d3d_device.clone();
-            let context = d3d_device_context.clone();
-            let result_frame_pool = result;
-
-            let mut last_size = item.Size()?;
-            let callback_frame_pool = callback;
-            let direct3d_device_recreate = SendDirectX::new(direct3d_device.clone());
-
-            move |frame, _| {
-                // Return early if the capture is closed
-                if halt_frame_pool.load(atomic::Ordering::Relaxed) {
-                    return Ok(());
-                }
+        let frame_arrived_event_token =
+            frame_pool.FrameArrived(&TypedEventHandler::<
+                Direct3D11CaptureFramePool,
+                IInspectable,
+            >::new({
+                // Init
+                let frame_pool_recreate = frame_pool.clone();
+                let halt_frame_pool = halt.clone();
+                let item_closed_frame_pool = item_closed.clone();
+                let mut d3d_device_frame_pool = d3d_device.clone();
+                let mut context = d3d_device_context.clone();
+                let devices_recreate = devices.clone();
+                let item_recreate = item.clone();
+                let mut adapter_monitor = window.and_then(|window| window.monitor());
+                let result_frame_pool = result;
 
-                // Get frame
-                let frame = frame
-                    .as_ref()
-                    .expect("FrameArrived parameter was None this should never happen.")
-                    .TryGetNextFrame()?;
-                let timespan = frame.SystemRelativeTime()?;
-
-                // Get frame content size
-                let frame_content_size = frame.ContentSize()?;
-
-                // Get frame surface
-                let frame_surface = frame.Surface()?;
-
-                // Convert surface to texture
-                let frame_dxgi_interface = frame_surface.cast::<IDirect3DDxgiInterfaceAccess>()?;
-                let frame_texture =
-                    unsafe { frame_dxgi_interface.GetInterface::<ID3D11Texture2D>()? };
-
-                // Get texture settings
-                let mut desc = D3D11_TEXTURE2D_DESC::default();
-                unsafe { frame_texture.GetDesc(&mut desc) }
-
-                // Check if the size has been changed
-                if frame_content_size.Width != last_size.Width
-                    || frame_content_size.Height != last_size.Height
-                {
-                    let direct3d_device_recreate = &direct3d_device_recreate;
-                    frame_pool_recreate.Recreate(
-                        &direct3d_device_recreate.0,
-                        pixel_format,
-                        1,
-                        frame_content_size,
-                    )?;
-
-                    last_size = frame_content_size;
-
-                    return Ok(());
-                }
+                let mut last_size = item.Size()?;
+                let callback_frame_pool = callback;
+                let mut direct3d_device_recreate = SendDirectX::new(direct3d_device.clone());
+                let separate_cursor = cursor_capture == CursorCaptureSettings::SeparateLayer;
+                let mut repeat_buffer = Vec::new();
+                let mut skip_remaining = skip_initial_frames;
+                let mut was_paused = false;
+                let mut last_delivered = None;
+                let mut last_accepted = None;
+                let mut frame_queue = VecDeque::<Direct3D11CaptureFrame>::new();
+                let frame_queue_capacity = buffer_frames as usize;
+                let mut black_frame_trimmer = trim_black_frames.then(BlackFrameTrimmer::new);
+                let mut region_trigger =
+                    trigger_region.map(|(rect, threshold)| RegionTrigger::new(rect, threshold));
+                let visible_region_window = window.filter(|_| visible_region_only);
+                let shadow_window = window.filter(|_| !include_window_shadow);
+                let mut frame_cropper = (!crop_inset.is_empty()
+                    || shadow_window.is_some()
+                    || crop_to_child.is_some()
+                    || follow_cursor.is_some())
+                .then(|| FrameCropper::new(crop_inset));
+                let mut frame_scaler = match capture_scale {
+                    CaptureScaleSettings::Full => None,
+                    capture_scale => Some(FrameScaler::new(capture_scale.mip_level())),
+                };
+                let mut frame_shader = frame_shader;
+                let mut vsync_pacer = match frame_pacing {
+                    FramePacingSettings::Default => None,
+                    FramePacingSettings::Vsync { refresh_rate } => {
+                        Some(VsyncPacer::new(refresh_rate.max(1)))
+                    }
+                };
 
-                // Set width & height
-                let texture_width = desc.Width;
-                let texture_height = desc.Height;
-
-                // Create a frame
-                let mut frame = Frame::new(
-                    &d3d_device_frame_pool,
-                    frame_surface,
-                    frame_texture,
-                    timespan,
-                    &context,
-                    &mut buffer,
-                    texture_width,
-                    texture_height,
-                    color_format,
-                );
-
-                // Init internal capture control
-                let stop = Arc::new(AtomicBool::new(false));
-                let internal_capture_control = InternalCaptureControl::new(stop.clone());
-
-                // Send the frame to the callback struct
-                let result = callback_frame_pool
-                    .lock()
-                    .on_frame_arrived(&mut frame, internal_capture_control);
-
-                if stop.load(atomic::Ordering::Relaxed) || result.is_err() {
-                    if let Err(e) = result {
-                        *result_frame_pool.lock() = Some(e);
+                move |frame, _| {
+                    // Return early if the capture is closed
+                    if halt_frame_pool.load(atomic::Ordering::Relaxed)
+                        || item_closed_frame_pool.load(atomic::Ordering::Relaxed)
+                    {
+                        return Ok(());
                     }
 
-                    halt_frame_pool.store(true, atomic::Ordering::Relaxed);
-
-                    // To stop the message loop
-                    unsafe {
-                        PostThreadMessageW(
-                            thread_id,
-                            WM_QUIT,
-                            WPARAM::default(),
-                            LPARAM::default(),
-                        )?;
-                    };
-                }
+                    let frame_pool = frame
+                        .as_ref()
+                        .expect("FrameArrived parameter was None this should never happen.");
 
-                Result::Ok(())
-            }
-        }))?;
+                    // Discard the frames while paused, the session keeps running so resuming is
+                    // instant
+                    if paused.load(atomic::Ordering::Relaxed) {
+                        while let Some(frame) = next_frame(frame_pool)? {
+                            frame.Close()?;
+                        }
+
+                        for frame in frame_queue.drain(..) {
+                            frame.Close()?;
+                        }
+
+                        was_paused = true;
+                        return Ok(());
+                    }
+
+                    // Don't repeat the last frame or count a gap for the time the capture was
+                    // paused
+                    if was_paused {
+                        if let Some(vsync_pacer) = &mut vsync_pacer {
+                            vsync_pacer.last_slot = None;
+                            vsync_pacer.last_frame = None;
+                        }
+
+                        if let Some(shared_frame) = &shared_frame {
+                            shared_frame.restart_pacing();
+                        }
+
+                        last_delivered = None;
+                        was_paused = false;
+                    }
+
+                    // Recreate the devices when the GPU was removed, or when the window moved to a
+                    // monitor of another GPU, for example on a hybrid laptop
+                    let mut devices_lost =
+                        unsafe { d3d_device_frame_pool.GetDeviceRemovedReason() }.is_err();
+                    if let Some(window) = window {
+                        let monitor = window.monitor();
+                        if monitor != adapter_monitor {
+                            adapter_monitor = monitor;
+                            devices_lost |= item_adapter(&item_recreate).is_some_and(|adapter| {
+                                is_on_other_adapter(&d3d_device_frame_pool, &adapter)
+                            });
+                        }
+                    }
+
+                    if devices_lost {
+                        while let Some(frame) = next_frame(frame_pool)? {
+                            frame.Close()?;
+                        }
+
+                        for frame in frame_queue.drain(..) {
+                            frame.Close()?;
+                        }
+
+                        let adapter_result = match create_devices(&item_recreate) {
+                            Ok((new_d3d_device, new_context, new_direct3d_device)) => {
+                                frame_pool_recreate.Recreate(
+                                    &new_direct3d_device,
+                                    pixel_format,
+                                    frame_pool_buffer_count,
+                                    last_size,
+                                )?;
+
+                                *devices_recreate.lock() = (
+                                    new_d3d_device.clone(),
+                                    new_context.clone(),
+                                    SendDirectX::new(new_direct3d_device.clone()),
+                                );
+
+                                d3d_device_frame_pool = new_d3d_device;
+                                context = new_context;
+                                direct3d_device_recreate = SendDirectX::new(new_direct3d_device);
+
+                                // The kept textures belong to the removed device
+                                if let Some(vsync_pacer) = &mut vsync_pacer {
+                                    vsync_pacer.last_slot = None;
+                                    vsync_pacer.last_frame = None;
+                                }
+
+                                if let Some(black_frame_trimmer) = &mut black_frame_trimmer {
+                                    black_frame_trimmer.held.clear();
+                                    black_frame_trimmer.held_frame = None;
+                                }
+
+                                if let Some(frame_cropper) = &mut frame_cropper {
+                                    frame_cropper.cropped_frame = None;
+                                }
+
+                                if let Some(region_trigger) = &mut region_trigger {
+                                    region_trigger.pending.clear();
+                                    region_trigger.free.clear();
+                                }
+
+                                if let Some(frame_scaler) = &mut frame_scaler {
+                                    frame_scaler.mip_texture = None;
+                                    frame_scaler.scaled_frame = None;
+                                }
+
+                                if let Some(frame_shader) = &mut frame_shader {
+                                    frame_shader.shader = None;
+                                    frame_shader.input_texture = None;
+                                    frame_shader.output_frames.clear();
+                                    frame_shader.next_output = 0;
+                                }
+
+                                match panic::catch_unwind(AssertUnwindSafe(|| {
+                                    callback_frame_pool.lock().on_adapter_changed()
+                                })) {
+                                    Ok(result) => {
+                                        result.map_err(GraphicsCaptureApiError::FrameHandlerError)
+                                    }
+                                    Err(payload) => Err(GraphicsCaptureApiError::HandlerPanic(
+                                        panic_message(&*payload),
+                                    )),
+                                }
+                            }
+                            Err(e) => Err(GraphicsCaptureApiError::GraphicsCaptureApiError(
+                                Error::DirectXError(e),
+                            )),
+                        };
+
+                        if let Err(e) = adapter_result {
+                            *result_frame_pool.lock() = Some(e);
+
+                            halt_frame_pool.store(true, atomic::Ordering::Relaxed);
+
+                            // To stop the message loop
+                            unsafe {
+                                PostThreadMessageW(
+                                    thread_id,
+                                    WM_QUIT,
+                                    WPARAM::default(),
+                                    LPARAM::default(),
+                                )?;
+                            };
+                        }
+
+                        return Ok(());
+                    }
+
+                    // Queue the frames, without a queue only the latest frame is kept and the older
+                    // frames still in the pool were not delivered because the handler was busy,
+                    // with a queue only the frames that don't fit in it are dropped
+                    let mut dropped_count = 0;
+                    let queue_capacity = frame_queue_capacity.max(1);
+                    while let Some(queued_frame) = next_frame(frame_pool)? {
+                        if frame_queue.len() >= queue_capacity {
+                            if let Some(oldest_frame) = frame_queue.pop_front() {
+                                oldest_frame.Close()?;
+                                dropped_count += 1;
+                            }
+                        }
+
+                        frame_queue.push_back(queued_frame);
+                    }
+
+                    // Deliver every queued frame oldest first, the frames that arrive meanwhile are
+                    // left for the next event so the message loop keeps running
+                    for _ in 0..frame_queue.len() {
+                        if halt_frame_pool.load(atomic::Ordering::Relaxed) {
+                            return Ok(());
+                        }
+
+                        let Some(frame) = frame_queue.pop_front() else {
+                            break;
+                        };
+
+                        let timespan = frame.SystemRelativeTime()?;
+                        let capture_time = timespan;
+
+                        if let Some(throttle_interval) = throttle_interval {
+                            if last_accepted.is_some_and(|last_accepted: TimeSpan| {
+                                timespan.Duration - last_accepted.Duration < throttle_interval
+                            }) {
+                                frame.Close()?;
+                                continue;
+                            }
+
+                            last_accepted = Some(timespan);
+                        }
+
+                        // Get frame content size
+                        let frame_content_size = frame.ContentSize()?;
+
+                        // Get frame surface
+                        let frame_surface = frame.Surface()?;
+
+                        // Convert surface to texture
+                        let frame_dxgi_interface =
+                            frame_surface.cast::<IDirect3DDxgiInterfaceAccess>()?;
+                        let frame_texture =
+                            unsafe { frame_dxgi_interface.GetInterface::<ID3D11Texture2D>()? };
+
+                        // Get texture settings
+                        let mut desc = D3D11_TEXTURE2D_DESC::default();
+                        unsafe { frame_texture.GetDesc(&mut desc) }
+
+                        // Check if the size has been changed
+                        if frame_content_size.Width != last_size.Width
+                            || frame_content_size.Height != last_size.Height
+                        {
+                            let direct3d_device_recreate = &direct3d_device_recreate;
+                            frame_pool_recreate.Recreate(
+                                &direct3d_device_recreate.0,
+                                pixel_format,
+                                frame_pool_buffer_count,
+                                frame_content_size,
+                            )?;
+
+                            last_size = frame_content_size;
+
+                            // The queued frames belong to the old frame pool buffers
+                            for frame in frame_queue.drain(..) {
+                                frame.Close()?;
+                            }
+
+                            // The stored frame has the old size and can't be repeated anymore
+                            if let Some(vsync_pacer) = &mut vsync_pacer {
+                                vsync_pacer.last_frame = None;
+                            }
+
+                            let result = match panic::catch_unwind(AssertUnwindSafe(|| {
+                                callback_frame_pool.lock().on_frame_pool_recreated(
+                                    u32::try_from(frame_content_size.Width).unwrap_or(0),
+                                    u32::try_from(frame_content_size.Height).unwrap_or(0),
+                                )
+                            })) {
+                                Ok(result) => {
+                                    result.map_err(GraphicsCaptureApiError::FrameHandlerError)
+                                }
+                                Err(payload) => Err(GraphicsCaptureApiError::HandlerPanic(
+                                    panic_message(&*payload),
+                                )),
+                            };
+
+                            if let Err(e) = result {
+                                *result_frame_pool.lock() = Some(e);
+
+                                halt_frame_pool.store(true, atomic::Ordering::Relaxed);
+
+                                // To stop the message loop
+                                unsafe {
+                                    PostThreadMessageW(
+                                        thread_id,
+                                        WM_QUIT,
+                                        WPARAM::default(),
+                                        LPARAM::default(),
+                                    )?;
+                                };
+                            }
+
+                            continue;
+                        }
+
+                        // Discard the first frames, they can be black while the source is composed
+                        if skip_remaining > 0 {
+                            skip_remaining -= 1;
+                            frame.Close()?;
+                            continue;
+                        }
+
+                        // Snap the frame to a display refresh and find the refreshes that had no
+                        // frame
+                        let (timespan, repeat_slots) = match &vsync_pacer {
+                            Some(vsync_pacer) => {
+                                let slot = vsync_pacer.slot(timespan);
+
+                                let repeat_slots = match vsync_pacer.last_slot {
+                                    // A frame was already delivered for this refresh
+                                    Some(last_slot) if slot <= last_slot => {
+                                        frame.Close()?;
+                                        continue;
+                                    }
+                                    Some(last_slot) if vsync_pacer.last_frame.is_some() => {
+                                        (last_slot + 1).max(slot - VsyncPacer::MAX_REPEATED_SLOTS)
+                                            ..slot
+                                    }
+                                    _ => slot..slot,
+                                };
+
+                                (vsync_pacer.slot_time(slot), repeat_slots)
+                            }
+                            None => (timespan, 0..0),
+                        };
+
+                        // Set width & height
+                        let texture_width = desc.Width;
+                        let texture_height = desc.Height;
+                        let content_size = (
+                            u32::try_from(frame_content_size.Width)
+                                .unwrap_or(0)
+                                .min(texture_width),
+                            u32::try_from(frame_content_size.Height)
+                                .unwrap_or(0)
+                                .min(texture_height),
+                        );
+
+                        // Cut everything around the region at the cursor, the child window, or the
+                        // borders and the shadow of the window, off together with the inset. The
+                        // region is placed inside the content that's left after the inset and
+                        // stays where it was if the cursor can't be read.
+                        let frame_inset = match (follow_cursor, crop_to_child, shadow_window) {
+                            (Some(region_size), _, _) => item_origin(monitor, window)
+                                .and_then(|origin| {
+                                    let origin = (
+                                        origin.0.saturating_add_unsigned(crop_inset.left),
+                                        origin.1.saturating_add_unsigned(crop_inset.top),
+                                    );
+                                    let content_size = (
+                                        content_size.0.saturating_sub(
+                                            crop_inset.left.saturating_add(crop_inset.right),
+                                        ),
+                                        content_size.1.saturating_sub(
+                                            crop_inset.top.saturating_add(crop_inset.bottom),
+                                        ),
+                                    );
+
+                                    cursor_region_inset(region_size, origin, content_size)
+                                })
+                                .map(|region_inset| region_inset.combined(&crop_inset))
+                                .or_else(|| {
+                                    frame_cropper
+                                        .as_ref()
+                                        .map(|frame_cropper| frame_cropper.crop_inset)
+                                })
+                                .unwrap_or(crop_inset),
+                            (None, Some(child), _) => child
+                                .root()
+                                .child_inset(&child)
+                                .map_or(crop_inset, |child_inset| {
+                                    child_inset.combined(&crop_inset)
+                                }),
+                            (None, None, Some(window)) => {
+                                window.shadow_inset().map_or(crop_inset, |shadow_inset| {
+                                    shadow_inset.combined(&crop_inset)
+                                })
+                            }
+                            (None, None, None) => crop_inset,
+                        };
+                        if let Some(frame_cropper) = &mut frame_cropper {
+                            frame_cropper.crop_inset = frame_inset;
+                        }
+
+                        // Cut the inset off the content on the GPU
+                        let (
+                            frame_surface,
+                            frame_texture,
+                            texture_width,
+                            texture_height,
+                            content_size,
+                        ) = match &mut frame_cropper {
+                            Some(frame_cropper) => {
+                                let (surface, texture, cropped_size) = frame_cropper.crop(
+                                    &d3d_device_frame_pool,
+                                    &context,
+                                    &frame_texture,
+                                    content_size,
+                                )?;
+
+                                (
+                                    surface,
+                                    texture,
+                                    cropped_size.0,
+                                    cropped_size.1,
+                                    cropped_size,
+                                )
+                            }
+                            None => (
+                                frame_surface,
+                                frame_texture,
+                                texture_width,
+                                texture_height,
+                                content_size,
+                            ),
+                        };
+
+                        // Scale the frame down on the GPU
+                        let mut frame_mip_level = 0;
+                        let (
+                            frame_surface,
+                            frame_texture,
+                            texture_width,
+                            texture_height,
+                            content_size,
+                        ) = match &mut frame_scaler {
+                            Some(frame_scaler) => {
+                                let (surface, texture, mip_level) = frame_scaler.scale(
+                                    &d3d_device_frame_pool,
+                                    &context,
+                                    &frame_texture,
+                                )?;
+                                let scale = |size: u32| (size >> mip_level).max(1);
+                                frame_mip_level = mip_level;
+
+                                (
+                                    surface,
+                                    texture,
+                                    scale(texture_width),
+                                    scale(texture_height),
+                                    (scale(content_size.0), scale(content_size.1)),
+                                )
+                            }
+                            None => (
+                                frame_surface,
+                                frame_texture,
+                                texture_width,
+                                texture_height,
+                                content_size,
+                            ),
+                        };
+
+                        // Run the compute shader of the user on the GPU
+                        let (frame_surface, frame_texture) = match &mut frame_shader {
+                            Some(frame_shader) => frame_shader.run(
+                                &d3d_device_frame_pool,
+                                &context,
+                                &frame_texture,
+                            )?,
+                            None => (frame_surface, frame_texture),
+                        };
+
+                        // Capture the cursor next to the frame and place it relative to the
+                        // delivered frame, it's left out while the item position can't be read
+                        let cursor_layer = if separate_cursor {
+                            match CursorLayer::capture() {
+                                Ok(cursor_layer) => cursor_layer
+                                    .zip(item_origin(monitor, window))
+                                    .map(|(cursor_layer, origin)| {
+                                        let frame_origin = (
+                                            origin.0.saturating_add_unsigned(frame_inset.left),
+                                            origin.1.saturating_add_unsigned(frame_inset.top),
+                                        );
+                                        cursor_layer.relative_to(frame_origin, frame_mip_level)
+                                    }),
+                                Err(e) => {
+                                    *result_frame_pool.lock() =
+                                        Some(GraphicsCaptureApiError::GraphicsCaptureApiError(
+                                            Error::CursorError(e),
+                                        ));
+
+                                    halt_frame_pool.store(true, atomic::Ordering::Relaxed);
+
+                                    // To stop the message loop
+                                    unsafe {
+                                        PostThreadMessageW(
+                                            thread_id,
+                                            WM_QUIT,
+                                            WPARAM::default(),
+                                            LPARAM::default(),
+                                        )?;
+                                    };
+
+                                    frame.Close()?;
+                                    return Ok(());
+                                }
+                            }
+                        } else {
+                            None
+                        };
+
+                        // Create a frame
+                        let capture_frame = frame;
+                        let mut frame = Frame::new(
+                            &d3d_device_frame_pool,
+                            frame_surface,
+                            frame_texture.clone(),
+                            timespan,
+                            &context,
+                            &mut buffer,
+                            texture_width,
+                            texture_height,
+                            content_size,
+                            color_format,
+                            cursor_layer,
+                        )
+                        .with_alpha_mode(alpha_mode)
+                        .with_hdr_to_sdr(sdr_format)
+                        .with_sdr_white_level(sdr_white_level);
+
+                        // Black out the areas covered by other windows, a frame that can't be
+                        // masked stops the capture so covered content is never delivered
+                        if let Some(window) = visible_region_window {
+                            let masked = window
+                                .occluded_rects()
+                                .map_err(|e| e.to_string())
+                                .and_then(|occluded_rects| {
+                                    let rects = occluded_rects
+                                        .iter()
+                                        .map(|rect| {
+                                            occluded_frame_rect(rect, frame_inset, frame_mip_level)
+                                        })
+                                        .collect::<Vec<_>>();
+
+                                    frame
+                                        .redact(&rects, RedactMode::Black)
+                                        .map_err(|e| e.to_string())
+                                });
+
+                            if let Err(e) = masked {
+                                *result_frame_pool.lock() =
+                                    Some(GraphicsCaptureApiError::GraphicsCaptureApiError(
+                                        Error::VisibleRegionError(e),
+                                    ));
+
+                                halt_frame_pool.store(true, atomic::Ordering::Relaxed);
+
+                                // To stop the message loop
+                                unsafe {
+                                    PostThreadMessageW(
+                                        thread_id,
+                                        WM_QUIT,
+                                        WPARAM::default(),
+                                        LPARAM::default(),
+                                    )?;
+                                };
+
+                                capture_frame.Close()?;
+                                return Ok(());
+                            }
+                        }
+
+                        // The cropped and scaled textures are reused for the next frames so only
+                        // untouched frames can be guarded
+                        if frame_cropper.is_none()
+                            && frame_scaler.is_none()
+                            && frame_shader.is_none()
+                        {
+                            frame = frame.with_capture_frame(capture_frame);
+                        }
+
+                        // Omit black frames at the start and hold back later ones, with vsync
+                        // pacing the later ones are delivered because the pacer repeats frames on
+                        // its own
+                        let held = match &mut black_frame_trimmer {
+                            Some(black_frame_trimmer) => {
+                                // A frame that can't be read back is treated as not black
+                                let is_black = frame.is_black().unwrap_or(false);
+
+                                if is_black && !black_frame_trimmer.started {
+                                    continue;
+                                }
+
+                                if is_black && vsync_pacer.is_none() {
+                                    black_frame_trimmer.hold(
+                                        &d3d_device_frame_pool,
+                                        &context,
+                                        &frame_texture,
+                                        timespan,
+                                        (texture_width, texture_height),
+                                        content_size,
+                                    )?;
+
+                                    continue;
+                                }
+
+                                black_frame_trimmer.started = true;
+
+                                if is_black {
+                                    Vec::new()
+                                } else {
+                                    mem::take(&mut black_frame_trimmer.held)
+                                }
+                            }
+                            None => Vec::new(),
+                        };
+
+                        // The held black frames were new frames, the refreshes without a frame
+                        // repeat the previous one
+                        let unique_count = held.len() as u64 + 1;
+                        let duplicated_count = match &vsync_pacer {
+                            Some(vsync_pacer) if vsync_pacer.last_frame.is_some() => {
+                                u64::try_from(repeat_slots.end - repeat_slots.start).unwrap_or(0)
+                            }
+                            _ => 0,
+                        };
+
+                        // The held black frames are delivered first, then the repeats and then the
+                        // frame
+                        frame = frame.with_previous_timespan(if duplicated_count > 0 {
+                            vsync_pacer
+                                .as_ref()
+                                .map(|vsync_pacer| vsync_pacer.slot_time(repeat_slots.end - 1))
+                        } else {
+                            held.last().copied().or(last_delivered)
+                        });
+                        let mut previous_timespan = last_delivered;
+
+                        // The time from the OS stamping the frame until it's handed to the handler
+                        frame = frame.with_acquisition_latency(acquisition_latency(capture_time));
+
+                        // Check the trigger region before the frame is handed to the handler, a
+                        // region that can't be copied doesn't trigger
+                        let triggered_pixels = region_trigger.as_mut().and_then(|region_trigger| {
+                            region_trigger
+                                .check(
+                                    &d3d_device_frame_pool,
+                                    &context,
+                                    &frame_texture,
+                                    color_format.bytes_per_pixel(),
+                                )
+                                .ok()
+                                .flatten()
+                        });
+
+                        // Queue the readback so the copy runs while the frame is delivered, if it
+                        // can't be queued the buffer is copied when it's read
+                        if readback_mode == ReadbackModeSettings::Deferred {
+                            let _ = frame.start_readback();
+                        }
+
+                        // Init internal capture control
+                        let stop = Arc::new(AtomicBool::new(false));
+                        let internal_capture_control =
+                            InternalCaptureControl::new(stop.clone(), capture_id);
+
+                        // Send the frame to the callback struct, a panic inside the handler is
+                        // caught so it can be returned to the user instead of silently killing the
+                        // thread
+                        let result = match panic::catch_unwind(AssertUnwindSafe(|| {
+                            let mut callback = callback_frame_pool.lock();
+
+                            // The capture may have been stopped while waiting for the handler, no
+                            // delivery starts once it is stopping
+                            if halt_frame_pool.load(atomic::Ordering::Relaxed) {
+                                return Ok(());
+                            }
+
+                            if dropped_count > 0 {
+                                callback.on_frame_dropped(dropped_count)?;
+                            }
+
+                            // Deliver the held black frames now that the capture continues
+                            if let Some(black_frame_trimmer) = &black_frame_trimmer {
+                                if let Some((surface, texture)) = &black_frame_trimmer.held_frame {
+                                    let (held_width, held_height) = black_frame_trimmer.held_size;
+
+                                    for held_timespan in held {
+                                        let mut held_frame = Frame::new(
+                                            &d3d_device_frame_pool,
+                                            surface.0.clone(),
+                                            texture.clone(),
+                                            held_timespan,
+                                            &context,
+                                            &mut repeat_buffer,
+                                            held_width,
+                                            held_height,
+                                            black_frame_trimmer.held_content_size,
+                                            color_format,
+                                            None,
+                                        )
+                                        .with_previous_timespan(previous_timespan)
+                                        .with_alpha_mode(alpha_mode)
+                                        .with_hdr_to_sdr(sdr_format)
+                                        .with_sdr_white_level(sdr_white_level);
+                                        previous_timespan = Some(held_timespan);
+
+                                        callback.on_frame_arrived(
+                                            &mut held_frame,
+                                            InternalCaptureControl::new(stop.clone(), capture_id),
+                                        )?;
+
+                                        if stop.load(atomic::Ordering::Relaxed)
+                                            || halt_frame_pool.load(atomic::Ordering::Relaxed)
+                                        {
+                                            return Ok(());
+                                        }
+                                    }
+                                }
+                            }
+
+                            // Repeat the previous frame for the refreshes that had no new frame
+                            if let Some(vsync_pacer) = &vsync_pacer {
+                                if let Some((last_surface, last_texture)) = &vsync_pacer.last_frame
+                                {
+                                    for slot in repeat_slots.clone() {
+                                        let mut repeated_frame = Frame::new(
+                                            &d3d_device_frame_pool,
+                                            last_surface.0.clone(),
+                                            last_texture.clone(),
+                                            vsync_pacer.slot_time(slot),
+                                            &context,
+                                            &mut repeat_buffer,
+                                            texture_width,
+                                            texture_height,
+                                            content_size,
+                                            color_format,
+                                            None,
+                                        )
+                                        .with_previous_timespan(previous_timespan)
+                                        .with_alpha_mode(alpha_mode)
+                                        .with_hdr_to_sdr(sdr_format)
+                                        .with_sdr_white_level(sdr_white_level);
+                                        previous_timespan = Some(vsync_pacer.slot_time(slot));
+
+                                        callback.on_frame_arrived(
+                                            &mut repeated_frame,
+                                            InternalCaptureControl::new(stop.clone(), capture_id),
+                                        )?;
+
+                                        if stop.load(atomic::Ordering::Relaxed)
+                                            || halt_frame_pool.load(atomic::Ordering::Relaxed)
+                                        {
+                                            return Ok(());
+                                        }
+                                    }
+                                }
+                            }
+
+                            if let Some(changed_pixels) = triggered_pixels {
+                                callback.on_triggered(changed_pixels)?;
+                            }
+
+                            callback.on_frame_arrived(&mut frame, internal_capture_control)
+                        })) {
+                            Ok(result) => {
+                                result.map_err(GraphicsCaptureApiError::FrameHandlerError)
+                            }
+                            Err(payload) => Err(GraphicsCaptureApiError::HandlerPanic(
+                                panic_message(&*payload),
+                            )),
+                        };
+
+                        // The dropped frames were reported with this frame
+                        dropped_count = 0;
+
+                        if result.is_ok() {
+                            last_delivered = Some(timespan);
+                        }
+
+                        // Keep a copy of the delivered frame so it can be repeated on the next
+                        // refreshes
+                        if let Some(vsync_pacer) = &mut vsync_pacer {
+                            if result.is_ok() {
+                                vsync_pacer.store(
+                                    &d3d_device_frame_pool,
+                                    &context,
+                                    &frame_texture,
+                                )?;
+                                vsync_pacer.last_slot = Some(repeat_slots.end);
+                            }
+                        }
+
+                        // Record the frame time and keep a copy of the delivered frame for the
+                        // CaptureControl
+                        if let Some(shared_frame) = &shared_frame {
+                            if result.is_ok() {
+                                shared_frame.record_arrival(timespan);
+                                shared_frame.record_delivered(unique_count, duplicated_count);
+                            }
+
+                            if result.is_ok() && shared_frame.wants_frame() {
+                                if let Ok(snapshot) = frame.snapshot() {
+                                    shared_frame.store(snapshot);
+                                }
+                            }
+                        }
+
+                        if stop.load(atomic::Ordering::Relaxed) || result.is_err() {
+                            if let Err(e) = result {
+                                *result_frame_pool.lock() = Some(e);
+                            }
+
+                            halt_frame_pool.store(true, atomic::Ordering::Relaxed);
+
+                            // To stop the message loop
+                            unsafe {
+                                PostThreadMessageW(
+                                    thread_id,
+                                    WM_QUIT,
+                                    WPARAM::default(),
+                                    LPARAM::default(),
+                                )?;
+                            };
+                        }
+                    }
+
+                    Result::Ok(())
+                }
+            }))?;
 
         if cursor_capture != CursorCaptureSettings::Default {
             if Self::is_cursor_settings_supported()? {
                 match cursor_capture {
                     CursorCaptureSettings::Default => (),
                     CursorCaptureSettings::WithCursor => session.SetIsCursorCaptureEnabled(true)?,
-                    CursorCaptureSettings::WithoutCursor => {
+                    CursorCaptureSettings::WithoutCursor | CursorCaptureSettings::SeparateLayer => {
                         session.SetIsCursorCaptureEnabled(false)?
                     }
                 };
@@ -323,12 +2230,11 @@ impl GraphicsCaptureApi {
 
         Ok(Self {
             item,
-            _d3d_device: d3d_device,
-            _direct3d_device: direct3d_device,
-            _d3d_device_context: d3d_device_context,
+            _devices: devices,
             frame_pool: Some(frame_pool),
             session: Some(session),
             halt,
+            item_closed,
             active: false,
             frame_arrived_event_token,
             capture_closed_event_token,
@@ -380,6 +2286,22 @@ impl GraphicsCaptureApi {
         self.halt.clone()
     }
 
+    /// Check if the capture ended because the capture item was closed.
+    #[must_use]
+    pub fn is_item_closed(&self) -> bool {
+        self.item_closed.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Get the capture session, `None` after the capture was stopped.
+    ///
+    /// # Returns
+    ///
+    /// Returns the `GraphicsCaptureSession` of the capture.
+    #[must_use]
+    pub fn session(&self) -> Option<GraphicsCaptureSession> {
+        self.session.clone()
+    }
+
     /// Check if the Windows Graphics Capture API is supported.
     ///
     /// # Returns
@@ -404,6 +2326,19 @@ impl GraphicsCaptureApi {
         )? && Self::is_supported()?)
     }
 
+    /// Check if the session can limit how often it produces frames, `Settings::min_update_interval`
+    /// drops the early frames itself otherwise.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the minimum update interval is supported, `false` otherwise.
+    pub fn is_min_update_interval_supported() -> Result<bool, Error> {
+        Ok(ApiInformation::IsPropertyPresent(
+            &HSTRING::from("Windows.Graphics.Capture.GraphicsCaptureSession"),
+            &HSTRING::from("MinUpdateInterval"),
+        )? && Self::is_supported()?)
+    }
+
     /// Check if you can change the border capture setting.
     ///
     /// # Returns
@@ -417,6 +2352,154 @@ impl GraphicsCaptureApi {
     }
 }
 
+/// The limits of the capture and the video encoders on this device, returned by `capabilities`.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct CaptureCapabilities {
+    /// Whether the Graphics Capture API is supported and the GPU reaches the feature level the
+    /// capture needs.
+    pub supported: bool,
+    /// The largest width and height of a texture on the GPU, 0 if no Direct3D device could be
+    /// created. It's reported even if the capture isn't supported.
+    pub max_texture_size: u32,
+    /// The limits of the hardware video encoders, encoder types without a hardware encoder are
+    /// missing.
+    pub encoders: Vec<EncoderCapabilities>,
+}
+
+/// Query what the capture and the video encoders support on this device, so sizes and frame
+/// rates the device can't handle can be disabled instead of failing at `start`.
+///
+/// The texture size comes from the feature level of the GPU, content larger than it can't be
+/// captured. The encoders of the `Mp4`, `Hevc`, `Av1` and `Vp9` encoder types are probed with
+/// `EncoderCapabilities::probe`, which activates every hardware encoder, so call it once and keep
+/// the result.
+///
+/// # Returns
+///
+/// The capabilities of this device.
+#[must_use]
+pub fn capabilities() -> CaptureCapabilities {
+    let max_texture_size = create_d3d_device().map_or(0, |(d3d_device, _)| {
+        max_texture_size(unsafe { d3d_device.GetFeatureLevel() })
+    });
+
+    let encoders = [
+        VideoEncoderType::Mp4,
+        VideoEncoderType::Hevc,
+        VideoEncoderType::Av1,
+        VideoEncoderType::Vp9,
+    ]
+    .into_iter()
+    .filter_map(EncoderCapabilities::probe)
+    .collect();
+
+    CaptureCapabilities {
+        supported: GraphicsCaptureApi::is_supported().unwrap_or(false) && max_texture_size > 0,
+        max_texture_size,
+        encoders,
+    }
+}
+
+// The largest width and height of a texture at a feature level.
+const fn max_texture_size(feature_level: D3D_FEATURE_LEVEL) -> u32 {
+    match feature_level {
+        D3D_FEATURE_LEVEL_9_1 | D3D_FEATURE_LEVEL_9_2 => 2048,
+        D3D_FEATURE_LEVEL_9_3 => 4096,
+        D3D_FEATURE_LEVEL_10_0 | D3D_FEATURE_LEVEL_10_1 => 8192,
+        _ => D3D11_REQ_TEXTURE2D_U_OR_V_DIMENSION,
+    }
+}
+
+/// Extracts a readable message from a panic payload.
+///
+/// # Arguments
+///
+/// * `payload` - The payload returned by `std::panic::catch_unwind`.
+///
+/// # Returns
+///
+/// The panic message, or a generic message if the payload is not a string.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("Unknown panic payload")
+    }
+}
+
+/// Get the format the desktop compositor composes an item in, half float on HDR monitors.
+///
+/// # Arguments
+///
+/// * `item` - The item to get the format of.
+///
+/// # Returns
+///
+/// `ColorFormat::Rgba16F` if the monitor of the item is in HDR mode, otherwise
+/// `ColorFormat::Bgra8`.
+fn item_native_color_format(item: &GraphicsCaptureItem) -> ColorFormat {
+    if item_display_monitor(item).is_some_and(|monitor| monitor.is_hdr().unwrap_or(false)) {
+        ColorFormat::Rgba16F
+    } else {
+        ColorFormat::Bgra8
+    }
+}
+
+/// Find the monitor that shows an item, a window is on the monitor that shows most of it.
+///
+/// # Arguments
+///
+/// * `item` - The item to find the monitor of.
+///
+/// # Returns
+///
+/// The monitor, or `None` if the item is neither a window nor a monitor.
+fn item_display_monitor(item: &GraphicsCaptureItem) -> Option<Monitor> {
+    Window::from_capture_item(item)
+        .and_then(|window| window.monitor())
+        .or_else(|| Monitor::from_capture_item(item))
+}
+
+/// Get the screen position of the top-left corner of the frames of a monitor or a window item,
+/// for a window that's its window rect including the invisible borders and the shadow. The
+/// frame inset, which contains the shadow when it's cut off, is added to it.
+///
+/// # Arguments
+///
+/// * `monitor` - The monitor of a monitor item.
+/// * `window` - The window of a window item.
+///
+/// # Returns
+///
+/// The position, or `None` if it can't be read.
+fn item_origin(monitor: Option<Monitor>, window: Option<Window>) -> Option<(i32, i32)> {
+    match monitor {
+        Some(monitor) => monitor.position().ok(),
+        None => window.and_then(|window| window.position().ok()),
+    }
+}
+
+/// Find the adapter of the GPU whose output shows an item.
+///
+/// # Arguments
+///
+/// * `item` - The item to find the adapter of.
+///
+/// # Returns
+///
+/// The adapter, or `None` if no output shows the item.
+fn item_adapter(item: &GraphicsCaptureItem) -> Option<IDXGIAdapter> {
+    let monitor = item_display_monitor(item)?;
+    let output = Monitor::enumerate_outputs()
+        .ok()?
+        .into_iter()
+        .find(|output| output.monitor() == Some(monitor))?;
+
+    unsafe { output.as_raw_dxgi_output().GetParent().ok() }
+}
+
 impl Drop for GraphicsCaptureApi {
     fn drop(&mut self) {
         if let Some(frame_pool) = self.frame_pool.take() {
@@ -0,0 +1,450 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    sync::{mpsc, Arc},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use windows::Win32::{
+    Foundation::{HINSTANCE, LPARAM, LRESULT, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::{
+        Input::KeyboardAndMouse::{
+            GetKeyNameTextW, VIRTUAL_KEY, VK_LCONTROL, VK_LMENU, VK_LSHIFT, VK_LWIN, VK_RCONTROL,
+            VK_RMENU, VK_RSHIFT, VK_RWIN,
+        },
+        WindowsAndMessaging::{
+            CallNextHookEx, DispatchMessageW, GetMessageW, PostThreadMessageW, SetWindowsHookExW,
+            TranslateMessage, UnhookWindowsHookEx, HHOOK, KBDLLHOOKSTRUCT, LLKHF_EXTENDED, MSG,
+            MSLLHOOKSTRUCT, WH_KEYBOARD_LL, WH_MOUSE_LL, WM_KEYDOWN, WM_KEYUP, WM_LBUTTONDOWN,
+            WM_MBUTTONDOWN, WM_QUIT, WM_RBUTTONDOWN, WM_SYSKEYDOWN, WM_SYSKEYUP,
+        },
+    },
+};
+
+use crate::{
+    frame::{self, Frame, TextStyle},
+    graphics_capture_api::system_relative_time,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("The input hook thread ended before the hooks were installed")]
+    HookThreadFailed,
+    #[error("Failed to draw the overlay: {0}")]
+    FrameError(#[from] frame::Error),
+    #[error("Windows API error: {0}")]
+    WindowsError(#[from] windows::core::Error),
+}
+
+/// The number of input events kept, older events are dropped.
+const MAX_EVENTS: usize = 64;
+
+/// The number of key presses shown in the caption at once.
+const MAX_CAPTION_KEYS: usize = 5;
+
+/// The space between the caption and the bottom edge of the frame in pixels.
+const CAPTION_MARGIN: u32 = 32;
+
+/// A mouse button whose clicks are shown by an `InputOverlay`.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+impl MouseButton {
+    // The color of the ripple of a click with the button.
+    const fn ripple_color(self) -> [u8; 4] {
+        match self {
+            Self::Left => [255, 196, 0, 255],
+            Self::Right => [0, 160, 255, 255],
+            Self::Middle => [0, 220, 120, 255],
+        }
+    }
+}
+
+// An input event, the time is on the clock of Frame::timespan.
+enum InputEvent {
+    Click {
+        button: MouseButton,
+        x: i32,
+        y: i32,
+        time: i64,
+    },
+    Key {
+        caption: String,
+        time: i64,
+    },
+}
+
+// The state of the hooks, they run on the thread that installed them.
+struct HookState {
+    events: Arc<Mutex<VecDeque<InputEvent>>>,
+    modifiers: Vec<VIRTUAL_KEY>,
+}
+
+thread_local! {
+    static HOOK_STATE: RefCell<Option<HookState>> = const { RefCell::new(None) };
+}
+
+/// Records mouse clicks and key presses with low-level hooks and draws them onto frames, a
+/// ripple for every click and a caption with the latest key presses, for example for tutorial
+/// recordings.
+///
+/// The hooks run on their own thread while the overlay exists. Call `draw` for every frame
+/// before it's encoded, the events are matched to the frame by its timestamp so the ripples
+/// animate at the speed of the recording.
+///
+/// Key captions show everything that is typed including passwords, only enable them when the
+/// recording is meant to show the typing.
+///
+/// # Example
+/// ```ignore
+/// // When the handler is created
+/// let overlay = InputOverlay::new(true)?;
+/// let origin = Monitor::primary()?.position()?;
+///
+/// // Inside on_frame_arrived
+/// overlay.draw(frame, origin)?;
+/// encoder.send_frame(frame)?;
+/// ```
+pub struct InputOverlay {
+    events: Arc<Mutex<VecDeque<InputEvent>>>,
+    thread_id: u32,
+    thread: Option<JoinHandle<()>>,
+    ripple_radius: f32,
+    ripple_duration: Duration,
+    caption_duration: Duration,
+    caption_style: TextStyle,
+}
+
+impl InputOverlay {
+    /// Starts recording the input.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_captions` - Whether key presses are recorded for the caption, clicks are always
+    ///   recorded.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(InputOverlay)` if the hooks were installed, otherwise returns an `Error`.
+    pub fn new(key_captions: bool) -> Result<Self, Error> {
+        let events = Arc::new(Mutex::new(VecDeque::with_capacity(MAX_EVENTS)));
+        let (sender, receiver) = mpsc::channel();
+
+        let thread = thread::spawn({
+            let events = events.clone();
+
+            move || {
+                HOOK_STATE.with(|state| {
+                    *state.borrow_mut() = Some(HookState {
+                        events,
+                        modifiers: Vec::new(),
+                    });
+                });
+
+                let hooks = match install_hooks(key_captions) {
+                    Ok(hooks) => hooks,
+                    Err(e) => {
+                        let _ = sender.send(Err(e));
+                        return;
+                    }
+                };
+
+                let _ = sender.send(Ok(unsafe { GetCurrentThreadId() }));
+
+                // The hooks are called from the message loop of this thread
+                let mut message = MSG::default();
+                unsafe {
+                    while GetMessageW(&mut message, None, 0, 0).as_bool() {
+                        let _ = TranslateMessage(&message);
+                        DispatchMessageW(&message);
+                    }
+
+                    for hook in hooks {
+                        let _ = UnhookWindowsHookEx(hook);
+                    }
+                };
+            }
+        });
+
+        let thread_id = receiver.recv().map_err(|_| Error::HookThreadFailed)??;
+
+        Ok(Self {
+            events,
+            thread_id,
+            thread: Some(thread),
+            ripple_radius: 28.0,
+            ripple_duration: Duration::from_millis(400),
+            caption_duration: Duration::from_millis(1500),
+            caption_style: TextStyle::new("Segoe UI", 32.0).background(Some([0, 0, 0, 192])),
+        })
+    }
+
+    /// Sets the size and duration of the ripple drawn for a click.
+    ///
+    /// # Arguments
+    ///
+    /// * `radius` - The radius the ripple grows to in pixels.
+    /// * `duration` - How long the ripple is shown after the click.
+    #[must_use]
+    pub fn ripple(mut self, radius: f32, duration: Duration) -> Self {
+        self.ripple_radius = radius;
+        self.ripple_duration = duration;
+        self
+    }
+
+    /// Sets how long a key press stays in the caption.
+    ///
+    /// # Arguments
+    ///
+    /// * `caption_duration` - How long a key press is shown after it was pressed.
+    #[must_use]
+    pub fn caption_duration(mut self, caption_duration: Duration) -> Self {
+        self.caption_duration = caption_duration;
+        self
+    }
+
+    /// Sets the font and colors of the caption, it's centered at the bottom of the frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `caption_style` - The style of the caption text.
+    #[must_use]
+    pub fn caption_style(mut self, caption_style: TextStyle) -> Self {
+        self.caption_style = caption_style;
+        self
+    }
+
+    /// Draws the recent clicks and key presses onto the frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The frame to draw on.
+    /// * `origin` - The screen position of the top-left pixel of the frame, for example
+    ///   `Monitor::position` for a monitor capture.
+    ///
+    /// # Returns
+    ///
+    /// An empty Result if successful, or an Error if the overlay could not be drawn, for example
+    /// because the frame is `Rgba16F`.
+    pub fn draw(&self, frame: &mut Frame, origin: (i32, i32)) -> Result<(), Error> {
+        let frame_time = frame.timespan().Duration;
+        let ripple_duration = duration_100ns(self.ripple_duration);
+        let caption_duration = duration_100ns(self.caption_duration);
+
+        // Copy the visible events so the hooks aren't blocked while drawing
+        let mut clicks = Vec::new();
+        let mut keys = Vec::new();
+        for event in self.events.lock().iter() {
+            match event {
+                InputEvent::Click { button, x, y, time } => {
+                    let age = frame_time - time;
+                    if (0..ripple_duration).contains(&age) {
+                        clicks.push((*button, *x, *y, age));
+                    }
+                }
+                InputEvent::Key { caption, time } => {
+                    let age = frame_time - time;
+                    if (0..caption_duration).contains(&age) {
+                        keys.push(caption.clone());
+                    }
+                }
+            }
+        }
+
+        for (button, x, y, age) in clicks {
+            // The ripple grows from a third of its radius and fades out
+            let progress = age as f32 / ripple_duration as f32;
+            let mut color = button.ripple_color();
+            color[3] = (f32::from(color[3]) * (1.0 - progress)) as u8;
+
+            frame.draw_circle(
+                (x - origin.0) as f32,
+                (y - origin.1) as f32,
+                self.ripple_radius * (1.0 + 2.0 * progress) / 3.0,
+                (self.ripple_radius / 6.0).max(1.0),
+                color,
+            )?;
+        }
+
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let caption = keys[keys.len().saturating_sub(MAX_CAPTION_KEYS)..].join("   ");
+        let (width, height) = self.caption_style.measure(&caption)?;
+        frame.draw_text(
+            &caption,
+            frame.width().saturating_sub(width) / 2,
+            frame.height().saturating_sub(height + CAPTION_MARGIN),
+            &self.caption_style,
+        )?;
+
+        Ok(())
+    }
+}
+
+impl Drop for InputOverlay {
+    fn drop(&mut self) {
+        // Ending the message loop removes the hooks
+        unsafe {
+            let _ = PostThreadMessageW(
+                self.thread_id,
+                WM_QUIT,
+                WPARAM::default(),
+                LPARAM::default(),
+            );
+        };
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Install the low-level mouse hook and optionally the keyboard hook on the current thread.
+fn install_hooks(key_captions: bool) -> Result<Vec<HHOOK>, Error> {
+    let mouse_hook =
+        unsafe { SetWindowsHookExW(WH_MOUSE_LL, Some(mouse_hook), HINSTANCE::default(), 0)? };
+
+    if !key_captions {
+        return Ok(vec![mouse_hook]);
+    }
+
+    let keyboard_hook =
+        unsafe { SetWindowsHookExW(WH_KEYBOARD_LL, Some(keyboard_hook), HINSTANCE::default(), 0) }
+            .map_err(|e| {
+                unsafe {
+                    let _ = UnhookWindowsHookEx(mouse_hook);
+                };
+                e
+            })?;
+
+    Ok(vec![mouse_hook, keyboard_hook])
+}
+
+// Record the button presses of the mouse.
+unsafe extern "system" fn mouse_hook(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code >= 0 {
+        let button = match u32::try_from(wparam.0).unwrap_or(0) {
+            WM_LBUTTONDOWN => Some(MouseButton::Left),
+            WM_RBUTTONDOWN => Some(MouseButton::Right),
+            WM_MBUTTONDOWN => Some(MouseButton::Middle),
+            _ => None,
+        };
+
+        if let (Some(button), Some(time)) = (button, system_relative_time()) {
+            let info = &*(lparam.0 as *const MSLLHOOKSTRUCT);
+
+            HOOK_STATE.with(|state| {
+                if let Some(state) = &*state.borrow() {
+                    push_event(
+                        &state.events,
+                        InputEvent::Click {
+                            button,
+                            x: info.pt.x,
+                            y: info.pt.y,
+                            time: time.Duration,
+                        },
+                    );
+                }
+            });
+        }
+    }
+
+    CallNextHookEx(HHOOK::default(), code, wparam, lparam)
+}
+
+// Record the key presses with the held modifiers, the modifiers alone are not shown.
+unsafe extern "system" fn keyboard_hook(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code >= 0 {
+        let info = &*(lparam.0 as *const KBDLLHOOKSTRUCT);
+        let key = VIRTUAL_KEY(u16::try_from(info.vkCode).unwrap_or(0));
+        let message = u32::try_from(wparam.0).unwrap_or(0);
+
+        HOOK_STATE.with(|state| {
+            let Some(state) = &mut *state.borrow_mut() else {
+                return;
+            };
+
+            match message {
+                WM_KEYDOWN | WM_SYSKEYDOWN if modifier_name(key).is_some() => {
+                    if !state.modifiers.contains(&key) {
+                        state.modifiers.push(key);
+                    }
+                }
+                WM_KEYDOWN | WM_SYSKEYDOWN => {
+                    let Some(time) = system_relative_time() else {
+                        return;
+                    };
+
+                    let mut names = ["Ctrl", "Alt", "Shift", "Win"]
+                        .into_iter()
+                        .filter(|name| {
+                            state
+                                .modifiers
+                                .iter()
+                                .any(|modifier| modifier_name(*modifier) == Some(*name))
+                        })
+                        .map(String::from)
+                        .collect::<Vec<_>>();
+                    names.push(key_name(info));
+
+                    push_event(
+                        &state.events,
+                        InputEvent::Key {
+                            caption: names.join(" + "),
+                            time: time.Duration,
+                        },
+                    );
+                }
+                WM_KEYUP | WM_SYSKEYUP => state.modifiers.retain(|modifier| *modifier != key),
+                _ => (),
+            }
+        });
+    }
+
+    CallNextHookEx(HHOOK::default(), code, wparam, lparam)
+}
+
+// Add an event, dropping the oldest event when the queue is full.
+fn push_event(events: &Mutex<VecDeque<InputEvent>>, event: InputEvent) {
+    let mut events = events.lock();
+    if events.len() >= MAX_EVENTS {
+        events.pop_front();
+    }
+    events.push_back(event);
+}
+
+// Get the caption name of a modifier key, None for other keys.
+const fn modifier_name(key: VIRTUAL_KEY) -> Option<&'static str> {
+    match key {
+        VK_LCONTROL | VK_RCONTROL => Some("Ctrl"),
+        VK_LMENU | VK_RMENU => Some("Alt"),
+        VK_LSHIFT | VK_RSHIFT => Some("Shift"),
+        VK_LWIN | VK_RWIN => Some("Win"),
+        _ => None,
+    }
+}
+
+// Get the name of a key in the keyboard layout, for example `Enter` or `A`.
+fn key_name(info: &KBDLLHOOKSTRUCT) -> String {
+    let extended = i32::from(info.flags.0 & LLKHF_EXTENDED.0 != 0);
+    let lparam = (i32::try_from(info.scanCode).unwrap_or(0) << 16) | (extended << 24);
+
+    let mut name = [0u16; 64];
+    let length = unsafe { GetKeyNameTextW(lparam, &mut name) };
+
+    String::from_utf16_lossy(&name[..usize::try_from(length).unwrap_or(0)])
+}
+
+// Convert a duration to 100ns units.
+fn duration_100ns(duration: Duration) -> i64 {
+    i64::try_from(duration.as_nanos() / 100).unwrap_or(i64::MAX)
+}
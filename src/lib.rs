@@ -66,11 +66,9 @@
 //!     fn new(message: Self::Flags) -> Result<Self, Self::Error> {
 //!         println!("Got The Flag: {message}");
 //!
-//!         let encoder = VideoEncoder::new(
+//!         let encoder = VideoEncoder::new_auto(
 //!             VideoEncoderType::Hevc,
 //!             VideoEncoderQuality::HD1080p,
-//!             1920,
-//!             1080,
 //!             "video.mp4",
 //!             Some(30),
 //!         )?;
@@ -149,17 +147,36 @@
 
 /// Contains the main capture functionality, including the `WindowsCaptureHandler` trait and related types.
 pub mod capture;
+/// Contains the `CursorLayer` struct for capturing the cursor as a separate layer.
+pub mod cursor;
 /// Internal module for Direct3D 11 related functionality.
 mod d3d11;
 /// Contains the encoder functionality for encoding captured frames.
 pub mod encoder;
 /// Contains the `Frame` struct and related types for representing captured frames.
 pub mod frame;
+/// Contains the `FrameIterator` struct for receiving frames without implementing a handler.
+pub mod frame_iterator;
+/// Contains the `GdiCapture` struct for capturing with GDI where the Graphics Capture API is
+/// unavailable.
+pub mod gdi_capture;
 /// Contains the types and functions related to the Graphics Capture API.
 pub mod graphics_capture_api;
+/// Contains the `InputOverlay` struct for drawing mouse clicks and key presses onto frames.
+pub mod input_overlay;
 /// Contains the functionality for working with monitors and screen information.
 pub mod monitor;
+/// Contains the `Mosaic` struct for tiling several window captures into one frame.
+pub mod mosaic;
+/// Internal module for delivering the frames of sources polled at a fixed rate.
+mod polling_capture;
 /// Contains the `Settings` struct and related types for configuring the capture settings.
 pub mod settings;
+/// Contains the `SharedMemorySink` struct for writing frames to shared memory for other processes.
+pub mod shared_memory;
+/// Contains the `TestSource` struct for delivering generated frames without a real display.
+pub mod test_source;
+/// Contains the `TimelapseRecorder` struct for recording one frame per interval into a video.
+pub mod timelapse;
 /// Contains the functionality for working with windows and capturing specific windows.
 pub mod window;
@@ -1,27 +1,50 @@
 use std::{mem, num::ParseIntError, ptr, string::FromUtf16Error};
 
+use parking_lot::Mutex;
 use windows::{
-    core::{HSTRING, PCWSTR},
+    core::{Interface, Weak, HSTRING, PCWSTR},
     Graphics::Capture::GraphicsCaptureItem,
     Win32::{
         Devices::Display::{
             DisplayConfigGetDeviceInfo, GetDisplayConfigBufferSizes, QueryDisplayConfig,
+            DISPLAYCONFIG_DEVICE_INFO_GET_SDR_WHITE_LEVEL,
             DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME, DISPLAYCONFIG_DEVICE_INFO_GET_TARGET_NAME,
             DISPLAYCONFIG_DEVICE_INFO_HEADER, DISPLAYCONFIG_MODE_INFO, DISPLAYCONFIG_PATH_INFO,
-            DISPLAYCONFIG_SOURCE_DEVICE_NAME, DISPLAYCONFIG_TARGET_DEVICE_NAME,
-            DISPLAYCONFIG_TARGET_DEVICE_NAME_FLAGS, DISPLAYCONFIG_VIDEO_OUTPUT_TECHNOLOGY,
-            QDC_ONLY_ACTIVE_PATHS,
+            DISPLAYCONFIG_SDR_WHITE_LEVEL, DISPLAYCONFIG_SOURCE_DEVICE_NAME,
+            DISPLAYCONFIG_TARGET_DEVICE_NAME, DISPLAYCONFIG_TARGET_DEVICE_NAME_FLAGS,
+            DISPLAYCONFIG_VIDEO_OUTPUT_TECHNOLOGY, QDC_ONLY_ACTIVE_PATHS,
         },
         Foundation::{BOOL, LPARAM, POINT, RECT, TRUE},
-        Graphics::Gdi::{
-            EnumDisplayDevicesW, EnumDisplayMonitors, EnumDisplaySettingsW, GetMonitorInfoW,
-            MonitorFromPoint, DEVMODEW, DISPLAY_DEVICEW, ENUM_CURRENT_SETTINGS, HDC, HMONITOR,
-            MONITORINFO, MONITORINFOEXW, MONITOR_DEFAULTTONULL,
+        Graphics::{
+            Dxgi::{
+                Common::{DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020, DXGI_COLOR_SPACE_TYPE},
+                CreateDXGIFactory1, IDXGIFactory1, IDXGIOutput, IDXGIOutput6, DXGI_ERROR_NOT_FOUND,
+            },
+            Gdi::{
+                EnumDisplayDevicesW, EnumDisplayMonitors, EnumDisplaySettingsW, GetMonitorInfoW,
+                MonitorFromPoint, DEVMODEW, DISPLAY_DEVICEW, ENUM_CURRENT_SETTINGS, HDC, HMONITOR,
+                MONITORINFO, MONITORINFOEXW, MONITOR_DEFAULTTONULL,
+            },
         },
         System::WinRT::Graphics::Capture::IGraphicsCaptureItemInterop,
+        UI::{
+            HiDpi::{GetDpiForMonitor, MDT_EFFECTIVE_DPI},
+            WindowsAndMessaging::MONITORINFOF_PRIMARY,
+        },
     },
 };
 
+use crate::{
+    capture::GraphicsCaptureApiError,
+    frame_iterator::{self, FrameIterator},
+    graphics_capture_api::CaptureItemInfo,
+    settings::Settings,
+};
+
+/// The monitors capture items were created from, see `Monitor::from_capture_item`.
+static CAPTURE_ITEM_MONITORS: Mutex<Vec<(Weak<GraphicsCaptureItem>, Monitor)>> =
+    Mutex::new(Vec::new());
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Failed to find monitor")]
@@ -44,6 +67,58 @@ pub enum Error {
     WindowsError(#[from] windows::core::Error),
 }
 
+/// Represents a DXGI output of a graphics adapter.
+///
+/// With display cloning one monitor can be shown on several outputs, the outputs tell which
+/// adapter and output a monitor belongs to.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct MonitorOutput {
+    adapter_index: u32,
+    output_index: u32,
+    adapter_name: String,
+    device_name: String,
+    monitor: Option<Monitor>,
+    output: IDXGIOutput,
+}
+
+impl MonitorOutput {
+    /// Get the index of the adapter the output belongs to.
+    #[must_use]
+    pub const fn adapter_index(&self) -> u32 {
+        self.adapter_index
+    }
+
+    /// Get the index of the output on its adapter.
+    #[must_use]
+    pub const fn output_index(&self) -> u32 {
+        self.output_index
+    }
+
+    /// Get the description of the adapter, for example the name of the graphics card.
+    #[must_use]
+    pub fn adapter_name(&self) -> &str {
+        &self.adapter_name
+    }
+
+    /// Get the device name of the output, for example `\\.\DISPLAY1`.
+    #[must_use]
+    pub fn device_name(&self) -> &str {
+        &self.device_name
+    }
+
+    /// Get the monitor shown on the output, `None` if the output is not attached to the desktop.
+    #[must_use]
+    pub const fn monitor(&self) -> Option<Monitor> {
+        self.monitor
+    }
+
+    /// Get the raw DXGI output.
+    #[must_use]
+    pub fn as_raw_dxgi_output(&self) -> IDXGIOutput {
+        self.output.clone()
+    }
+}
+
 /// Represents A Monitor Device
 ///
 /// # Example
@@ -349,6 +424,31 @@ impl Monitor {
         Ok(device_mode.dmDisplayFrequency)
     }
 
+    /// Returns the effective DPI of the monitor, 96 is a scale of 100%.
+    ///
+    /// Monitors can have different DPIs, use the DPI of the captured monitor to map its pixels
+    /// to the coordinates of your overlay.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if there is an error retrieving the monitor DPI.
+    pub fn dpi(&self) -> Result<u32, Error> {
+        let mut dpi_x = 0;
+        let mut dpi_y = 0;
+        unsafe { GetDpiForMonitor(self.monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y)? };
+
+        Ok(dpi_x)
+    }
+
+    /// Returns the scale factor of the monitor, for example `1.5` for a scale of 150%.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if there is an error retrieving the monitor DPI.
+    pub fn scale_factor(&self) -> Result<f64, Error> {
+        Ok(f64::from(self.dpi()?) / 96.0)
+    }
+
     /// Returns the width of the monitor in pixels.
     ///
     /// # Errors
@@ -399,6 +499,88 @@ impl Monitor {
         Ok(device_mode.dmPelsHeight)
     }
 
+    /// Returns `true` if this is the primary monitor.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if there is an error retrieving the monitor info.
+    pub fn is_primary(&self) -> Result<bool, Error> {
+        let monitor_info = self.monitor_info()?;
+
+        Ok(monitor_info.monitorInfo.dwFlags & MONITORINFOF_PRIMARY != 0)
+    }
+
+    /// Returns the display name, size and closed state of the monitor as a capture item.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the capture item can't be created or read.
+    pub fn capture_item_info(&self) -> Result<CaptureItemInfo, Error> {
+        // A disconnected monitor has no monitor info anymore
+        if self.monitor_info().is_err() {
+            return Ok(CaptureItemInfo::closed());
+        }
+
+        let item = GraphicsCaptureItem::try_from(*self)?;
+
+        Ok(CaptureItemInfo::from_item(&item)?)
+    }
+
+    /// Returns the monitor a capture item was created from with `GraphicsCaptureItem::try_from`.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The capture item.
+    ///
+    /// # Returns
+    ///
+    /// The monitor, or `None` if the item was not created from a monitor, like a window or an
+    /// item picked with the `GraphicsCapturePicker`.
+    #[must_use]
+    pub fn from_capture_item(item: &GraphicsCaptureItem) -> Option<Self> {
+        CAPTURE_ITEM_MONITORS
+            .lock()
+            .iter()
+            .find(|(weak, _)| weak.upgrade().as_ref() == Some(item))
+            .map(|(_, monitor)| *monitor)
+    }
+
+    /// Returns the position of the monitor's top-left corner in the virtual desktop.
+    ///
+    /// The primary monitor is always at `(0, 0)`, monitors placed to the left or above it have
+    /// negative coordinates.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if there is an error retrieving the monitor info.
+    pub fn position(&self) -> Result<(i32, i32), Error> {
+        let monitor_info = self.monitor_info()?;
+
+        Ok((
+            monitor_info.monitorInfo.rcMonitor.left,
+            monitor_info.monitorInfo.rcMonitor.top,
+        ))
+    }
+
+    /// Starts capturing the monitor with the settings and returns an iterator over its frames.
+    ///
+    /// The item of the settings is replaced by the monitor, so the settings of another capture
+    /// can be reused.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The capture settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `GraphicsCaptureApiError` if the capture could not be started.
+    pub fn frames<T: TryInto<GraphicsCaptureItem>>(
+        &self,
+        settings: Settings<(), T>,
+    ) -> Result<FrameIterator, GraphicsCaptureApiError<frame_iterator::Error>> {
+        FrameIterator::new(settings.with_item(*self))
+    }
+
     /// Returns a list of all monitors.
     ///
     /// # Errors
@@ -420,6 +602,214 @@ impl Monitor {
         Ok(monitors)
     }
 
+    /// Returns the DXGI outputs of every graphics adapter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if there is an error enumerating the adapters or outputs.
+    pub fn enumerate_outputs() -> Result<Vec<MonitorOutput>, Error> {
+        let factory = unsafe { CreateDXGIFactory1::<IDXGIFactory1>()? };
+
+        let mut outputs = Vec::new();
+        for adapter_index in 0.. {
+            let adapter = match unsafe { factory.EnumAdapters1(adapter_index) } {
+                Ok(adapter) => adapter,
+                Err(e) if e.code() == DXGI_ERROR_NOT_FOUND => break,
+                Err(e) => return Err(Error::WindowsError(e)),
+            };
+            let adapter_desc = unsafe { adapter.GetDesc1()? };
+            let adapter_name = wide_to_string(&adapter_desc.Description)?;
+
+            for output_index in 0.. {
+                let output = match unsafe { adapter.EnumOutputs(output_index) } {
+                    Ok(output) => output,
+                    Err(e) if e.code() == DXGI_ERROR_NOT_FOUND => break,
+                    Err(e) => return Err(Error::WindowsError(e)),
+                };
+                let output_desc = unsafe { output.GetDesc()? };
+
+                outputs.push(MonitorOutput {
+                    adapter_index,
+                    output_index,
+                    adapter_name: adapter_name.clone(),
+                    device_name: wide_to_string(&output_desc.DeviceName)?,
+                    monitor: (output_desc.AttachedToDesktop.as_bool()
+                        && !output_desc.Monitor.is_invalid())
+                    .then_some(Self {
+                        monitor: output_desc.Monitor,
+                    }),
+                    output,
+                });
+            }
+        }
+
+        Ok(outputs)
+    }
+
+    /// Checks if the monitor is in HDR mode, the desktop is then composed in half float scRGB.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::NotFound` if no DXGI output shows the monitor.
+    pub fn is_hdr(&self) -> Result<bool, Error> {
+        Ok(self.color_space()? == Some(DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020))
+    }
+
+    /// Returns the brightness SDR content is shown at on the monitor in nits, set with the SDR
+    /// content brightness slider of the HDR settings. In HDR mode scRGB `1.0` is 80 nits, SDR
+    /// white is composed at this level instead.
+    ///
+    /// Pass it to `Frame::with_sdr_white_level` to tone map HDR frames without darkening the
+    /// SDR content.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::NotFound` if no active display path shows the monitor.
+    pub fn sdr_white_level(&self) -> Result<u32, Error> {
+        let path = self.display_config_path()?;
+
+        let mut white_level = DISPLAYCONFIG_SDR_WHITE_LEVEL {
+            header: DISPLAYCONFIG_DEVICE_INFO_HEADER {
+                r#type: DISPLAYCONFIG_DEVICE_INFO_GET_SDR_WHITE_LEVEL,
+                size: u32::try_from(mem::size_of::<DISPLAYCONFIG_SDR_WHITE_LEVEL>()).unwrap(),
+                adapterId: path.targetInfo.adapterId,
+                id: path.targetInfo.id,
+            },
+            SDRWhiteLevel: 0,
+        };
+        if unsafe { DisplayConfigGetDeviceInfo(&mut white_level.header) } != 0 {
+            return Err(Error::FailedToGetMonitorInfo);
+        }
+
+        // The level is a multiple of 80 nits in thousandths
+        Ok(white_level.SDRWhiteLevel.saturating_mul(80) / 1000)
+    }
+
+    // Find the active display path whose source shows the monitor.
+    fn display_config_path(&self) -> Result<DISPLAYCONFIG_PATH_INFO, Error> {
+        let device_name = self.device_name()?;
+
+        let mut number_of_paths = 0;
+        let mut number_of_modes = 0;
+        unsafe {
+            GetDisplayConfigBufferSizes(
+                QDC_ONLY_ACTIVE_PATHS,
+                &mut number_of_paths,
+                &mut number_of_modes,
+            )
+            .ok()?;
+        };
+
+        let mut paths = vec![DISPLAYCONFIG_PATH_INFO::default(); number_of_paths as usize];
+        let mut modes = vec![DISPLAYCONFIG_MODE_INFO::default(); number_of_modes as usize];
+        unsafe {
+            QueryDisplayConfig(
+                QDC_ONLY_ACTIVE_PATHS,
+                &mut number_of_paths,
+                paths.as_mut_ptr(),
+                &mut number_of_modes,
+                modes.as_mut_ptr(),
+                None,
+            )
+        }
+        .ok()?;
+        paths.truncate(number_of_paths as usize);
+
+        for path in paths {
+            let mut source = DISPLAYCONFIG_SOURCE_DEVICE_NAME {
+                header: DISPLAYCONFIG_DEVICE_INFO_HEADER {
+                    r#type: DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME,
+                    size: u32::try_from(mem::size_of::<DISPLAYCONFIG_SOURCE_DEVICE_NAME>())
+                        .unwrap(),
+                    adapterId: path.sourceInfo.adapterId,
+                    id: path.sourceInfo.id,
+                },
+                viewGdiDeviceName: [0; 32],
+            };
+            if unsafe { DisplayConfigGetDeviceInfo(&mut source.header) } != 0 {
+                continue;
+            }
+
+            let view_gdi_device_name = String::from_utf16(
+                &source
+                    .viewGdiDeviceName
+                    .iter()
+                    .take_while(|ch| **ch != 0x0000)
+                    .copied()
+                    .collect::<Vec<u16>>(),
+            )?;
+            if view_gdi_device_name == device_name {
+                return Ok(path);
+            }
+        }
+
+        Err(Error::NotFound)
+    }
+
+    // Get the color space of the DXGI output showing the monitor, None for outputs without
+    // IDXGIOutput6 which predate wide color gamut support.
+    fn color_space(&self) -> Result<Option<DXGI_COLOR_SPACE_TYPE>, Error> {
+        let output = Self::enumerate_outputs()?
+            .into_iter()
+            .find(|output| output.monitor == Some(*self))
+            .ok_or(Error::NotFound)?;
+
+        let Ok(output) = output.output.cast::<IDXGIOutput6>() else {
+            return Ok(None);
+        };
+        let output_desc = unsafe { output.GetDesc1()? };
+
+        Ok(Some(output_desc.ColorSpace))
+    }
+
+    /// Returns the monitor shown on a DXGI output.
+    ///
+    /// # Arguments
+    ///
+    /// * `adapter_index` - The index of the graphics adapter, starting from 0.
+    /// * `output_index` - The index of the output on the adapter, starting from 0.
+    ///
+    /// With display cloning every output of the cloned monitor returns the same `Monitor`. The
+    /// capture is made per `HMONITOR`, so it shows the same desktop whichever of the outputs it
+    /// was looked up from. `enumerate_outputs` tells which outputs share the monitor.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::NotFound` if the output doesn't exist or is not attached to the desktop.
+    pub fn from_output_index(adapter_index: u32, output_index: u32) -> Result<Self, Error> {
+        Self::enumerate_outputs()?
+            .into_iter()
+            .find(|output| {
+                output.adapter_index == adapter_index && output.output_index == output_index
+            })
+            .and_then(|output| output.monitor)
+            .ok_or(Error::NotFound)
+    }
+
+    /// Returns the monitor shown on a DXGI output.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - The DXGI output.
+    ///
+    /// With display cloning every output of the cloned monitor returns the same `Monitor`, see
+    /// `Monitor::from_output_index`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::NotFound` if the output is not attached to the desktop.
+    pub fn from_dxgi_output(output: &IDXGIOutput) -> Result<Self, Error> {
+        let output_desc = unsafe { output.GetDesc()? };
+
+        if !output_desc.AttachedToDesktop.as_bool() || output_desc.Monitor.is_invalid() {
+            return Err(Error::NotFound);
+        }
+
+        Ok(Self {
+            monitor: output_desc.Monitor,
+        })
+    }
+
     /// Creates a `Monitor` instance from a raw HMONITOR.
     ///
     /// # Arguments
@@ -438,6 +828,30 @@ impl Monitor {
         self.monitor.0
     }
 
+    // Gets The Monitor Info Of The Monitor
+    fn monitor_info(&self) -> Result<MONITORINFOEXW, Error> {
+        let mut monitor_info = MONITORINFOEXW {
+            monitorInfo: MONITORINFO {
+                cbSize: u32::try_from(mem::size_of::<MONITORINFOEXW>()).unwrap(),
+                rcMonitor: RECT::default(),
+                rcWork: RECT::default(),
+                dwFlags: 0,
+            },
+            szDevice: [0; 32],
+        };
+        if unsafe {
+            !GetMonitorInfoW(
+                HMONITOR(self.as_raw_hmonitor()),
+                std::ptr::addr_of_mut!(monitor_info).cast(),
+            )
+            .as_bool()
+        } {
+            return Err(Error::FailedToGetMonitorInfo);
+        }
+
+        Ok(monitor_info)
+    }
+
     // Callback Used For Enumerating All Monitors
     unsafe extern "system" fn enum_monitors_callback(
         monitor: HMONITOR,
@@ -453,6 +867,16 @@ impl Monitor {
     }
 }
 
+// Converts a null terminated wide string buffer to a String
+fn wide_to_string(buffer: &[u16]) -> Result<String, Error> {
+    let length = buffer
+        .iter()
+        .position(|ch| *ch == 0x0000)
+        .unwrap_or(buffer.len());
+
+    Ok(String::from_utf16(&buffer[..length])?)
+}
+
 // Implements TryFrom For Monitor To Convert It To GraphicsCaptureItem
 impl TryFrom<Monitor> for GraphicsCaptureItem {
     type Error = Error;
@@ -461,6 +885,15 @@ impl TryFrom<Monitor> for GraphicsCaptureItem {
         let monitor = HMONITOR(value.as_raw_hmonitor());
 
         let interop = windows::core::factory::<Self, IGraphicsCaptureItemInterop>()?;
-        Ok(unsafe { interop.CreateForMonitor(monitor)? })
+        let item: Self = unsafe { interop.CreateForMonitor(monitor)? };
+
+        // Remember the monitor, identical monitors can't be told apart by the item
+        if let Ok(weak) = item.downgrade() {
+            let mut item_monitors = CAPTURE_ITEM_MONITORS.lock();
+            item_monitors.retain(|(weak, _)| weak.upgrade().is_some());
+            item_monitors.push((weak, value));
+        }
+
+        Ok(item)
     }
 }
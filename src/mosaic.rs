@@ -0,0 +1,318 @@
+use std::{convert::Infallible, mem, time::Instant};
+
+use windows::Foundation::TimeSpan;
+
+use crate::{
+    capture::{
+        CaptureControl, CaptureControlError, GraphicsCaptureApiError, GraphicsCaptureApiHandler,
+    },
+    encoder::{VideoEncoder, VideoEncoderError},
+    frame::{Frame, FrameSnapshot},
+    graphics_capture_api::InternalCaptureControl,
+    settings::{ColorFormat, CursorCaptureSettings, DrawBorderSettings, Settings},
+    window::Window,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("A mosaic needs at least one window")]
+    NoWindows,
+    #[error("The number of columns and the tile size must not be zero")]
+    InvalidSize,
+    #[error("Failed to start a tile capture: {0}")]
+    CaptureError(#[from] GraphicsCaptureApiError<Infallible>),
+    #[error("Failed to stop a tile capture: {0}")]
+    CaptureControlError(#[from] CaptureControlError<Infallible>),
+    #[error("Failed to encode the mosaic: {0}")]
+    VideoEncoderError(#[from] VideoEncoderError),
+}
+
+/// Captures several windows at once and tiles them into a grid, for example four windows in a
+/// 2x2 grid recorded into one video.
+///
+/// Every window is captured on its own thread, composing uses the latest frame of each window
+/// scaled to fit its tile. Tiles of windows without a frame yet stay black.
+///
+/// # Example
+/// ```no_run
+/// use std::{thread, time::Duration};
+///
+/// use windows_capture::{
+///     encoder::{VideoEncoder, VideoSettingsBuilder},
+///     mosaic::Mosaic,
+///     window::Window,
+/// };
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let windows = Window::enumerate()?.into_iter().take(4).collect::<Vec<_>>();
+///     let mosaic = Mosaic::new(&windows, 2, 960, 540)?;
+///
+///     let mut encoder = VideoEncoder::new_with_settings(
+///         VideoSettingsBuilder::new(mosaic.width(), mosaic.height()).fps(30),
+///         "mosaic.mp4",
+///     )?;
+///
+///     for _ in 0..300 {
+///         mosaic.encode_frame(&mut encoder)?;
+///         thread::sleep(Duration::from_millis(33));
+///     }
+///
+///     encoder.finish()?;
+///     mosaic.stop()?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Mosaic {
+    tiles: Vec<CaptureControl<MosaicTileHandler, Infallible>>,
+    columns: u32,
+    rows: u32,
+    tile_width: u32,
+    tile_height: u32,
+    start: Instant,
+}
+
+impl Mosaic {
+    /// Starts capturing the windows of the mosaic.
+    ///
+    /// # Arguments
+    ///
+    /// * `windows` - The windows to tile, from left to right and top to bottom.
+    /// * `columns` - The number of tiles in a row.
+    /// * `tile_width` - The width of a tile.
+    /// * `tile_height` - The height of a tile.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(Mosaic)` if every capture started, otherwise returns an `Error`.
+    pub fn new(
+        windows: &[Window],
+        columns: u32,
+        tile_width: u32,
+        tile_height: u32,
+    ) -> Result<Self, Error> {
+        if windows.is_empty() {
+            return Err(Error::NoWindows);
+        }
+
+        if columns == 0 || tile_width == 0 || tile_height == 0 {
+            return Err(Error::InvalidSize);
+        }
+
+        let count = u32::try_from(windows.len()).unwrap();
+        let rows = count.div_ceil(columns);
+
+        let mut mosaic = Self {
+            tiles: Vec::with_capacity(windows.len()),
+            columns,
+            rows,
+            tile_width,
+            tile_height,
+            start: Instant::now(),
+        };
+
+        // Already started tiles are stopped when the mosaic is dropped
+        for window in windows {
+            let settings = Settings::new(
+                *window,
+                CursorCaptureSettings::Default,
+                DrawBorderSettings::Default,
+                ColorFormat::Bgra8,
+                (),
+            )
+            .keep_latest_frame(true);
+
+            mosaic
+                .tiles
+                .push(MosaicTileHandler::start_free_threaded(settings)?);
+        }
+
+        Ok(mosaic)
+    }
+
+    /// Get the width of the composed frames.
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.columns * self.tile_width
+    }
+
+    /// Get the height of the composed frames.
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.rows * self.tile_height
+    }
+
+    /// Compose the latest frames of the windows into one frame.
+    ///
+    /// # Returns
+    ///
+    /// A `Bgra8` snapshot of the whole grid, its time is the time since the mosaic started.
+    #[must_use]
+    pub fn compose(&self) -> FrameSnapshot {
+        FrameSnapshot::new(
+            self.compose_buffer(false),
+            self.width(),
+            self.height(),
+            ColorFormat::Bgra8,
+            self.timespan(),
+            None,
+        )
+    }
+
+    /// Compose the latest frames of the windows and send the result to an encoder, the encoder
+    /// must be created with the size of the mosaic.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder` - The encoder to send the composed frame to.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the frame was sent, otherwise returns an `Error`.
+    pub fn encode_frame(&self, encoder: &mut VideoEncoder) -> Result<(), Error> {
+        // The encoder expects the rows bottom to top
+        let buffer = self.compose_buffer(true);
+
+        encoder.send_frame_buffer(&buffer, self.timespan().Duration)?;
+
+        Ok(())
+    }
+
+    /// Stops capturing the windows.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if every capture stopped successfully, otherwise the first error.
+    pub fn stop(mut self) -> Result<(), Error> {
+        let mut result = Ok(());
+
+        for tile in mem::take(&mut self.tiles) {
+            if let Err(e) = tile.stop() {
+                if result.is_ok() {
+                    result = Err(Error::from(e));
+                }
+            }
+        }
+
+        result
+    }
+
+    // The time since the mosaic started in 100ns units.
+    fn timespan(&self) -> TimeSpan {
+        TimeSpan {
+            Duration: i64::try_from(self.start.elapsed().as_nanos() / 100).unwrap_or(i64::MAX),
+        }
+    }
+
+    // Draw every tile into a black Bgra8 buffer the size of the grid.
+    fn compose_buffer(&self, bottom_up: bool) -> Vec<u8> {
+        let width = self.width() as usize;
+        let height = self.height() as usize;
+
+        let mut buffer = vec![0u8; width * height * 4];
+        for pixel in buffer.chunks_exact_mut(4) {
+            pixel[3] = 255;
+        }
+
+        for (index, tile) in self.tiles.iter().enumerate() {
+            let Some(snapshot) = tile.latest_frame() else {
+                continue;
+            };
+
+            let index = u32::try_from(index).unwrap();
+            let tile_x = (index % self.columns) * self.tile_width;
+            let tile_y = (index / self.columns) * self.tile_height;
+
+            self.draw_tile(&mut buffer, &snapshot, tile_x, tile_y, bottom_up);
+        }
+
+        buffer
+    }
+
+    // Scale a snapshot to fit its tile while keeping the aspect ratio, centered in the tile.
+    fn draw_tile(
+        &self,
+        buffer: &mut [u8],
+        snapshot: &FrameSnapshot,
+        tile_x: u32,
+        tile_y: u32,
+        bottom_up: bool,
+    ) {
+        let source_width = u64::from(snapshot.width());
+        let source_height = u64::from(snapshot.height());
+        if source_width == 0 || source_height == 0 {
+            return;
+        }
+
+        let tile_width = u64::from(self.tile_width);
+        let tile_height = u64::from(self.tile_height);
+
+        let (scaled_width, scaled_height) =
+            if source_width * tile_height <= tile_width * source_height {
+                (
+                    (source_width * tile_height / source_height).max(1),
+                    tile_height,
+                )
+            } else {
+                (
+                    tile_width,
+                    (source_height * tile_width / source_width).max(1),
+                )
+            };
+
+        let offset_x = u64::from(tile_x) + (tile_width - scaled_width) / 2;
+        let offset_y = u64::from(tile_y) + (tile_height - scaled_height) / 2;
+
+        let width = u64::from(self.width());
+        let height = u64::from(self.height());
+        let source = snapshot.as_raw_buffer();
+
+        for y in 0..scaled_height {
+            let source_y = y * source_height / scaled_height;
+
+            let mut target_y = offset_y + y;
+            if bottom_up {
+                target_y = height - 1 - target_y;
+            }
+
+            for x in 0..scaled_width {
+                let source_x = x * source_width / scaled_width;
+
+                let source_index = ((source_y * source_width + source_x) * 4) as usize;
+                let target_index = ((target_y * width + offset_x + x) * 4) as usize;
+
+                buffer[target_index..target_index + 4]
+                    .copy_from_slice(&source[source_index..source_index + 4]);
+            }
+        }
+    }
+}
+
+impl Drop for Mosaic {
+    fn drop(&mut self) {
+        for tile in mem::take(&mut self.tiles) {
+            let _ = tile.stop();
+        }
+    }
+}
+
+/// The handler of the window captures of a `Mosaic`, the frames are read with
+/// `CaptureControl::latest_frame` when composing.
+pub struct MosaicTileHandler;
+
+impl GraphicsCaptureApiHandler for MosaicTileHandler {
+    type Flags = ();
+    type Error = Infallible;
+
+    fn new(_: Self::Flags) -> Result<Self, Self::Error> {
+        Ok(Self)
+    }
+
+    fn on_frame_arrived(
+        &mut self,
+        _frame: &mut Frame,
+        _capture_control: InternalCaptureControl,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
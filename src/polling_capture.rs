@@ -0,0 +1,199 @@
+use std::{
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use windows::{
+    core::Interface,
+    Foundation::TimeSpan,
+    Graphics::DirectX::Direct3D11::IDirect3DSurface,
+    Win32::{
+        Graphics::{
+            Direct3D::D3D_DRIVER_TYPE_WARP,
+            Direct3D11::{
+                ID3D11Device, ID3D11Texture2D, D3D11_BIND_SHADER_RESOURCE, D3D11_SUBRESOURCE_DATA,
+                D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT,
+            },
+            Dxgi::{Common::DXGI_SAMPLE_DESC, IDXGISurface},
+        },
+        System::WinRT::Direct3D11::CreateDirect3D11SurfaceFromDXGISurface,
+    },
+};
+
+use crate::{
+    capture::{self, GraphicsCaptureApiError, GraphicsCaptureApiHandler},
+    d3d11::{create_d3d_device, create_d3d_device_with_driver_type},
+    frame::Frame,
+    graphics_capture_api::{self, InternalCaptureControl},
+    settings::ColorFormat,
+};
+
+/// What a polled source has for one tick of a `run` loop.
+pub enum Poll {
+    /// The pixels of a frame without padding, in the color format given to `run`.
+    Frame {
+        pixels: Vec<u8>,
+        width: u32,
+        height: u32,
+        time: TimeSpan,
+    },
+    /// Nothing to deliver on this tick, the source is polled again on the next one.
+    Skip,
+    /// The source is gone, `on_closed` is called and the loop ends.
+    Closed,
+}
+
+/// Deliver the frames of a source that is polled at a fixed rate to a handler and take control
+/// of the current thread.
+///
+/// # Arguments
+///
+/// * `flags` - The flags used to create the handler.
+/// * `fps` - The rate the source is polled at, `0` polls it as fast as the handler accepts frames.
+/// * `color_format` - The color format of the pixels the source returns.
+/// * `poll` - Called with the index of the tick to get what the source has.
+///
+/// # Returns
+///
+/// Returns `Ok(())` when the handler stops the capture or the source is closed, otherwise
+/// returns an error of type `GraphicsCaptureApiError`.
+pub fn run<H: GraphicsCaptureApiHandler>(
+    flags: H::Flags,
+    fps: u32,
+    color_format: ColorFormat,
+    mut poll: impl FnMut(u64) -> windows::core::Result<Poll>,
+) -> Result<(), GraphicsCaptureApiError<H::Error>> {
+    let api_error = |e: windows::core::Error| {
+        GraphicsCaptureApiError::GraphicsCaptureApiError(graphics_capture_api::Error::from(e))
+    };
+
+    // A software device makes the source usable without a GPU
+    let (d3d_device, context) = create_d3d_device()
+        .or_else(|_| create_d3d_device_with_driver_type(D3D_DRIVER_TYPE_WARP))
+        .map_err(|e| {
+            GraphicsCaptureApiError::GraphicsCaptureApiError(graphics_capture_api::Error::from(e))
+        })?;
+
+    let mut callback = H::new(flags).map_err(GraphicsCaptureApiError::NewHandlerError)?;
+
+    let capture_id = capture::next_capture_id();
+
+    let mut buffer = Vec::new();
+    let interval = (fps != 0).then(|| Duration::from_secs(1) / fps);
+    let start = Instant::now();
+
+    let mut index = 0u64;
+    let mut previous_time = None;
+    loop {
+        let skipped = match poll(index).map_err(api_error)? {
+            Poll::Frame {
+                pixels,
+                width,
+                height,
+                time,
+            } => {
+                let (frame_surface, frame_texture) =
+                    create_texture(&d3d_device, &pixels, width, height, color_format)
+                        .map_err(api_error)?;
+
+                let mut frame = Frame::new(
+                    &d3d_device,
+                    frame_surface,
+                    frame_texture,
+                    time,
+                    &context,
+                    &mut buffer,
+                    width,
+                    height,
+                    (width, height),
+                    color_format,
+                    None,
+                )
+                .with_previous_timespan(previous_time);
+                previous_time = Some(time);
+
+                let stop = Arc::new(AtomicBool::new(false));
+                let internal_capture_control =
+                    InternalCaptureControl::new(stop.clone(), capture_id);
+
+                callback
+                    .on_frame_arrived(&mut frame, internal_capture_control)
+                    .map_err(GraphicsCaptureApiError::FrameHandlerError)?;
+
+                if stop.load(atomic::Ordering::Relaxed) {
+                    break;
+                }
+
+                false
+            }
+            Poll::Skip => true,
+            Poll::Closed => {
+                callback
+                    .on_closed()
+                    .map_err(GraphicsCaptureApiError::FrameHandlerError)?;
+                break;
+            }
+        };
+
+        index += 1;
+
+        match interval {
+            Some(interval) => {
+                let next = start + interval * u32::try_from(index).unwrap_or(u32::MAX);
+                thread::sleep(next.saturating_duration_since(Instant::now()));
+            }
+            // Don't spin while the source has nothing to deliver
+            None if skipped => thread::sleep(Duration::from_millis(10)),
+            None => (),
+        }
+    }
+
+    Ok(())
+}
+
+// Upload pixels without padding to a texture like the ones the frame pool delivers.
+fn create_texture(
+    d3d_device: &ID3D11Device,
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    color_format: ColorFormat,
+) -> windows::core::Result<(IDirect3DSurface, ID3D11Texture2D)> {
+    let texture_desc = D3D11_TEXTURE2D_DESC {
+        Width: width,
+        Height: height,
+        MipLevels: 1,
+        ArraySize: 1,
+        Format: color_format.dxgi_format(),
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: 1,
+            Quality: 0,
+        },
+        Usage: D3D11_USAGE_DEFAULT,
+        BindFlags: D3D11_BIND_SHADER_RESOURCE.0 as u32,
+        CPUAccessFlags: 0,
+        MiscFlags: 0,
+    };
+
+    let initial_data = D3D11_SUBRESOURCE_DATA {
+        pSysMem: pixels.as_ptr().cast(),
+        SysMemPitch: width * u32::try_from(color_format.bytes_per_pixel()).unwrap(),
+        SysMemSlicePitch: 0,
+    };
+
+    let mut texture = None;
+    unsafe {
+        d3d_device.CreateTexture2D(&texture_desc, Some(&initial_data), Some(&mut texture))?;
+    };
+    let texture = texture.unwrap();
+
+    let dxgi_surface: IDXGISurface = texture.cast()?;
+    let surface: IDirect3DSurface =
+        unsafe { CreateDirect3D11SurfaceFromDXGISurface(&dxgi_surface)? }.cast()?;
+
+    Ok((surface, texture))
+}
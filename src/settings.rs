@@ -1,12 +1,65 @@
-use windows::Graphics::Capture::GraphicsCaptureItem;
+use std::time::Duration;
 
+use windows::{Graphics::Capture::GraphicsCaptureItem, Win32::Graphics::Dxgi::Common::DXGI_FORMAT};
+
+use crate::{capture::CancellationToken, frame::Rect, window::Window};
+
+/// The pixel layout of frames, `channel_order` describes the exact byte order.
 #[derive(Eq, PartialEq, Clone, Copy, Debug)]
 pub enum ColorFormat {
+    /// Eight bytes per pixel, four little endian half floats in the order red, green, blue,
+    /// alpha. The colors are linear scRGB, 1.0 is SDR white.
     Rgba16F = 10,
+    /// Four bytes per pixel in the order red, green, blue, alpha. Read as a little endian `u32`
+    /// the pixel is `0xAABBGGRR`, the layout most image libraries call RGBA.
     Rgba8 = 28,
+    /// Four bytes per pixel in the order blue, green, red, alpha. Read as a little endian `u32`
+    /// the pixel is `0xAARRGGBB`, the layout GDI and Direct2D call ARGB.
     Bgra8 = 87,
 }
 
+/// A color channel of a pixel.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+impl ColorFormat {
+    /// Get the order of the channels in memory, the first channel is at the lowest address.
+    ///
+    /// # Returns
+    ///
+    /// The channels of a pixel from the first to the last byte, every channel takes
+    /// `bytes_per_pixel / 4` bytes.
+    #[must_use]
+    pub const fn channel_order(&self) -> [Channel; 4] {
+        match self {
+            Self::Rgba16F | Self::Rgba8 => {
+                [Channel::Red, Channel::Green, Channel::Blue, Channel::Alpha]
+            }
+            Self::Bgra8 => [Channel::Blue, Channel::Green, Channel::Red, Channel::Alpha],
+        }
+    }
+
+    /// Get the number of bytes of a pixel.
+    #[must_use]
+    pub const fn bytes_per_pixel(&self) -> usize {
+        match self {
+            Self::Rgba16F => 8,
+            Self::Rgba8 | Self::Bgra8 => 4,
+        }
+    }
+
+    /// Get the DXGI format of the pixels, the value of the format is the discriminant.
+    #[must_use]
+    pub const fn dxgi_format(&self) -> DXGI_FORMAT {
+        DXGI_FORMAT(*self as i32)
+    }
+}
+
 impl Default for ColorFormat {
     fn default() -> Self {
         Self::Rgba8
@@ -18,6 +71,13 @@ pub enum CursorCaptureSettings {
     Default,
     WithCursor,
     WithoutCursor,
+    /// Captures the frame without the cursor and provides the cursor separately through
+    /// `Frame::cursor_layer`, placed relative to the frame.
+    ///
+    /// The item must be a monitor or created from a `Window`, otherwise starting the capture
+    /// fails with `graphics_capture_api::Error::ItemWindowUnknown`. A failure to read the cursor
+    /// stops the capture with `graphics_capture_api::Error::CursorError`.
+    SeparateLayer,
 }
 
 #[derive(Eq, PartialEq, Clone, Debug)]
@@ -27,6 +87,217 @@ pub enum DrawBorderSettings {
     WithoutBorder,
 }
 
+/// The scheduling priority of the thread that runs the capture.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum ThreadPrioritySettings {
+    Default,
+    Idle,
+    Lowest,
+    BelowNormal,
+    Normal,
+    AboveNormal,
+    Highest,
+    TimeCritical,
+}
+
+/// The Multimedia Class Scheduler Service (MMCSS) task the capture thread registers with.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum MmcssSettings {
+    Default,
+    Audio,
+    Capture,
+    Games,
+    Playback,
+    ProAudio,
+}
+
+impl MmcssSettings {
+    /// Returns the MMCSS task name, or `None` if the thread should not be registered.
+    #[must_use]
+    pub const fn task_name(&self) -> Option<&'static str> {
+        match self {
+            Self::Default => None,
+            Self::Audio => Some("Audio"),
+            Self::Capture => Some("Capture"),
+            Self::Games => Some("Games"),
+            Self::Playback => Some("Playback"),
+            Self::ProAudio => Some("Pro Audio"),
+        }
+    }
+}
+
+/// Controls how often frames are delivered to the handler.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum FramePacingSettings {
+    /// Frames are delivered whenever the content changes.
+    Default,
+    /// Exactly one frame is delivered per display refresh, based on the frame timestamps.
+    ///
+    /// Extra frames within one refresh are skipped and refreshes without a new frame repeat the
+    /// previous frame, so the handler receives `refresh_rate` frames per second of capture time.
+    /// After a gap of more than 8 refreshes, for example when the handler stalled, the previous
+    /// frame is only repeated for the last 8 and `Frame::duration_since_previous` shows the gap.
+    /// Use `Monitor::refresh_rate` to get the refresh rate of the captured monitor.
+    Vsync { refresh_rate: u32 },
+}
+
+/// The resolution frames are delivered at, frames are scaled down on the GPU before they reach
+/// the handler.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum CaptureScaleSettings {
+    /// Frames have the size of the captured item.
+    Full,
+    /// Frames have half the width and height of the captured item.
+    Half,
+    /// Frames have a quarter of the width and height of the captured item.
+    Quarter,
+    /// Frames have an eighth of the width and height of the captured item.
+    Eighth,
+}
+
+impl CaptureScaleSettings {
+    /// Returns the mip level of the scaled frames, every level halves the width and height.
+    #[must_use]
+    pub const fn mip_level(&self) -> u32 {
+        match self {
+            Self::Full => 0,
+            Self::Half => 1,
+            Self::Quarter => 2,
+            Self::Eighth => 3,
+        }
+    }
+}
+
+/// What the capture does when the captured item closes.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum CloseBehaviorSettings {
+    /// The capture ends.
+    Stop,
+    /// The capture waits for a window with the same title to appear and continues capturing it
+    /// with the same handler, for apps that briefly recreate their main window.
+    WaitAndReconnect,
+}
+
+/// How the alpha channel of frame buffers is delivered.
+///
+/// The capture composes windows with premultiplied alpha, the color of a transparent pixel is
+/// already multiplied by its alpha for window and monitor captures alike.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum AlphaModeSettings {
+    /// The colors are premultiplied by alpha, as the capture delivers them.
+    Premultiplied,
+    /// The colors are divided by alpha, for consumers that expect straight alpha.
+    Straight,
+    /// The alpha is set to fully opaque and the colors are left premultiplied.
+    Ignore,
+}
+
+/// How `Frame::buffer` reads a frame back from the GPU.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum ReadbackModeSettings {
+    /// The frame is copied when the buffer is read and the CPU waits for the copy.
+    Immediate,
+    /// The copy is queued when the frame arrives and the buffer waits on an event query, the
+    /// copy runs while the handler does other work.
+    Deferred,
+}
+
+/// The pixels cut off the edges of the captured content on the GPU, for example to remove the
+/// overscan borders of a TV used as a monitor.
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub struct CropInsetSettings {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl CropInsetSettings {
+    /// Create a crop inset with a different size for every edge.
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - The pixels cut off the left edge.
+    /// * `top` - The pixels cut off the top edge.
+    /// * `right` - The pixels cut off the right edge.
+    /// * `bottom` - The pixels cut off the bottom edge.
+    #[must_use]
+    pub const fn new(left: u32, top: u32, right: u32, bottom: u32) -> Self {
+        Self {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    /// Create a crop inset with the same size for every edge.
+    ///
+    /// # Arguments
+    ///
+    /// * `inset` - The pixels cut off every edge.
+    #[must_use]
+    pub const fn uniform(inset: u32) -> Self {
+        Self::new(inset, inset, inset, inset)
+    }
+
+    /// Create a crop inset that cuts off both insets, the sizes of every edge are added.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The inset to add.
+    #[must_use]
+    pub const fn combined(&self, other: &Self) -> Self {
+        Self::new(
+            self.left.saturating_add(other.left),
+            self.top.saturating_add(other.top),
+            self.right.saturating_add(other.right),
+            self.bottom.saturating_add(other.bottom),
+        )
+    }
+
+    /// Returns `true` if no pixels are cut off.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.left == 0 && self.top == 0 && self.right == 0 && self.bottom == 0
+    }
+}
+
+/// A compute shader run on the GPU on every frame before it's delivered, for example for edge
+/// detection or color keying without reading the frame back to the CPU.
+///
+/// The shader reads the frame from the texture in register `t0` and writes the delivered frame to
+/// the texture in register `u0`, both have the size and format of the frame. The constants are
+/// bound as a constant buffer in register `b0`. The shader is dispatched with one thread per
+/// pixel in groups of 8x8, so it must be declared with `[numthreads(8, 8, 1)]`, starting the
+/// capture fails with `graphics_capture_api::Error::GpuShaderThreadGroupSize` otherwise. The
+/// delivered frames are written to a few textures in turn, so a frame keeps its content while the
+/// next frames are shaded.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct GpuShaderSettings {
+    /// The compiled compute shader, for example from `fxc /T cs_5_0`.
+    pub bytecode: Vec<u8>,
+    /// The data of the constant buffer, padded to a multiple of 16 bytes. Empty for no constant
+    /// buffer.
+    pub constants: Vec<u8>,
+}
+
+impl GpuShaderSettings {
+    /// Create a shader from its compiled bytecode and constants.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytecode` - The compiled compute shader.
+    /// * `constants` - The data of the constant buffer, empty for no constant buffer.
+    #[must_use]
+    pub fn new(bytecode: &[u8], constants: &[u8]) -> Self {
+        Self {
+            bytecode: bytecode.to_vec(),
+            constants: constants.to_vec(),
+        }
+    }
+}
+
 #[derive(Eq, PartialEq, Clone, Debug)]
 /// Represents the settings for screen capturing.
 pub struct Settings<Flags, T: TryInto<GraphicsCaptureItem>> {
@@ -40,6 +311,56 @@ pub struct Settings<Flags, T: TryInto<GraphicsCaptureItem>> {
     pub color_format: ColorFormat,
     /// Additional flags for capturing graphics.
     pub flags: Flags,
+    /// The scheduling priority of the capture thread.
+    pub thread_priority: ThreadPrioritySettings,
+    /// The MMCSS task the capture thread registers with.
+    pub mmcss: MmcssSettings,
+    /// Specifies whether to keep a copy of the latest frame for `CaptureControl::latest_frame`.
+    pub keep_latest_frame: bool,
+    /// Controls how often frames are delivered to the handler.
+    pub frame_pacing: FramePacingSettings,
+    /// The number of frames discarded at the start of the capture.
+    pub skip_initial_frames: u32,
+    /// Specifies whether to omit black frames at the start and end of the capture.
+    pub trim_black_frames: bool,
+    /// Specifies whether the capture starts paused until `CaptureControl::resume` is called.
+    pub start_paused: bool,
+    /// The resolution frames are delivered at.
+    pub capture_scale: CaptureScaleSettings,
+    /// What the capture does when the captured item closes.
+    pub on_close_behavior: CloseBehaviorSettings,
+    /// The number of frames queued while the handler is busy.
+    pub buffer_frames: u32,
+    /// Specifies whether frames that arrive while the handler is busy are reported as dropped.
+    pub report_dropped_frames: bool,
+    /// Specifies whether the capture switches to every new foreground window.
+    pub follow_foreground: bool,
+    /// How the alpha channel of frame buffers is delivered.
+    pub alpha_mode: AlphaModeSettings,
+    /// The pixels cut off the edges of the captured content.
+    pub crop_inset: CropInsetSettings,
+    /// Specifies whether HDR content is captured in half float and tone mapped to 8-bit.
+    pub hdr_to_sdr: bool,
+    /// Specifies whether the areas of a captured window covered by other windows are masked.
+    pub visible_region_only: bool,
+    /// Specifies whether a captured window includes its invisible borders and shadow.
+    pub include_window_shadow: bool,
+    /// The child window the frames of a window capture are cropped to.
+    pub crop_to_child: Option<Window>,
+    /// Specifies whether frames use the format the item is composed in instead of `color_format`.
+    pub native_color_format: bool,
+    /// The shortest time between two frames of the capture session.
+    pub min_update_interval: Option<Duration>,
+    /// The compute shader run on every frame.
+    pub gpu_shader: Option<GpuShaderSettings>,
+    /// The size of the region around the cursor the frames are cropped to.
+    pub follow_cursor: Option<(u32, u32)>,
+    /// How frame buffers are read back from the GPU.
+    pub readback_mode: ReadbackModeSettings,
+    /// The region watched for changes and the number of its pixels that must change.
+    pub trigger_region: Option<(Rect, u32)>,
+    /// The token that cancels starting the capture.
+    pub cancellation_token: Option<CancellationToken>,
 }
 
 impl<Flags, T: TryInto<GraphicsCaptureItem>> Settings<Flags, T> {
@@ -65,6 +386,685 @@ impl<Flags, T: TryInto<GraphicsCaptureItem>> Settings<Flags, T> {
             draw_border,
             color_format,
             flags,
+            thread_priority: ThreadPrioritySettings::Default,
+            mmcss: MmcssSettings::Default,
+            keep_latest_frame: false,
+            frame_pacing: FramePacingSettings::Default,
+            skip_initial_frames: 0,
+            trim_black_frames: false,
+            start_paused: false,
+            capture_scale: CaptureScaleSettings::Full,
+            on_close_behavior: CloseBehaviorSettings::Stop,
+            buffer_frames: 0,
+            report_dropped_frames: false,
+            follow_foreground: false,
+            alpha_mode: AlphaModeSettings::Premultiplied,
+            crop_inset: CropInsetSettings::new(0, 0, 0, 0),
+            hdr_to_sdr: false,
+            visible_region_only: false,
+            include_window_shadow: true,
+            crop_to_child: None,
+            native_color_format: false,
+            min_update_interval: None,
+            gpu_shader: None,
+            follow_cursor: None,
+            readback_mode: ReadbackModeSettings::Immediate,
+            trigger_region: None,
+            cancellation_token: None,
+        }
+    }
+
+    /// Set the scheduling priority of the capture thread.
+    ///
+    /// When the capture is started with `start` the priority is applied to the calling thread and
+    /// restored when the capture ends.
+    ///
+    /// # Arguments
+    ///
+    /// * `thread_priority` - The priority to give the capture thread.
+    #[must_use]
+    pub const fn thread_priority(mut self, thread_priority: ThreadPrioritySettings) -> Self {
+        self.thread_priority = thread_priority;
+        self
+    }
+
+    /// Register the capture thread with the Multimedia Class Scheduler Service so it gets
+    /// scheduling priority on a busy machine.
+    ///
+    /// # Arguments
+    ///
+    /// * `mmcss` - The MMCSS task to register with, for example `MmcssSettings::Capture`.
+    #[must_use]
+    pub const fn mmcss(mut self, mmcss: MmcssSettings) -> Self {
+        self.mmcss = mmcss;
+        self
+    }
+
+    /// Keep a copy of the latest delivered frame so it can be read from another thread with
+    /// `CaptureControl::latest_frame`.
+    ///
+    /// Only used by `start_free_threaded`. Every frame is copied to the CPU when enabled, so only
+    /// enable it when needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep_latest_frame` - Whether to keep a copy of the latest frame.
+    #[must_use]
+    pub const fn keep_latest_frame(mut self, keep_latest_frame: bool) -> Self {
+        self.keep_latest_frame = keep_latest_frame;
+        self
+    }
+
+    /// Control how often frames are delivered to the handler.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_pacing` - The frame pacing, for example `FramePacingSettings::Vsync` to get one
+    ///   frame per display refresh.
+    #[must_use]
+    pub const fn frame_pacing(mut self, frame_pacing: FramePacingSettings) -> Self {
+        self.frame_pacing = frame_pacing;
+        self
+    }
+
+    /// Discard the first frames of the capture before delivering any to the handler.
+    ///
+    /// The first frames after the capture starts can be black or empty while the source is
+    /// still being composed, skipping them avoids a black flash at the start of recordings.
+    ///
+    /// # Arguments
+    ///
+    /// * `skip_initial_frames` - The number of frames to discard.
+    #[must_use]
+    pub const fn skip_initial_frames(mut self, skip_initial_frames: u32) -> Self {
+        self.skip_initial_frames = skip_initial_frames;
+        self
+    }
+
+    /// Omit black frames at the start and end of the capture, for example from protected
+    /// content or transitions.
+    ///
+    /// Every frame is read back to the CPU and sampled, frames where every sampled pixel is near
+    /// black count as black. Black frames before the first non-black frame are discarded. Later
+    /// black frames are held back and only delivered once a non-black frame arrives, so black
+    /// frames at the end of the capture are never delivered. With `FramePacingSettings::Vsync`
+    /// only the black frames at the start are omitted.
+    ///
+    /// # Arguments
+    ///
+    /// * `trim_black_frames` - Whether to omit black frames at the start and end.
+    #[must_use]
+    pub const fn trim_black_frames(mut self, trim_black_frames: bool) -> Self {
+        self.trim_black_frames = trim_black_frames;
+        self
+    }
+
+    /// Start the capture paused, the session and frame pool are created but no frames are
+    /// delivered until `CaptureControl::resume` is called.
+    ///
+    /// Only used by `start_free_threaded`. The expensive setup happens ahead of time so the
+    /// first frame is delivered with almost no delay after resuming.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_paused` - Whether to start the capture paused.
+    #[must_use]
+    pub const fn start_paused(mut self, start_paused: bool) -> Self {
+        self.start_paused = start_paused;
+        self
+    }
+
+    /// Deliver frames at a reduced resolution, for example for low resolution recordings.
+    ///
+    /// The frames are scaled down on the GPU right after capture, so copying and reading back
+    /// the frames is cheaper. `Frame::guard` isn't available for scaled frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `capture_scale` - The resolution of the delivered frames, for example
+    ///   `CaptureScaleSettings::Half`.
+    #[must_use]
+    pub const fn capture_scale(mut self, capture_scale: CaptureScaleSettings) -> Self {
+        self.capture_scale = capture_scale;
+        self
+    }
+
+    /// Choose what happens when the captured item closes.
+    ///
+    /// `on_closed` is called every time the item closes. With
+    /// `CloseBehaviorSettings::WaitAndReconnect` the capture then waits until a window with the
+    /// title of the closed item exists and continues with it, until the capture is stopped.
+    ///
+    /// # Arguments
+    ///
+    /// * `on_close_behavior` - The behavior, for example `CloseBehaviorSettings::WaitAndReconnect`.
+    #[must_use]
+    pub const fn on_close_behavior(mut self, on_close_behavior: CloseBehaviorSettings) -> Self {
+        self.on_close_behavior = on_close_behavior;
+        self
+    }
+
+    /// Queue frames that arrive while the handler is busy instead of dropping them, so short
+    /// stalls like a slow disk write in the encoder are absorbed.
+    ///
+    /// By default only the latest frame is delivered. With a buffer the frames are delivered in
+    /// order and only the oldest frames beyond the buffer are dropped and reported to
+    /// `on_frame_dropped`. The frame pool holds a buffer per queued frame, and every queued frame
+    /// is delivered back to back when the handler is free again.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer_frames` - The number of frames to queue, 0 disables the queue.
+    #[must_use]
+    pub const fn buffer_frames(mut self, buffer_frames: u32) -> Self {
+        self.buffer_frames = buffer_frames;
+        self
+    }
+
+    /// Report the frames that arrive while the handler is busy to `on_frame_dropped`.
+    ///
+    /// Without a frame buffer the frame pool holds a single frame and the frames it had no room
+    /// for are skipped without a trace. Reporting them keeps an extra frame in the pool, which
+    /// costs the memory of a frame and lets a frame wait there for the handler.
+    ///
+    /// # Arguments
+    ///
+    /// * `report_dropped_frames` - Whether to report the dropped frames.
+    #[must_use]
+    pub const fn report_dropped_frames(mut self, report_dropped_frames: bool) -> Self {
+        self.report_dropped_frames = report_dropped_frames;
+        self
+    }
+
+    /// Switch the capture to every window that becomes the foreground window, frames keep
+    /// arriving at the same handler so a recording continues across window switches.
+    ///
+    /// Start with `Window::foreground` as the item. Windows that can't be captured, like the
+    /// taskbar or protected windows, are skipped and the previous window stays captured. The
+    /// frame size changes with the size of the captured window.
+    ///
+    /// # Arguments
+    ///
+    /// * `follow_foreground` - Whether to follow the foreground window.
+    #[must_use]
+    pub const fn follow_foreground(mut self, follow_foreground: bool) -> Self {
+        self.follow_foreground = follow_foreground;
+        self
+    }
+
+    /// Choose how the alpha channel of frame buffers is delivered, converting premultiplied
+    /// colors to straight alpha avoids dark edges around transparent windows.
+    ///
+    /// The conversion happens on the CPU when a frame buffer is read with `Frame::buffer` or
+    /// `Frame::buffer_crop`, textures are always premultiplied. `ColorFormat::Rgba16F` buffers
+    /// stay premultiplied with `AlphaModeSettings::Straight`.
+    ///
+    /// # Arguments
+    ///
+    /// * `alpha_mode` - The alpha mode, for example `AlphaModeSettings::Straight`.
+    #[must_use]
+    pub const fn alpha_mode(mut self, alpha_mode: AlphaModeSettings) -> Self {
+        self.alpha_mode = alpha_mode;
+        self
+    }
+
+    /// Cut pixels off the edges of the captured content, for example to remove the overscan
+    /// borders of a TV used as a monitor.
+    ///
+    /// The crop happens on the GPU before `capture_scale` is applied, frames are delivered with
+    /// the size of the remaining content. At least one pixel is always kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `crop_inset` - The pixels to cut off every edge, for example
+    ///   `CropInsetSettings::uniform(32)`.
+    #[must_use]
+    pub const fn crop_inset(mut self, crop_inset: CropInsetSettings) -> Self {
+        self.crop_inset = crop_inset;
+        self
+    }
+
+    /// Capture HDR content in `ColorFormat::Rgba16F` and tone map it to the 8-bit color format
+    /// of the settings with dithering, so gradients of HDR content don't band in SDR output.
+    ///
+    /// The tone mapping happens on the CPU when a frame buffer is read with `Frame::buffer` or
+    /// `Frame::buffer_crop`, the frame textures and surfaces stay in `ColorFormat::Rgba16F`. Send
+    /// the buffers to the encoder with `VideoEncoder::send_frame_buffer` to encode 8-bit video.
+    /// SDR content keeps the brightness it has at the SDR white level of the monitor, see
+    /// `Monitor::sdr_white_level`.
+    ///
+    /// # Arguments
+    ///
+    /// * `hdr_to_sdr` - Whether to tone map HDR content to 8-bit.
+    #[must_use]
+    pub const fn hdr_to_sdr(mut self, hdr_to_sdr: bool) -> Self {
+        self.hdr_to_sdr = hdr_to_sdr;
+        self
+    }
+
+    /// Mask the areas of a captured window that are covered by other windows with black, so
+    /// overlapping windows are never recorded.
+    ///
+    /// The covered areas are computed from the frame bounds of the windows above the captured
+    /// window for every frame, see `Window::occluded_rects`. Monitor captures are not masked,
+    /// other items must be created from a `Window` or starting the capture fails with
+    /// `graphics_capture_api::Error::ItemWindowUnknown`. A frame that can't be masked stops the
+    /// capture with `graphics_capture_api::Error::VisibleRegionError`.
+    ///
+    /// # Arguments
+    ///
+    /// * `visible_region_only` - Whether to mask the covered areas.
+    #[must_use]
+    pub const fn visible_region_only(mut self, visible_region_only: bool) -> Self {
+        self.visible_region_only = visible_region_only;
+        self
+    }
+
+    /// Choose whether a captured window includes the invisible resize borders and the shadow
+    /// around it, the default. Without them the frames are cropped to the frame bounds of the
+    /// window from `DwmGetWindowAttribute(DWMWA_EXTENDED_FRAME_BOUNDS)`, the window as it's seen
+    /// on screen.
+    ///
+    /// The bounds are read for every frame and the `crop_inset` is cut off inside of them. The
+    /// item must be created from a `Window` like with `visible_region_only`, monitor captures
+    /// are not affected.
+    ///
+    /// # Arguments
+    ///
+    /// * `include_window_shadow` - Whether to keep the borders and the shadow.
+    #[must_use]
+    pub const fn include_window_shadow(mut self, include_window_shadow: bool) -> Self {
+        self.include_window_shadow = include_window_shadow;
+        self
+    }
+
+    /// Crop the frames to a child window of the captured window, for example an embedded browser
+    /// or the view of a game. Child windows can't be captured on their own, so the top-level
+    /// window from `Window::root` is captured and cut down to the child on the GPU.
+    ///
+    /// The rect of the child is read for every frame so the crop follows it when it moves or is
+    /// resized, the `crop_inset` is cut off inside of it. `include_window_shadow` has no effect.
+    ///
+    /// # Arguments
+    ///
+    /// * `child` - The child window to keep.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use windows_capture::{
+    ///     settings::{ColorFormat, CursorCaptureSettings, DrawBorderSettings, Settings},
+    ///     window::Window,
+    /// };
+    ///
+    /// let child = Window::from_raw_hwnd(std::ptr::null_mut());
+    ///
+    /// let settings = Settings::new(
+    ///     child.root(),
+    ///     CursorCaptureSettings::Default,
+    ///     DrawBorderSettings::Default,
+    ///     ColorFormat::Rgba8,
+    ///     (),
+    /// )
+    /// .crop_to_child(child);
+    /// ```
+    #[must_use]
+    pub const fn crop_to_child(mut self, child: Window) -> Self {
+        self.crop_to_child = Some(child);
+        self
+    }
+
+    /// Capture in the format the desktop compositor composes the item in, so the pixels are
+    /// delivered without a format or gamma conversion. `color_format` and `hdr_to_sdr` are
+    /// ignored.
+    ///
+    /// The capture API always delivers one of two formats and converts everything else, the
+    /// native format is `ColorFormat::Rgba16F` with linear scRGB values when the monitor of the
+    /// item is in HDR mode and `ColorFormat::Bgra8` with sRGB values otherwise. The format is
+    /// chosen when the capture starts, read it from `Frame::color_format`.
+    ///
+    /// # Arguments
+    ///
+    /// * `native_color_format` - Whether to capture in the native format of the item.
+    #[must_use]
+    pub const fn native_color_format(mut self, native_color_format: bool) -> Self {
+        self.native_color_format = native_color_format;
+        self
+    }
+
+    /// Limit how often the capture session produces frames, for example to capture at 1 fps
+    /// without the GPU copying every frame the content changes.
+    ///
+    /// On Windows 11 the interval is set on the session so the system doesn't produce the extra
+    /// frames at all. On Windows 10 the frames that arrive sooner than the interval after the
+    /// previous frame are dropped before they reach the handler, so a change right before the
+    /// content stops changing may not be delivered. Use
+    /// `GraphicsCaptureApi::is_min_update_interval_supported` to check which applies.
+    ///
+    /// # Arguments
+    ///
+    /// * `min_update_interval` - The shortest time between two frames.
+    #[must_use]
+    pub const fn min_update_interval(mut self, min_update_interval: Duration) -> Self {
+        self.min_update_interval = Some(min_update_interval);
+        self
+    }
+
+    /// Run a compute shader on the texture of every frame before it's delivered, the handler gets
+    /// the output of the shader so only the processed result has to be read back or encoded.
+    ///
+    /// The shader runs after the frame is cropped and scaled. The format of the frames must
+    /// support typed unordered access views, `ColorFormat::Rgba8` and `ColorFormat::Rgba16F`
+    /// always do, `ColorFormat::Bgra8` depends on the GPU. Starting the capture fails if it
+    /// doesn't or if the bytecode is invalid, see `GpuShaderSettings` for the bindings.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytecode` - The compiled compute shader.
+    /// * `constants` - The data of the constant buffer bound to `b0`, empty for none.
+    #[must_use]
+    pub fn gpu_shader(mut self, bytecode: &[u8], constants: &[u8]) -> Self {
+        self.gpu_shader = Some(GpuShaderSettings::new(bytecode, constants));
+        self
+    }
+
+    /// Crop the frames to a region of a fixed size centered on the cursor, the region moves with
+    /// the cursor from frame to frame, for example for a magnifier.
+    ///
+    /// The region is kept inside the captured content, near the edges the cursor is off center.
+    /// The cursor is read when a frame arrives, so the region only moves when the content
+    /// changes, draw the cursor with `CursorCaptureSettings::WithCursor` to get a frame for every
+    /// move. `crop_inset` is applied first and the region is placed inside the content that's
+    /// left, `crop_to_child` and `include_window_shadow` are ignored.
+    ///
+    /// The process must be per monitor DPI aware, otherwise the cursor position is scaled but
+    /// the frames are not.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the region.
+    /// * `height` - The height of the region.
+    #[must_use]
+    pub const fn follow_cursor(mut self, width: u32, height: u32) -> Self {
+        self.follow_cursor = Some((width, height));
+        self
+    }
+
+    /// Set how `Frame::buffer` reads frames back from the GPU.
+    ///
+    /// With `ReadbackModeSettings::Deferred` every frame is copied to a CPU readable texture
+    /// before it's handed to the handler, the handler should read the buffer of most frames or
+    /// the copies are wasted. Only `buffer` uses the queued copy, `buffer_crop` and the other
+    /// readbacks still copy on demand.
+    ///
+    /// # Arguments
+    ///
+    /// * `readback_mode` - The readback mode, for example `ReadbackModeSettings::Deferred`.
+    #[must_use]
+    pub const fn readback_mode(mut self, readback_mode: ReadbackModeSettings) -> Self {
+        self.readback_mode = readback_mode;
+        self
+    }
+
+    /// Watch a region of the frames and call `GraphicsCaptureApiHandler::on_triggered` when
+    /// enough of its pixels changed, for example to start recording when a status light turns
+    /// on.
+    ///
+    /// The region is compared with its content at the first frame, after a trigger the changed
+    /// content becomes the new reference so slow changes add up. The region is copied on the GPU
+    /// and read once the copy is done without waiting for it, so a change is reported on a frame
+    /// or two after the one that shows it. Each copy is hashed and only compared pixel by pixel
+    /// when the hash differs. The region is clipped to the frame and read after cropping and
+    /// scaling, a region outside of the frame never triggers.
+    ///
+    /// # Arguments
+    ///
+    /// * `rect` - The region in frame coordinates, keep it small so it's cheap to read.
+    /// * `threshold` - The number of pixels of the region that must change, at least one.
+    #[must_use]
+    pub const fn trigger_region(mut self, rect: Rect, threshold: u32) -> Self {
+        self.trigger_region = Some((rect, threshold));
+        self
+    }
+
+    /// Cancel starting the capture with a token, `start` and `start_free_threaded` return
+    /// `GraphicsCaptureApiError::Cancelled` if the token is cancelled before the capture runs.
+    ///
+    /// # Arguments
+    ///
+    /// * `cancellation_token` - The token, keep a clone to cancel from another thread.
+    #[must_use]
+    pub fn cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.cancellation_token = Some(cancellation_token);
+        self
+    }
+
+    /// Get a copy of the settings without the item, the flags and the cancellation token.
+    ///
+    /// # Returns
+    ///
+    /// The snapshot of the settings, for example to store them in a recording profile.
+    #[must_use]
+    pub fn snapshot(&self) -> SettingsSnapshot {
+        // Destructured so a new setting can't be left out without a compile error
+        let Self {
+            item: _,
+            flags: _,
+            cancellation_token: _,
+            cursor_capture,
+            draw_border,
+            color_format,
+            thread_priority,
+            mmcss,
+            keep_latest_frame,
+            frame_pacing,
+            skip_initial_frames,
+            trim_black_frames,
+            start_paused,
+            capture_scale,
+            on_close_behavior,
+            buffer_frames,
+            report_dropped_frames,
+            follow_foreground,
+            alpha_mode,
+            crop_inset,
+            hdr_to_sdr,
+            visible_region_only,
+            include_window_shadow,
+            crop_to_child,
+            native_color_format,
+            min_update_interval,
+            gpu_shader,
+            follow_cursor,
+            readback_mode,
+            trigger_region,
+        } = self;
+
+        SettingsSnapshot {
+            cursor_capture: cursor_capture.clone(),
+            draw_border: draw_border.clone(),
+            color_format: *color_format,
+            thread_priority: *thread_priority,
+            mmcss: *mmcss,
+            keep_latest_frame: *keep_latest_frame,
+            frame_pacing: *frame_pacing,
+            skip_initial_frames: *skip_initial_frames,
+            trim_black_frames: *trim_black_frames,
+            start_paused: *start_paused,
+            capture_scale: *capture_scale,
+            on_close_behavior: *on_close_behavior,
+            buffer_frames: *buffer_frames,
+            report_dropped_frames: *report_dropped_frames,
+            follow_foreground: *follow_foreground,
+            alpha_mode: *alpha_mode,
+            crop_inset: *crop_inset,
+            hdr_to_sdr: *hdr_to_sdr,
+            visible_region_only: *visible_region_only,
+            include_window_shadow: *include_window_shadow,
+            crop_to_child: *crop_to_child,
+            native_color_format: *native_color_format,
+            min_update_interval: *min_update_interval,
+            gpu_shader: gpu_shader.clone(),
+            follow_cursor: *follow_cursor,
+            readback_mode: *readback_mode,
+            trigger_region: *trigger_region,
+        }
+    }
+
+    /// Replace the flags that are passed to the handler.
+    ///
+    /// # Arguments
+    ///
+    /// * `flags` - The new flags.
+    #[must_use]
+    pub fn with_flags<NewFlags>(self, flags: NewFlags) -> Settings<NewFlags, T> {
+        self.map_parts(|item, _| (item, flags))
+    }
+
+    /// Replace the item that is captured, the other settings are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The new item.
+    #[must_use]
+    pub fn with_item<NewT: TryInto<GraphicsCaptureItem>>(
+        self,
+        item: NewT,
+    ) -> Settings<Flags, NewT> {
+        self.map_parts(|_, flags| (item, flags))
+    }
+
+    // Replace the item and the flags, every other setting is moved over as it is.
+    fn map_parts<NewFlags, NewT: TryInto<GraphicsCaptureItem>>(
+        self,
+        map: impl FnOnce(T, Flags) -> (NewT, NewFlags),
+    ) -> Settings<NewFlags, NewT> {
+        let Self {
+            item,
+            flags,
+            cancellation_token,
+            cursor_capture,
+            draw_border,
+            color_format,
+            thread_priority,
+            mmcss,
+            keep_latest_frame,
+            frame_pacing,
+            skip_initial_frames,
+            trim_black_frames,
+            start_paused,
+            capture_scale,
+            on_close_behavior,
+            buffer_frames,
+            report_dropped_frames,
+            follow_foreground,
+            alpha_mode,
+            crop_inset,
+            hdr_to_sdr,
+            visible_region_only,
+            include_window_shadow,
+            crop_to_child,
+            native_color_format,
+            min_update_interval,
+            gpu_shader,
+            follow_cursor,
+            readback_mode,
+            trigger_region,
+        } = self;
+        let (item, flags) = map(item, flags);
+
+        Settings {
+            item,
+            flags,
+            cancellation_token,
+            cursor_capture,
+            draw_border,
+            color_format,
+            thread_priority,
+            mmcss,
+            keep_latest_frame,
+            frame_pacing,
+            skip_initial_frames,
+            trim_black_frames,
+            start_paused,
+            capture_scale,
+            on_close_behavior,
+            buffer_frames,
+            report_dropped_frames,
+            follow_foreground,
+            alpha_mode,
+            crop_inset,
+            hdr_to_sdr,
+            visible_region_only,
+            include_window_shadow,
+            crop_to_child,
+            native_color_format,
+            min_update_interval,
+            gpu_shader,
+            follow_cursor,
+            readback_mode,
+            trigger_region,
         }
     }
 }
+
+/// The settings a capture was started with, without the item, the flags and the cancellation
+/// token, see `CaptureControl::settings`.
+///
+/// The encoder settings are not part of the capture, they are kept by the `VideoEncoder`.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct SettingsSnapshot {
+    /// Specifies whether to capture the cursor.
+    pub cursor_capture: CursorCaptureSettings,
+    /// Specifies whether to draw a border around the captured region.
+    pub draw_border: DrawBorderSettings,
+    /// The color format for the captured graphics.
+    pub color_format: ColorFormat,
+    /// The scheduling priority of the capture thread.
+    pub thread_priority: ThreadPrioritySettings,
+    /// The MMCSS task the capture thread registers with.
+    pub mmcss: MmcssSettings,
+    /// Specifies whether to keep a copy of the latest frame for `CaptureControl::latest_frame`.
+    pub keep_latest_frame: bool,
+    /// Controls how often frames are delivered to the handler.
+    pub frame_pacing: FramePacingSettings,
+    /// The number of frames discarded at the start of the capture.
+    pub skip_initial_frames: u32,
+    /// Specifies whether to omit black frames at the start and end of the capture.
+    pub trim_black_frames: bool,
+    /// Specifies whether the capture starts paused until `CaptureControl::resume` is called.
+    pub start_paused: bool,
+    /// The resolution frames are delivered at.
+    pub capture_scale: CaptureScaleSettings,
+    /// What the capture does when the captured item closes.
+    pub on_close_behavior: CloseBehaviorSettings,
+    /// The number of frames queued while the handler is busy.
+    pub buffer_frames: u32,
+    /// Specifies whether frames that arrive while the handler is busy are reported as dropped.
+    pub report_dropped_frames: bool,
+    /// Specifies whether the capture switches to every new foreground window.
+    pub follow_foreground: bool,
+    /// How the alpha channel of frame buffers is delivered.
+    pub alpha_mode: AlphaModeSettings,
+    /// The pixels cut off the edges of the captured content.
+    pub crop_inset: CropInsetSettings,
+    /// Specifies whether HDR content is captured in half float and tone mapped to 8-bit.
+    pub hdr_to_sdr: bool,
+    /// Specifies whether the areas of a captured window covered by other windows are masked.
+    pub visible_region_only: bool,
+    /// Specifies whether a captured window includes its invisible borders and shadow.
+    pub include_window_shadow: bool,
+    /// The child window the frames of a window capture are cropped to.
+    pub crop_to_child: Option<Window>,
+    /// Specifies whether frames use the format the item is composed in instead of `color_format`.
+    pub native_color_format: bool,
+    /// The shortest time between two frames of the capture session.
+    pub min_update_interval: Option<Duration>,
+    /// The compute shader run on every frame.
+    pub gpu_shader: Option<GpuShaderSettings>,
+    /// The size of the region around the cursor the frames are cropped to.
+    pub follow_cursor: Option<(u32, u32)>,
+    /// How frame buffers are read back from the GPU.
+    pub readback_mode: ReadbackModeSettings,
+    /// The region watched for changes and the number of its pixels that must change.
+    pub trigger_region: Option<(Rect, u32)>,
+}
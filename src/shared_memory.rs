@@ -0,0 +1,309 @@
+use std::{
+    mem, ptr,
+    sync::atomic::{self, AtomicU64},
+};
+
+use windows::{
+    core::HSTRING,
+    Win32::{
+        Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE},
+        System::Memory::{
+            CreateFileMappingW, MapViewOfFile, UnmapViewOfFile, FILE_MAP_ALL_ACCESS,
+            MEMORY_MAPPED_VIEW_ADDRESS, PAGE_READWRITE,
+        },
+    },
+};
+
+use crate::{
+    capture::GraphicsCaptureApiHandler,
+    frame::{self, Frame},
+    graphics_capture_api::InternalCaptureControl,
+    settings::ColorFormat,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("The frame is larger than the buffers of the shared memory")]
+    FrameTooLarge,
+    #[error("The size of the shared memory must not be zero")]
+    InvalidSize,
+    #[error("Failed to read the frame: {0}")]
+    FrameError(#[from] frame::Error),
+    #[error("Windows API error: {0}")]
+    WindowsError(#[from] windows::core::Error),
+}
+
+/// The value of `SharedMemoryHeader::magic`, `WCSM` in little endian.
+pub const SHARED_MEMORY_MAGIC: u32 = u32::from_le_bytes(*b"WCSM");
+
+/// The offset of the first frame buffer, the header is padded to this size.
+pub const SHARED_MEMORY_HEADER_SIZE: usize = 64;
+
+/// The header at the start of the shared memory of a `SharedMemorySink`.
+///
+/// The two frame buffers follow the header at `SHARED_MEMORY_HEADER_SIZE` and
+/// `SHARED_MEMORY_HEADER_SIZE + buffer_size`. `sequence` is a seqlock: it's odd while a frame is
+/// being written. To read the latest frame, load `sequence` with acquire ordering and start over
+/// if it's odd, then copy the header and the buffer at `buffer_index`, issue an acquire fence
+/// and load `sequence` again. The copy is complete if `sequence` didn't change, otherwise read it
+/// again.
+#[repr(C)]
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub struct SharedMemoryHeader {
+    /// Always `SHARED_MEMORY_MAGIC`.
+    pub magic: u32,
+    /// The offset of the first frame buffer, `SHARED_MEMORY_HEADER_SIZE`.
+    pub header_size: u32,
+    /// Twice the number of frames written while no frame is being written, odd while the header
+    /// or a buffer is being written.
+    pub sequence: u64,
+    /// The buffer that holds the latest frame, 0 or 1.
+    pub buffer_index: u32,
+    /// The width of the latest frame.
+    pub width: u32,
+    /// The height of the latest frame.
+    pub height: u32,
+    /// The `ColorFormat` of the latest frame as its DXGI format value.
+    pub color_format: u32,
+    /// The time of the latest frame in 100ns units.
+    pub timespan: i64,
+    /// The size of each of the two frame buffers in bytes.
+    pub buffer_size: u64,
+}
+
+/// Writes the latest frame into a named shared memory region, so other processes can read the
+/// frames with low latency without a callback.
+///
+/// The region holds a `SharedMemoryHeader` followed by two frame buffers. A frame is written to
+/// the buffer that doesn't hold the latest frame while the sequence is odd and published by
+/// making it even again, so readers that check the sequence never use a frame that is only
+/// partially written. The frames are stored without padding, top to bottom.
+///
+/// # Example
+/// ```ignore
+/// let sink = SharedMemorySink::new("Local\\MyCapture", 3840, 2160)?;
+///
+/// // The frames are written to the shared memory instead of a handler
+/// let capture_control = SharedMemoryHandler::start_free_threaded(settings.with_flags(sink))?;
+/// ```
+pub struct SharedMemorySink {
+    handle: HANDLE,
+    view: MEMORY_MAPPED_VIEW_ADDRESS,
+    buffer_size: usize,
+    sequence: u64,
+    buffer_index: u32,
+}
+
+impl SharedMemorySink {
+    /// Creates or opens the named shared memory region with room for two frames of the
+    /// maximum size in any color format.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the file mapping, for example `Local\MyCapture`.
+    /// * `max_width` - The width of the largest frame that will be written.
+    /// * `max_height` - The height of the largest frame that will be written.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(SharedMemorySink)` if the shared memory was mapped, otherwise returns an
+    /// `Error`.
+    pub fn new(name: &str, max_width: u32, max_height: u32) -> Result<Self, Error> {
+        // Room for the largest color format, `Rgba16F`
+        let buffer_size = max_width as usize * max_height as usize * 8;
+        if buffer_size == 0 {
+            return Err(Error::InvalidSize);
+        }
+
+        let size = (SHARED_MEMORY_HEADER_SIZE + buffer_size * 2) as u64;
+
+        let handle = unsafe {
+            CreateFileMappingW(
+                INVALID_HANDLE_VALUE,
+                None,
+                PAGE_READWRITE,
+                (size >> 32) as u32,
+                size as u32,
+                &HSTRING::from(name),
+            )?
+        };
+
+        let view = unsafe { MapViewOfFile(handle, FILE_MAP_ALL_ACCESS, 0, 0, size as usize) };
+        if view.Value.is_null() {
+            let error = windows::core::Error::from_win32();
+            unsafe { CloseHandle(handle)? };
+            return Err(Error::WindowsError(error));
+        }
+
+        let sink = Self {
+            handle,
+            view,
+            buffer_size,
+            sequence: 0,
+            buffer_index: 1,
+        };
+
+        let header = sink.header();
+        unsafe {
+            ptr::write(
+                header,
+                SharedMemoryHeader {
+                    magic: SHARED_MEMORY_MAGIC,
+                    header_size: SHARED_MEMORY_HEADER_SIZE as u32,
+                    buffer_size: buffer_size as u64,
+                    ..SharedMemoryHeader::default()
+                },
+            );
+        };
+
+        Ok(sink)
+    }
+
+    /// Get the size of each of the two frame buffers in bytes.
+    #[must_use]
+    pub const fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Get the number of frames written.
+    #[must_use]
+    pub const fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Writes a frame to the shared memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The frame to write.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the frame was written, otherwise returns an `Error`.
+    pub fn write_frame(&mut self, frame: &mut Frame) -> Result<(), Error> {
+        let timespan = frame.timespan().Duration;
+        let mut frame_buffer = frame.buffer()?;
+
+        let width = frame_buffer.width();
+        let height = frame_buffer.height();
+        let color_format = frame_buffer.color_format();
+
+        self.write_buffer(
+            frame_buffer.as_raw_nopadding_buffer()?,
+            width,
+            height,
+            color_format,
+            timespan,
+        )
+    }
+
+    /// Writes a frame buffer without padding to the shared memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The pixels of the frame without padding, top to bottom.
+    /// * `width` - The width of the frame.
+    /// * `height` - The height of the frame.
+    /// * `color_format` - The color format of the frame.
+    /// * `timespan` - The time of the frame in 100ns units.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the frame was written, otherwise returns `Error::FrameTooLarge` if the
+    /// buffer doesn't fit.
+    pub fn write_buffer(
+        &mut self,
+        buffer: &[u8],
+        width: u32,
+        height: u32,
+        color_format: ColorFormat,
+        timespan: i64,
+    ) -> Result<(), Error> {
+        if buffer.len() > self.buffer_size {
+            return Err(Error::FrameTooLarge);
+        }
+
+        let header = self.header();
+        let sequence = unsafe { &*ptr::addr_of!((*header).sequence).cast::<AtomicU64>() };
+
+        // An odd sequence tells readers that their copy may be torn, the fence keeps the writes
+        // below from becoming visible before it
+        sequence.store(self.sequence * 2 + 1, atomic::Ordering::Relaxed);
+        atomic::fence(atomic::Ordering::Release);
+
+        // Write to the buffer readers aren't reading the latest frame from, so a reader only
+        // retries when it's still copying when the next frame is written
+        let buffer_index = 1 - self.buffer_index;
+        unsafe {
+            let target = self
+                .view
+                .Value
+                .cast::<u8>()
+                .add(SHARED_MEMORY_HEADER_SIZE + buffer_index as usize * self.buffer_size);
+            ptr::copy_nonoverlapping(buffer.as_ptr(), target, buffer.len());
+
+            ptr::addr_of_mut!((*header).buffer_index).write_volatile(buffer_index);
+            ptr::addr_of_mut!((*header).width).write_volatile(width);
+            ptr::addr_of_mut!((*header).height).write_volatile(height);
+            ptr::addr_of_mut!((*header).color_format).write_volatile(color_format as u32);
+            ptr::addr_of_mut!((*header).timespan).write_volatile(timespan);
+        };
+
+        self.buffer_index = buffer_index;
+        self.sequence += 1;
+
+        // Publishing the even sequence last makes the frame visible to readers
+        sequence.store(self.sequence * 2, atomic::Ordering::Release);
+
+        Ok(())
+    }
+
+    // Get the header at the start of the mapped view.
+    fn header(&self) -> *mut SharedMemoryHeader {
+        const _: () = assert!(mem::size_of::<SharedMemoryHeader>() <= SHARED_MEMORY_HEADER_SIZE);
+
+        self.view.Value.cast()
+    }
+}
+
+impl Drop for SharedMemorySink {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = UnmapViewOfFile(self.view);
+            let _ = CloseHandle(self.handle);
+        };
+    }
+}
+
+unsafe impl Send for SharedMemorySink {}
+
+/// A handler that writes every frame to a `SharedMemorySink`, start it with the sink as the
+/// flags of the settings.
+pub struct SharedMemoryHandler {
+    sink: SharedMemorySink,
+}
+
+impl SharedMemoryHandler {
+    /// Get the sink the frames are written to.
+    #[must_use]
+    pub const fn sink(&self) -> &SharedMemorySink {
+        &self.sink
+    }
+}
+
+impl GraphicsCaptureApiHandler for SharedMemoryHandler {
+    type Flags = SharedMemorySink;
+    type Error = Error;
+
+    fn new(sink: Self::Flags) -> Result<Self, Self::Error> {
+        Ok(Self { sink })
+    }
+
+    fn on_frame_arrived(
+        &mut self,
+        frame: &mut Frame,
+        _capture_control: InternalCaptureControl,
+    ) -> Result<(), Self::Error> {
+        self.sink.write_frame(frame)
+    }
+}
@@ -0,0 +1,204 @@
+use windows::Foundation::TimeSpan;
+
+use crate::{
+    capture::{GraphicsCaptureApiError, GraphicsCaptureApiHandler},
+    polling_capture::{self, Poll},
+    settings::ColorFormat,
+};
+
+/// A synthetic capture source that delivers deterministic generated frames to a
+/// `GraphicsCaptureApiHandler` without a real display.
+///
+/// Each frame is a gradient that moves by one pixel per frame, so the same frame index always
+/// produces the same pixels. A software (WARP) device is used when no GPU is available, which
+/// makes it usable in CI.
+///
+/// # Example
+/// ```no_run
+/// use windows_capture::{
+///     capture::GraphicsCaptureApiHandler, frame::Frame,
+///     graphics_capture_api::InternalCaptureControl, test_source::TestSource,
+/// };
+///
+/// struct Capture;
+///
+/// impl GraphicsCaptureApiHandler for Capture {
+///     type Flags = ();
+///     type Error = Box<dyn std::error::Error + Send + Sync>;
+///
+///     fn new(_: Self::Flags) -> Result<Self, Self::Error> {
+///         Ok(Self)
+///     }
+///
+///     fn on_frame_arrived(
+///         &mut self,
+///         frame: &mut Frame,
+///         _: InternalCaptureControl,
+///     ) -> Result<(), Self::Error> {
+///         assert_eq!(frame.width(), 320);
+///         Ok(())
+///     }
+/// }
+///
+/// TestSource::new(320, 240).fps(30).frame_count(90).start::<Capture>(()).unwrap();
+/// ```
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct TestSource {
+    width: u32,
+    height: u32,
+    fps: u32,
+    frame_count: Option<u64>,
+    color_format: ColorFormat,
+}
+
+impl TestSource {
+    /// Create a new test source that delivers Rgba8 frames at 60 frames per second until stopped.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the generated frames, `start` fails if it's 0.
+    /// * `height` - The height of the generated frames, `start` fails if it's 0.
+    #[must_use]
+    pub const fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            fps: 60,
+            frame_count: None,
+            color_format: ColorFormat::Rgba8,
+        }
+    }
+
+    /// Set the rate frames are delivered at, `0` delivers frames as fast as the handler accepts them.
+    ///
+    /// # Arguments
+    ///
+    /// * `fps` - The number of frames per second.
+    #[must_use]
+    pub const fn fps(mut self, fps: u32) -> Self {
+        self.fps = fps;
+        self
+    }
+
+    /// Set the number of frames to deliver, after the last frame `on_closed` is called like when a
+    /// captured window closes.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_count` - The number of frames to deliver.
+    #[must_use]
+    pub const fn frame_count(mut self, frame_count: u64) -> Self {
+        self.frame_count = Some(frame_count);
+        self
+    }
+
+    /// Set the color format of the generated frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `color_format` - The color format of the generated frames.
+    #[must_use]
+    pub const fn color_format(mut self, color_format: ColorFormat) -> Self {
+        self.color_format = color_format;
+        self
+    }
+
+    /// Generate the pixels of a frame without padding.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the frame.
+    ///
+    /// # Returns
+    ///
+    /// The pixel data of the frame in the color format of the source.
+    #[must_use]
+    pub fn generate_frame(&self, index: u64) -> Vec<u8> {
+        let bytes_per_pixel = match self.color_format {
+            ColorFormat::Rgba16F => 8,
+            ColorFormat::Rgba8 | ColorFormat::Bgra8 => 4,
+        };
+
+        let mut buffer =
+            Vec::with_capacity(self.width as usize * self.height as usize * bytes_per_pixel);
+
+        let offset = index % 256;
+        for y in 0..u64::from(self.height) {
+            for x in 0..u64::from(self.width) {
+                let red = ((x + offset) % 256) as u8;
+                let green = ((y + offset) % 256) as u8;
+                let blue = offset as u8;
+
+                match self.color_format {
+                    ColorFormat::Rgba16F => {
+                        for value in [red, green, blue, 255] {
+                            buffer.extend_from_slice(&unorm_to_f16(value).to_le_bytes());
+                        }
+                    }
+                    ColorFormat::Rgba8 => buffer.extend_from_slice(&[red, green, blue, 255]),
+                    ColorFormat::Bgra8 => buffer.extend_from_slice(&[blue, green, red, 255]),
+                }
+            }
+        }
+
+        buffer
+    }
+
+    /// Starts delivering frames and takes control of the current thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `flags` - The flags used to create the handler.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` when the frame count is reached or the handler stops the capture,
+    /// otherwise returns an error of type `GraphicsCaptureApiError`, `EmptyFrameSize` if the
+    /// width or the height is 0.
+    pub fn start<H: GraphicsCaptureApiHandler>(
+        &self,
+        flags: H::Flags,
+    ) -> Result<(), GraphicsCaptureApiError<H::Error>> {
+        if self.width == 0 || self.height == 0 {
+            return Err(GraphicsCaptureApiError::EmptyFrameSize(
+                self.width,
+                self.height,
+            ));
+        }
+
+        polling_capture::run::<H>(flags, self.fps, self.color_format, |index| {
+            if self
+                .frame_count
+                .is_some_and(|frame_count| index >= frame_count)
+            {
+                return Ok(Poll::Closed);
+            }
+
+            Ok(Poll::Frame {
+                pixels: self.generate_frame(index),
+                width: self.width,
+                height: self.height,
+                // Timestamps follow the configured rate so they are deterministic too
+                time: TimeSpan {
+                    Duration: i64::try_from(
+                        u128::from(index) * 10_000_000 / u128::from(self.fps.max(1)),
+                    )
+                    .unwrap(),
+                },
+            })
+        })
+    }
+}
+
+// Convert a normalized 8-bit value to a half precision float, the values are never denormal.
+fn unorm_to_f16(value: u8) -> u16 {
+    if value == 0 {
+        return 0;
+    }
+
+    let bits = (f32::from(value) / 255.0).to_bits();
+    let exponent = ((bits >> 23) & 0xFF) as i32 - 127 + 15;
+    let mantissa = (bits >> 13) & 0x3FF;
+
+    u16::try_from((u32::try_from(exponent).unwrap() << 10) | mantissa).unwrap()
+}
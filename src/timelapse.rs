@@ -0,0 +1,221 @@
+use std::{path::PathBuf, time::Duration};
+
+use windows::Graphics::Capture::GraphicsCaptureItem;
+
+use crate::{
+    capture::{
+        CaptureControl, CaptureControlError, GraphicsCaptureApiError, GraphicsCaptureApiHandler,
+    },
+    encoder::{VideoEncoder, VideoEncoderError, VideoEncoderQuality, VideoEncoderType},
+    frame::Frame,
+    graphics_capture_api::InternalCaptureControl,
+    settings::{ColorFormat, CursorCaptureSettings, DrawBorderSettings, Settings},
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("The interval and the output frame rate must not be zero")]
+    InvalidTiming,
+    #[error("Failed to encode the timelapse: {0}")]
+    VideoEncoderError(#[from] VideoEncoderError),
+}
+
+/// Records a timelapse, one frame is captured every interval and the frames are encoded back to
+/// back at the output frame rate, for example one frame every 5 seconds played at 30 fps.
+///
+/// The capture only delivers frames when the content changes, an interval without a new frame
+/// holds the previous frame in the video for as long as the missed intervals would have played.
+/// The video size is taken from the first frame.
+///
+/// # Example
+/// ```no_run
+/// use std::{thread, time::Duration};
+///
+/// use windows_capture::{monitor::Monitor, timelapse::TimelapseRecorder};
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let recorder = TimelapseRecorder::new(
+///         Monitor::primary()?,
+///         Duration::from_secs(5),
+///         30,
+///         "timelapse.mp4",
+///     )?;
+///
+///     thread::sleep(Duration::from_secs(60 * 60));
+///
+///     recorder.stop()?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct TimelapseRecorder {
+    capture_control: Option<CaptureControl<TimelapseHandler, Error>>,
+}
+
+impl TimelapseRecorder {
+    /// Starts capturing the item for a timelapse.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The item to capture.
+    /// * `interval` - The capture time between two frames of the timelapse.
+    /// * `output_fps` - The frame rate the timelapse is played at.
+    /// * `path` - The file path where the timelapse will be saved.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(TimelapseRecorder)` if the capture started, otherwise returns an error of type
+    /// `GraphicsCaptureApiError`.
+    pub fn new<T: TryInto<GraphicsCaptureItem> + Send + 'static, P: Into<PathBuf>>(
+        item: T,
+        interval: Duration,
+        output_fps: u32,
+        path: P,
+    ) -> Result<Self, GraphicsCaptureApiError<Error>> {
+        let settings = Settings::new(
+            item,
+            CursorCaptureSettings::Default,
+            DrawBorderSettings::Default,
+            ColorFormat::Bgra8,
+            TimelapseFlags {
+                interval,
+                output_fps,
+                path: path.into(),
+            },
+        );
+
+        Ok(Self {
+            capture_control: Some(TimelapseHandler::start_free_threaded(settings)?),
+        })
+    }
+
+    /// Get the number of frames encoded so far.
+    #[must_use]
+    pub fn frame_count(&self) -> u64 {
+        self.capture_control.as_ref().map_or(0, |capture_control| {
+            capture_control.callback().lock().frame_count
+        })
+    }
+
+    /// Checks if the capture ended, for example because the captured window closed.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.capture_control
+            .as_ref()
+            .map_or(true, CaptureControl::is_finished)
+    }
+
+    /// Stops the capture and finishes the video.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the capture stopped and the video was written, otherwise the first error.
+    pub fn stop(mut self) -> Result<(), CaptureControlError<Error>> {
+        self.stop_and_finish()
+    }
+
+    // Stop the capture first so no frame is sent to the encoder while it finishes.
+    fn stop_and_finish(&mut self) -> Result<(), CaptureControlError<Error>> {
+        let Some(capture_control) = self.capture_control.take() else {
+            return Ok(());
+        };
+
+        let callback = capture_control.callback();
+        let result = capture_control.stop();
+
+        let encoder = callback.lock().encoder.take();
+        if let Some(encoder) = encoder {
+            encoder
+                .finish()
+                .map_err(|e| CaptureControlError::StoppedHandlerError(Error::from(e)))?;
+        }
+
+        result
+    }
+}
+
+impl Drop for TimelapseRecorder {
+    fn drop(&mut self) {
+        let _ = self.stop_and_finish();
+    }
+}
+
+/// The flags the handler of a `TimelapseRecorder` is created with.
+pub struct TimelapseFlags {
+    interval: Duration,
+    output_fps: u32,
+    path: PathBuf,
+}
+
+/// The handler of a `TimelapseRecorder`, it picks one frame per interval and encodes it with
+/// the timestamp of its place in the timelapse.
+pub struct TimelapseHandler {
+    encoder: Option<VideoEncoder>,
+    // In 100ns units
+    interval: i64,
+    frame_duration: i64,
+    start: Option<i64>,
+    last_tick: Option<i64>,
+    frame_count: u64,
+}
+
+impl GraphicsCaptureApiHandler for TimelapseHandler {
+    type Flags = TimelapseFlags;
+    type Error = Error;
+
+    fn new(flags: Self::Flags) -> Result<Self, Self::Error> {
+        let interval = i64::try_from(flags.interval.as_nanos() / 100).unwrap_or(i64::MAX);
+        if interval == 0 || flags.output_fps == 0 {
+            return Err(Error::InvalidTiming);
+        }
+
+        let encoder = VideoEncoder::new_auto(
+            VideoEncoderType::Mp4,
+            VideoEncoderQuality::HD1080p,
+            flags.path,
+            Some(flags.output_fps),
+        )?;
+
+        Ok(Self {
+            encoder: Some(encoder),
+            interval,
+            frame_duration: 10_000_000 / i64::from(flags.output_fps),
+            start: None,
+            last_tick: None,
+            frame_count: 0,
+        })
+    }
+
+    fn on_frame_arrived(
+        &mut self,
+        frame: &mut Frame,
+        _capture_control: InternalCaptureControl,
+    ) -> Result<(), Self::Error> {
+        let Some(encoder) = &mut self.encoder else {
+            return Ok(());
+        };
+
+        let time = frame.timespan().Duration;
+        let start = *self.start.get_or_insert(time);
+
+        // Only the first frame of every interval is part of the timelapse
+        let tick = (time - start) / self.interval;
+        if self.last_tick.is_some_and(|last_tick| tick <= last_tick) {
+            return Ok(());
+        }
+        self.last_tick = Some(tick);
+
+        encoder.send_frame_at(frame, tick * self.frame_duration)?;
+        self.frame_count += 1;
+
+        Ok(())
+    }
+
+    fn on_closed(&mut self) -> Result<(), Self::Error> {
+        if let Some(encoder) = self.encoder.take() {
+            encoder.finish()?;
+        }
+
+        Ok(())
+    }
+}
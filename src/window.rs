@@ -1,23 +1,45 @@
-use std::{ptr, string::FromUtf16Error};
+use std::{
+    mem, ptr,
+    string::FromUtf16Error,
+    thread,
+    time::{Duration, Instant},
+};
 
+use parking_lot::Mutex;
 use windows::{
-    core::HSTRING,
+    core::{Interface, Weak, HSTRING},
     Graphics::Capture::GraphicsCaptureItem,
     Win32::{
         Foundation::{BOOL, HWND, LPARAM, RECT, TRUE},
-        Graphics::Gdi::{MonitorFromWindow, MONITOR_DEFAULTTONULL},
+        Graphics::{
+            Dwm::{DwmGetWindowAttribute, DWMWA_CLOAKED, DWMWA_EXTENDED_FRAME_BOUNDS},
+            Gdi::{MonitorFromWindow, MONITOR_DEFAULTTONULL},
+        },
         System::{
             Threading::GetCurrentProcessId, WinRT::Graphics::Capture::IGraphicsCaptureItemInterop,
         },
-        UI::WindowsAndMessaging::{
-            EnumChildWindows, FindWindowW, GetClientRect, GetDesktopWindow, GetForegroundWindow,
-            GetWindowLongPtrW, GetWindowTextLengthW, GetWindowTextW, GetWindowThreadProcessId,
-            IsWindowVisible, GWL_EXSTYLE, GWL_STYLE, WS_CHILD, WS_EX_TOOLWINDOW,
+        UI::{
+            HiDpi::GetDpiForWindow,
+            WindowsAndMessaging::{
+                EnumChildWindows, FindWindowW, GetAncestor, GetClientRect, GetDesktopWindow,
+                GetForegroundWindow, GetWindow, GetWindowDisplayAffinity, GetWindowLongPtrW,
+                GetWindowRect, GetWindowTextLengthW, GetWindowTextW, GetWindowThreadProcessId,
+                IsIconic, IsWindow, IsWindowVisible, SetWindowDisplayAffinity, GA_ROOT,
+                GWL_EXSTYLE, GWL_STYLE, GW_HWNDPREV, WDA_EXCLUDEFROMCAPTURE, WDA_NONE, WS_CHILD,
+                WS_EX_TOOLWINDOW,
+            },
         },
     },
 };
 
-use crate::monitor::Monitor;
+use crate::{
+    capture::GraphicsCaptureApiError,
+    frame::Rect,
+    frame_iterator::{self, FrameIterator},
+    graphics_capture_api::CaptureItemInfo,
+    monitor::Monitor,
+    settings::{CropInsetSettings, Settings},
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -31,6 +53,13 @@ pub enum Error {
     WindowsError(#[from] windows::core::Error),
 }
 
+/// How often `Window::wait_for_name` looks for the window.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The windows capture items were created from, see `Window::from_capture_item`.
+static CAPTURE_ITEM_WINDOWS: Mutex<Vec<(Weak<GraphicsCaptureItem>, Window)>> =
+    Mutex::new(Vec::new());
+
 /// Represents a window in the Windows operating system.
 ///
 /// # Example
@@ -110,6 +139,97 @@ impl Window {
         target_window.map_or_else(|| Err(Error::NotFound(String::from(title))), Ok)
     }
 
+    /// Waits until a window whose name contains the substring exists, for example after
+    /// launching the application that creates it.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The substring to search for in window names, like `from_contains_name`.
+    /// * `timeout` - The longest time to wait for the window.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::NotFound` if no matching window appeared before the timeout elapsed.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::{process::Command, time::Duration};
+    ///
+    /// use windows_capture::window::Window;
+    ///
+    /// fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     Command::new("notepad.exe").spawn()?;
+    ///
+    ///     let window = Window::wait_for_name("Notepad", Duration::from_secs(10))?;
+    ///     println!("Found {}", window.title()?);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn wait_for_name(title: &str, timeout: Duration) -> Result<Self, Error> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            match Self::from_contains_name(title) {
+                Err(Error::NotFound(_)) if Instant::now() < deadline => {
+                    thread::sleep(
+                        WAIT_POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())),
+                    );
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Creates a `Window` instance from a window name that matches exactly, like `from_name`
+    /// but case-sensitive.
+    ///
+    /// Unlike `from_contains_name`, this won't select "Document - Copy" when looking for
+    /// "Document".
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The exact name of the window.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::NotFound` if no window with exactly this name is found.
+    pub fn from_title_exact(title: &str) -> Result<Self, Error> {
+        let window = Self::from_name(title)?;
+
+        // FindWindowW ignores the case of the title
+        if window.title()? != title {
+            return Err(Error::NotFound(String::from(title)));
+        }
+
+        Ok(window)
+    }
+
+    /// Creates a `Window` instance from a window name that matches exactly, ignoring case.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The exact name of the window.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::NotFound` if no window with this name is found.
+    pub fn from_title_exact_ignore_case(title: &str) -> Result<Self, Error> {
+        let windows = Self::enumerate()?;
+
+        let title_lowercase = title.to_lowercase();
+
+        let mut target_window = None;
+        for window in windows {
+            if window.title()?.to_lowercase() == title_lowercase {
+                target_window = Some(window);
+                break;
+            }
+        }
+
+        target_window.map_or_else(|| Err(Error::NotFound(String::from(title))), Ok)
+    }
+
     /// Returns the title of the window.
     ///
     /// # Errors
@@ -154,6 +274,60 @@ impl Window {
         }
     }
 
+    /// Returns the position of the window's top-left corner in the virtual desktop, including
+    /// the invisible borders and the shadow like the frames of a capture of the window.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the window rect could not be retrieved.
+    pub fn position(&self) -> Result<(i32, i32), Error> {
+        let mut window_rect = RECT::default();
+        unsafe { GetWindowRect(self.window, &mut window_rect)? };
+
+        Ok((window_rect.left, window_rect.top))
+    }
+
+    /// Returns the DPI of the window, 96 is a scale of 100%.
+    ///
+    /// The DPI changes when the window moves to a monitor with another scale, read it for every
+    /// frame to map the pixels of the window to the coordinates of your overlay.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the window is invalid.
+    pub fn dpi(&self) -> Result<u32, Error> {
+        let dpi = unsafe { GetDpiForWindow(self.window) };
+        if dpi == 0 {
+            return Err(Error::WindowsError(windows::core::Error::from_win32()));
+        }
+
+        Ok(dpi)
+    }
+
+    /// Returns the scale factor of the window, for example `1.5` for a scale of 150%.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the window is invalid.
+    pub fn scale_factor(&self) -> Result<f64, Error> {
+        Ok(f64::from(self.dpi()?) / 96.0)
+    }
+
+    /// Returns the display name, size and closed state of the window as a capture item.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the capture item can't be created or read.
+    pub fn capture_item_info(&self) -> Result<CaptureItemInfo, Error> {
+        if !unsafe { IsWindow(self.window) }.as_bool() {
+            return Ok(CaptureItemInfo::closed());
+        }
+
+        let item = GraphicsCaptureItem::try_from(*self)?;
+
+        Ok(CaptureItemInfo::from_item(&item)?)
+    }
+
     /// Checks if the window is a valid window.
     ///
     /// # Returns
@@ -190,6 +364,206 @@ impl Window {
         true
     }
 
+    /// Checks if the window is cloaked, for example a suspended UWP app or a window on another
+    /// virtual desktop. `IsWindowVisible` reports cloaked windows as visible but they capture as
+    /// blank.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the window is cloaked, `false` otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the cloaked state could not be retrieved.
+    pub fn is_cloaked(&self) -> Result<bool, Error> {
+        let mut cloaked = 0u32;
+        unsafe {
+            DwmGetWindowAttribute(
+                self.window,
+                DWMWA_CLOAKED,
+                ptr::addr_of_mut!(cloaked).cast(),
+                u32::try_from(mem::size_of::<u32>()).unwrap(),
+            )?;
+        };
+
+        Ok(cloaked != 0)
+    }
+
+    /// Returns the areas of the window that are covered by other windows, relative to the top
+    /// left corner of its window rect where a capture of the window starts.
+    ///
+    /// Every visible window above it in the z-order that is neither minimized nor cloaked covers
+    /// the intersection of its frame bounds with the window, windows with a transparent or
+    /// irregular shape cover their whole bounds.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the frame bounds of the window could not be retrieved.
+    pub fn occluded_rects(&self) -> Result<Vec<Rect>, Error> {
+        let bounds = Self::frame_bounds(self.window)?;
+        let mut window_rect = RECT::default();
+        unsafe { GetWindowRect(self.window, &mut window_rect)? };
+
+        let mut rects = Vec::new();
+
+        let mut current = self.window;
+        while let Ok(window) = unsafe { GetWindow(current, GW_HWNDPREV) } {
+            current = window;
+
+            if !unsafe { IsWindowVisible(window) }.as_bool()
+                || unsafe { IsIconic(window) }.as_bool()
+                || Self::from_raw_hwnd(window.0).is_cloaked().unwrap_or(false)
+            {
+                continue;
+            }
+
+            let Ok(other) = Self::frame_bounds(window) else {
+                continue;
+            };
+
+            let left = other.left.max(bounds.left);
+            let top = other.top.max(bounds.top);
+            let right = other.right.min(bounds.right);
+            let bottom = other.bottom.min(bounds.bottom);
+
+            if left >= right || top >= bottom {
+                continue;
+            }
+
+            rects.push(Rect {
+                x: u32::try_from(left - window_rect.left).unwrap_or(0),
+                y: u32::try_from(top - window_rect.top).unwrap_or(0),
+                width: u32::try_from(right - left).unwrap_or(0),
+                height: u32::try_from(bottom - top).unwrap_or(0),
+            });
+        }
+
+        Ok(rects)
+    }
+
+    /// Returns the invisible resize borders and the shadow around the window, the part of the
+    /// window rect outside of its frame bounds. A capture of the window includes them, cropping
+    /// the inset off leaves the window as it's seen on screen.
+    ///
+    /// The process must be per monitor DPI aware, otherwise the window rect is scaled but the
+    /// frame bounds are not.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the window rect or the frame bounds could not be retrieved.
+    pub fn shadow_inset(&self) -> Result<CropInsetSettings, Error> {
+        let bounds = Self::frame_bounds(self.window)?;
+        let mut window_rect = RECT::default();
+        unsafe { GetWindowRect(self.window, &mut window_rect)? };
+
+        Ok(CropInsetSettings::new(
+            u32::try_from(bounds.left - window_rect.left).unwrap_or(0),
+            u32::try_from(bounds.top - window_rect.top).unwrap_or(0),
+            u32::try_from(window_rect.right - bounds.right).unwrap_or(0),
+            u32::try_from(window_rect.bottom - bounds.bottom).unwrap_or(0),
+        ))
+    }
+
+    /// Returns the top-level window that contains the window, or the window itself if it's a
+    /// top-level window. Child windows can't be captured on their own, capture their root with
+    /// `Settings::crop_to_child` instead.
+    #[must_use]
+    pub fn root(&self) -> Self {
+        let root = unsafe { GetAncestor(self.window, GA_ROOT) };
+
+        if root.is_invalid() {
+            *self
+        } else {
+            Self { window: root }
+        }
+    }
+
+    /// Returns the inset that crops a capture of the window to one of its child windows.
+    ///
+    /// # Arguments
+    ///
+    /// * `child` - A child window inside the window.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the rect of either window could not be retrieved.
+    pub fn child_inset(&self, child: &Self) -> Result<CropInsetSettings, Error> {
+        let mut window_rect = RECT::default();
+        unsafe { GetWindowRect(self.window, &mut window_rect)? };
+
+        let mut child_rect = RECT::default();
+        unsafe { GetWindowRect(child.window, &mut child_rect)? };
+
+        Ok(CropInsetSettings::new(
+            u32::try_from(child_rect.left - window_rect.left).unwrap_or(0),
+            u32::try_from(child_rect.top - window_rect.top).unwrap_or(0),
+            u32::try_from(window_rect.right - child_rect.right).unwrap_or(0),
+            u32::try_from(window_rect.bottom - child_rect.bottom).unwrap_or(0),
+        ))
+    }
+
+    /// Checks if the window protects its content from being captured with
+    /// `SetWindowDisplayAffinity`, capturing such a window only records black.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the window content is protected, `false` otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the display affinity could not be retrieved.
+    pub fn is_content_protected(&self) -> Result<bool, Error> {
+        let mut affinity = 0;
+        unsafe { GetWindowDisplayAffinity(self.window, &mut affinity)? };
+
+        Ok(affinity != WDA_NONE.0)
+    }
+
+    /// Hides the window from all screen captures, including captures of the monitor it's on,
+    /// useful to keep an overlay of the capturing app out of its own recordings.
+    ///
+    /// The window must belong to the current process and needs Windows 10 version 2004 or
+    /// later.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the display affinity could not be set.
+    pub fn exclude_from_capture(&self) -> Result<(), Error> {
+        unsafe { SetWindowDisplayAffinity(self.window, WDA_EXCLUDEFROMCAPTURE)? };
+
+        Ok(())
+    }
+
+    /// Makes a window hidden with `exclude_from_capture` visible to screen captures again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the display affinity could not be set.
+    pub fn clear_capture_exclusion(&self) -> Result<(), Error> {
+        unsafe { SetWindowDisplayAffinity(self.window, WDA_NONE)? };
+
+        Ok(())
+    }
+
+    /// Starts capturing the window with the settings and returns an iterator over its frames.
+    ///
+    /// The item of the settings is replaced by the window, so the settings of another capture
+    /// can be reused.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The capture settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `GraphicsCaptureApiError` if the capture could not be started.
+    pub fn frames<T: TryInto<GraphicsCaptureItem>>(
+        &self,
+        settings: Settings<(), T>,
+    ) -> Result<FrameIterator, GraphicsCaptureApiError<frame_iterator::Error>> {
+        FrameIterator::new(settings.with_item(*self))
+    }
+
     /// Returns a list of all windows.
     ///
     /// # Errors
@@ -210,6 +584,41 @@ impl Window {
         Ok(windows)
     }
 
+    /// Returns a list of all windows without the cloaked ones, for example to leave the windows
+    /// of other virtual desktops out of a window picker because they capture as blank.
+    ///
+    /// `Window::enumerate` keeps cloaked windows, see `Window::is_cloaked`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if there is an error enumerating the windows.
+    pub fn enumerate_uncloaked() -> Result<Vec<Self>, Error> {
+        let mut windows = Self::enumerate()?;
+        windows.retain(|window| !window.is_cloaked().unwrap_or(false));
+
+        Ok(windows)
+    }
+
+    /// Returns a list of all windows that are mostly on a monitor, for example to only show
+    /// the windows of the selected display in a window picker.
+    ///
+    /// A window belongs to the monitor that has the largest area of intersection with it, see
+    /// `Window::monitor`.
+    ///
+    /// # Arguments
+    ///
+    /// * `monitor` - The monitor the windows must be on.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if there is an error enumerating the windows.
+    pub fn enumerate_on_monitor(monitor: &Monitor) -> Result<Vec<Self>, Error> {
+        let mut windows = Self::enumerate()?;
+        windows.retain(|window| window.monitor().as_ref() == Some(monitor));
+
+        Ok(windows)
+    }
+
     /// Creates a `Window` instance from a raw HWND.
     ///
     /// # Arguments
@@ -220,12 +629,47 @@ impl Window {
         Self { window: HWND(hwnd) }
     }
 
+    /// Finds the window a capture item was created from with `GraphicsCaptureItem::try_from`.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The capture item.
+    ///
+    /// # Returns
+    ///
+    /// The window, or `None` if the item was not created from a window, like a monitor or an
+    /// item picked with the `GraphicsCapturePicker`.
+    #[must_use]
+    pub fn from_capture_item(item: &GraphicsCaptureItem) -> Option<Self> {
+        CAPTURE_ITEM_WINDOWS
+            .lock()
+            .iter()
+            .find(|(weak, _)| weak.upgrade().as_ref() == Some(item))
+            .map(|(_, window)| *window)
+    }
+
     /// Returns the raw HWND of the window.
     #[must_use]
     pub const fn as_raw_hwnd(&self) -> *mut std::ffi::c_void {
         self.window.0
     }
 
+    // Get the bounds of a window without its invisible resize borders, the area a capture of
+    // the window shows.
+    pub(crate) fn frame_bounds(window: HWND) -> Result<RECT, Error> {
+        let mut rect = RECT::default();
+        unsafe {
+            DwmGetWindowAttribute(
+                window,
+                DWMWA_EXTENDED_FRAME_BOUNDS,
+                ptr::addr_of_mut!(rect).cast(),
+                u32::try_from(mem::size_of::<RECT>()).unwrap(),
+            )?;
+        };
+
+        Ok(rect)
+    }
+
     // Callback used for enumerating all windows.
     unsafe extern "system" fn enum_windows_callback(window: HWND, vec: LPARAM) -> BOOL {
         let windows = &mut *(vec.0 as *mut Vec<Self>);
@@ -246,6 +690,15 @@ impl TryFrom<Window> for GraphicsCaptureItem {
         let window = HWND(value.as_raw_hwnd());
 
         let interop = windows::core::factory::<Self, IGraphicsCaptureItemInterop>()?;
-        Ok(unsafe { interop.CreateForWindow(window)? })
+        let item: Self = unsafe { interop.CreateForWindow(window)? };
+
+        // Remember the window, the item can't be mapped back to it
+        if let Ok(weak) = item.downgrade() {
+            let mut item_windows = CAPTURE_ITEM_WINDOWS.lock();
+            item_windows.retain(|(weak, _)| weak.upgrade().is_some());
+            item_windows.push((weak, value));
+        }
+
+        Ok(item)
     }
 }